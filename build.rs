@@ -39,4 +39,20 @@ fn main() {
     }
 
     println!("cargo:rustc-env=ASSET_DIR={}", &dir);
+
+    #[cfg(feature = "grpc")]
+    compile_protos();
+}
+
+// protox parses the definitions, so no protoc is needed
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    println!("cargo:rerun-if-changed=proto/segmenter.proto");
+
+    let descriptors = protox::compile(["proto/segmenter.proto"], ["proto"]).unwrap();
+
+    tonic_prost_build::configure()
+        .build_client(false)
+        .compile_fds(descriptors)
+        .unwrap();
 }
\ No newline at end of file
@@ -1,29 +1,17 @@
 use std::collections::HashMap;
 use std::fs::{DirEntry, File};
 use std::hash::BuildHasherDefault;
-use std::io::{BufReader, Read, Write};
-use std::ops::{AddAssign, Div};
-use std::ops::Mul;
+use std::ops::AddAssign;
 use std::path::Path;
 
-use cortical_io::density::{Density, Kde};
-use num::complex::ComplexFloat;
-use num::Float;
-use num_traits::FromPrimitive;
 use rayon::iter::IntoParallelRefIterator;
 use rayon::iter::ParallelIterator;
 use twox_hash::XxHash;
-use zstd::Decoder;
 
-use serializer::deserialize;
-use text::text_item::TextItem;
-use crate::serializer::SerializerFeedback;
-
-use crate::text::STOPWORDS;
-use crate::text::text_item::{PooMap, PooMapInner};
-
-mod text;
-mod serializer;
+use poo::index::decode_file;
+use poo::serializer::{deserialize_any, SerializerFeedback};
+use poo::text::STOPWORDS;
+use poo::text::text_item::PooMapInner;
 
 fn std_deviation(values: &[f32]) -> f32 {
     let mean = values.iter().sum::<f32>() / values.len() as f32;
@@ -31,42 +19,14 @@ fn std_deviation(values: &[f32]) -> f32 {
     variance.sqrt()
 }
 
-// double y;
-//
-//     c = c & 0xFF;
-//     y = (double) c;
-//     y = y / 255.0;
-//     if (c <= 0.04045)
-//         y = y / 12.92;
-//     else
-//         y = pow(((y + 0.055) / 1.055), 2.4);
-//     return (y);
-
-fn srgb_to_linear(c: f32) -> f32 {
-    let y = c / 255.0;
-    if y <= 0.04045 {
-        y / 12.92
-    } else {
-        ((y + 0.055) / 1.055).powf(2.4)
-    }
-}
-
-fn linear_to_srgb(c: f32) -> f32 {
-    if c <= 0.0031308 {
-        c * 12.92
-    } else {
-        1.055 * c.powf(1.0 / 2.4) - 0.055
-    }
-}
-
 fn save_fingerpint(poo_map: &PooMapInner, name: &str, fp_type: &str) {
-    let mut gwf = {
+    let gwf = {
         let mut f =
             poo_map
                 .iter()
                 .collect::<Vec<_>>();
 
-        f.sort_by(|a, b| b.1.cmp(a.1));
+        f.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
 
         let mut p = HashMap::<Vec<u8>, u64, BuildHasherDefault<XxHash>>::default();
 
@@ -77,10 +37,10 @@ fn save_fingerpint(poo_map: &PooMapInner, name: &str, fp_type: &str) {
         p
     };
 
-    let mut f =
+    let f =
         gwf
-            .iter()
-            .map(|(k, v)| *v as u32)
+            .values()
+            .map(|v| *v as u32)
             .collect::<Vec<_>>();
 
     let f_stddev = std_deviation(&f.iter().map(|x| *x as f32).collect::<Vec<_>>());
@@ -116,24 +76,11 @@ fn save_fingerpint(poo_map: &PooMapInner, name: &str, fp_type: &str) {
         &buffer.iter().map(|x| x.norm() as u32).collect::<Vec<_>>(),
         //&f,
         10,
-        |p, i|
+        |p, _i|
             match p {
                 0 => [0, 0, 0],
                 //_ if densest_points.contains(&i) => [255, 0, 0],
                 _ => {
-                    // normalize p
-                    let r1 = 255.0 / 255.0;
-                    let g1 = 20.0 / 255.0;
-                    let b1 = 147.0 / 255.0;
-
-                    let r2 = 255.0 / 255.0;
-                    let g2 = 0.0 / 255.0;
-                    let b2 = 255.0 / 255.0;
-
-                    let r = (((r2 - r1) * p as f32 + r1) * 255.0) as u8;
-                    let g = (((g2 - g1) * p as f32 + g1) * 255.0) as u8;
-                    let b = (((b2 - b1) * p as f32 + b1) * 255.0) as u8;
-
                     //[255 - p, (20 + p).max(255), (147 - p).min(0)]
                     [
                         p / 3,
@@ -142,7 +89,7 @@ fn save_fingerpint(poo_map: &PooMapInner, name: &str, fp_type: &str) {
                     ]
                 },
             },
-    ).save(&format!("./fps/{}.{}.png", name, fp_type)).unwrap();
+    ).save(format!("./fps/{}.{}.png", name, fp_type)).unwrap();
 }
 
 fn run_for_file(path: &Path) {
@@ -152,23 +99,22 @@ fn run_for_file(path: &Path) {
 
     let mut file = File::open(path).unwrap();
 
-    let mut decoder =
-        Decoder::new(&mut file).unwrap();
-
-    let mut buf = Vec::new();
-    decoder.read_to_end(&mut buf).unwrap();
-    //file.read_to_end(&mut buf).unwrap();
+    let buf = decode_file(&mut file).unwrap();
 
     let poo =
-        deserialize(
+        match deserialize_any(
             &buf,
             |x| if let SerializerFeedback::Message(m) = x { println!("{}", m) },
-        );
-
-    let author_count = poo.len();
+        ) {
+            Ok(poo) => poo,
+            Err(e) => {
+                println!("Error reading {}: {}", name, e);
+                return;
+            }
+        };
 
     // create a PooMap merging the frequencies of all comments by the same author
-    let mut poo_map = PooMapInner::new();
+    let mut poo_map = PooMapInner::default();
 
     for (_, ref mut freqs) in poo.iter() {
         for (word, freq) in freqs.iter() {
@@ -185,18 +131,13 @@ fn run_for_file(path: &Path) {
 
     save_fingerpint(&poo_map, "global", "global");
 
-    use rustfft::{FftPlanner, num_complex::Complex};
-
-    let mut planner = FftPlanner::<f32>::new();
-    let fft = planner.plan_fft_inverse(16384);
-
     let mut authors = poo
         .iter()
         .collect::<Vec<_>>();
 
-    authors.sort_by(|a, b| b.1.len().cmp(&a.1.len()));
+    authors.sort_by_key(|a| std::cmp::Reverse(a.1.len()));
 
-    let mut authors = authors
+    let authors = authors
         .iter()
         .take(100)
         .collect::<Vec<_>>();
@@ -215,20 +156,21 @@ fn run_for_file(path: &Path) {
                 }
             }
 
-            let author = String::from_utf8_lossy(author.iter().filter(|&b| *b != 0).cloned().collect::<Vec<_>>().as_slice()).to_string();
+            let _author = String::from_utf8_lossy(author.iter().filter(|&b| *b != 0).cloned().collect::<Vec<_>>().as_slice()).to_string();
 
             // count zeros in xy
             let not_zero_count = xy.iter().filter(|(_, v)| **v > 0).count();
 
-            if not_zero_count < 128 {
-                return;
+            if not_zero_count >= 128 {
+                //save_fingerpint(&xy, &author, "norm");
             }
-
-            //save_fingerpint(&xy, &author, "norm");
         });
 }
 
 fn main() {
+    // for the warnings of the deserializer
+    tracing_subscriber::fmt().with_target(false).init();
+
     // find folder located at first argument
     let path = std::env::args().nth(1).expect("No path provided");
     let path = std::path::Path::new(&path);
@@ -0,0 +1,269 @@
+use std::collections::HashSet;
+use std::io::{Error, ErrorKind, Read};
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::filter::Timestamp;
+use crate::remote::Remote;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiConfig {
+    // an archive speaking the arctic shift search api
+    pub base_url: String,
+    // items per request, at most 100
+    pub page_size: usize,
+    // pause between requests to stay under the rate limit
+    pub delay_ms: u64,
+    // attempts at a page that failed or was rate limited
+    pub retries: u32,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            base_url: "https://arctic-shift.photon-reddit.com".to_string(),
+            page_size: 100,
+            delay_ms: 1000,
+            retries: 5,
+        }
+    }
+}
+
+// `api:subreddit=rust,programming&after=2024-05-01&before=2024-06-01`, or `author=` with names or
+// `@file` holding one per line
+pub fn is_api(path: &Path) -> bool {
+    path.to_str().is_some_and(|v| v.starts_with("api:"))
+}
+
+// the query as a file name, so every window gets its own output
+pub fn output_name(spec: &str) -> String {
+    spec.chars()
+        .map(|c| match c {
+            ':' | '&' => '_',
+            '=' | ',' | '/' | '@' => '-',
+            c => c,
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+struct Query {
+    // `subreddit` or `author`
+    field: &'static str,
+    names: Vec<String>,
+    after: Option<i64>,
+    before: Option<i64>,
+}
+
+// some archives return floats
+fn created_utc(item: &Value) -> Option<i64> {
+    let value = &item["created_utc"];
+
+    value.as_i64().or_else(|| value.as_f64().map(|v| v as i64))
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+fn parse(spec: &str) -> std::io::Result<Query> {
+    let mut query = Query { field: "subreddit", names: Vec::new(), after: None, before: None };
+
+    for param in spec.trim_start_matches("api:").split('&') {
+        let Some((key, value)) = param.split_once('=') else {
+            return Err(invalid(format!("expected key=value in {}, got '{}'", spec, param)));
+        };
+
+        let timestamp = || value.parse::<Timestamp>().map(|v| Some(v.0)).map_err(invalid);
+
+        match key {
+            "subreddit" | "author" => {
+                query.field = if key == "author" { "author" } else { "subreddit" };
+
+                query.names =
+                    match value.strip_prefix('@') {
+                        Some(file) => std::fs::read_to_string(file)?.lines().map(|v| v.trim().to_string()).collect(),
+                        None => value.split(',').map(|v| v.trim().to_string()).collect(),
+                    };
+
+                query.names.retain(|v| !v.is_empty() && !v.starts_with('#'));
+            }
+            "after" => query.after = timestamp()?,
+            "before" => query.before = timestamp()?,
+            _ => return Err(invalid(format!("unknown api parameter '{}', expected subreddit, author, after or before", key))),
+        }
+    }
+
+    if query.names.is_empty() {
+        return Err(invalid(format!("{} names no subreddit or author", spec)));
+    }
+
+    Ok(query)
+}
+
+// pages through the archive oldest first and hands out the items as NDJSON lines, like a dump
+pub struct ApiReader {
+    config: ApiConfig,
+    endpoint: &'static str,
+    query: Query,
+    // the name being paged through
+    current: usize,
+    after: Option<i64>,
+    // ids already handed out at `after`, the next page starts at that second again
+    seen: HashSet<String>,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl ApiReader {
+    pub fn open(spec: &str, config: &ApiConfig, submissions: bool) -> std::io::Result<Self> {
+        let query = parse(spec)?;
+
+        Ok(Self {
+            config: config.clone(),
+            endpoint: if submissions { "posts" } else { "comments" },
+            after: query.after,
+            query,
+            current: 0,
+            seen: HashSet::new(),
+            buf: Vec::new(),
+            pos: 0,
+        })
+    }
+
+    fn url(&self) -> String {
+        let mut url =
+            format!(
+                "{}/api/{}/search?{}={}&sort=asc&limit={}",
+                self.config.base_url.trim_end_matches('/'),
+                self.endpoint,
+                self.query.field,
+                self.query.names[self.current],
+                self.config.page_size.clamp(1, 100),
+            );
+
+        // `after` is exclusive, ask for the last second again and drop what was seen
+        if let Some(after) = self.after {
+            url.push_str(&format!("&after={}", after - 1));
+        }
+
+        if let Some(before) = self.query.before {
+            url.push_str(&format!("&before={}", before));
+        }
+
+        url
+    }
+
+    fn get(&self, url: &str) -> std::io::Result<Vec<Value>> {
+        let mut attempt = 0;
+
+        loop {
+            let page =
+                Remote::open(url)
+                    .and_then(|mut body| {
+                        let mut buf = Vec::new();
+                        body.read_to_end(&mut buf)?;
+
+                        Ok(buf)
+                    })
+                    .and_then(|buf| {
+                        let mut page = serde_json::from_slice::<Value>(&buf).map_err(Error::other)?;
+
+                        match page.get_mut("data").map(Value::take) {
+                            Some(Value::Array(items)) => Ok(items),
+                            _ => Err(Error::new(ErrorKind::InvalidData, format!("{} returned no data", url))),
+                        }
+                    });
+
+            match page {
+                Ok(items) => return Ok(items),
+                Err(e) if attempt >= self.config.retries => return Err(e),
+                Err(_) => {
+                    attempt += 1;
+                    std::thread::sleep(Duration::from_secs(1 << attempt.min(6)));
+                }
+            }
+        }
+    }
+
+    // false once every name is exhausted
+    fn next_page(&mut self) -> std::io::Result<bool> {
+        while self.current < self.query.names.len() {
+            std::thread::sleep(Duration::from_millis(self.config.delay_ms));
+
+            let items = self.get(&self.url())?;
+
+            let last = items.last().and_then(created_utc);
+
+            self.buf.clear();
+            self.pos = 0;
+
+            for item in items.iter() {
+                let id = item["id"].as_str().unwrap_or_default();
+
+                // at the boundary second only
+                if created_utc(item) == self.after && self.seen.contains(id) {
+                    continue;
+                }
+
+                serde_json::to_writer(&mut self.buf, item).map_err(Error::other)?;
+                self.buf.push(b'\n');
+            }
+
+            let full = items.len() >= self.config.page_size.clamp(1, 100);
+
+            match last {
+                // a page of a single second can't be paged past, move on without the rest of it
+                Some(last) if full && self.buf.is_empty() => {
+                    self.after = Some(last + 1);
+                    self.seen.clear();
+                }
+                Some(last) if full => {
+                    if self.after != Some(last) {
+                        self.seen.clear();
+                    }
+
+                    self.after = Some(last);
+
+                    self.seen.extend(
+                        items.iter()
+                            .filter(|v| created_utc(v) == Some(last))
+                            .filter_map(|v| v["id"].as_str().map(|v| v.to_string()))
+                    );
+                }
+                // the last page of this name
+                _ => {
+                    self.current += 1;
+                    self.after = self.query.after;
+                    self.seen.clear();
+                }
+            }
+
+            if !self.buf.is_empty() {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+impl Read for ApiReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos >= self.buf.len() {
+            if !self.next_page()? {
+                return Ok(0);
+            }
+        }
+
+        let read = buf.len().min(self.buf.len() - self.pos);
+        buf[..read].copy_from_slice(&self.buf[self.pos..self.pos + read]);
+        self.pos += read;
+
+        Ok(read)
+    }
+}
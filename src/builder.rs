@@ -0,0 +1,116 @@
+use std::path::PathBuf;
+
+use crate::commands::ingest::{self, Context};
+use crate::config::{Config, OutputFormat};
+use crate::dedup::DedupConfig;
+use crate::error::Result;
+use crate::filter::FilterConfig;
+use crate::progress::{Progress, ProgressCallback, ProgressFormat, ProgressLog};
+use crate::text::pipeline::Tokenizer;
+
+// `poo ingest` set up from code, e.g.
+//
+//     PipelineBuilder::new()
+//         .input("RC_2020-05.zst")
+//         .output_dir("out")
+//         .on_progress(|p| eprintln!("{} {}/{:?}", p.file, p.bytes, p.total_bytes))
+//         .run()?;
+pub struct PipelineBuilder {
+    config: Config,
+    inputs: Vec<PathBuf>,
+    tokenizer: Option<Box<dyn Tokenizer>>,
+    on_progress: Option<ProgressCallback>,
+    resume: bool,
+}
+
+impl Default for PipelineBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PipelineBuilder {
+    // embedded runs log no progress unless asked to
+    pub fn new() -> Self {
+        let mut config = Config::default();
+        config.ingest.progress = ProgressFormat::None;
+
+        Self::with_config(config)
+    }
+
+    // everything not set on the builder comes from `config`, like from segmenter.toml
+    pub fn with_config(config: Config) -> Self {
+        Self { config, inputs: Vec::new(), tokenizer: None, on_progress: None, resume: false }
+    }
+
+    // a dump, a directory of them, a url, an `api:` query or `-` for stdin, may be given more than once
+    pub fn input(mut self, path: impl Into<PathBuf>) -> Self {
+        self.inputs.push(path.into());
+        self
+    }
+
+    pub fn filter(mut self, filter: FilterConfig) -> Self {
+        self.config.filter = filter;
+        self
+    }
+
+    pub fn dedup(mut self, dedup: DedupConfig) -> Self {
+        self.config.dedup = dedup;
+        self
+    }
+
+    // instead of the one the tokenizer settings of the config describe
+    pub fn tokenizer(mut self, tokenizer: impl Tokenizer + 'static) -> Self {
+        self.tokenizer = Some(Box::new(tokenizer));
+        self
+    }
+
+    // outputs go next to the inputs without one
+    pub fn output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.output.dir = Some(dir.into());
+        self
+    }
+
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.config.output.format = format;
+        self
+    }
+
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.config.ingest.jobs = jobs;
+        self
+    }
+
+    // continue from the checkpoints of an interrupted run
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    // after every batch of every file and once at the end of each, from the threads of the jobs
+    pub fn on_progress(mut self, callback: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        self.on_progress = Some(Box::new(callback));
+        self
+    }
+
+    // like `poo ingest`, an error in one input doesn't stop the others and fails the run at the end
+    pub fn run(self) -> Result<()> {
+        let config = &self.config;
+
+        let tokenizer =
+            match self.tokenizer {
+                Some(tokenizer) => tokenizer,
+                None => ingest::tokenizer(config)?,
+            };
+
+        let progress =
+            match self.on_progress {
+                Some(callback) => Some(ProgressLog::callback(callback)),
+                None => ProgressLog::open(&config.ingest)?,
+            };
+
+        let ctx = Context::new(config, self.resume, tokenizer, progress)?;
+
+        ingest::run_inputs(&ctx, &self.inputs)
+    }
+}
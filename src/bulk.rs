@@ -0,0 +1,125 @@
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::remote;
+
+// attempts at a batch the cluster failed or was too busy for
+const RETRIES: u32 = 5;
+
+// documents for the `_bulk` api of elasticsearch and opensearch, sent `batch` at a time. an existing document of
+// the same id is replaced
+pub struct BulkSink {
+    url: String,
+    index: String,
+    api_key: Option<String>,
+    batch: usize,
+    body: Vec<u8>,
+    pending: usize,
+    sent: u64,
+}
+
+impl BulkSink {
+    // `url` is the cluster, e.g. http://localhost:9200
+    pub fn new(url: &str, index: &str, api_key: Option<String>, batch: usize) -> Self {
+        Self {
+            url: format!("{}/_bulk", url.trim_end_matches('/')),
+            index: index.to_string(),
+            api_key,
+            batch: batch.max(1),
+            body: Vec::new(),
+            pending: 0,
+            sent: 0,
+        }
+    }
+
+    pub fn push(&mut self, id: &str, doc: &impl Serialize) -> std::io::Result<()> {
+        serde_json::to_writer(&mut self.body, &json!({ "index": { "_index": self.index, "_id": id } }))?;
+        self.body.push(b'\n');
+        serde_json::to_writer(&mut self.body, doc)?;
+        self.body.push(b'\n');
+
+        self.pending += 1;
+
+        if self.pending >= self.batch {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    // the documents sent
+    pub fn finish(mut self) -> std::io::Result<u64> {
+        self.flush()?;
+
+        Ok(self.sent)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.pending == 0 {
+            return Ok(());
+        }
+
+        let authorization = self.api_key.as_ref().map(|key| format!("ApiKey {}", key));
+
+        let mut headers = vec![("Content-Type", "application/x-ndjson")];
+
+        if let Some(authorization) = &authorization {
+            headers.push(("Authorization", authorization));
+        }
+
+        let mut attempt = 0;
+
+        let answer =
+            loop {
+                let error =
+                    match remote::post(&self.url, &headers, &self.body) {
+                        Ok((200, answer)) => break answer,
+                        // too many requests or an unavailable shard, worth another try
+                        Ok((status @ (429 | 502 | 503 | 504), _)) => Error::other(format!("{} answered with status {}", self.url, status)),
+                        Ok((status, answer)) => {
+                            return Err(Error::other(format!(
+                                "{} answered with status {}: {}",
+                                self.url,
+                                status,
+                                String::from_utf8_lossy(&answer),
+                            )));
+                        }
+                        Err(e) => e,
+                    };
+
+                if attempt >= RETRIES {
+                    return Err(error);
+                }
+
+                attempt += 1;
+                warn!(attempt, "Bulk request failed, retrying: {}", error);
+                std::thread::sleep(Duration::from_secs(1 << attempt.min(6)));
+            };
+
+        let answer = serde_json::from_slice::<Value>(&answer).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+
+        // the request went through but documents may still have been rejected one by one
+        if answer.get("errors").and_then(Value::as_bool).unwrap_or(false) {
+            let items = answer.get("items").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+
+            let failed = items.iter().filter_map(|item| item.get("index")?.get("error")).collect::<Vec<_>>();
+
+            return Err(Error::other(format!(
+                "{} of {} documents were rejected, the first with {}",
+                failed.len(),
+                self.pending,
+                failed.first().map(|e| e.to_string()).unwrap_or_default(),
+            )));
+        }
+
+        self.sent += self.pending as u64;
+        self.body.clear();
+        self.pending = 0;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,846 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use clap::{ArgAction, Args, Parser, Subcommand};
+
+use crate::commands::export::{ExportFormat, ExportLayout};
+use crate::commands::ingest::{ErrorPolicy, InputKind, MemoryAction, Period};
+use crate::commands::similarity::SimilarityWeights;
+use crate::config::{ByteSize, OutputFormat, ShardKey};
+use crate::dedup::DedupMode;
+use crate::dump::{ReadAheadIo, ZstdDecoder};
+use crate::filter::{DeletedPolicy, Timestamp};
+use crate::log::LogFormat;
+use crate::progress::ProgressFormat;
+use crate::segment::{BotPolicy, SegmentMethod};
+use crate::serializer::Codec;
+use crate::similarity::Metric;
+use crate::text::{StemLanguage, TokenizerMode};
+use crate::text::language::LanguageMode;
+use crate::text::special::SpecialAction;
+use crate::weighting::TfScheme;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Segments pushshift dumps into per-user word frequencies")]
+pub struct Cli {
+    /// Number of worker threads (defaults to the number of cores)
+    #[arg(short = 'j', long, global = true)]
+    pub threads: Option<usize>,
+
+    /// Path to the config file (defaults to `segmenter.toml` if present)
+    #[arg(short, long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Log more, repeat for even more
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Log less and hide the progress bars, repeat to log only errors
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub quiet: u8,
+
+    /// Human readable logs or one JSON object per event
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+// parsed once, the size of the ingest options doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Build `.users.freqs` files from pushshift comment or submission dumps or directories of them (compressed or NDJSON)
+    Ingest(IngestArgs),
+    /// Rewrite `.freqs` files of older format versions, found in their header, using the current on-disk format
+    Migrate(MigrateArgs),
+    /// Combine several `.users.freqs` files into one
+    Merge(MergeArgs),
+    /// Drop rare, ubiquitous or out-of-vocabulary words from a `.users.freqs` file
+    Prune(PruneFileArgs),
+    /// Print the top words of a single author
+    Query(QueryArgs),
+    /// Print corpus-level statistics of a `.users.freqs` file: vocabulary, Zipf fit, tokens per author and heavy hitters
+    Stats(StatsArgs),
+    /// Check `.users.freqs` files end to end: header, checksum, author order, counts and the index, and with --manifest the totals the manifest recorded
+    Verify(VerifyArgs),
+    /// Salvage the intact authors of a truncated or partly corrupt `.users.freqs` file into a new one and list the authors that were lost
+    Repair(RepairArgs),
+    /// Convert a `.users.freqs` file for use outside of this tool
+    Export(ExportArgs),
+    /// Weight every author's words by TF-IDF across all authors of a `.users.freqs` file
+    Tfidf(TfidfArgs),
+    /// Cluster the authors of a `.users.freqs` file by their TF-IDF vectors with mini-batch k-means
+    Segment(SegmentArgs),
+    /// Find the most similar authors of a `.users.freqs` file by cosine or Jaccard similarity
+    Similarity(SimilarityArgs),
+    /// Find pairs of authors with nearly identical vocabularies, like alt accounts or bot farms, with MinHash and LSH
+    Duplicates(DuplicatesArgs),
+    /// Train word vectors by skip-gram with negative sampling on the comments of dumps, tokenized like on ingest
+    Embed(EmbedArgs),
+    /// Write the comments of dumps tokenized like on ingest, one per line, to train fastText or StarSpace on
+    Corpus(CorpusArgs),
+    /// Index the top words of every author of a `.users.freqs` file in Elasticsearch or OpenSearch with the bulk api,
+    /// for "more like this" searches of authors
+    Elastic(ElasticArgs),
+    /// Answer HTTP queries over `.users.freqs` files: an author's top words, how alike two authors are and the authors
+    /// using a word most
+    Serve(ServeArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct OutputArgs {
+    /// Directory to write outputs to (defaults to next to the input)
+    #[arg(short, long)]
+    pub output_dir: Option<PathBuf>,
+
+    /// zstd compression level of the output
+    #[arg(short = 'l', long, visible_alias = "output-compression-level")]
+    pub compression_level: Option<i32>,
+
+    /// Blocks of a `.freqs` output to compress at the same time, so writing isn't the single-threaded tail of a run
+    #[arg(long, visible_alias = "output-compression-threads")]
+    pub compression_threads: Option<usize>,
+
+    /// Write ragegun `.freqs` files, `.parquet` tables or `.duckdb` databases
+    #[arg(long, value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// Encoding of the authors in `.freqs` files, readers detect it from the header
+    #[arg(long, value_enum)]
+    pub codec: Option<Codec>,
+
+    /// Compress `.freqs` files with a zstd dictionary trained on their authors, which shrinks the small blocks
+    /// lookups read, the files then need this tool or `zstd -D` with the embedded dictionary to decompress
+    #[arg(long)]
+    pub dictionary: bool,
+
+    /// Split `.freqs` outputs into this many files, `<name>.users.freqs.00` and on, so readers can work in parallel
+    #[arg(long)]
+    pub shards: Option<usize>,
+
+    /// Put authors into shards by a hash of their name or by consecutive ranges of names
+    #[arg(long, value_enum)]
+    pub shard_by: Option<ShardKey>,
+}
+
+#[derive(Debug, Args)]
+pub struct IngestArgs {
+    /// Dumps or directories containing `.zst`, `.gz`, `.bz2`, `.xz`, `.json`/`.ndjson` or extensionless dumps, `-`
+    /// to read a single dump from stdin into `stdin.users.freqs`, http(s):// or public s3:// urls to stream, or
+    /// archive api queries like `api:subreddit=rust,golang&after=2024-05-01` (or `author=` names or `@file`)
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    /// Also ingest the dumps in subdirectories of the directory, like per-year folders
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// Only ingest dumps of the directory whose name matches one of these globs (e.g. `RC_2019-*.zst`), or whose
+    /// path below it does if the glob has a `/`
+    #[arg(long)]
+    pub include: Vec<String>,
+
+    /// Skip dumps of the directory matching one of these globs
+    #[arg(long)]
+    pub exclude: Vec<String>,
+
+    /// Base url of the archive `api:` queries page through
+    #[arg(long)]
+    pub api_url: Option<String>,
+
+    /// Whether the dumps contain comments (RC_*) or submissions (RS_*)
+    #[arg(long, value_enum)]
+    pub kind: Option<InputKind>,
+
+    /// Number of files to process concurrently
+    #[arg(long)]
+    pub jobs: Option<usize>,
+
+    /// Estimated memory all concurrent files may use together (e.g. 16G)
+    #[arg(long)]
+    pub memory_budget: Option<ByteSize>,
+
+    /// Spill a file's state to sorted runs on disk once it is estimated to exceed this size (e.g. 4G)
+    #[arg(long)]
+    pub spill_threshold: Option<ByteSize>,
+
+    /// Directory for spilled runs (defaults to next to the output)
+    #[arg(long)]
+    pub spill_dir: Option<PathBuf>,
+
+    /// Keep at most this many words per author, new words replace the least used so the rarest counts are approximate
+    #[arg(long, conflicts_with = "sketch")]
+    pub max_author_words: Option<usize>,
+
+    /// Count the words of authors with more than --sketch-width of them in a count-min sketch written to `<name>.users.sketch`, keeping only the most used in the output
+    #[arg(long)]
+    pub sketch: bool,
+
+    /// Counters per row of a sketch, more overcount less
+    #[arg(long)]
+    pub sketch_width: Option<usize>,
+
+    /// Words of a sketched author written to the output with their estimated counts
+    #[arg(long)]
+    pub heavy_hitters: Option<usize>,
+
+    /// Estimated size the state of all concurrent files may grow to before --on-max-memory kicks in
+    #[arg(long)]
+    pub max_memory: Option<ByteSize>,
+
+    /// Spill to sorted runs or rotate to a new output part once --max-memory is exceeded
+    #[arg(long, value_enum)]
+    pub on_max_memory: Option<MemoryAction>,
+
+    /// Continue from the last checkpoint of an interrupted run
+    #[arg(long)]
+    pub resume: bool,
+
+    /// Ingest the inputs again even if their outputs are current, to reprocess a month
+    #[arg(long)]
+    pub force: bool,
+
+    /// List the files that would be ingested or skipped with their sizes and an estimate of the time they take,
+    /// without reading or writing any data
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Decompressed bytes a job is expected to read per second, for the --dry-run estimate (e.g. 100M)
+    #[arg(long)]
+    pub throughput: Option<ByteSize>,
+
+    /// Lines between checkpoints (0 disables checkpointing)
+    #[arg(long)]
+    pub checkpoint_lines: Option<u64>,
+
+    /// Also aggregate subreddit, created_utc, score and bot signals per author
+    #[arg(long)]
+    pub metadata: bool,
+
+    /// Unparseable lines a file may have before --on-max-errors kicks in
+    #[arg(long)]
+    pub max_errors: Option<usize>,
+
+    /// Fail the file, stop the whole run or ignore the errors once --max-errors is exceeded
+    #[arg(long, value_enum)]
+    pub on_max_errors: Option<ErrorPolicy>,
+
+    /// Log the offset, error and start of every unparseable line to <name>.errors.jsonl
+    #[arg(long)]
+    pub log_errors: bool,
+
+    /// Serve prometheus metrics on this address, like 127.0.0.1:9898
+    #[arg(long)]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// Draw bars on a terminal and log lines elsewhere, always log lines, write json records with the bytes, comments, authors and eta of every file, or show nothing
+    #[arg(long, value_enum)]
+    pub progress: Option<ProgressFormat>,
+
+    /// Write the json progress records here instead of stderr, like a named pipe
+    #[arg(long)]
+    pub progress_file: Option<PathBuf>,
+
+    /// Seconds between progress lines or json records of a file
+    #[arg(long)]
+    pub progress_interval: Option<u64>,
+
+    /// Decode zstd dumps with the faster C library or the pure rust ruzstd
+    #[arg(long, value_enum)]
+    pub decoder: Option<ZstdDecoder>,
+
+    /// Compressed input to read ahead of decompression on its own thread, so downloads and decoding overlap (e.g. 64M, 0 to disable)
+    #[arg(long)]
+    pub read_ahead: Option<ByteSize>,
+
+    /// Read ahead on a thread per input or with tokio tasks, tokio needs the `async-io` feature
+    #[arg(long, value_enum)]
+    pub read_ahead_io: Option<ReadAheadIo>,
+
+    /// Largest zstd window to decode as a power of two (10 to 31, pushshift dumps use up to 31)
+    #[arg(long)]
+    pub window_log_max: Option<u32>,
+
+    /// Count every author's words separately per month or week, as "author@2020-05" or "author@2020-W19"
+    #[arg(long, value_enum)]
+    pub period: Option<Period>,
+
+    /// Only keep comments from these subreddits
+    #[arg(long, value_delimiter = ',')]
+    pub subreddits: Vec<String>,
+
+    /// Drop comments from these subreddits
+    #[arg(long, value_delimiter = ',')]
+    pub exclude_subreddits: Vec<String>,
+
+    /// Only keep comments created at or after this time (unix seconds or YYYY-MM-DD[THH:MM[:SS]])
+    #[arg(long)]
+    pub after: Option<Timestamp>,
+
+    /// Only keep comments created before this time
+    #[arg(long)]
+    pub before: Option<Timestamp>,
+
+    /// File with authors to drop, one per line
+    #[arg(long)]
+    pub exclude_authors_file: Option<PathBuf>,
+
+    /// Drop comments by likely bots (names ending in "bot", known bots, "I am a bot" footers)
+    #[arg(long)]
+    pub exclude_bots: bool,
+
+    /// How to handle [deleted]/[removed] authors and bodies
+    #[arg(long, value_enum)]
+    pub deleted: Option<DeletedPolicy>,
+
+    /// Count a body an author posted before, like copypasta, not again or only for a --dedup-weight of its repeats
+    #[arg(long, value_enum)]
+    pub dedup: Option<DedupMode>,
+
+    /// Fraction of the repeats of a body counted with `--dedup downweight`, 0.1 counts every tenth
+    #[arg(long)]
+    pub dedup_weight: Option<f64>,
+
+    /// Split bodies by a fast character filter or the slower but more accurate english tokenizer
+    #[arg(long, value_enum)]
+    pub tokenizer: Option<TokenizerMode>,
+
+    /// Strip markdown formatting, quotes, code and link urls from bodies before tokenizing
+    #[arg(long)]
+    pub strip_markdown: bool,
+
+    /// Keep `>` quoted lines when stripping markdown
+    #[arg(long)]
+    pub keep_quotes: bool,
+
+    /// Keep code blocks and inline code when stripping markdown
+    #[arg(long)]
+    pub keep_code: bool,
+
+    /// Keep the urls of `[text](url)` links when stripping markdown
+    #[arg(long)]
+    pub keep_link_urls: bool,
+
+    /// Split, drop or replace urls by `<URL>`
+    #[arg(long, value_enum)]
+    pub urls: Option<SpecialAction>,
+
+    /// Split, drop or replace u/user mentions by `<USER>`
+    #[arg(long, value_enum)]
+    pub user_mentions: Option<SpecialAction>,
+
+    /// Split, drop or keep r/subreddit references as a single lowercase token
+    #[arg(long, value_enum)]
+    pub subreddit_mentions: Option<SpecialAction>,
+
+    /// Split, drop or replace numbers by `<YEAR>` (1900 to 2099), `<ID>` (7 digits or more) or `<NUM>`, split numbers aren't counted
+    #[arg(long, value_enum)]
+    pub numbers: Option<SpecialAction>,
+
+    /// Drop words of fewer characters, e.g. 2 for single letters
+    #[arg(long)]
+    pub min_token_len: Option<usize>,
+
+    /// Drop words of more characters, e.g. 40 for keyboard mashing and pasted hashes
+    #[arg(long)]
+    pub max_token_len: Option<usize>,
+
+    /// Count emoji and emoji sequences as words of their own
+    #[arg(long)]
+    pub keep_emoji: bool,
+
+    /// Detect the language of every comment to only count some languages or tag words with it
+    #[arg(long, value_enum)]
+    pub language_mode: Option<LanguageMode>,
+
+    /// ISO 639-3 codes of the languages --language-mode filter keeps
+    #[arg(long, value_delimiter = ',')]
+    pub languages: Vec<String>,
+
+    /// Confidence below which a comment's language counts as undetermined
+    #[arg(long)]
+    pub min_language_confidence: Option<f64>,
+
+    /// Drop comments of undetermined language when filtering
+    #[arg(long)]
+    pub drop_undetermined: bool,
+
+    /// Count words case-sensitively, so "LOL", "Lol" and "lol" are three words
+    #[arg(long, conflicts_with = "normalize_unicode")]
+    pub keep_case: bool,
+
+    /// Apply NFKC normalization and unicode case folding before tokenizing
+    #[arg(long)]
+    pub normalize_unicode: bool,
+
+    /// Drop stopwords while tokenizing
+    #[arg(long, conflicts_with = "keep_stopwords")]
+    pub remove_stopwords: bool,
+
+    /// Keep stopwords even if the config removes them
+    #[arg(long)]
+    pub keep_stopwords: bool,
+
+    /// File with one stopword per line replacing the built-in list, implies --remove-stopwords
+    #[arg(long, conflicts_with = "keep_stopwords")]
+    pub stopwords_file: Option<PathBuf>,
+
+    /// Reduce words to their Snowball stem in this language
+    #[arg(long, value_enum, conflicts_with = "lemmas")]
+    pub stem: Option<StemLanguage>,
+
+    /// Replace words by their lemma from a file of `form,lemma` lines instead of stemming
+    #[arg(long)]
+    pub lemmas: Option<PathBuf>,
+
+    /// Also count n-grams of up to this many consecutive words
+    #[arg(long)]
+    pub ngrams: Option<usize>,
+
+    /// String joining the words of an n-gram
+    #[arg(long)]
+    pub ngram_separator: Option<String>,
+
+    /// Only count the n-grams of two or more words
+    #[arg(long)]
+    pub no_unigrams: bool,
+
+    /// Count words in 2^N hashed buckets instead of by themselves, bounding memory (e.g. 20)
+    #[arg(long)]
+    pub hash_bits: Option<u32>,
+
+    #[command(flatten)]
+    pub prune: PruneArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct MigrateArgs {
+    /// A `.freqs` file or a directory of them, files already in the current format are left alone
+    pub path: PathBuf,
+
+    /// Number of files to migrate concurrently (defaults to the number of cores)
+    #[arg(long)]
+    pub jobs: Option<usize>,
+
+    /// Estimated memory all concurrent files may use together (e.g. 16G), files wait for their share
+    #[arg(long)]
+    pub memory_budget: Option<ByteSize>,
+
+    /// Leave files alone that are estimated to need more memory than this to migrate (e.g. 8G)
+    #[arg(long)]
+    pub max_file_memory: Option<ByteSize>,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct MergeArgs {
+    /// `.users.freqs` files to merge
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
+
+    /// File to write the merged frequencies to (a `.parquet` name writes parquet)
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// zstd compression level of the output
+    #[arg(short = 'l', long)]
+    pub compression_level: Option<i32>,
+
+    /// Merge the inputs that can be read instead of failing on the first that can't, the run still fails
+    #[arg(long)]
+    pub skip_bad_inputs: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct PruneArgs {
+    /// Drop the words an author used fewer times from that author
+    #[arg(long)]
+    pub min_word_count: Option<u64>,
+
+    /// Also apply --min-word-count to the in-memory state every this many lines (approximate)
+    #[arg(long)]
+    pub min_word_count_lines: Option<u64>,
+
+    /// Drop words used fewer times across all authors
+    #[arg(long)]
+    pub min_total_count: Option<u64>,
+
+    /// Drop words used by a larger share of authors, e.g. 0.5
+    #[arg(long)]
+    pub max_doc_freq: Option<f64>,
+
+    /// Keep only this many of the most used words
+    #[arg(long)]
+    pub top_words: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+pub struct PruneFileArgs {
+    /// `.users.freqs` file to read
+    pub file: PathBuf,
+
+    #[command(flatten)]
+    pub prune: PruneArgs,
+
+    /// File to write the pruned frequencies to (a `.parquet` name writes parquet)
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// zstd compression level of the output
+    #[arg(short = 'l', long)]
+    pub compression_level: Option<i32>,
+}
+
+#[derive(Debug, Args)]
+pub struct QueryArgs {
+    /// `.users.freqs` file to read
+    pub file: PathBuf,
+
+    /// Author to look up
+    pub author: String,
+
+    /// Number of words to print
+    #[arg(short = 'n', long, default_value_t = 25)]
+    pub top: usize,
+
+    /// Print the count of this word instead of the top words, can be repeated (estimated from the sketch of a sketched author)
+    #[arg(short, long = "word")]
+    pub words: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    /// `.users.freqs` files to check, exits non-zero if any of them is corrupt
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// Also check each file against the manifest of its directory: that it was completed, with the totals recorded
+    #[arg(long)]
+    pub manifest: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct RepairArgs {
+    /// Damaged `.users.freqs` file, written to `<file>.repaired` with the lost authors in `<file>.lost`
+    pub file: PathBuf,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct StatsArgs {
+    /// `.users.freqs` file to read
+    pub file: PathBuf,
+
+    /// Number of heavy hitters to print
+    #[arg(short = 'n', long, default_value_t = 25)]
+    pub top: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// `.users.freqs` file to read
+    pub file: PathBuf,
+
+    /// Format to convert to
+    #[arg(short, long, value_enum)]
+    pub format: ExportFormat,
+
+    /// One row per (author, word) pair, one row per author, or one row per author of word ids and counts
+    #[arg(long, value_enum, default_value_t = ExportLayout::Long)]
+    pub layout: ExportLayout,
+
+    /// Keep only the most frequent words of each author in the wide layout and vw (all if omitted)
+    #[arg(short = 'n', long)]
+    pub top: Option<usize>,
+
+    /// File to write to, `-` for stdout, or the http(s) url of a ClickHouse server to insert into
+    /// (defaults to the input with the extension of the format)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// ClickHouse table the rows sent to a url go into, created if missing, or the PostgreSQL table of pg-copy
+    #[arg(long, default_value = "freqs")]
+    pub table: String,
+
+    /// Write pg-copy in the binary format, which PostgreSQL loads faster than text
+    #[arg(long)]
+    pub binary: bool,
+
+    /// `<id>\t<word>` file numbering the words of the sparse layout and matrix columns, created if missing and extended with new words,
+    /// share it between exports so a word has the same id in all of them
+    #[arg(long)]
+    pub vocabulary: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct WeightingArgs {
+    /// How a word's count turns into its term frequency
+    #[arg(long, value_enum)]
+    pub tf: Option<TfScheme>,
+
+    /// Drop words used by fewer authors
+    #[arg(long)]
+    pub min_df: Option<u64>,
+
+    /// Drop words used by a larger share of authors, e.g. 0.5
+    #[arg(long)]
+    pub max_df: Option<f64>,
+
+    /// Keep the raw weights instead of scaling every author to unit length
+    #[arg(long)]
+    pub no_normalize: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct TfidfArgs {
+    /// `.users.freqs` file to read
+    pub file: PathBuf,
+
+    #[command(flatten)]
+    pub weighting: WeightingArgs,
+
+    /// Keep only the highest weighted words of each author (all if omitted)
+    #[arg(short = 'n', long)]
+    pub top: Option<usize>,
+
+    /// File to write one JSON object per author to, `-` for stdout (defaults to `<name>.users.tfidf.jsonl`)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct SegmentArgs {
+    /// `.users.freqs` file to read
+    pub file: PathBuf,
+
+    #[command(flatten)]
+    pub weighting: WeightingArgs,
+
+    /// Cluster the tf-idf vectors into k segments, or find the modes of the density of the authors along a few words
+    #[arg(long, value_enum)]
+    pub method: Option<SegmentMethod>,
+
+    /// Number of segments
+    #[arg(short)]
+    pub k: Option<usize>,
+
+    /// Number of mini-batches
+    #[arg(long)]
+    pub iterations: Option<usize>,
+
+    /// Authors sampled per mini-batch
+    #[arg(long)]
+    pub batch_size: Option<usize>,
+
+    /// Seed for choosing the initial centers and the batches
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Word whose weight is an axis of the density method, once or twice (the two with the most variance if omitted)
+    #[arg(long = "dimension")]
+    pub dimensions: Vec<String>,
+
+    /// Scale the kernel bandwidth of the density method, larger merges more modes
+    #[arg(long)]
+    pub bandwidth: Option<f64>,
+
+    /// Smallest share of the authors a density segment keeps, smaller ones join their neighbour, e.g. 0.01
+    #[arg(long)]
+    pub min_size: Option<f64>,
+
+    /// Leave likely bots out of the segments, or assign them to a `bots` segment of their own (needs the `.users.meta` of `ingest --metadata`)
+    #[arg(long, value_enum)]
+    pub bots: Option<BotPolicy>,
+
+    /// Bot score from 0 to 1 from which an author counts as a bot
+    #[arg(long)]
+    pub bot_threshold: Option<f64>,
+
+    /// File to write the author to segment assignment to, `-` for stdout (defaults to `<name>.users.segments.tsv`)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct SimilarityArgs {
+    /// `.users.freqs` file to read
+    pub file: PathBuf,
+
+    /// Author to find similar authors for, can be repeated (all authors if neither this nor `--authors-file` is given)
+    #[arg(short, long = "author")]
+    pub authors: Vec<String>,
+
+    /// File with one author per line, `#` starts a comment
+    #[arg(long)]
+    pub authors_file: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = Metric::Cosine)]
+    pub metric: Metric,
+
+    /// Compare raw counts or TF-IDF weights
+    #[arg(long, value_enum, default_value_t = SimilarityWeights::Counts)]
+    pub weights: SimilarityWeights,
+
+    #[command(flatten)]
+    pub weighting: WeightingArgs,
+
+    /// Number of similar authors per author
+    #[arg(short = 'n', long, default_value_t = 10)]
+    pub top: usize,
+
+    /// File to write (author, similar author, similarity) rows to, `-` for stdout (defaults to `<name>.users.similar.tsv`)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct DuplicatesArgs {
+    /// `.users.freqs` file to read
+    pub file: PathBuf,
+
+    /// Hash functions per signature, more estimate the similarity more precisely
+    #[arg(long, default_value_t = 128)]
+    pub hashes: usize,
+
+    /// LSH bands the signature is cut into, more find pairs of lower similarity (and more false candidates)
+    #[arg(long, default_value_t = 16)]
+    pub bands: usize,
+
+    /// Lowest estimated Jaccard similarity of a reported pair
+    #[arg(short, long, default_value_t = 0.8)]
+    pub threshold: f64,
+
+    /// Skip authors with fewer distinct words, small vocabularies match by chance
+    #[arg(long, default_value_t = 10)]
+    pub min_words: usize,
+
+    /// Skip LSH buckets with more authors, their pairs grow quadratically
+    #[arg(long, default_value_t = 1000)]
+    pub max_bucket: usize,
+
+    /// Seed of the hash functions
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// File to write (author, other author, estimated, exact Jaccard similarity) rows to, `-` for stdout
+    /// (defaults to `<name>.users.duplicates.tsv`)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct EmbedArgs {
+    /// A dump or a directory of them, read once to count the words and once per epoch, so not `-`
+    pub path: PathBuf,
+
+    /// Whether the dumps contain comments (RC_*) or submissions (RS_*)
+    #[arg(long, value_enum)]
+    pub kind: Option<InputKind>,
+
+    /// Size of the vectors
+    #[arg(long)]
+    pub dimensions: Option<usize>,
+
+    /// Words on either side of a word that count as its context
+    #[arg(long)]
+    pub window: Option<usize>,
+
+    /// Noise words drawn per context word
+    #[arg(long)]
+    pub negative: Option<usize>,
+
+    /// Skip words that occur less often
+    #[arg(long)]
+    pub min_count: Option<u64>,
+
+    /// Passes over the dumps
+    #[arg(long)]
+    pub epochs: Option<usize>,
+
+    /// Seed of the initial vectors and the sampling
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// File to write the vectors to in the word2vec text format (defaults to `<name>.vectors.txt`)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct CorpusArgs {
+    /// A dump, a directory of them, `-` for stdin, a url or an `api:` query, like on ingest
+    pub path: PathBuf,
+
+    /// Whether the dumps contain comments (RC_*) or submissions (RS_*)
+    #[arg(long, value_enum)]
+    pub kind: Option<InputKind>,
+
+    /// Start every line with `__label__<subreddit>`, for training a classifier
+    #[arg(long)]
+    pub labels: bool,
+
+    /// File to write the lines to, `-` for stdout (defaults to `<name>.corpus.txt`)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ElasticArgs {
+    /// `.users.freqs` file to read
+    pub file: PathBuf,
+
+    /// The cluster, e.g. http://localhost:9200
+    #[arg(long)]
+    pub url: String,
+
+    /// Index to write the authors to, an author's document is replaced on the next run
+    #[arg(long, default_value = "users")]
+    pub index: String,
+
+    /// Words kept per author
+    #[arg(short = 'n', long, default_value_t = 50)]
+    pub top: usize,
+
+    /// Rank the words of an author by count or TF-IDF weight
+    #[arg(long, value_enum, default_value_t = SimilarityWeights::Counts)]
+    pub weights: SimilarityWeights,
+
+    #[command(flatten)]
+    pub weighting: WeightingArgs,
+
+    /// Authors per bulk request
+    #[arg(long, default_value_t = 1000)]
+    pub batch: usize,
+
+    /// Encoded api key, sent as `Authorization: ApiKey <key>`
+    #[arg(long)]
+    pub api_key: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// `.users.freqs` files to load, authors in several of them are merged
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub addr: SocketAddr,
+
+    /// Also answer gRPC queries, defined in proto/segmenter.proto, on this address. Needs the `grpc` feature
+    #[arg(long)]
+    pub grpc_addr: Option<SocketAddr>,
+
+    /// Words or authors answered when a request has no `n`
+    #[arg(short = 'n', long, default_value_t = 25)]
+    pub top: usize,
+}
@@ -0,0 +1,153 @@
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use kdam::BarExt;
+use rayon::prelude::*;
+use tracing::{info, warn};
+
+use crate::cli::CorpusArgs;
+use crate::commands::{output_path, progress_bar};
+use crate::commands::export::open_output;
+use crate::commands::ingest::{self, Comment};
+use crate::config::Config;
+use crate::dump;
+use crate::error::{PooError, Result};
+use crate::filter::{is_deleted, Filter};
+use crate::text::pipeline::Tokenizer;
+
+// the comments of dumps after the tokenizer, for what learns from text
+pub(crate) struct Corpus<'a> {
+    config: &'a Config,
+    tokenizer: Box<dyn Tokenizer>,
+    filter: Filter,
+    inputs: Vec<PathBuf>,
+}
+
+impl<'a> Corpus<'a> {
+    // a dump, a directory of them, a url or an `api:` query, like on ingest
+    pub(crate) fn open(path: &Path, config: &'a Config) -> Result<Self> {
+        let inputs = ingest::list_dumps(path, &config.ingest)?;
+
+        Ok(Self {
+            config,
+            tokenizer: ingest::tokenizer(config)?,
+            filter: Filter::from_config(&config.filter).map_err(|source| PooError::Load { what: "the author filter", source })?,
+            inputs,
+        })
+    }
+
+    pub(crate) fn inputs(&self) -> usize {
+        self.inputs.len()
+    }
+
+    // the comments of every input with their words in batches of `per_iter` lines, parsed and tokenized in
+    // parallel. the filters apply like on ingest, comments by deleted authors or with removed bodies are left out
+    pub(crate) fn for_each_batch(&self, mut f: impl FnMut(Vec<(Comment, Vec<Vec<u8>>)>) -> Result<()>) -> Result<()> {
+        let mut pb = progress_bar(0, 1000, "it");
+
+        for path in &self.inputs {
+            let kind = self.config.ingest.kind.for_file(path);
+
+            let decoder = dump::open(path, self.config, kind == ingest::InputKind::Submissions).map_err(PooError::read(path))?;
+            let mut decoder = BufReader::new(decoder);
+
+            let mut bad = 0;
+
+            loop {
+                let mut lines = Vec::with_capacity(self.config.ingest.per_iter);
+
+                for _ in 0..self.config.ingest.per_iter.max(1) {
+                    let mut line = Vec::new();
+
+                    match dump::read_until(&mut decoder, b'\n', &mut line).map_err(PooError::read(path))? {
+                        0 => break,
+                        _ => lines.push(line),
+                    }
+                }
+
+                if lines.is_empty() {
+                    break;
+                }
+
+                let read = lines.len();
+
+                let comments =
+                    lines.into_par_iter()
+                        .map(|mut line| kind.parse(&mut line).ok())
+                        .collect::<Vec<_>>();
+
+                bad += comments.iter().filter(|comment| comment.is_none()).count();
+
+                let words =
+                    comments.into_par_iter()
+                        .flatten()
+                        .filter(|comment| !is_deleted(comment) && self.filter.accepts(comment))
+                        .map(|comment| {
+                            let words = self.tokenizer.words(&comment.body);
+                            (comment, words)
+                        })
+                        .filter(|(_, words)| !words.is_empty())
+                        .collect::<Vec<_>>();
+
+                f(words)?;
+
+                pb.update(read);
+            }
+
+            if bad > 0 {
+                warn!(path = %path.display(), lines = bad, "Skipped unparseable lines");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// one line of space separated words per comment, like fastText and StarSpace train on
+pub fn run(args: &CorpusArgs, config: &Config) -> Result<()> {
+    let corpus = Corpus::open(&args.path, config)?;
+
+    // <name>.corpus.txt next to the dump, or the directory of them
+    let output =
+        args.output
+            .clone()
+            .unwrap_or_else(|| output_path(&dump::output_name(&args.path), config.output.dir.as_deref(), ".corpus.txt"));
+
+    info!(inputs = corpus.inputs(), path = %output.display(), "Corpus: Writing the comments");
+
+    let mut out = open_output(&output).map_err(PooError::write(&output))?;
+    let mut written = 0u64;
+
+    corpus.for_each_batch(|comments| {
+        for (comment, words) in comments {
+            line(&mut out, comment.subreddit.as_deref().filter(|_| args.labels), &words).map_err(PooError::write(&output))?;
+
+            written += 1;
+        }
+
+        Ok(())
+    })?;
+
+    out.flush().map_err(PooError::write(&output))?;
+
+    info!(comments = written, "Corpus: Finished");
+
+    Ok(())
+}
+
+// `__label__<subreddit>` first if given
+fn line(out: &mut dyn Write, label: Option<&str>, words: &[Vec<u8>]) -> std::io::Result<()> {
+    if let Some(label) = label {
+        write!(out, "__label__{} ", label)?;
+    }
+
+    for (i, word) in words.iter().enumerate() {
+        if i > 0 {
+            out.write_all(b" ")?;
+        }
+
+        out.write_all(word)?;
+    }
+
+    out.write_all(b"\n")
+}
@@ -0,0 +1,120 @@
+use std::io::Write;
+
+use rayon::prelude::*;
+use tracing::{info, warn};
+
+use crate::cli::DuplicatesArgs;
+use crate::commands::{progress_bar, read_freqs};
+use crate::commands::export::open_output;
+use crate::error::{PooError, Result};
+use crate::minhash::{candidates, estimate, word_hash, MinHasher};
+use crate::serializer::SortedFreqs;
+
+// of two sorted sets
+fn jaccard(a: &[u64], b: &[u64]) -> f64 {
+    let (mut i, mut j, mut common) = (0, 0, 0);
+
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                common += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+
+    common as f64 / (a.len() + b.len() - common).max(1) as f64
+}
+
+pub fn run(args: &DuplicatesArgs) -> Result<()> {
+    if args.bands == 0 || !args.hashes.is_multiple_of(args.bands) {
+        return Err(PooError::Unsupported("the --hashes have to split evenly into the --bands"));
+    }
+
+    let mut pb = progress_bar(0, 1000, "B");
+
+    let poo = read_freqs(&args.file, &mut pb)?;
+
+    // the sorted word hashes of every author with enough of them
+    let mut authors = Vec::with_capacity(poo.len());
+    let mut sets = Vec::with_capacity(poo.len());
+    let mut small = 0;
+
+    poo.for_each_sorted(|author, freqs| {
+        // without words all signatures would be equal
+        if freqs.len() < args.min_words.max(1) {
+            small += 1;
+            return Ok(());
+        }
+
+        let mut set = freqs.iter().map(|(word, _)| word_hash(word)).collect::<Vec<_>>();
+
+        set.sort_unstable();
+        set.dedup();
+
+        authors.push(author.to_vec());
+        sets.push(set);
+
+        Ok(())
+    })
+        .map_err(PooError::read(&args.file))?;
+
+    drop(poo);
+
+    info!(authors = authors.len(), skipped = small, hashes = args.hashes, bands = args.bands, "Duplicates: Signing the authors");
+
+    let hasher = MinHasher::new(args.hashes, args.seed);
+    let mut signatures = vec![0; sets.len() * hasher.len()];
+
+    signatures.par_chunks_mut(hasher.len())
+        .zip(sets.par_iter())
+        .for_each(|(signature, set)| hasher.sign(set, signature));
+
+    let (pairs, skipped) = candidates(&signatures, args.hashes, args.bands, args.max_bucket);
+
+    if skipped > 0 {
+        warn!(buckets = skipped, max_bucket = args.max_bucket, "Duplicates: Skipped crowded buckets, their pairs are missing");
+    }
+
+    info!(candidates = pairs.len(), "Duplicates: Checking the candidates");
+
+    let signature = |i: u32| &signatures[i as usize * args.hashes..(i as usize + 1) * args.hashes];
+
+    let mut duplicates =
+        pairs.par_iter()
+            .filter_map(|&(a, b)| {
+                let estimated = estimate(signature(a), signature(b));
+
+                (estimated >= args.threshold)
+                    .then(|| (a, b, estimated, jaccard(&sets[a as usize], &sets[b as usize])))
+            })
+            .collect::<Vec<_>>();
+
+    duplicates.par_sort_unstable_by(|x, y| y.2.total_cmp(&x.2).then_with(|| (x.0, x.1).cmp(&(y.0, y.1))));
+
+    // <name>.users.duplicates.tsv next to <name>.users.freqs
+    let output =
+        args.output
+            .clone()
+            .unwrap_or_else(|| args.file.with_extension("duplicates.tsv"));
+
+    info!(pairs = duplicates.len(), path = %output.display(), "Duplicates: Writing the pairs");
+
+    open_output(&output)
+        .and_then(|mut out| {
+            out.write_all(b"author\tduplicate\testimated\tjaccard\n")?;
+
+            for &(a, b, estimated, exact) in &duplicates {
+                out.write_all(&authors[a as usize])?;
+                out.write_all(b"\t")?;
+                out.write_all(&authors[b as usize])?;
+                writeln!(out, "\t{:.6}\t{:.6}", estimated, exact)?;
+            }
+
+            out.flush()
+        })
+        .map_err(PooError::write(&output))
+}
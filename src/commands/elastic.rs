@@ -0,0 +1,97 @@
+use kdam::BarExt;
+use serde::Serialize;
+use tracing::info;
+
+use crate::bulk::BulkSink;
+use crate::cli::ElasticArgs;
+use crate::commands::merge::{Input, MergedInputs};
+use crate::commands::progress_bar;
+use crate::commands::similarity::SimilarityWeights;
+use crate::config::Config;
+use crate::error::{PooError, Result};
+use crate::serializer::SortedFreqs;
+use crate::weighting::Tfidf;
+
+// `terms` suits a `keyword` field, which `more_like_this` queries can match authors on
+#[derive(Serialize)]
+struct AuthorDoc<'a> {
+    author: &'a str,
+    terms: Vec<String>,
+    weights: Vec<f64>,
+}
+
+// the `top` heaviest words, ties in byte order
+fn top_terms(mut weights: Vec<(&[u8], f64)>, top: usize) -> Vec<(&[u8], f64)> {
+    weights.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    weights.truncate(top);
+
+    weights
+}
+
+// an author per document, with the most frequent or heaviest tf-idf words
+pub fn run(args: &ElasticArgs, config: &Config) -> Result<()> {
+    let mut pb = progress_bar(0, 1000, "it");
+
+    let input = Input::open(&args.file, &mut pb)?;
+
+    let data = MergedInputs { inputs: vec![input] };
+
+    let tfidf =
+        match args.weights {
+            SimilarityWeights::Tfidf => Some(Tfidf::fit(&data, &config.tfidf).map_err(PooError::read(&args.file))?),
+            SimilarityWeights::Counts => None,
+        };
+
+    info!(url = %args.url, index = %args.index, authors = data.author_count(), "Elastic: Indexing the authors");
+
+    pb.reset(Some(data.author_count()));
+
+    let mut sink = BulkSink::new(&args.url, &args.index, args.api_key.clone(), args.batch);
+    let mut read = 0usize;
+
+    // only read errors come out of the walk, the first failed request is kept aside
+    let mut failed = None;
+
+    data.for_each_sorted(|author, freqs| {
+        if failed.is_some() {
+            return Ok(());
+        }
+
+        let weights =
+            match &tfidf {
+                Some(tfidf) => tfidf.weigh(freqs),
+                None => freqs.iter().map(|&(word, count)| (word, count as f64)).collect(),
+            };
+
+        let (terms, weights) =
+            top_terms(weights, args.top)
+                .into_iter()
+                .map(|(word, weight)| (String::from_utf8_lossy(word).into_owned(), weight))
+                .unzip();
+
+        let author = String::from_utf8_lossy(author);
+
+        if let Err(e) = sink.push(&author, &AuthorDoc { author: &author, terms, weights }) {
+            failed = Some(e);
+        }
+
+        read += 1;
+
+        if read.is_multiple_of(1000) {
+            pb.update_to(read);
+        }
+
+        Ok(())
+    })
+        .map_err(PooError::read(&args.file))?;
+
+    if let Some(e) = failed {
+        return Err(e.into());
+    }
+
+    let sent = sink.finish()?;
+
+    info!(documents = sent, "Elastic: Finished");
+
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rayon::prelude::*;
+use tracing::info;
+
+use crate::cli::EmbedArgs;
+use crate::commands::corpus::Corpus;
+use crate::commands::output_path;
+use crate::config::Config;
+use crate::dump;
+use crate::embedding::Sgns;
+use crate::error::{PooError, Result};
+
+// the words of all comments after the tokenizer, filtered like on ingest, train the vectors. a first pass counts
+// the words, every epoch reads the inputs again
+pub fn run(args: &EmbedArgs, config: &Config) -> Result<()> {
+    if dump::is_stdin(&args.path) {
+        return Err(PooError::Unsupported("the dumps are read once per epoch and once more for the vocabulary, not from stdin"));
+    }
+
+    let corpus = Corpus::open(&args.path, config)?;
+
+    // <name>.vectors.txt next to the dump, or the directory of them
+    let output =
+        args.output
+            .clone()
+            .unwrap_or_else(|| output_path(&dump::output_name(&args.path), config.output.dir.as_deref(), ".vectors.txt"));
+
+    info!(inputs = corpus.inputs(), "Embed: Counting the words");
+
+    let mut counts = HashMap::<Vec<u8>, u64>::new();
+
+    corpus.for_each_batch(|comments| {
+        for word in comments.into_iter().flat_map(|(_, words)| words) {
+            *counts.entry(word).or_insert(0) += 1;
+        }
+
+        Ok(())
+    })?;
+
+    let embedding = &config.embedding;
+
+    let Some(model) = Sgns::new(counts, embedding) else {
+        return Err(PooError::Unsupported("no word is frequent enough for a vector, lower min_count"));
+    };
+
+    let mut batch = 0u64;
+
+    for epoch in 0..embedding.epochs.max(1) {
+        info!(epoch = epoch + 1, epochs = embedding.epochs.max(1), words = model.len(), "Embed: Training");
+
+        corpus.for_each_batch(|comments| {
+            comments.par_iter()
+                .enumerate()
+                .for_each_init(
+                    || model.scratch(),
+                    |scratch, (i, (_, words))| {
+                        // a seed per comment, so one thread trains the same vectors every run
+                        let mut rng = StdRng::seed_from_u64(embedding.seed ^ (batch << 32 | i as u64));
+
+                        model.train(words, &mut rng, scratch);
+                    },
+                );
+
+            batch += 1;
+
+            Ok(())
+        })?;
+    }
+
+    info!(tokens = model.seen(), path = %output.display(), "Embed: Writing the vectors");
+
+    write(&model, &output)
+}
+
+fn write(model: &Sgns, path: &Path) -> Result<()> {
+    let file = File::create(path).map_err(PooError::write(path))?;
+
+    model.write(&mut BufWriter::new(file)).map_err(PooError::write(path))
+}
@@ -0,0 +1,918 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::Serialize;
+use tracing::info;
+
+use crate::cli::ExportArgs;
+use crate::commands::{progress_bar, read_freqs, tmp_path};
+use crate::error::{PooError, Result};
+use crate::npz::NpzWriter;
+use crate::remote;
+use crate::serializer::SortedFreqs;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    // Arrow IPC file (Feather v2), needs the `arrow` feature
+    Arrow,
+    // one JSON object per line
+    Jsonl,
+    // RFC 4180 quoting, tokens are written as raw bytes
+    Csv,
+    // backslash escapes for tab, newline, carriage return and backslash
+    Tsv,
+    // a scipy CSR matrix of authors by words for `scipy.sparse.load_npz`, with the labels in `.rows.txt` and
+    // `.columns.txt`, in any layout
+    Npz,
+    // a MatrixMarket coordinate matrix of authors by words, for R, Julia and gensim, labelled like npz
+    Mtx,
+    // a Vowpal Wabbit example per author, `'author | word:count ...`, the sparse layout names the features by id
+    Vw,
+    // ClickHouse TabSeparatedWithNamesAndTypes in the long layout, to a file or inserted into `--table` of an
+    // http(s) output
+    Clickhouse,
+    // a PostgreSQL COPY stream of the long layout in the text format, or the binary one with `--binary`, and
+    // the table it loads into in `.sql` next to it
+    PgCopy,
+}
+
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Arrow => "arrow",
+            Self::Jsonl => "jsonl",
+            Self::Csv => "csv",
+            Self::Tsv => "tsv",
+            Self::Npz => "npz",
+            Self::Mtx => "mtx",
+            Self::Vw => "vw",
+            Self::Clickhouse => "clickhouse.tsv",
+            Self::PgCopy => "pgcopy",
+        }
+    }
+
+    // an author by word matrix with its labels in files next to it
+    fn is_matrix(self) -> bool {
+        matches!(self, Self::Npz | Self::Mtx)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ExportLayout {
+    // one row per (author, word) pair
+    #[default]
+    Long,
+    // one row per author, see `--top`
+    Wide,
+    // one row per author with the ids of its words in `--vocabulary` and their counts, ordered by id
+    Sparse,
+}
+
+// word -> column of sparse exports, a `<id>\t<word>` line per word with the tsv escapes
+//
+// exports only ever add words at the end, so the exports of all months that share the file agree on every column
+#[derive(Default)]
+struct Vocabulary {
+    ids: HashMap<Vec<u8>, u32>,
+    words: Vec<Vec<u8>>,
+}
+
+impl Vocabulary {
+    // a missing file is an empty vocabulary
+    fn read(path: &Path) -> std::io::Result<Self> {
+        let mut vocabulary = Self::default();
+
+        let file =
+            match File::open(path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vocabulary),
+                Err(e) => return Err(e),
+            };
+
+        for (i, line) in BufReader::new(file).split(b'\n').enumerate() {
+            let line = line?;
+
+            let invalid = || Error::new(ErrorKind::InvalidData, format!("line {} is not <id>\\t<word>", i + 1));
+
+            let tab = line.iter().position(|&b| b == b'\t').ok_or_else(invalid)?;
+
+            let id =
+                std::str::from_utf8(&line[..tab])
+                    .ok()
+                    .and_then(|id| id.parse::<usize>().ok())
+                    .ok_or_else(invalid)?;
+
+            // ids are the line numbers, written out so other tools don't have to count
+            if id != vocabulary.words.len() {
+                return Err(Error::new(ErrorKind::InvalidData, format!("line {} has id {}, not {}", i + 1, id, i)));
+            }
+
+            vocabulary.push(unescape(&line[tab + 1..]));
+        }
+
+        Ok(vocabulary)
+    }
+
+    fn push(&mut self, word: Vec<u8>) {
+        self.ids.insert(word.clone(), self.words.len() as u32);
+        self.words.push(word);
+    }
+
+    // new words get the next ids, the most frequent first and ties in byte order
+    fn extend(&mut self, data: &impl SortedFreqs) -> std::io::Result<usize> {
+        let mut new = HashMap::<Vec<u8>, u64>::new();
+
+        data.for_each_sorted(|_, freqs| {
+            for &(word, count) in freqs {
+                if !self.ids.contains_key(word) {
+                    *new.entry(word.to_vec()).or_default() += count;
+                }
+            }
+
+            Ok(())
+        })?;
+
+        if self.words.len() + new.len() > u32::MAX as usize {
+            return Err(Error::other("the vocabulary would have more than 2^32 words"));
+        }
+
+        let mut new = new.into_iter().collect::<Vec<_>>();
+        new.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let added = new.len();
+
+        for (word, _) in new {
+            self.push(word);
+        }
+
+        Ok(added)
+    }
+
+    // replaced at once, a failed write leaves the previous vocabulary
+    fn write(&self, path: &Path) -> std::io::Result<()> {
+        let tmp = tmp_path(path);
+
+        let mut out = BufWriter::new(File::create(&tmp)?);
+
+        for (id, word) in self.words.iter().enumerate() {
+            let mut line = format!("{}\t", id).into_bytes();
+            push_field(&mut line, word, ExportFormat::Tsv);
+            line.push(b'\n');
+
+            out.write_all(&line)?;
+        }
+
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+        std::fs::rename(tmp, path)
+    }
+
+    // the ids and counts of an author's words, by id
+    fn sparse(&self, freqs: &[(&[u8], u64)]) -> (Vec<u32>, Vec<u64>) {
+        let mut pairs = freqs.iter().map(|&(word, count)| (self.ids[word], count)).collect::<Vec<_>>();
+        pairs.sort_unstable();
+
+        pairs.into_iter().unzip()
+    }
+}
+
+// the inverse of the tsv escapes of `push_field`
+fn unescape(field: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(field.len());
+    let mut bytes = field.iter();
+
+    while let Some(&b) = bytes.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+
+        match bytes.next() {
+            Some(b't') => out.push(b'\t'),
+            Some(b'n') => out.push(b'\n'),
+            Some(b'r') => out.push(b'\r'),
+            Some(&b) => out.push(b),
+            None => out.push(b'\\'),
+        }
+    }
+
+    out
+}
+
+// the `top` most frequent words, ties in byte order
+fn top_words<'a>(freqs: &[(&'a [u8], u64)], top: Option<usize>) -> Vec<(&'a [u8], u64)> {
+    let mut words = freqs.to_vec();
+
+    if let Some(top) = top {
+        words.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        words.truncate(top);
+    }
+
+    words
+}
+
+// <name>.users.<ext> next to <name>.users.freqs
+fn default_output(input: &Path, format: ExportFormat) -> PathBuf {
+    input.with_extension(format.extension())
+}
+
+// rows per record batch
+#[cfg(feature = "arrow")]
+const BATCH_SIZE: usize = 1 << 20;
+
+#[cfg(feature = "arrow")]
+fn write_arrow(out: impl Write, data: &impl SortedFreqs) -> std::io::Result<()> {
+    use std::sync::Arc;
+
+    use arrow_array::builder::{ArrayBuilder, StringBuilder, UInt64Builder};
+    use arrow_array::{ArrayRef, RecordBatch};
+    use arrow_ipc::writer::FileWriter;
+    use arrow_schema::{DataType, Field, Schema};
+
+    let schema =
+        Arc::new(Schema::new(vec![
+            Field::new("author", DataType::Utf8, false),
+            Field::new("word", DataType::Utf8, false),
+            Field::new("count", DataType::UInt64, false),
+        ]));
+
+    let mut writer =
+        FileWriter::try_new(out, &schema)
+            .map_err(Error::other)?;
+
+    let mut authors = StringBuilder::new();
+    let mut words = StringBuilder::new();
+    let mut counts = UInt64Builder::new();
+
+    let mut flush = |authors: &mut StringBuilder, words: &mut StringBuilder, counts: &mut UInt64Builder| {
+        let batch =
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(authors.finish()) as ArrayRef,
+                    Arc::new(words.finish()),
+                    Arc::new(counts.finish()),
+                ],
+            )
+                .map_err(Error::other)?;
+
+        writer.write(&batch).map_err(Error::other)
+    };
+
+    data.for_each_sorted(|author, freqs| {
+        let author = String::from_utf8_lossy(author);
+
+        for &(word, freq) in freqs {
+            authors.append_value(&author);
+            words.append_value(String::from_utf8_lossy(word));
+            counts.append_value(freq);
+        }
+
+        if counts.len() >= BATCH_SIZE {
+            flush(&mut authors, &mut words, &mut counts)?;
+        }
+
+        Ok(())
+    })?;
+
+    if !counts.is_empty() {
+        flush(&mut authors, &mut words, &mut counts)?;
+    }
+
+    writer.finish().map_err(Error::other)
+}
+
+#[cfg(not(feature = "arrow"))]
+fn write_arrow(_: impl Write, _: &impl SortedFreqs) -> std::io::Result<()> {
+    Err(Error::other("built without arrow support, enable the `arrow` feature"))
+}
+
+#[derive(Serialize)]
+struct JsonRow<'a> {
+    author: &'a str,
+    word: Cow<'a, str>,
+    count: u64,
+}
+
+#[derive(Serialize)]
+struct JsonAuthor<'a> {
+    author: &'a str,
+    words: BTreeMap<Cow<'a, str>, u64>,
+}
+
+#[derive(Serialize)]
+struct JsonSparse<'a> {
+    author: &'a str,
+    indices: Vec<u32>,
+    values: Vec<u64>,
+}
+
+fn write_jsonl(
+    mut out: impl Write,
+    data: &impl SortedFreqs,
+    layout: ExportLayout,
+    top: Option<usize>,
+    vocabulary: &Vocabulary,
+) -> std::io::Result<()> {
+    data.for_each_sorted(|author, freqs| {
+        let author = String::from_utf8_lossy(author);
+
+        match layout {
+            ExportLayout::Long => {
+                for &(word, count) in freqs {
+                    let row =
+                        JsonRow {
+                            author: &author,
+                            word: String::from_utf8_lossy(word),
+                            count,
+                        };
+
+                    serde_json::to_writer(&mut out, &row)?;
+                    out.write_all(b"\n")?;
+                }
+            }
+            ExportLayout::Wide => {
+                let row =
+                    JsonAuthor {
+                        author: &author,
+                        words:
+                            top_words(freqs, top)
+                                .into_iter()
+                                .map(|(word, count)| (String::from_utf8_lossy(word), count))
+                                .collect(),
+                    };
+
+                serde_json::to_writer(&mut out, &row)?;
+                out.write_all(b"\n")?;
+            }
+            ExportLayout::Sparse => {
+                let (indices, values) = vocabulary.sparse(freqs);
+
+                serde_json::to_writer(&mut out, &JsonSparse { author: &author, indices, values })?;
+                out.write_all(b"\n")?;
+            }
+        }
+
+        Ok(())
+    })?;
+
+    out.flush()
+}
+
+fn push_field(row: &mut Vec<u8>, field: &[u8], format: ExportFormat) {
+    match format {
+        ExportFormat::Tsv => {
+            for &b in field {
+                match b {
+                    b'\t' => row.extend_from_slice(b"\\t"),
+                    b'\n' => row.extend_from_slice(b"\\n"),
+                    b'\r' => row.extend_from_slice(b"\\r"),
+                    b'\\' => row.extend_from_slice(b"\\\\"),
+                    b => row.push(b),
+                }
+            }
+        }
+        _ => {
+            if !field.iter().any(|b| matches!(b, b',' | b'"' | b'\n' | b'\r')) {
+                row.extend_from_slice(field);
+                return;
+            }
+
+            row.push(b'"');
+
+            for &b in field {
+                if b == b'"' {
+                    row.push(b'"');
+                }
+
+                row.push(b);
+            }
+
+            row.push(b'"');
+        }
+    }
+}
+
+fn write_row(out: &mut impl Write, fields: &[&[u8]], format: ExportFormat) -> std::io::Result<()> {
+    let delimiter = if format == ExportFormat::Tsv { b'\t' } else { b',' };
+
+    let mut row = Vec::new();
+
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            row.push(delimiter);
+        }
+
+        push_field(&mut row, field, format);
+    }
+
+    row.push(b'\n');
+
+    out.write_all(&row)
+}
+
+fn write_delimited(
+    mut out: impl Write,
+    data: &impl SortedFreqs,
+    format: ExportFormat,
+    layout: ExportLayout,
+    top: Option<usize>,
+    vocabulary: &Vocabulary,
+) -> std::io::Result<()> {
+    match layout {
+        ExportLayout::Long => {
+            write_row(&mut out, &[b"author", b"word", b"count"], format)?;
+
+            data.for_each_sorted(|author, freqs| {
+                for &(word, count) in freqs {
+                    write_row(&mut out, &[author, word, count.to_string().as_bytes()], format)?;
+                }
+
+                Ok(())
+            })?;
+        }
+        ExportLayout::Wide => {
+            // every row needs the same number of columns
+            let columns =
+                match top {
+                    Some(top) => top,
+                    None => {
+                        let mut columns = 0;
+
+                        data.for_each_sorted(|_, freqs| {
+                            columns = columns.max(freqs.len());
+                            Ok(())
+                        })?;
+
+                        columns
+                    }
+                };
+
+            let mut header = vec![b"author".to_vec()];
+
+            for i in 1..=columns {
+                header.push(format!("word_{}", i).into_bytes());
+                header.push(format!("count_{}", i).into_bytes());
+            }
+
+            write_row(&mut out, &header.iter().map(|v| v.as_slice()).collect::<Vec<_>>(), format)?;
+
+            data.for_each_sorted(|author, freqs| {
+                let words = top_words(freqs, Some(columns));
+                let counts = words.iter().map(|(_, count)| count.to_string()).collect::<Vec<_>>();
+
+                let mut fields = vec![author];
+
+                for ((word, _), count) in words.iter().zip(counts.iter()) {
+                    fields.push(word);
+                    fields.push(count.as_bytes());
+                }
+
+                // authors with fewer words get empty cells
+                fields.resize(1 + 2 * columns, b"");
+
+                write_row(&mut out, &fields, format)
+            })?;
+        }
+        ExportLayout::Sparse => {
+            // the lists are space separated, neither ids nor counts contain spaces
+            let join = |values: &[String]| values.join(" ");
+
+            write_row(&mut out, &[b"author", b"indices", b"values"], format)?;
+
+            data.for_each_sorted(|author, freqs| {
+                let (indices, values) = vocabulary.sparse(freqs);
+
+                let indices = join(&indices.iter().map(|id| id.to_string()).collect::<Vec<_>>());
+                let values = join(&values.iter().map(|count| count.to_string()).collect::<Vec<_>>());
+
+                write_row(&mut out, &[author, indices.as_bytes(), values.as_bytes()], format)
+            })?;
+        }
+    }
+
+    out.flush()
+}
+
+// a label per line with the tsv escapes, the authors for the rows of a matrix or the words for its columns
+fn write_label(out: &mut impl Write, label: &[u8]) -> std::io::Result<()> {
+    let mut line = Vec::with_capacity(label.len() + 1);
+    push_field(&mut line, label, ExportFormat::Tsv);
+    line.push(b'\n');
+
+    out.write_all(&line)
+}
+
+fn write_labels<'a>(path: &Path, labels: impl IntoIterator<Item = &'a [u8]>) -> std::io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    for label in labels {
+        write_label(&mut out, label)?;
+    }
+
+    out.flush()
+}
+
+// <output without its extension>.rows.txt or .columns.txt
+fn label_path(output: &Path, axis: &str) -> PathBuf {
+    output.with_extension(format!("{}.txt", axis))
+}
+
+// indptr, indices and data of `scipy.sparse.csr_matrix`, a row per author in byte order and a column per word of
+// the vocabulary, so matrices of the same vocabulary line up
+//
+// every array is another pass over the authors, the sizes of the arrays go before them
+fn write_npz(out: impl Write, output: &Path, data: &impl SortedFreqs, vocabulary: &Vocabulary) -> std::io::Result<()> {
+    let mut npz = NpzWriter::new(out);
+
+    let rows = data.author_count() as u64;
+    let columns = vocabulary.words.len() as u64;
+
+    let mut authors = BufWriter::new(File::create(label_path(output, "rows"))?);
+    let mut nnz = 0u64;
+
+    npz.array("indptr", "<i8", &[rows + 1], 8, |out| {
+        out.write_all(&0i64.to_le_bytes())?;
+
+        data.for_each_sorted(|author, freqs| {
+            write_label(&mut authors, author)?;
+
+            nnz += freqs.len() as u64;
+            out.write_all(&(nnz as i64).to_le_bytes())
+        })
+    })?;
+
+    authors.flush()?;
+
+    npz.array("indices", "<i4", &[nnz], 4, |out| {
+        data.for_each_sorted(|_, freqs| {
+            for id in vocabulary.sparse(freqs).0 {
+                out.write_all(&(id as i32).to_le_bytes())?;
+            }
+
+            Ok(())
+        })
+    })?;
+
+    npz.array("data", "<i8", &[nnz], 8, |out| {
+        data.for_each_sorted(|_, freqs| {
+            for count in vocabulary.sparse(freqs).1 {
+                out.write_all(&(count as i64).to_le_bytes())?;
+            }
+
+            Ok(())
+        })
+    })?;
+
+    // what `save_npz` stores besides the arrays
+    npz.array("format", "|S3", &[], 3, |out| out.write_all(b"csr"))?;
+
+    npz.array("shape", "<i8", &[2], 8, |out| {
+        out.write_all(&(rows as i64).to_le_bytes())?;
+        out.write_all(&(columns as i64).to_le_bytes())
+    })?;
+
+    write_labels(&label_path(output, "columns"), vocabulary.words.iter().map(|word| word.as_slice()))?;
+
+    npz.finish()?.flush()
+}
+
+// `row column count` lines counting from 1, the rows and columns of npz
+fn write_mtx(mut out: impl Write, output: &Path, data: &impl SortedFreqs, vocabulary: &Vocabulary) -> std::io::Result<()> {
+    let mut nnz = 0u64;
+
+    data.for_each_sorted(|_, freqs| {
+        nnz += freqs.len() as u64;
+        Ok(())
+    })?;
+
+    writeln!(out, "%%MatrixMarket matrix coordinate integer general")?;
+    let name = |axis| label_path(output, axis).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+
+    writeln!(out, "% rows are the authors of {} and columns the words of {}, by line", name("rows"), name("columns"))?;
+    writeln!(out, "{} {} {}", data.author_count(), vocabulary.words.len(), nnz)?;
+
+    let mut authors = BufWriter::new(File::create(label_path(output, "rows"))?);
+    let mut row = 0u64;
+
+    data.for_each_sorted(|author, freqs| {
+        write_label(&mut authors, author)?;
+        row += 1;
+
+        let (indices, values) = vocabulary.sparse(freqs);
+
+        for (id, count) in indices.into_iter().zip(values) {
+            writeln!(out, "{} {} {}", row, id + 1, count)?;
+        }
+
+        Ok(())
+    })?;
+
+    authors.flush()?;
+
+    write_labels(&label_path(output, "columns"), vocabulary.words.iter().map(|word| word.as_slice()))?;
+
+    out.flush()
+}
+
+// vw splits on whitespace, `|` starts a namespace and `:` a value
+fn push_vw(line: &mut Vec<u8>, name: &[u8]) {
+    line.extend(name.iter().map(|&b| if b.is_ascii_whitespace() || b == b'|' || b == b':' { b'_' } else { b }));
+}
+
+// unlabelled, the author is the tag
+fn write_vw(
+    mut out: impl Write,
+    data: &impl SortedFreqs,
+    layout: ExportLayout,
+    top: Option<usize>,
+    vocabulary: &Vocabulary,
+) -> std::io::Result<()> {
+    let mut line = Vec::new();
+
+    data.for_each_sorted(|author, freqs| {
+        line.clear();
+        line.push(b'\'');
+        push_vw(&mut line, author);
+        line.extend_from_slice(b" |");
+
+        match layout {
+            ExportLayout::Sparse => {
+                let (indices, values) = vocabulary.sparse(freqs);
+
+                for (id, count) in indices.into_iter().zip(values) {
+                    write!(line, " {}:{}", id, count)?;
+                }
+            }
+            _ => {
+                for (word, count) in top_words(freqs, top) {
+                    line.push(b' ');
+                    push_vw(&mut line, word);
+                    write!(line, ":{}", count)?;
+                }
+            }
+        }
+
+        line.push(b'\n');
+        out.write_all(&line)
+    })?;
+
+    out.flush()
+}
+
+const CLICKHOUSE_HEADER: &[u8] = b"author\tword\tcount\nString\tString\tUInt64\n";
+
+// bytes of rows per insert
+const CLICKHOUSE_BATCH: usize = 64 << 20;
+
+// the escapes of clickhouse's TabSeparated are the ones of tsv
+fn write_clickhouse(mut out: impl Write, data: &impl SortedFreqs) -> std::io::Result<()> {
+    out.write_all(CLICKHOUSE_HEADER)?;
+
+    data.for_each_sorted(|author, freqs| {
+        for &(word, count) in freqs {
+            write_row(&mut out, &[author, word, count.to_string().as_bytes()], ExportFormat::Tsv)?;
+        }
+
+        Ok(())
+    })?;
+
+    out.flush()
+}
+
+fn clickhouse_query(url: &str, query: &str, body: &[u8]) -> std::io::Result<()> {
+    let separator =
+        match url.split_once("://").map_or(url, |(_, rest)| rest) {
+            rest if rest.contains('?') => "&",
+            rest if rest.contains('/') => "?",
+            // just the server
+            _ => "/?",
+        };
+
+    let url = format!("{}{}query={}", url, separator, remote::encode_query(query));
+
+    match remote::post(&url, &[("Content-Type", "text/tab-separated-values")], body)? {
+        (200, _) => Ok(()),
+        (status, answer) => Err(Error::other(format!("clickhouse answered with status {}: {}", status, String::from_utf8_lossy(&answer).trim()))),
+    }
+}
+
+// creates `table` if it's missing and inserts the rows in batches, credentials go in the url as `?user=&password=`
+fn insert_clickhouse(url: &str, table: &str, data: &impl SortedFreqs) -> std::io::Result<()> {
+    let create = format!("CREATE TABLE IF NOT EXISTS {} (author String, word String, count UInt64) ENGINE = MergeTree ORDER BY (author, word)", table);
+    clickhouse_query(url, &create, b"")?;
+
+    let insert = format!("INSERT INTO {} FORMAT TabSeparatedWithNamesAndTypes", table);
+    let mut body = CLICKHOUSE_HEADER.to_vec();
+
+    data.for_each_sorted(|author, freqs| {
+        for &(word, count) in freqs {
+            write_row(&mut body, &[author, word, count.to_string().as_bytes()], ExportFormat::Tsv)?;
+        }
+
+        if body.len() >= CLICKHOUSE_BATCH {
+            clickhouse_query(url, &insert, &body)?;
+            body.truncate(CLICKHOUSE_HEADER.len());
+        }
+
+        Ok(())
+    })?;
+
+    match body.len() > CLICKHOUSE_HEADER.len() {
+        true => clickhouse_query(url, &insert, &body),
+        false => Ok(()),
+    }
+}
+
+const PG_COPY_SIGNATURE: &[u8] = b"PGCOPY\n\xff\r\n\0";
+
+// the escapes of the text format are the ones of tsv, the binary format has a length before every field and
+// integers in network order
+fn write_pg_copy(mut out: impl Write, data: &impl SortedFreqs, binary: bool) -> std::io::Result<()> {
+    if binary {
+        // no flags and no header extension
+        out.write_all(PG_COPY_SIGNATURE)?;
+        out.write_all(&0u32.to_be_bytes())?;
+        out.write_all(&0u32.to_be_bytes())?;
+    }
+
+    let mut row = Vec::new();
+
+    data.for_each_sorted(|author, freqs| {
+        for &(word, count) in freqs {
+            row.clear();
+
+            if binary {
+                row.extend_from_slice(&3u16.to_be_bytes());
+
+                for field in [author, word] {
+                    row.extend_from_slice(&(field.len() as u32).to_be_bytes());
+                    row.extend_from_slice(field);
+                }
+
+                row.extend_from_slice(&8u32.to_be_bytes());
+                row.extend_from_slice(&(count as i64).to_be_bytes());
+
+                out.write_all(&row)?;
+            } else {
+                write_row(&mut out, &[author, word, count.to_string().as_bytes()], ExportFormat::Tsv)?;
+            }
+        }
+
+        Ok(())
+    })?;
+
+    if binary {
+        out.write_all(&(-1i16).to_be_bytes())?;
+    }
+
+    out.flush()
+}
+
+// `CREATE TABLE` of what a pg-copy export holds, with the command loading it
+fn write_pg_ddl(path: &Path, table: &str, output: &Path, binary: bool) -> std::io::Result<()> {
+    let format = if binary { "binary" } else { "text" };
+
+    let source =
+        match output == Path::new("-") {
+            true => "STDIN".to_string(),
+            false => format!("'{}'", output.display()),
+        };
+
+    let ddl = format!(
+        "-- \\copy {table} FROM {source} (FORMAT {format})\n\
+         CREATE TABLE IF NOT EXISTS {table} (\n    \
+             author text NOT NULL,\n    \
+             word text NOT NULL,\n    \
+             count bigint NOT NULL\n\
+         );\n",
+    );
+
+    std::fs::write(path, ddl)
+}
+
+// `-` writes to stdout so the output can be piped into e.g. jq
+pub fn open_output(path: &Path) -> std::io::Result<Box<dyn Write>> {
+    if path == Path::new("-") {
+        Ok(Box::new(BufWriter::new(std::io::stdout().lock())))
+    } else {
+        Ok(Box::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+pub fn run(args: &ExportArgs) -> Result<()> {
+    let output =
+        args.output
+            .clone()
+            .unwrap_or_else(|| default_output(&args.file, args.format));
+
+    // before anything is read or created
+    if args.format == ExportFormat::Arrow && args.layout != ExportLayout::Long {
+        return Err(PooError::Unsupported("arrow only supports the long layout"));
+    }
+
+    #[cfg(not(feature = "arrow"))]
+    if args.format == ExportFormat::Arrow {
+        return Err(PooError::Unsupported("built without arrow support, enable the `arrow` feature"));
+    }
+
+    if args.layout == ExportLayout::Sparse && args.vocabulary.is_none() && !args.format.is_matrix() {
+        return Err(PooError::Unsupported("the sparse layout needs a --vocabulary to number the words"));
+    }
+
+    // the labels go next to the matrix
+    if args.format.is_matrix() && output == Path::new("-") {
+        return Err(PooError::Unsupported("a matrix export needs a file to write, its labels go next to it"));
+    }
+
+    if matches!(args.format, ExportFormat::Clickhouse | ExportFormat::PgCopy) && args.layout != ExportLayout::Long {
+        return Err(PooError::Unsupported("ClickHouse and PostgreSQL only support the long layout"));
+    }
+
+    let url = output.to_str().filter(|url| url.starts_with("http://") || url.starts_with("https://"));
+
+    if url.is_some() && args.format != ExportFormat::Clickhouse {
+        return Err(PooError::Unsupported("only ClickHouse exports can be sent to a url"));
+    }
+
+    let mut pb = progress_bar(0, 1000, "B");
+
+    let poo = read_freqs(&args.file, &mut pb)?;
+
+    let mut vocabulary = Vocabulary::default();
+
+    if let Some(path) = &args.vocabulary {
+        let added =
+            Vocabulary::read(path)
+                .and_then(|read| {
+                    vocabulary = read;
+                    vocabulary.extend(&poo)
+                })
+                .map_err(PooError::read(path))?;
+
+        info!(path = %path.display(), words = vocabulary.words.len(), added, "Vocabulary");
+    } else if args.format.is_matrix() {
+        // the columns of this file only, in `.columns.txt`
+        vocabulary.extend(&poo).map_err(PooError::read(&args.file))?;
+    }
+
+    // rows go straight into the table
+    if let Some(url) = url {
+        info!(url, table = %args.table, "Inserting");
+
+        return insert_clickhouse(url, &args.table, &poo).map_err(PooError::write(url));
+    }
+
+    info!(path = %output.display(), "Exporting");
+
+    // renamed once written, failed exports leave no file behind
+    let target =
+        match output == Path::new("-") {
+            true => output.clone(),
+            false => tmp_path(&output),
+        };
+
+    open_output(&target)
+        .and_then(|out| {
+            match (args.format, args.layout) {
+                (ExportFormat::Arrow, _) => write_arrow(out, &poo),
+                (ExportFormat::Npz, _) => write_npz(out, &output, &poo, &vocabulary),
+                (ExportFormat::Mtx, _) => write_mtx(out, &output, &poo, &vocabulary),
+                (ExportFormat::Vw, layout) => write_vw(out, &poo, layout, args.top, &vocabulary),
+                (ExportFormat::Clickhouse, _) => write_clickhouse(out, &poo),
+                (ExportFormat::PgCopy, _) => write_pg_copy(out, &poo, args.binary),
+                (ExportFormat::Jsonl, layout) => write_jsonl(out, &poo, layout, args.top, &vocabulary),
+                (format, layout) => write_delimited(out, &poo, format, layout, args.top, &vocabulary),
+            }
+        })
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&target);
+            PooError::write(&output)(e)
+        })?;
+
+    if target != output {
+        std::fs::rename(&target, &output).map_err(PooError::write(&output))?;
+    }
+
+    if args.format == ExportFormat::PgCopy {
+        // next to the input when the stream goes to stdout
+        let ddl =
+            match output == Path::new("-") {
+                true => args.file.with_extension("pgcopy.sql"),
+                false => PathBuf::from(format!("{}.sql", output.display())),
+            };
+
+        write_pg_ddl(&ddl, &args.table, &output, args.binary).map_err(PooError::write(&ddl))?;
+    }
+
+    // only once the export that uses the new ids was written
+    if let Some(path) = &args.vocabulary {
+        vocabulary.write(path).map_err(PooError::write(path))?;
+    }
+
+    Ok(())
+}
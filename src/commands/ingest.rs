@@ -0,0 +1,1480 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::ops::AddAssign;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, SyncSender};
+
+use kdam::{BarExt, RichProgress};
+use clap::ValueEnum;
+use glob::Pattern;
+use rayon::prelude::*;
+use ruzstd::FrameDecoder;
+use serde::{Deserialize, Deserializer, Serialize};
+use tracing::{error, info, info_span, warn};
+
+use crate::cli::IngestArgs;
+use crate::commands::{list_files_matching, meta_path, MemoryBudget, output_exists, output_path, progress_bar_at, read_bincode, sketch_path, stats_path, Summary, tmp_path, write_bincode, write_freqs_draining, write_output};
+use crate::config::{ByteSize, Config, IngestConfig, OutputFormat};
+use crate::dedup::{Dedup, DedupMode, Seen};
+use crate::dump::{self, Compression};
+use crate::error::{PooError, Result};
+use crate::filter::{civil_from_days, days_from_civil, has_bot_name, has_bot_phrase, is_deleted, DeletedPolicy, Filter};
+use crate::manifest::{self, Manifests, Status};
+use crate::metrics::{self, Metrics};
+use crate::progress::{clock, ProgressLog, Reporter};
+use crate::prune::{Pruned, Vocabulary};
+use crate::serializer::SortedFreqs;
+use crate::text::pipeline::{Pipeline, Tokenizer};
+use crate::spill::{MergedRuns, run_path, write_run};
+use crate::text::interner::{Counting, FreqStore};
+use crate::text::text_item::{MetaMap, PooMap, StatsMap, TextItem};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Comment {
+    pub author: String,
+    pub body: String,
+    #[serde(default)]
+    pub subreddit: Option<String>,
+    #[serde(rename = "created_utc", default, deserialize_with = "lenient_i64")]
+    pub created_utc: Option<i64>,
+    #[serde(default, deserialize_with = "lenient_i64")]
+    pub score: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Submission {
+    pub author: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub selftext: String,
+    #[serde(default)]
+    pub subreddit: Option<String>,
+    #[serde(default, deserialize_with = "lenient_i64")]
+    pub created_utc: Option<i64>,
+    #[serde(default, deserialize_with = "lenient_i64")]
+    pub score: Option<i64>,
+}
+
+// submissions are aggregated like comments, with the title and selftext as body
+impl From<Submission> for Comment {
+    fn from(submission: Submission) -> Self {
+        let body =
+            match submission.selftext.trim() {
+                "" | "[deleted]" | "[removed]" => submission.title,
+                selftext => format!("{}\n\n{}", submission.title, selftext),
+            };
+
+        Self {
+            author: submission.author,
+            body,
+            subreddit: submission.subreddit,
+            created_utc: submission.created_utc,
+            score: submission.score,
+        }
+    }
+}
+
+// which kind of pushshift dump a file contains
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum InputKind {
+    // RS_* files are submissions, everything else comments
+    #[default]
+    Auto,
+    Comments,
+    Submissions,
+}
+
+// what a file does once the state of all files exceeds `max_memory`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MemoryAction {
+    // flush the state to a sorted run and merge the runs on save
+    #[default]
+    Spill,
+    // finish the current output as <name>.part<n>.users.<ext> and start a new one
+    Rotate,
+}
+
+// what happens once a file has more than `max_errors` unparseable lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorPolicy {
+    // the file fails without an output, the others go on
+    #[default]
+    AbortFile,
+    // the file fails and every other file stops where it is, checkpoints are kept for --resume
+    AbortRun,
+    // keep going, the bad lines are only counted
+    Ignore,
+}
+
+impl ErrorPolicy {
+    fn exceeded(self, errors: usize, max_errors: usize) -> bool {
+        self != Self::Ignore && errors > max_errors
+    }
+}
+
+// counts of an author are kept apart per period as "author@2020-05" or "author@2020-W19"
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    #[default]
+    None,
+    Month,
+    // ISO 8601 weeks, starting on monday
+    Week,
+}
+
+impl Period {
+    fn label(self, created_utc: Option<i64>) -> Option<String> {
+        let days =
+            match (self, created_utc) {
+                (Self::None, _) => return None,
+                (_, None) => return Some("unknown".to_string()),
+                (_, Some(ts)) => ts.div_euclid(86400),
+            };
+
+        match self {
+            Self::Month => {
+                let (year, month, _) = civil_from_days(days);
+
+                Some(format!("{}-{:02}", year, month))
+            }
+            _ => {
+                // a week belongs to the year of its thursday
+                let thursday = days - (days + 3).rem_euclid(7) + 3;
+                let (year, _, _) = civil_from_days(thursday);
+                let week = (thursday - days_from_civil(year, 1, 1)) / 7 + 1;
+
+                Some(format!("{}-W{:02}", year, week))
+            }
+        }
+    }
+}
+
+impl InputKind {
+    pub(crate) fn for_file(self, path: &Path) -> Self {
+        match self {
+            Self::Auto => {
+                let is_submissions =
+                    path.file_name()
+                        .and_then(|n| n.to_str())
+                        .map(|n| n.starts_with("RS_"))
+                        .unwrap_or(false);
+
+                if is_submissions {
+                    Self::Submissions
+                } else {
+                    Self::Comments
+                }
+            }
+            kind => kind,
+        }
+    }
+
+    pub(crate) fn parse(self, line: &mut [u8]) -> simd_json::Result<Comment> {
+        match self {
+            Self::Submissions => simd_json::from_slice::<Submission>(line).map(Comment::from),
+            _ => simd_json::from_slice::<Comment>(line),
+        }
+    }
+}
+
+// some pushshift months store numeric fields as strings or floats
+fn lenient_i64<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<i64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Lenient {
+        Int(i64),
+        Float(f64),
+        Str(String),
+    }
+
+    Ok(
+        match Option::<Lenient>::deserialize(deserializer)? {
+            Some(Lenient::Int(v)) => Some(v),
+            Some(Lenient::Float(v)) => Some(v as i64),
+            Some(Lenient::Str(v)) => v.trim().parse().ok(),
+            None => None,
+        }
+    )
+}
+
+// <output>.ckpt
+fn checkpoint_path(out_path: &Path) -> PathBuf {
+    let mut name = out_path.as_os_str().to_os_string();
+    name.push(".ckpt");
+
+    PathBuf::from(name)
+}
+
+// tokenizes a batch in parallel and merges it into one map per author, with the stats of every author
+fn aggregate(comments: &[Comment], tokenizer: &dyn Tokenizer) -> (PooMap, StatsMap) {
+    comments
+        .par_iter()
+        .map(|comment|
+            (
+                comment.author.as_bytes().to_vec(),
+                tokenizer.tokenize(&comment.body),
+                comment.body.as_str(),
+            )
+        )
+        .fold(
+            <(PooMap, StatsMap)>::default,
+            |(mut acc, mut stats), (author, freqs, body)| {
+                stats
+                    .entry(author.clone())
+                    .or_default()
+                    .observe(freqs.values().sum(), body);
+
+                let author_map =
+                    &mut acc
+                        .entry(author)
+                        .or_default();
+
+                for (word, freq) in freqs.iter() {
+                    author_map
+                        .entry(word.clone())
+                        .or_insert(0)
+                        .add_assign(*freq);
+                }
+
+                (acc, stats)
+            },
+        )
+        .reduce(
+            <(PooMap, StatsMap)>::default,
+            |(mut acc, mut stats), (all_freqs, all_stats)| {
+                for (author, freqs) in all_freqs.iter() {
+                    let author_map =
+                        &mut acc
+                            .entry(author.clone())
+                            .or_default();
+
+                    for (word, freq) in freqs.iter() {
+                        author_map
+                            .entry(word.clone())
+                            .or_insert(0)
+                            .add_assign(*freq);
+                    }
+                }
+
+                for (author, other) in all_stats {
+                    stats.entry(author).or_default().merge(&other);
+                }
+
+                (acc, stats)
+            },
+        )
+}
+
+fn counting(config: &Config) -> Counting<'_> {
+    match (config.sketch.enabled, config.ingest.max_author_words) {
+        (true, _) => Counting::Sketched(&config.sketch),
+        (false, Some(cap)) => Counting::Capped(cap),
+        (false, None) => Counting::Exact,
+    }
+}
+
+// the metadata goes first, an existing .users.freqs marks the input as done
+fn save(ti: &mut TextItem, runs: &[PathBuf], out_path: &Path, config: &Config, pb: &mut RichProgress) -> Result<()> {
+    if config.ingest.metadata {
+        let meta = meta_path(out_path);
+
+        info!(path = %meta.display(), "Saving: Writing author metadata");
+
+        write_bincode(&meta, &ti.meta, config.output.compression_level).map_err(PooError::write(&meta))?;
+    }
+
+    // before pruning, the unique words are all an author used
+    let stats = stats_path(out_path);
+
+    info!(path = %stats.display(), "Saving: Writing author stats");
+
+    match runs.is_empty() {
+        true => {
+            for (author, freqs) in ti.word_freqs.freqs.iter() {
+                if let Some(stats) = ti.stats.get_mut(ti.word_freqs.authors.resolve(*author)) {
+                    stats.unique_words = freqs.len() as u64;
+                }
+            }
+        }
+        false => {
+            let merged = MergedRuns::open(runs)?;
+
+            count_unique_words(&mut ti.stats, &merged).map_err(PooError::read(&runs[0]))?;
+            ti.word_freqs.union_distinct(&merged).map_err(PooError::read(&runs[0]))?;
+        }
+    }
+
+    // estimated for the authors who lost words to --max-author-words, sketches or pruning during ingestion
+    for (author, distinct) in ti.word_freqs.distinct_words() {
+        if let Some(stats) = ti.stats.get_mut(author) {
+            stats.unique_words = distinct.estimate();
+            stats.distinct = Some(distinct.clone());
+        }
+    }
+
+    write_bincode(&stats, &ti.stats, config.output.compression_level).map_err(PooError::write(&stats))?;
+
+    if config.sketch.enabled {
+        let sketches = sketch_path(out_path);
+
+        info!(path = %sketches.display(), "Saving: Writing the sketches");
+
+        write_bincode(&sketches, &ti.word_freqs.sketches(), config.output.compression_level).map_err(PooError::write(&sketches))?;
+    }
+
+    if runs.is_empty() {
+        if let Some(min) = config.prune.min_word_count {
+            ti.word_freqs.retain_min_count(min);
+        }
+
+        if config.prune.limits_vocabulary() {
+            let vocabulary = vocabulary(&ti.word_freqs, config)?;
+
+            ti.word_freqs.retain_words(|word| vocabulary.contains(word));
+        }
+
+        return match config.output.format {
+            OutputFormat::Freqs => write_freqs_draining(out_path, std::mem::take(&mut ti.word_freqs), &config.output, pb),
+            _ => write_output(out_path, &ti.word_freqs, &config.output, pb),
+        };
+    }
+
+    info!(runs = runs.len(), "Saving: Merging spilled runs");
+
+    let merged = MergedRuns::open(runs)?;
+
+    if !config.prune.is_active() {
+        return write_output(out_path, &merged, &config.output, pb);
+    }
+
+    // the runs are merged twice if the vocabulary is limited, once for the word counts and once for writing
+    let vocabulary =
+        match config.prune.limits_vocabulary() {
+            true => Some(vocabulary(&merged, config)?),
+            false => None,
+        };
+
+    let pruned =
+        Pruned {
+            data: &merged,
+            min_word_count: config.prune.min_word_count.unwrap_or(0),
+            vocabulary: vocabulary.as_ref(),
+        };
+
+    write_output(out_path, &pruned, &config.output, pb)
+}
+
+// the spilled runs have to be merged for it
+fn count_unique_words(stats: &mut StatsMap, data: &impl SortedFreqs) -> std::io::Result<()> {
+    data.for_each_sorted(|author, freqs| {
+        if let Some(stats) = stats.get_mut(author) {
+            stats.unique_words = freqs.len() as u64;
+        }
+
+        Ok(())
+    })
+}
+
+fn vocabulary(data: &impl SortedFreqs, config: &Config) -> Result<Vocabulary> {
+    let vocabulary = Vocabulary::build(data, &config.prune)?;
+
+    info!(kept = vocabulary.len(), seen = vocabulary.seen(), "Saving: Limiting the vocabulary");
+
+    Ok(vocabulary)
+}
+
+fn spill(ti: &mut TextItem, runs: &mut Vec<PathBuf>, out_path: &Path, config: &Config) -> Result<()> {
+    let run = run_path(out_path, config.ingest.spill_dir.as_deref(), runs.len());
+
+    info!(authors = ti.word_freqs.len(), run = %run.display(), "Spilling to a sorted run");
+
+    write_run(&run, &ti.word_freqs).map_err(PooError::write(&run))?;
+
+    let distinct = ti.word_freqs.take_distinct();
+
+    ti.word_freqs = FreqStore::new();
+    ti.word_freqs.restore_distinct(distinct);
+    runs.push(run);
+
+    Ok(())
+}
+
+// saves the in-memory state together with any spilled runs, then removes the runs
+fn flush(ti: &mut TextItem, runs: &mut Vec<PathBuf>, out_path: &Path, config: &Config, pb: &mut RichProgress) -> Result<()> {
+    // whatever is still in memory becomes the last run
+    if !runs.is_empty() && !ti.word_freqs.is_empty() {
+        spill(ti, runs, out_path, config)?;
+    }
+
+    save(ti, runs, out_path, config, pb)?;
+
+    for run in runs.drain(..) {
+        let _ = std::fs::remove_file(run);
+    }
+
+    Ok(())
+}
+
+// <name>.errors.jsonl next to <name>.users.<ext>
+fn errors_path(out_path: &Path) -> PathBuf {
+    let name = out_path.file_name().unwrap().to_str().unwrap();
+
+    let name =
+        match name.rfind(".users.") {
+            Some(i) => format!("{}.errors.jsonl", &name[..i]),
+            None => format!("{}.errors.jsonl", name),
+        };
+
+    out_path.with_file_name(name)
+}
+
+// a fresh run starts a new log, a resumed one adds to it
+fn open_error_log(path: &Path, append: bool) -> std::io::Result<BufWriter<File>> {
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .map(BufWriter::new)
+}
+
+// <name>.part<n>.users.<ext> next to <name>.users.<ext>
+fn shard_path(out_path: &Path, index: usize) -> PathBuf {
+    let name = out_path.file_name().unwrap().to_str().unwrap();
+
+    let name =
+        match name.rfind(".users.") {
+            Some(i) => format!("{}.part{}{}", &name[..i], index, &name[i..]),
+            None => format!("{}.part{}", name, index),
+        };
+
+    out_path.with_file_name(name)
+}
+
+// estimated size of the aggregation state of all files being processed
+#[derive(Default)]
+struct MemoryTracker {
+    used: AtomicU64,
+}
+
+impl MemoryTracker {
+    // replaces a file's last reported size, returns the new total
+    fn update(&self, reported: &mut u64, size: u64) -> u64 {
+        let total =
+            if size >= *reported {
+                self.used.fetch_add(size - *reported, Ordering::SeqCst) + (size - *reported)
+            } else {
+                self.used.fetch_sub(*reported - size, Ordering::SeqCst) - (*reported - size)
+            };
+
+        *reported = size;
+
+        total
+    }
+}
+
+// settings shared by all files of a run
+pub(crate) struct Context<'a> {
+    config: &'a Config,
+    tokenizer: Box<dyn Tokenizer>,
+    filter: Filter,
+    resume: bool,
+    memory: MemoryTracker,
+    // unparseable lines of all files
+    errors: AtomicUsize,
+    // set by `ErrorPolicy::AbortRun`
+    aborted: AtomicBool,
+    metrics: Arc<Metrics>,
+    progress: Option<ProgressLog>,
+    manifests: Manifests,
+    // ingest inputs with current outputs too
+    force: bool,
+}
+
+// the aggregated maps are a fraction of the raw text, this is a rough upper bound
+const MEMORY_ESTIMATE_RATIO: f64 = 0.25;
+
+// declared decompressed size of a dump, 0 if the zstd frame header doesn't say or it's compressed otherwise
+fn content_size(path: &Path) -> u64 {
+    if let Ok(Some(Compression::Plain)) = Compression::detect(path) {
+        return std::fs::metadata(path).map_or(0, |m| m.len());
+    }
+
+    let mut dec = FrameDecoder::new();
+
+    match File::open(path).map(|file| dec.init(file)) {
+        Ok(Ok(())) => dec.content_size().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+// consecutive raw lines and the stream position after the last of them
+struct RawBatch {
+    lines: Vec<Vec<u8>>,
+    offset: u64,
+    line_count: u64,
+}
+
+// a line of <name>.errors.jsonl
+#[derive(Serialize)]
+struct BadLine {
+    // where the line starts in the decompressed stream
+    offset: u64,
+    line: u64,
+    error: String,
+    // the start of the line as it was read, the parser rewrites it in place
+    sample: String,
+}
+
+// what a single batch contributes to one sink
+#[derive(Default)]
+struct BatchState {
+    freqs: PooMap,
+    meta: MetaMap,
+    stats: StatsMap,
+}
+
+// the parsed and tokenized contents of a `RawBatch`
+struct ParsedBatch {
+    state: BatchState,
+    deleted: Option<BatchState>,
+    errors: usize,
+    // lines that passed the filters
+    comments: usize,
+    // of those, the repeated bodies `Dedup` found
+    duplicates: usize,
+    // the unparseable lines when they're logged
+    bad: Vec<BadLine>,
+    offset: u64,
+    lines: u64,
+}
+
+// splits the decompressed stream into batches of `per_iter` lines, starting `offset` bytes in
+fn read_batches(
+    path: &Path,
+    config: &Config,
+    kind: InputKind,
+    mut offset: u64,
+    mut line_count: u64,
+    tx: SyncSender<std::io::Result<RawBatch>>,
+) {
+    let per_iter = config.ingest.per_iter;
+
+    let decoder = dump::open(path, config, kind == InputKind::Submissions);
+
+    let mut decoder =
+        match decoder {
+            Ok(decoder) => BufReader::new(decoder),
+            Err(e) => {
+                let _ = tx.send(Err(e));
+                return;
+            }
+        };
+
+    // the stream can't seek, skip over the already processed bytes
+    if offset > 0 {
+        match std::io::copy(&mut (&mut decoder).take(offset), &mut std::io::sink()) {
+            Ok(skipped) if skipped == offset => {}
+            Ok(_) => {
+                let _ = tx.send(Err(Error::new(ErrorKind::UnexpectedEof, "checkpoint offset beyond end of file")));
+                return;
+            }
+            Err(e) => {
+                let _ = tx.send(Err(e));
+                return;
+            }
+        }
+    }
+
+    loop {
+        let mut lines = Vec::with_capacity(per_iter);
+        let mut eof = false;
+
+        for _ in 0..per_iter.max(1) {
+            let mut line = Vec::new();
+
+            match dump::read_until(&mut decoder, b'\n', &mut line) {
+                Ok(0) => {
+                    eof = true;
+                    break;
+                }
+                Ok(read) => {
+                    offset += read as u64;
+                    line_count += 1;
+
+                    lines.push(line);
+                }
+                Err(e) => {
+                    let _ = tx.send(Err(e));
+                    return;
+                }
+            }
+        }
+
+        if !lines.is_empty() && tx.send(Ok(RawBatch { lines, offset, line_count })).is_err() {
+            return;
+        }
+
+        if eof {
+            return;
+        }
+    }
+}
+
+fn parse_batch(ctx: &Context, kind: InputKind, batch: RawBatch, dedup: &mut Dedup) -> ParsedBatch {
+    let sample = ctx.config.ingest.log_errors.then_some(ctx.config.ingest.error_sample);
+
+    // each line ends where the next starts, the batch ends at `offset`
+    let size = batch.lines.iter().map(|line| line.len() as u64).sum::<u64>();
+
+    let starts =
+        batch.lines
+            .iter()
+            .scan(batch.offset - size, |start, line| {
+                *start += line.len() as u64;
+                Some(*start - line.len() as u64)
+            })
+            .collect::<Vec<_>>();
+
+    let first_line = batch.line_count - batch.lines.len() as u64;
+
+    let parsed =
+        batch.lines
+            .into_par_iter()
+            .enumerate()
+            .map(|(i, mut line)| {
+                let sample = sample.map(|n| line[..line.len().min(n)].to_vec());
+
+                kind.parse(&mut line)
+                    .map_err(|e| {
+                        sample.map(|sample| BadLine {
+                            offset: starts[i],
+                            line: first_line + i as u64 + 1,
+                            error: e.to_string(),
+                            sample: String::from_utf8_lossy(sample.trim_ascii_end()).into_owned(),
+                        })
+                    })
+            })
+            .collect::<Vec<_>>();
+
+    let errors = parsed.iter().filter(|c| c.is_err()).count();
+
+    let mut bad = Vec::new();
+    let mut duplicates = 0;
+
+    let mut state = BatchState::default();
+    let mut comments = Vec::new();
+
+    // sink for comments by deleted authors or with removed bodies
+    let mut deleted =
+        match ctx.config.filter.deleted {
+            DeletedPolicy::Separate => Some((BatchState::default(), Vec::new())),
+            _ => None,
+        };
+
+    for comment in parsed {
+        // the line is skipped, the next one starts after its newline
+        let mut comment =
+            match comment {
+                Ok(comment) => comment,
+                Err(line) => {
+                    bad.extend(line);
+                    continue;
+                }
+            };
+
+        if !ctx.filter.accepts(&comment) {
+            continue;
+        }
+
+        // a deleted author is told by the bare name, before the period label
+        let (sink, batch) =
+            match (is_deleted(&comment), deleted.as_mut()) {
+                (false, _) => (&mut state, &mut comments),
+                (true, Some((sink, batch))) => (sink, batch),
+                (true, None) if ctx.config.filter.deleted == DeletedPolicy::Skip => continue,
+                (true, None) => (&mut state, &mut comments),
+            };
+
+        if let Some(label) = ctx.config.ingest.period.label(comment.created_utc) {
+            comment.author = format!("{}@{}", comment.author, label);
+        }
+
+        let seen = dedup.check(&comment.author, &comment.body);
+
+        // a repeat still counts as activity in the metadata
+        if ctx.config.ingest.metadata {
+            let templated = dedup.templated(&comment.author, &comment.body) && seen == Seen::First;
+
+            // a period label isn't part of the name
+            let named = has_bot_name(comment.author.split('@').next().unwrap_or_default());
+
+            let meta = sink.meta.entry(comment.author.as_bytes().to_vec()).or_default();
+
+            meta.observe_bot(comment.created_utc, named, seen != Seen::First, templated, has_bot_phrase(&comment.body));
+            meta.observe(comment.created_utc, comment.score, comment.subreddit.as_deref());
+        }
+
+        match seen {
+            Seen::First => batch.push(comment),
+            Seen::Counted => {
+                duplicates += 1;
+                batch.push(comment);
+            }
+            Seen::Dropped => duplicates += 1,
+        }
+    }
+
+    let accepted = comments.len() + deleted.as_ref().map_or(0, |(_, batch)| batch.len());
+
+    (state.freqs, state.stats) = aggregate(&comments, ctx.tokenizer.as_ref());
+
+    ParsedBatch {
+        state,
+        deleted:
+            deleted.map(|(mut sink, batch)| {
+                (sink.freqs, sink.stats) = aggregate(&batch, ctx.tokenizer.as_ref());
+                sink
+            }),
+        errors,
+        comments: accepted,
+        duplicates,
+        bad,
+        offset: batch.offset,
+        lines: batch.line_count,
+    }
+}
+
+fn run_for_file(
+    ctx: &Context,
+    path: &Path,
+    out_path: &Path,
+    deleted_path: &Path,
+    position: u16,
+    summary: Option<&Summary>,
+) -> Result<()> {
+    let config = ctx.config;
+
+    let name = path.file_name().ok_or_else(|| PooError::NoFileName(path.to_path_buf()))?.to_string_lossy().into_owned();
+
+    // every event of this file carries its name
+    let _span = info_span!("ingest", file = %name).entered();
+
+    let mut ti = TextItem::new();
+
+    // sink for comments by deleted authors or with removed bodies
+    let mut deleted =
+        match config.filter.deleted {
+            DeletedPolicy::Separate => Some(TextItem::new()),
+            _ => None,
+        };
+
+    let size = content_size(path) as usize;
+
+    let mut pb = progress_bar_at(position, &name, size, 1024, "B");
+
+    let compression = Compression::detect(path).ok().flatten().map_or("unknown", Compression::name);
+
+    info!(bytes = size, compression, "Loading");
+
+    let ckpt_path = checkpoint_path(out_path);
+
+    // bytes consumed from the decompressed stream, including unparseable lines
+    let mut offset = 0u64;
+    let mut lines = 0u64;
+    let mut errors = 0usize;
+
+    // sorted partial results flushed to disk once the state grows past `spill_threshold`
+    let mut runs = Vec::new();
+    // outputs already finished by `MemoryAction::Rotate`
+    let mut shards = 0usize;
+
+    if ctx.resume && ckpt_path.exists() {
+        // the checkpoint holds (decompressed offset, lines read, unparseable lines, partial state, deleted sink, spilled
+        // runs, shards)
+        match read_bincode::<(u64, u64, usize, TextItem, Option<TextItem>, Vec<PathBuf>, usize)>(&ckpt_path) {
+            Ok((ckpt_offset, ckpt_lines, ckpt_errors, state, deleted_state, ckpt_runs, ckpt_shards)) => {
+                info!(line = ckpt_lines, offset = ckpt_offset, "Resuming from the checkpoint");
+
+                offset = ckpt_offset;
+                lines = ckpt_lines;
+                errors = ckpt_errors;
+                ti = state;
+                deleted = deleted_state;
+                runs = ckpt_runs;
+                shards = ckpt_shards;
+            }
+            Err(e) => {
+                warn!(error = %e, "Ignoring unreadable checkpoint");
+            }
+        }
+    }
+
+    let errors_path = errors_path(out_path);
+
+    // lines past the checkpoint may be logged twice after --resume
+    let mut error_log =
+        match config.ingest.log_errors {
+            true => Some(open_error_log(&errors_path, lines > 0).map_err(PooError::write(&errors_path))?),
+            false => None,
+        };
+
+    ctx.metrics.start_file(&name, size as u64, offset, lines);
+
+    // what a resumed file skips counts as read
+    let mut summarized = offset;
+
+    if let Some(summary) = summary {
+        summary.advance(offset);
+    }
+
+    let mut reporter = ctx.progress.as_ref().map(|log| Reporter::new(log, &name, size as u64, offset));
+    let mut comments = 0u64;
+    let mut duplicates = 0u64;
+
+    info!("Processing");
+
+    let mut last_checkpoint = lines;
+    let mut last_prune = lines;
+
+    // this file's share of `ctx.memory`
+    let mut reported = 0u64;
+
+    let kind = config.ingest.kind.for_file(path);
+
+    let depth = config.ingest.pipeline_depth.max(1);
+
+    let (raw_tx, raw_rx) = sync_channel::<std::io::Result<RawBatch>>(depth);
+    let (parsed_tx, parsed_rx) = sync_channel::<std::io::Result<ParsedBatch>>(depth);
+
+    // decode -> parse/tokenize -> aggregate, each stage on its own thread
+    let processed = std::thread::scope(|scope| {
+        scope.spawn(move || read_batches(path, config, kind, offset, lines, raw_tx));
+
+        scope.spawn(move || {
+            // --max-errors counts the lines before the checkpoint too
+            let mut errors = errors;
+
+            // a resumed file has forgotten the bodies before the checkpoint
+            let mut dedup = Dedup::new(&config.dedup, config.ingest.metadata);
+
+            for batch in raw_rx {
+                let parsed = batch.map(|batch| parse_batch(ctx, kind, batch, &mut dedup));
+
+                let stop =
+                    match &parsed {
+                        Ok(parsed) => {
+                            errors += parsed.errors;
+                            config.ingest.on_max_errors.exceeded(errors, config.ingest.max_errors)
+                        }
+                        Err(_) => true,
+                    };
+
+                // dropping the receiver on return stops the reader as well
+                if parsed_tx.send(parsed).is_err() || stop {
+                    return;
+                }
+            }
+        });
+
+        for parsed in parsed_rx {
+            let parsed =
+                match parsed {
+                    Ok(parsed) => parsed,
+                    Err(e) => return Err(PooError::read(path)(e)),
+                };
+
+            ti.ingest(&parsed.state.freqs, counting(config));
+            ti.ingest_meta(&parsed.state.meta);
+            ti.ingest_stats(&parsed.state.stats);
+
+            if let (Some(sink), Some(batch)) = (deleted.as_mut(), &parsed.deleted) {
+                sink.ingest(&batch.freqs, counting(config));
+                sink.ingest_meta(&batch.meta);
+                sink.ingest_stats(&batch.stats);
+            }
+
+            offset = parsed.offset;
+            lines = parsed.lines;
+            errors += parsed.errors;
+
+            if let Some(log) = error_log.as_mut() {
+                for bad in parsed.bad.iter() {
+                    serde_json::to_writer(&mut *log, bad)
+                        .map_err(Error::other)
+                        .and_then(|_| log.write_all(b"\n"))
+                        .map_err(PooError::write(&errors_path))?;
+                }
+            }
+
+            pb.update_to(offset as usize);
+
+            if let Some(summary) = summary {
+                summary.advance(offset - summarized);
+                summarized = offset;
+            }
+
+            ctx.metrics.progress(&name, offset, lines, ti.word_freqs.len() as u64, parsed.errors as u64);
+
+            comments += parsed.comments as u64;
+            duplicates += parsed.duplicates as u64;
+
+            if let Some(reporter) = reporter.as_mut() {
+                reporter.update(offset, lines, comments, ti.word_freqs.len() as u64, errors, false);
+            }
+
+            match config.prune.min_word_count {
+                Some(min) if config.prune.min_word_count_lines > 0 && lines - last_prune >= config.prune.min_word_count_lines => {
+                    ti.word_freqs.forget_min_count(min);
+
+                    if let Some(sink) = deleted.as_mut() {
+                        sink.word_freqs.forget_min_count(min);
+                    }
+
+                    last_prune = lines;
+                }
+                _ => {}
+            }
+
+            if config.ingest.spill_threshold.is_some_and(|limit| ti.word_freqs.estimated_size() as u64 > limit.0) {
+                spill(&mut ti, &mut runs, out_path, config)?;
+            }
+
+            // the checkpoint must not refer to runs a rotation removed
+            let mut force_checkpoint = false;
+
+            let state_size = ti.word_freqs.estimated_size() as u64;
+            let total = ctx.memory.update(&mut reported, state_size);
+            ctx.metrics.set_memory(total);
+
+            if let Some(limit) = config.ingest.max_memory {
+                // at least one file holds this share whenever the total is over the limit
+                let share = limit.0 / config.ingest.jobs.max(1) as u64;
+
+                if total > limit.0 && state_size >= share {
+                    warn!(estimated = total, limit = limit.0, "Estimated state exceeds --max-memory");
+
+                    match config.ingest.on_max_memory {
+                        MemoryAction::Spill => spill(&mut ti, &mut runs, out_path, config)?,
+                        MemoryAction::Rotate => {
+                            let shard = shard_path(out_path, shards);
+
+                            info!(path = %shard.display(), "Rotating");
+
+                            flush(&mut ti, &mut runs, &shard, config, &mut pb)?;
+
+                            ti = TextItem::new();
+                            shards += 1;
+                            force_checkpoint = true;
+
+                            // saving took the bar over
+                            pb.reset(Some(size));
+                            pb.update_to(offset as usize);
+                        }
+                    }
+
+                    ctx.metrics.set_memory(ctx.memory.update(&mut reported, ti.word_freqs.estimated_size() as u64));
+                }
+            }
+
+            if config.ingest.on_max_errors.exceeded(errors, config.ingest.max_errors) {
+                if config.ingest.on_max_errors == ErrorPolicy::AbortRun {
+                    ctx.aborted.store(true, Ordering::SeqCst);
+                }
+
+                return Err(PooError::TooManyErrors { path: path.to_path_buf(), errors, line: lines });
+            }
+
+            if ctx.aborted.load(Ordering::SeqCst) {
+                return Err(PooError::Aborted);
+            }
+
+            if config.ingest.checkpoint_lines > 0
+                && (force_checkpoint || lines - last_checkpoint >= config.ingest.checkpoint_lines) {
+                info!(line = lines, offset, "Checkpointing");
+
+                if let Some(log) = error_log.as_mut() {
+                    log.flush().map_err(PooError::write(&errors_path))?;
+                }
+
+                if let Err(e) = write_bincode(&ckpt_path, &(offset, lines, errors, &ti, &deleted, &runs, shards), 1) {
+                    warn!(error = %e, "Could not write the checkpoint");
+                }
+
+                last_checkpoint = lines;
+            }
+        }
+
+        Ok(())
+    });
+
+    let authors = ti.word_freqs.len() as u64;
+
+    ctx.metrics.set_memory(ctx.memory.update(&mut reported, 0));
+    ctx.errors.fetch_add(errors, Ordering::SeqCst);
+
+    if let Some(mut log) = error_log {
+        log.flush().map_err(PooError::write(&errors_path))?;
+
+        // no log for clean dumps
+        if log.get_ref().metadata().is_ok_and(|m| m.len() == 0) {
+            let _ = std::fs::remove_file(&errors_path);
+        } else if errors > 0 {
+            warn!(errors, path = %errors_path.display(), "Logged unparseable lines");
+        }
+    }
+
+    // a failure keeps the checkpoint around so a later --resume can retry
+    processed?;
+
+    if let Some(sink) = &mut deleted {
+        info!(path = %deleted_path.display(), "Saving: Writing the deleted sink");
+
+        save(sink, &[], deleted_path, config, &mut pb)?;
+    }
+
+    flush(&mut ti, &mut runs, out_path, config, &mut pb)?;
+
+    if shards > 0 {
+        info!(shards, "Rotated, merge the parts with the output");
+    }
+
+    if ckpt_path.exists() {
+        let _ = std::fs::remove_file(&ckpt_path);
+    }
+
+    ctx.metrics.finish_file(&name);
+
+    if let Some(reporter) = reporter.as_mut() {
+        reporter.update(offset, lines, comments, authors, errors, true);
+    }
+
+    match config.dedup.mode {
+        DedupMode::Off => info!(lines, errors, "Finished"),
+        _ => info!(lines, errors, duplicates, "Finished"),
+    }
+
+    Ok(())
+}
+
+impl<'a> Context<'a> {
+    pub(crate) fn new(config: &'a Config, resume: bool, tokenizer: Box<dyn Tokenizer>, progress: Option<ProgressLog>) -> Result<Self> {
+        Ok(Self {
+            config,
+            tokenizer,
+            filter: Filter::from_config(&config.filter).map_err(|source| PooError::Load { what: "the author filter", source })?,
+            resume,
+            memory: MemoryTracker::default(),
+            errors: AtomicUsize::new(0),
+            aborted: AtomicBool::new(false),
+            metrics: Arc::new(Metrics::default()),
+            progress,
+            manifests: Manifests::default(),
+            force: false,
+        })
+    }
+}
+
+// the one the tokenizer, stopwords, markdown and language settings describe
+pub(crate) fn tokenizer(config: &Config) -> Result<Box<dyn Tokenizer>> {
+    Ok(Box::new(
+        Pipeline::from_config(&config.tokenizer, &config.stopwords, &config.markdown, &config.language)
+            .map_err(|source| PooError::Load { what: "the tokenizer settings", source })?
+    ))
+}
+
+pub fn run(args: &IngestArgs, config: &Config) -> Result<()> {
+    if args.dry_run {
+        return dry_run(config, &args.paths, args.force);
+    }
+
+    let mut ctx = Context::new(config, args.resume, tokenizer(config)?, ProgressLog::open(&config.ingest)?)?;
+    ctx.force = args.force;
+
+    run_inputs(&ctx, &args.paths)
+}
+
+// a dump, a url, an `api:` query or stdin, or the dumps of a directory the ingest settings pick
+pub(crate) fn list_dumps(path: &Path, config: &IngestConfig) -> Result<Vec<PathBuf>> {
+    if dump::is_stream(path) || path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let patterns = |globs: &[String]| {
+        globs.iter()
+            .map(|glob| Pattern::new(glob).map_err(|e| Error::new(ErrorKind::InvalidInput, format!("{}: {}", glob, e))))
+            .collect::<std::io::Result<Vec<_>>>()
+            .map_err(|source| PooError::Load { what: "the include and exclude globs", source })
+    };
+
+    list_files_matching(path, &Compression::EXTENSIONS, config.recursive, &patterns(&config.include)?, &patterns(&config.exclude)?)
+        .map_err(PooError::read(path))
+}
+
+// why an input is ingested
+#[derive(Debug, Clone, Copy)]
+enum Why {
+    New,
+    Interrupted,
+    Stale(&'static str),
+    Forced,
+}
+
+impl std::fmt::Display for Why {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::New => write!(f, "new"),
+            Self::Interrupted => write!(f, "interrupted"),
+            Self::Stale(reason) => write!(f, "redo, {}", reason),
+            Self::Forced => write!(f, "redo, forced"),
+        }
+    }
+}
+
+// an input of a run and where its outputs go
+struct Planned {
+    path: PathBuf,
+    freqs_path: PathBuf,
+    deleted_path: PathBuf,
+    source: String,
+    // none if the output is current
+    why: Option<Why>,
+}
+
+// every input of `paths` and whether it's ingested, without writing anything
+fn plan(config: &Config, manifests: &Manifests, paths: &[PathBuf], force: bool) -> Result<Vec<Planned>> {
+    let extension = config.output.format.extension();
+
+    let mut inputs = Vec::new();
+
+    for path in paths {
+        inputs.extend(list_dumps(path, &config.ingest)?);
+    }
+
+    // a dump named and in a named directory is ingested once, stdin can only be read once anyway
+    let mut seen = std::collections::HashSet::new();
+    inputs.retain(|path| seen.insert(path.clone()));
+
+    let config_hash = manifest::config_hash(config);
+
+    let mut planned = Vec::with_capacity(inputs.len());
+
+    for path in inputs {
+        // stdin is written to stdin.users.<ext>, urls after their file name
+        let name = dump::output_name(&path);
+
+        let freqs_path = output_path(&name, config.output.dir.as_deref(), &format!(".users.{}", extension));
+
+        let source =
+            manifest::source_hash(&path).unwrap_or_else(|e| {
+                warn!(file = %path.display(), error = %e, "Could not hash the input");
+                String::new()
+            });
+
+        // inputs that already have a current <name>.users.<ext> are skipped, stdin is new every time
+        let why =
+            match output_exists(&freqs_path, &config.output) && !dump::is_stdin(&path) {
+                true if force => Some(Why::Forced),
+                true => manifest::stale(manifests.get(&freqs_path).as_ref(), &source, &config_hash).map(Why::Stale),
+                false if tmp_path(&freqs_path).exists() => Some(Why::Interrupted),
+                false => Some(Why::New),
+            };
+
+        let deleted_path = output_path(&name, config.output.dir.as_deref(), &format!(".deleted.users.{}", extension));
+
+        planned.push(Planned { path, freqs_path, deleted_path, source, why });
+    }
+
+    Ok(planned)
+}
+
+// what a run would ingest or skip, with the sizes and how long the ingested files would take at the configured
+// throughput of a job
+fn dry_run(config: &Config, paths: &[PathBuf], force: bool) -> Result<()> {
+    let planned = plan(config, &Manifests::default(), paths, force)?;
+
+    let (mut compressed, mut decompressed, mut files, mut estimated) = (0, 0, 0, false);
+
+    for planned in &planned {
+        let path = &planned.path;
+
+        let (size, declared) =
+            match dump::is_stream(path) {
+                true => (None, None),
+                false => (std::fs::metadata(path).ok().map(|m| m.len()), Some(content_size(path))),
+            };
+
+        let size_text = size.map_or("?".to_string(), |size| ByteSize(size).to_string());
+
+        // unknown sizes are assumed to compress at ~8x, like for the memory budget
+        let declared_text =
+            match (declared, size) {
+                (Some(0) | None, Some(size)) => format!("~{}", ByteSize(size * 8)),
+                (Some(declared), _) => ByteSize(declared).to_string(),
+                (None, None) => "?".to_string(),
+            };
+
+        match planned.why {
+            Some(why) => {
+                let read =
+                    match declared {
+                        Some(0) | None => size.map(|size| size * 8),
+                        declared => declared,
+                    };
+
+                compressed += size.unwrap_or(0);
+                decompressed += read.unwrap_or(0);
+                estimated |= !matches!(declared, Some(d) if d > 0);
+                files += 1;
+
+                let eta = read.map_or("?".to_string(), |read| clock(read as f64 / config.ingest.throughput.0.max(1) as f64));
+
+                println!("ingest\t{}\t{}\t{}\t{}\t{}", path.display(), size_text, declared_text, eta, why);
+            }
+            None => println!("skip\t{}\t{}\t{}\t-\tcurrent", path.display(), size_text, declared_text),
+        }
+    }
+
+    // the jobs work through the files side by side
+    let jobs = config.ingest.jobs.max(1).min(files.max(1));
+    let eta = decompressed as f64 / (config.ingest.throughput.0.max(1) * jobs as u64) as f64;
+
+    println!(
+        "{} to ingest, {} to skip, {} compressed, {}{} decompressed, {} at {}/s with {} job{}",
+        files,
+        planned.len() - files,
+        ByteSize(compressed),
+        if estimated { "~" } else { "" },
+        ByteSize(decompressed),
+        clock(eta),
+        config.ingest.throughput,
+        jobs,
+        if jobs == 1 { "" } else { "s" },
+    );
+
+    Ok(())
+}
+
+// every dump in `paths`, which are dumps, directories of them, urls, `api:` queries or stdin
+pub(crate) fn run_inputs(ctx: &Context, paths: &[PathBuf]) -> Result<()> {
+    let config = ctx.config;
+
+    for dir in config.output.dir.iter().chain(config.ingest.spill_dir.iter()) {
+        std::fs::create_dir_all(dir).map_err(|source| PooError::CreateDir { path: dir.clone(), source })?;
+    }
+
+    if let Some(addr) = config.ingest.metrics_addr {
+        metrics::serve(addr, ctx.metrics.clone())?;
+    }
+
+    if !cfg!(feature = "parquet") && config.output.format == OutputFormat::Parquet {
+        return Err(PooError::Unsupported("Parquet output needs a build with the `parquet` feature"));
+    }
+
+    if !cfg!(feature = "duckdb") && config.output.format == OutputFormat::Duckdb {
+        return Err(PooError::Unsupported("DuckDB output needs a build with the `duckdb` feature"));
+    }
+
+    if config.sketch.enabled {
+        config.sketch.check().map_err(|source| PooError::Load { what: "the sketch settings", source })?;
+
+        if config.ingest.max_author_words.is_some() {
+            return Err(PooError::Unsupported("sketches and max_author_words are alternatives, set only one"));
+        }
+
+        let spills = config.ingest.max_memory.is_some() && config.ingest.on_max_memory == MemoryAction::Spill;
+
+        if config.ingest.spill_threshold.is_some() || spills {
+            return Err(PooError::Unsupported("sketches aren't spilled, they bound the memory of an author themselves"));
+        }
+    }
+
+    let mut work = Vec::new();
+
+    for planned in plan(config, &ctx.manifests, paths, ctx.force)? {
+        match planned.why {
+            None => continue,
+            Some(Why::Stale(reason)) => info!(path = %planned.freqs_path.display(), "Redoing the output, {}", reason),
+            Some(Why::Forced) => info!(path = %planned.freqs_path.display(), "Redoing the output"),
+            // a leftover .tmp is an interrupted write, it gets overwritten
+            Some(Why::Interrupted) => info!(path = %planned.freqs_path.display(), "Restarting interrupted output"),
+            Some(Why::New) => {}
+        }
+
+        work.push((planned.path, planned.freqs_path, planned.deleted_path, planned.source));
+    }
+
+    let config_hash = manifest::config_hash(config);
+    let total = work.len();
+    let ingested = AtomicUsize::new(0);
+
+    let jobs = config.ingest.jobs.max(1);
+
+    // only worth a row when there's more than one file
+    let summary =
+        (total > 1).then(|| {
+            let sizes = work.iter().map(|(path, _, _, _)| content_size(path)).collect::<Vec<_>>();
+            let size = if sizes.contains(&0) { 0 } else { sizes.iter().sum() };
+
+            Summary::new(jobs.min(total) as u16, total, size)
+        });
+
+    let budget = MemoryBudget::new(config.ingest.memory_budget.map(|v| v.0));
+    let queue = Mutex::new(work.into_iter());
+
+    // every job pulls files off the shared queue and draws its bar on its own row
+    std::thread::scope(|scope| {
+        for position in 0..jobs {
+            let (budget, queue, ingested, summary, config_hash) = (&budget, &queue, &ingested, summary.as_ref(), &config_hash);
+
+            scope.spawn(move || {
+                while !ctx.aborted.load(Ordering::SeqCst) {
+                    let next = queue.lock().unwrap().next();
+
+                    let (path, freqs_path, deleted_path, source) =
+                        match next {
+                            Some(next) => next,
+                            None => break,
+                        };
+
+                    // unknown sizes are assumed to compress at ~8x
+                    let estimate =
+                        match content_size(&path) {
+                            0 => std::fs::metadata(&path).map(|m| m.len() * 8).unwrap_or(0),
+                            size => size,
+                        };
+
+                    let _reservation = budget.reserve((estimate as f64 * MEMORY_ESTIMATE_RATIO) as u64);
+
+                    let record = |status| {
+                        if dump::is_stdin(&path) {
+                            return;
+                        }
+
+                        let totals = (status == Status::Complete).then(|| manifest::totals(&freqs_path)).flatten();
+
+                        if let Err(e) = ctx.manifests.set(&freqs_path, &path, &source, config_hash, status, totals) {
+                            warn!("Could not update the manifest: {}", e);
+                        }
+                    };
+
+                    record(Status::Started);
+
+                    // one bad dump doesn't stop the others
+                    match run_for_file(ctx, &path, &freqs_path, &deleted_path, position as u16, summary) {
+                        Ok(()) => {
+                            record(Status::Complete);
+                            ingested.fetch_add(1, Ordering::SeqCst);
+                        }
+                        Err(e) => {
+                            record(Status::Failed);
+                            error!(file = %path.display(), "{}", e);
+                        }
+                    }
+
+                    if let Some(summary) = summary {
+                        summary.finish_file();
+                    }
+                }
+            });
+        }
+    });
+
+    let (ingested, errors) = (ingested.into_inner(), ctx.errors.load(Ordering::SeqCst));
+
+    info!(ingested, inputs = total, unparseable = errors, "Finished the run");
+
+    // inputs never started after `ErrorPolicy::AbortRun` count as failed too
+    match total - ingested {
+        0 => Ok(()),
+        failed => Err(PooError::Failed { failed, total }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(year: i64, month: i64, day: i64, seconds: i64) -> Option<i64> {
+        Some(days_from_civil(year, month, day) * 86400 + seconds)
+    }
+
+    #[test]
+    fn weeks_are_iso() {
+        let weeks = [
+            // thursday and the sunday after it, still in the last week of 2020
+            ((2020, 12, 31), "2020-W53"),
+            ((2021, 1, 3), "2020-W53"),
+            ((2021, 1, 4), "2021-W01"),
+            // mondays whose thursday is in the next year
+            ((2019, 12, 30), "2020-W01"),
+            ((2018, 12, 31), "2019-W01"),
+            ((2024, 12, 30), "2025-W01"),
+            ((2016, 1, 3), "2015-W53"),
+            ((2020, 5, 10), "2020-W19"),
+            ((2020, 5, 11), "2020-W20"),
+            // before the epoch
+            ((1969, 12, 31), "1970-W01"),
+            ((1969, 12, 28), "1969-W52"),
+        ];
+
+        for ((year, month, day), week) in weeks {
+            for seconds in [0, 86399] {
+                assert_eq!(Period::Week.label(at(year, month, day, seconds)).as_deref(), Some(week), "{}-{}-{}", year, month, day);
+            }
+        }
+    }
+
+    fn parsed(config: &Config, lines: &[&str]) -> ParsedBatch {
+        let ctx = Context::new(config, false, tokenizer(config).unwrap(), None).unwrap();
+        let mut dedup = Dedup::new(&config.dedup, config.ingest.metadata);
+
+        let lines = lines.iter().map(|line| format!("{}\n", line).into_bytes()).collect::<Vec<_>>();
+        let offset = lines.iter().map(|line| line.len() as u64).sum();
+
+        let batch = RawBatch { line_count: lines.len() as u64, lines, offset };
+
+        parse_batch(&ctx, InputKind::Comments, batch, &mut dedup)
+    }
+
+    #[test]
+    fn deleted_authors_are_found_before_the_period_label() {
+        let lines = [
+            r#"{"author": "[deleted]", "body": "guitar chord riff", "created_utc": 1714521600}"#,
+            r#"{"author": "someone", "body": "guitar chord riff", "created_utc": 1714521600}"#,
+        ];
+
+        let mut config = Config::default();
+        config.ingest.period = Period::Month;
+        config.filter.deleted = DeletedPolicy::Skip;
+
+        let skipped = parsed(&config, &lines);
+
+        assert_eq!(skipped.state.freqs.keys().collect::<Vec<_>>(), [b"someone@2024-05"]);
+        assert!(skipped.deleted.is_none());
+
+        config.filter.deleted = DeletedPolicy::Separate;
+
+        let separated = parsed(&config, &lines);
+
+        assert_eq!(separated.state.freqs.keys().collect::<Vec<_>>(), [b"someone@2024-05"]);
+        assert_eq!(separated.deleted.unwrap().freqs.keys().collect::<Vec<_>>(), [b"[deleted]@2024-05"]);
+    }
+
+    #[test]
+    fn months_and_unknown() {
+        assert_eq!(Period::Month.label(at(2020, 12, 31, 86399)).as_deref(), Some("2020-12"));
+        assert_eq!(Period::Month.label(at(2021, 1, 1, 0)).as_deref(), Some("2021-01"));
+        assert_eq!(Period::Week.label(None).as_deref(), Some("unknown"));
+        assert_eq!(Period::None.label(at(2021, 1, 1, 0)), None);
+    }
+}
@@ -0,0 +1,275 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use kdam::RichProgress;
+use tracing::{error, info, warn};
+
+use crate::cli::MergeArgs;
+use crate::commands::{meta_path, progress_bar, read_bincode, read_freqs, sketch_path, stats_path, write_bincode, write_output};
+use crate::config::{Config, OutputConfig, OutputFormat};
+use crate::error::{PooError, Result};
+use crate::index::{BlockReader, FreqIndex};
+use crate::serializer::SortedFreqs;
+use crate::sketch::{word_hash, SketchMap};
+use crate::spill::{merge_records, RecordSource, RunRecord};
+use crate::text::text_item::{MetaMap, PooMap, PooMapInner, StatsMap, TextItem};
+
+pub enum Input {
+    // read block by block while merging
+    Indexed(PathBuf, FreqIndex),
+    // files written before the index existed are loaded whole
+    Loaded(PooMap),
+}
+
+impl Input {
+    pub fn open(path: &Path, pb: &mut RichProgress) -> Result<Self> {
+        let index =
+            File::open(path)
+                .and_then(|mut file| FreqIndex::read(&mut file))
+                .map_err(PooError::read(path))?;
+
+        match index {
+            Some(index) => Ok(Self::Indexed(path.to_path_buf(), index)),
+            None => Ok(Self::Loaded(read_freqs(path, pb)?)),
+        }
+    }
+
+    fn author_count(&self) -> usize {
+        match self {
+            Self::Indexed(_, index) => index.author_count() as usize,
+            Self::Loaded(freqs) => freqs.len(),
+        }
+    }
+
+    fn reader(&self) -> std::io::Result<InputReader<'_>> {
+        match self {
+            Self::Indexed(path, index) => Ok(InputReader::Blocks(index.reader(File::open(path)?))),
+            Self::Loaded(freqs) => {
+                let mut authors = freqs.iter().collect::<Vec<_>>();
+                authors.sort_unstable_by(|a, b| a.0.cmp(b.0));
+
+                Ok(InputReader::Loaded(authors.into_iter()))
+            }
+        }
+    }
+}
+
+pub enum InputReader<'a> {
+    Blocks(BlockReader<'a>),
+    Loaded(std::vec::IntoIter<(&'a Vec<u8>, &'a PooMapInner)>),
+}
+
+impl RecordSource for InputReader<'_> {
+    fn next(&mut self) -> std::io::Result<Option<RunRecord>> {
+        match self {
+            Self::Blocks(reader) => reader.next(),
+            Self::Loaded(authors) => {
+                Ok(authors.next().map(|(author, freqs)| {
+                    let mut words =
+                        freqs.iter()
+                            .map(|(word, freq)| (word.clone(), *freq))
+                            .collect::<Vec<_>>();
+
+                    words.sort_unstable();
+
+                    (author.clone(), words)
+                }))
+            }
+        }
+    }
+}
+
+// streaming k-way merge over the inputs' sorted authors
+pub struct MergedInputs {
+    pub inputs: Vec<Input>,
+}
+
+impl SortedFreqs for MergedInputs {
+    fn author_count(&self) -> usize {
+        self.inputs.iter().map(Input::author_count).sum()
+    }
+
+    fn for_each_sorted(
+        &self,
+        f: impl FnMut(&[u8], &[(&[u8], u64)]) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        let readers =
+            self.inputs.iter()
+                .map(Input::reader)
+                .collect::<std::io::Result<Vec<_>>>()?;
+
+        merge_records(readers, f)
+    }
+}
+
+// the unique words of a merged author are known once its words are, so they're counted on the way to the output.
+// those estimated in an input have the merged words added to their hyperloglog instead
+struct CountingUnique<'a, D: SortedFreqs> {
+    data: &'a D,
+    stats: RefCell<&'a mut StatsMap>,
+}
+
+impl<D: SortedFreqs> SortedFreqs for CountingUnique<'_, D> {
+    fn author_count(&self) -> usize {
+        self.data.author_count()
+    }
+
+    fn for_each_sorted(
+        &self,
+        mut f: impl FnMut(&[u8], &[(&[u8], u64)]) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        self.data.for_each_sorted(|author, freqs| {
+            if let Some(stats) = self.stats.borrow_mut().get_mut(author) {
+                stats.unique_words =
+                    match &mut stats.distinct {
+                        Some(distinct) => {
+                            freqs.iter().for_each(|(word, _)| distinct.insert(word_hash(word)));
+                            distinct.estimate()
+                        }
+                        None => freqs.len() as u64,
+                    };
+            }
+
+            f(author, freqs)
+        })
+    }
+}
+
+// adds the sketches of the input at `path` to `sketches`, returning the authors it sketched
+fn merge_sketches(sketches: &mut SketchMap, path: &Path) -> Result<BTreeSet<Vec<u8>>> {
+    let path = sketch_path(path);
+
+    if !path.exists() {
+        return Ok(BTreeSet::new());
+    }
+
+    let mut authors = BTreeSet::new();
+
+    for (author, sketch) in read_bincode::<SketchMap>(&path).map_err(PooError::read(&path))? {
+        match sketches.get_mut(&author) {
+            Some(merged) => merged.merge(&sketch).map_err(PooError::read(&path))?,
+            None => {
+                sketches.insert(author.clone(), sketch);
+            }
+        }
+
+        authors.insert(author);
+    }
+
+    Ok(authors)
+}
+
+// an author sketched in some of the inputs has all its words in the others, those are added to its sketch
+fn add_exact_words(sketches: &mut SketchMap, input: &Input, sketched: &BTreeSet<Vec<u8>>) -> std::io::Result<()> {
+    let mut reader = input.reader()?;
+
+    while let Some((author, words)) = reader.next()? {
+        if sketched.contains(&author) {
+            continue;
+        }
+
+        if let Some(sketch) = sketches.get_mut(&author) {
+            for (word, freq) in words {
+                sketch.add(word_hash(&word), freq);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn run(args: &MergeArgs, config: &Config) -> Result<()> {
+    let mut pb = progress_bar(0, 1000, "it");
+
+    let mut ti = TextItem::new();
+    let mut inputs = Vec::new();
+    let mut failed = 0;
+
+    // metadata and stats are only merged if every input has a sidecar
+    let mut with_meta = true;
+    let mut with_stats = true;
+
+    // the sketches of any input, and the authors sketched in each
+    let mut sketches = SketchMap::new();
+    let mut sketched = Vec::new();
+
+    for path in args.inputs.iter() {
+        match Input::open(path, &mut pb) {
+            Ok(input) => inputs.push(input),
+            Err(e) if args.skip_bad_inputs => {
+                error!(error = %e, "Skipping the input");
+                failed += 1;
+                continue;
+            }
+            // nothing has been written yet
+            Err(e) => return Err(e),
+        }
+
+        sketched.push((path, merge_sketches(&mut sketches, path)?));
+
+        if with_stats {
+            match read_bincode::<StatsMap>(&stats_path(path)) {
+                Ok(stats) => ti.ingest_stats(&stats),
+                Err(_) => with_stats = false,
+            }
+        }
+
+        if !with_meta {
+            continue;
+        }
+
+        match read_bincode::<MetaMap>(&meta_path(path)) {
+            Ok(meta) => ti.ingest_meta(&meta),
+            Err(_) => with_meta = false,
+        }
+    }
+
+    if inputs.is_empty() {
+        return Err(PooError::Failed { failed, total: args.inputs.len() });
+    }
+
+    if with_meta {
+        if let Err(e) = write_bincode(&meta_path(&args.output), &ti.meta, config.output.compression_level) {
+            warn!(error = %e, "Could not write the metadata");
+        }
+    }
+
+    let output =
+        OutputConfig {
+            format: OutputFormat::for_path(&args.output),
+            ..config.output.clone()
+        };
+
+    info!(inputs = inputs.len(), path = %args.output.display(), "Merging");
+
+    let merged = MergedInputs { inputs };
+
+    match with_stats {
+        true => write_output(&args.output, &CountingUnique { data: &merged, stats: RefCell::new(&mut ti.stats) }, &output, &mut pb)?,
+        false => write_output(&args.output, &merged, &output, &mut pb)?,
+    }
+
+    if !sketches.is_empty() {
+        for (input, (path, authors)) in merged.inputs.iter().zip(&sketched) {
+            add_exact_words(&mut sketches, input, authors).map_err(PooError::read(*path))?;
+        }
+
+        if let Err(e) = write_bincode(&sketch_path(&args.output), &sketches, config.output.compression_level) {
+            warn!(error = %e, "Could not write the sketches");
+        }
+    }
+
+    if with_stats {
+        if let Err(e) = write_bincode(&stats_path(&args.output), &ti.stats, config.output.compression_level) {
+            warn!(error = %e, "Could not write the stats");
+        }
+    }
+
+    // the skipped inputs still fail the run
+    match failed {
+        0 => Ok(()),
+        failed => Err(PooError::Failed { failed, total: args.inputs.len() }),
+    }
+}
@@ -0,0 +1,135 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use kdam::RichProgress;
+use tracing::{error, info};
+
+use crate::cli::MigrateArgs;
+use crate::commands::{list_files, output_path, progress_bar_at, read_freqs, write_freqs, MemoryBudget, Summary};
+use crate::config::{ByteSize, Config, OutputConfig};
+use crate::error::{PooError, Result};
+use crate::index::{self, FreqIndex};
+use crate::serializer::{format_version, FORMAT_VERSION};
+
+// a file is decoded whole, ~6x its compressed size, and its authors take about as much again once they are maps
+const MEMORY_PER_BYTE: u64 = 12;
+
+// false if the file is already in the current format, with an index
+fn run_for_file(path: &Path, out_path: &Path, output: &OutputConfig, pb: &mut RichProgress) -> Result<bool> {
+    let name = path.file_name().ok_or_else(|| PooError::NoFileName(path.to_path_buf()))?.to_string_lossy();
+
+    let mut file = File::open(path).map_err(PooError::read(path))?;
+
+    let version = format_version(&index::decode_head(&mut file, 11).map_err(PooError::read(path))?);
+    let indexed = FreqIndex::read(&mut file).map_err(PooError::read(path))?.is_some();
+
+    if version == Some(FORMAT_VERSION) && indexed {
+        info!(file = %name, version = FORMAT_VERSION, "Already current");
+        return Ok(false);
+    }
+
+    // the version picks the deserializer once the file is read
+    match version {
+        Some(version) => info!(file = %name, version, indexed, "Migrating"),
+        None => info!(file = %name, "Migrating a classic file"),
+    }
+
+    let poo = read_freqs(path, pb)?;
+
+    write_freqs(out_path, &poo, output, pb)?;
+
+    Ok(true)
+}
+
+// every job takes the next file off the shared queue once the memory budget has room for it, with a bar of its own
+// above the row of all files
+pub fn run(args: &MigrateArgs, config: &Config) -> Result<()> {
+    if let Some(dir) = &config.output.dir {
+        std::fs::create_dir_all(dir).map_err(|source| PooError::CreateDir { path: dir.clone(), source })?;
+    }
+
+    let files =
+        match args.path.is_file() {
+            true => vec![args.path.clone()],
+            false => list_files(&args.path, &["freqs"]).map_err(PooError::read(&args.path))?,
+        };
+
+    let sizes = files.iter().map(|path| std::fs::metadata(path).map_or(0, |m| m.len())).collect::<Vec<_>>();
+
+    let total = files.len();
+    let jobs =
+        args.jobs
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()))
+            .clamp(1, total.max(1));
+
+    // only worth a row when there's more than one file
+    let summary = (total > 1).then(|| Summary::new(jobs as u16, total, sizes.iter().sum()));
+
+    let budget = MemoryBudget::new(args.memory_budget.map(|v| v.0));
+    let queue = Mutex::new(files.iter().zip(sizes.iter().copied()));
+
+    let (failed, migrated) = (AtomicUsize::new(0), AtomicUsize::new(0));
+
+    std::thread::scope(|scope| {
+        for position in 0..jobs {
+            let (budget, queue, summary, failed, migrated) = (&budget, &queue, summary.as_ref(), &failed, &migrated);
+
+            scope.spawn(move || {
+                let mut pb = progress_bar_at(position as u16, "?", 0, 1000, "it");
+
+                loop {
+                    let Some((path, size)) = queue.lock().unwrap().next() else {
+                        break;
+                    };
+
+                    let estimate = size * MEMORY_PER_BYTE;
+
+                    let result =
+                        match args.max_file_memory {
+                            Some(limit) if estimate > limit.0 => {
+                                Err(PooError::TooLarge { path: path.clone(), estimated: ByteSize(estimate), limit })
+                            }
+                            _ => {
+                                let _reservation = budget.reserve(estimate);
+
+                                run_for_file(
+                                    path,
+                                    &output_path(path, config.output.dir.as_deref(), ".users.freqs.migrated"),
+                                    &config.output,
+                                    &mut pb,
+                                )
+                            }
+                        };
+
+                    // the remaining files are still migrated
+                    match result {
+                        Ok(true) => {
+                            migrated.fetch_add(1, Ordering::SeqCst);
+                        }
+                        Ok(false) => {}
+                        Err(e) => {
+                            error!(file = %path.display(), "{}", e);
+                            failed.fetch_add(1, Ordering::SeqCst);
+                        }
+                    }
+
+                    if let Some(summary) = summary {
+                        summary.advance(size);
+                        summary.finish_file();
+                    }
+                }
+            });
+        }
+    });
+
+    let (failed, migrated) = (failed.into_inner(), migrated.into_inner());
+
+    info!(migrated, current = total - migrated - failed, failed, "Finished migrating");
+
+    match failed {
+        0 => Ok(()),
+        failed => Err(PooError::Failed { failed, total }),
+    }
+}
@@ -0,0 +1,470 @@
+use std::fs::{DirEntry, File};
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use glob::Pattern;
+use kdam::{BarExt, Column, RichProgress, tqdm};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tracing::info;
+use twox_hash::XxHash64;
+
+use crate::config::{OutputConfig, OutputFormat, ShardKey};
+use crate::error::{PooError, Result};
+use crate::index::{self, BlockEncoder};
+use crate::serializer::{deserialize_any, serialize_draining, serialize_sharded, serialize_with_writer, SerializerFeedback, SortedFreqs};
+use crate::text::interner::FreqStore;
+use crate::text::text_item::PooMap;
+
+pub mod corpus;
+pub mod duplicates;
+pub mod elastic;
+pub mod embed;
+pub mod export;
+pub mod prune;
+pub mod segment;
+pub mod serve;
+pub mod similarity;
+pub mod tfidf;
+pub mod verify;
+pub mod ingest;
+pub mod merge;
+pub mod migrate;
+pub mod query;
+pub mod repair;
+pub mod stats;
+
+pub fn progress_bar(total: usize, unit_divisor: usize, unit: &str) -> RichProgress {
+    progress_bar_at(0, "?", total, unit_divisor, unit)
+}
+
+// a bar drawn `position` rows below the cursor, for concurrent jobs
+pub fn progress_bar_at(position: u16, label: &str, total: usize, unit_divisor: usize, unit: &str) -> RichProgress {
+    RichProgress::new(
+        tqdm!(
+            total = total,
+            unit_scale = true,
+            unit_divisor = unit_divisor,
+            unit = unit,
+            position = position,
+            disable = !crate::log::progress()
+        ),
+        vec![
+            Column::Spinner(
+                "⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏"
+                    .chars()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<String>>(),
+                80.0,
+                1.0,
+            ),
+            Column::text(&format!("[bold blue]{}", label)),
+            Column::Bar,
+            Column::Percentage(1),
+            Column::text("•"),
+            Column::CountTotal,
+            Column::text("•"),
+            Column::Rate,
+            Column::text("•"),
+            Column::RemainingTime,
+        ],
+    )
+}
+
+pub fn report(pb: &mut RichProgress, fb: SerializerFeedback) {
+    match fb {
+        SerializerFeedback::Message(msg) => {
+            info!("{}", msg);
+        }
+        SerializerFeedback::Total(total) => {
+            pb.reset(Some(total as usize));
+        }
+        SerializerFeedback::Progress(progress) => {
+            pb.update_to(progress as usize);
+        }
+    }
+}
+
+// hands out reservations against a shared byte budget, blocking while it's exhausted
+pub(crate) struct MemoryBudget {
+    limit: Option<u64>,
+    used: Mutex<u64>,
+    freed: Condvar,
+}
+
+pub(crate) struct Reservation<'a> {
+    budget: &'a MemoryBudget,
+    bytes: u64,
+}
+
+impl MemoryBudget {
+    pub(crate) fn new(limit: Option<u64>) -> Self {
+        Self {
+            limit,
+            used: Mutex::new(0),
+            freed: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn reserve(&self, bytes: u64) -> Reservation<'_> {
+        let mut used = self.used.lock().unwrap();
+
+        let bytes =
+            match self.limit {
+                Some(limit) => {
+                    let bytes = bytes.min(limit);
+
+                    // a job always runs if nothing else holds a reservation
+                    while *used > 0 && *used + bytes > limit {
+                        used = self.freed.wait(used).unwrap();
+                    }
+
+                    bytes
+                }
+                None => bytes,
+            };
+
+        *used += bytes;
+
+        Reservation {
+            budget: self,
+            bytes,
+        }
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        *self.budget.used.lock().unwrap() -= self.bytes;
+        self.budget.freed.notify_all();
+    }
+}
+
+// the row under the bars of the files, with the bytes of all of them and how many are done
+pub(crate) struct Summary {
+    pb: Mutex<RichProgress>,
+    files: usize,
+    done: AtomicUsize,
+}
+
+impl Summary {
+    // `size` is 0 if any of the files doesn't declare its own
+    pub(crate) fn new(position: u16, files: usize, size: u64) -> Self {
+        Self {
+            pb: Mutex::new(progress_bar_at(position, &format!("0/{} files", files), size as usize, 1024, "B")),
+            files,
+            done: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn advance(&self, bytes: u64) {
+        self.pb.lock().unwrap().update(bytes as usize);
+    }
+
+    // failed files count as done
+    pub(crate) fn finish_file(&self) {
+        let done = self.done.fetch_add(1, Ordering::SeqCst) + 1;
+
+        let mut pb = self.pb.lock().unwrap();
+        pb.replace(1, Column::text(&format!("[bold blue]{}/{} files", done, self.files)));
+        pb.refresh();
+    }
+}
+
+// list all files in `dir` with the given extension, sorted by name
+pub fn list_files(dir: &Path, extensions: &[&str]) -> std::io::Result<Vec<PathBuf>> {
+    let files = std::fs::read_dir(dir)?;
+
+    let mut files =
+        files
+            .filter_map(|f| f.ok())
+            .filter(|f| f.path().is_file() && !f.file_name().to_string_lossy().starts_with('.'))
+            .filter(|f| {
+                f.path()
+                    .extension()
+                    .map(|ext| extensions.iter().any(|v| ext == *v))
+                    .unwrap_or(extensions.contains(&""))
+            })
+            .collect::<Vec<DirEntry>>();
+
+    files.sort_by_key(|a| a.path().file_name().map(|n| n.to_os_string()));
+
+    Ok(
+        files
+            .iter()
+            .map(|f| f.path())
+            .collect()
+    )
+}
+
+fn subdirs(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    Ok(
+        std::fs::read_dir(dir)?
+            .filter_map(|f| f.ok())
+            .filter(|f| f.path().is_dir() && !f.file_name().to_string_lossy().starts_with('.'))
+            .map(|f| f.path())
+            .collect()
+    )
+}
+
+// `list_files` of `dir` and, if `recursive`, of its subdirectories but hidden ones. a pattern matches the file name,
+// or the path below `dir` if it has a `/`. files any `include` matches, all without one, are kept unless an
+// `exclude` matches them
+pub fn list_files_matching(
+    dir: &Path,
+    extensions: &[&str],
+    recursive: bool,
+    include: &[Pattern],
+    exclude: &[Pattern],
+) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = list_files(dir, extensions)?;
+
+    let mut dirs = if recursive { subdirs(dir)? } else { Vec::new() };
+
+    while let Some(sub) = dirs.pop() {
+        files.extend(list_files(&sub, extensions)?);
+        dirs.extend(subdirs(&sub)?);
+    }
+
+    let matches = |pattern: &Pattern, path: &Path| {
+        match pattern.as_str().contains('/') {
+            true => path.strip_prefix(dir).is_ok_and(|relative| pattern.matches_path(relative)),
+            false => path.file_name().is_some_and(|name| pattern.matches(&name.to_string_lossy())),
+        }
+    };
+
+    files.retain(|path| {
+        (include.is_empty() || include.iter().any(|pattern| matches(pattern, path)))
+            && !exclude.iter().any(|pattern| matches(pattern, path))
+    });
+
+    // by name like `list_files`, so the months of per-year folders stay in order
+    files.sort_by(|a, b| a.file_name().cmp(&b.file_name()).then_with(|| a.cmp(b)));
+
+    Ok(files)
+}
+
+#[cfg(feature = "parquet")]
+pub use crate::parquet_sink::write_parquet;
+
+#[cfg(not(feature = "parquet"))]
+pub fn write_parquet(_: &Path, _: &impl SortedFreqs, _: i32, _: &mut RichProgress) -> Result<()> {
+    Err(PooError::Unsupported("built without parquet support, enable the `parquet` feature"))
+}
+
+#[cfg(feature = "duckdb")]
+pub use crate::duckdb_sink::write_duckdb;
+
+#[cfg(not(feature = "duckdb"))]
+pub fn write_duckdb(_: &Path, _: &impl SortedFreqs, _: &mut RichProgress) -> Result<()> {
+    Err(PooError::Unsupported("built without duckdb support, enable the `duckdb` feature"))
+}
+
+// writes `data` in the given format
+pub fn write_output(path: &Path, data: &impl SortedFreqs, output: &OutputConfig, pb: &mut RichProgress) -> Result<()> {
+    match output.format {
+        OutputFormat::Freqs => write_freqs(path, data, output, pb),
+        OutputFormat::Parquet => write_parquet(path, data, output.compression_level, pb),
+        OutputFormat::Duckdb => write_duckdb(path, data, pb),
+    }
+}
+
+// <output_dir or input dir>/<input file name><suffix>
+pub fn output_path(input: &Path, output_dir: Option<&Path>, suffix: &str) -> PathBuf {
+    let name = format!(
+        "{}{}",
+        input.file_name().unwrap().to_str().unwrap(),
+        suffix,
+    );
+
+    match output_dir {
+        Some(dir) => dir.join(name),
+        None => input.with_file_name(name),
+    }
+}
+
+pub fn read_freqs(path: &Path, pb: &mut RichProgress) -> Result<PooMap> {
+    let mut file = File::open(path).map_err(PooError::read(path))?;
+
+    info!(path = %path.display(), "Reading");
+
+    let buf = index::decode_file(&mut file).map_err(PooError::read(path))?;
+
+    deserialize_any(&buf, |fb| report(pb, fb)).map_err(PooError::read(path))
+}
+
+// <path>.tmp, renamed over <path> once it has been written completely
+pub fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".tmp");
+
+    PathBuf::from(name)
+}
+
+// <name>.users.meta next to <name>.users.freqs or <name>.users.parquet
+pub fn meta_path(freqs_path: &Path) -> PathBuf {
+    match freqs_path.extension() {
+        Some(ext) if ext == "freqs" || ext == "parquet" => freqs_path.with_extension("meta"),
+        _ => {
+            let mut name = freqs_path.as_os_str().to_os_string();
+            name.push(".meta");
+
+            PathBuf::from(name)
+        }
+    }
+}
+
+// <name>.users.stats, like `meta_path`
+pub fn stats_path(freqs_path: &Path) -> PathBuf {
+    meta_path(freqs_path).with_extension("stats")
+}
+
+// <name>.users.sketch, like `meta_path`
+pub fn sketch_path(freqs_path: &Path) -> PathBuf {
+    meta_path(freqs_path).with_extension("sketch")
+}
+
+// zstd-compressed bincode, used for checkpoints and metadata sidecars
+pub fn write_bincode<T: Serialize>(path: &Path, data: &T, compression_level: i32) -> std::io::Result<()> {
+    let tmp = tmp_path(path);
+
+    let mut file = File::create(&tmp)?;
+    let mut encoder = zstd::stream::Encoder::new(&mut file, compression_level)?;
+
+    bincode::serialize_into(&mut encoder, data)
+        .map_err(std::io::Error::other)?;
+
+    encoder.finish()?;
+    file.sync_all()?;
+
+    std::fs::rename(&tmp, path)
+}
+
+pub fn read_bincode<T: DeserializeOwned>(path: &Path) -> std::io::Result<T> {
+    let decoder = zstd::stream::Decoder::new(File::open(path)?)?;
+
+    bincode::deserialize_from(decoder)
+        .map_err(std::io::Error::other)
+}
+
+// writes to a temporary file first so a crash never leaves a truncated `path` behind
+fn write_freqs_with(
+    path: &Path,
+    output: &OutputConfig,
+    pb: &mut RichProgress,
+    serialize: impl FnOnce(&mut BlockEncoder<&mut File>, &mut RichProgress) -> std::io::Result<()>,
+) -> Result<()> {
+    let tmp = tmp_path(path);
+
+    let mut file = File::create(&tmp).map_err(PooError::write(&tmp))?;
+
+    let mut encoder = BlockEncoder::new(&mut file, output).map_err(PooError::write(&tmp))?;
+
+    serialize(&mut encoder, pb).map_err(PooError::write(&tmp))?;
+
+    encoder.finish().and_then(|file| file.sync_all()).map_err(PooError::write(&tmp))?;
+
+    std::fs::rename(&tmp, path).map_err(PooError::write(path))
+}
+
+// <path>.00 to <path>.<shards - 1>, with as many digits as the last one needs
+pub fn author_shard_path(path: &Path, index: usize, shards: usize) -> PathBuf {
+    let width = (shards.saturating_sub(1)).to_string().len().max(2);
+
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{:0width$}", index, width = width));
+
+    PathBuf::from(name)
+}
+
+// whether `path` was written completely, all of its shards for a sharded output
+pub fn output_exists(path: &Path, output: &OutputConfig) -> bool {
+    match output.shards > 1 && output.format == OutputFormat::Freqs {
+        true => (0..output.shards).all(|i| author_shard_path(path, i, output.shards).exists()),
+        false => path.exists(),
+    }
+}
+
+// reddit names are of these, in byte order
+const NAME_BYTES: &[u8] = b"-0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ_abcdefghijklmnopqrstuvwxyz";
+
+// the shard of `author` with `ShardKey::Prefix`, from its first two bytes only so a lookup knows where to look.
+// the ranks keep the byte order so every shard is a range of names, a missing byte ranks below all others
+pub fn prefix_shard(author: &[u8], shards: usize) -> usize {
+    let base = NAME_BYTES.len() + 1;
+    let rank = |i: usize| author.get(i).map_or(0, |b| NAME_BYTES.partition_point(|c| c < b) + 1);
+
+    (rank(0) * base + rank(1)) * shards / (base * base)
+}
+
+// all shards are written side by side in one pass over the authors, then renamed one by one
+fn write_freqs_sharded(path: &Path, data: &impl SortedFreqs, output: &OutputConfig, pb: &mut RichProgress) -> Result<()> {
+    let shards = output.shards;
+
+    let paths = (0..shards).map(|i| author_shard_path(path, i, shards)).collect::<Vec<_>>();
+
+    let mut files =
+        paths.iter()
+            .map(|path| File::create(tmp_path(path)).map_err(PooError::write(tmp_path(path))))
+            .collect::<Result<Vec<_>>>()?;
+
+    let mut encoders =
+        files.iter_mut()
+            .map(|file| BlockEncoder::new(file, output))
+            .collect::<std::io::Result<Vec<_>>>()
+            .map_err(PooError::write(path))?;
+
+    let shard = |author: &[u8]| {
+        match output.shard_by {
+            ShardKey::Hash => {
+                let mut hasher = XxHash64::with_seed(0);
+                hasher.write(author);
+
+                (hasher.finish() % shards as u64) as usize
+            }
+            ShardKey::Prefix => prefix_shard(author, shards),
+        }
+    };
+
+    pb.reset(Some(data.author_count()));
+
+    serialize_sharded(data, &mut encoders, output.codec, shard, |fb| report(pb, fb)).map_err(PooError::write(path))?;
+
+    for (encoder, path) in encoders.into_iter().zip(paths.iter()) {
+        encoder.finish().and_then(|file| file.sync_all()).map_err(PooError::write(tmp_path(path)))?;
+    }
+
+    for path in paths.iter() {
+        std::fs::rename(tmp_path(path), path).map_err(PooError::write(path))?;
+    }
+
+    Ok(())
+}
+
+pub fn write_freqs(path: &Path, data: &impl SortedFreqs, output: &OutputConfig, pb: &mut RichProgress) -> Result<()> {
+    if output.shards > 1 {
+        return write_freqs_sharded(path, data, output, pb);
+    }
+
+    pb.reset(Some(data.author_count()));
+
+    write_freqs_with(path, output, pb, |encoder, pb| {
+        serialize_with_writer(data, encoder, output.codec, |fb| report(pb, fb))
+    })
+}
+
+// frees the store while writing, so peak memory doesn't double at the end of a run
+pub fn write_freqs_draining(path: &Path, data: FreqStore, output: &OutputConfig, pb: &mut RichProgress) -> Result<()> {
+    // each shard needs the authors in order, the store is kept until all are written
+    if output.shards > 1 {
+        return write_freqs_sharded(path, &data, output, pb);
+    }
+
+    pb.reset(Some(data.len()));
+
+    write_freqs_with(path, output, pb, |encoder, pb| {
+        serialize_draining(data, encoder, output.codec, |fb| report(pb, fb))
+    })
+}
@@ -0,0 +1,65 @@
+use tracing::{info, warn};
+
+use crate::cli::PruneFileArgs;
+use crate::commands::{meta_path, progress_bar, stats_path, write_output};
+use crate::commands::merge::{Input, MergedInputs};
+use crate::config::{Config, OutputConfig, OutputFormat};
+use crate::error::{PooError, Result};
+use crate::prune::{Pruned, Vocabulary};
+
+pub fn run(args: &PruneFileArgs, config: &Config) -> Result<()> {
+    let mut pb = progress_bar(0, 1000, "it");
+
+    if !config.prune.is_active() {
+        return Err(PooError::Unsupported("nothing to prune, pass --min-word-count, --min-total-count, --max-doc-freq or --top-words"));
+    }
+
+    let input = Input::open(&args.file, &mut pb)?;
+
+    // indexed files are streamed twice instead of being loaded
+    let data = MergedInputs { inputs: vec![input] };
+
+    let vocabulary =
+        match config.prune.limits_vocabulary() {
+            true => {
+                info!("Pruning: Counting words");
+
+                let vocabulary = Vocabulary::build(&data, &config.prune).map_err(PooError::read(&args.file))?;
+
+                info!(kept = vocabulary.len(), seen = vocabulary.seen(), "Pruning: Limiting the vocabulary");
+
+                Some(vocabulary)
+            }
+            false => None,
+        };
+
+    info!(path = %args.output.display(), "Pruning: Writing the output");
+
+    let output =
+        OutputConfig {
+            format: OutputFormat::for_path(&args.output),
+            ..config.output.clone()
+        };
+
+    let pruned =
+        Pruned {
+            data: &data,
+            min_word_count: config.prune.min_word_count.unwrap_or(0),
+            vocabulary: vocabulary.as_ref(),
+        };
+
+    write_output(&args.output, &pruned, &output, &mut pb)?;
+
+    // the metadata doesn't depend on the words, the stats are of the words before pruning
+    for sidecar in [meta_path, stats_path] {
+        let from = sidecar(&args.file);
+
+        if from.exists() && from != sidecar(&args.output) {
+            if let Err(e) = std::fs::copy(&from, sidecar(&args.output)) {
+                warn!(error = %e, path = %from.display(), "Could not copy the sidecar");
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use std::fs::File;
+use std::path::Path;
+
+use crate::cli::QueryArgs;
+use kdam::RichProgress;
+use tracing::info;
+
+use crate::commands::{meta_path, progress_bar, read_bincode, report, sketch_path, stats_path};
+use crate::error::{PooError, Result};
+use crate::index::{self, FreqIndex};
+use crate::serializer::find_author;
+use crate::sketch::{word_hash, SketchMap};
+use crate::text::text_item::{MetaMap, PooMapInner, StatsMap};
+
+// None if the file has no usable index
+fn find_indexed(path: &Path, author: &[u8]) -> Option<Option<PooMapInner>> {
+    let mut file = File::open(path).ok()?;
+    let index = FreqIndex::read(&mut file).ok()??;
+
+    index.lookup(&mut file, author).ok()
+}
+
+// decompresses the whole file but only decodes authors up to `author`
+fn find_scanning(path: &Path, author: &[u8], pb: &mut RichProgress) -> std::io::Result<Option<PooMapInner>> {
+    info!(path = %path.display(), "Reading: No index, scanning the file");
+
+    let buf = index::decode_file(&mut File::open(path)?)?;
+
+    find_author(&buf, author, |fb| report(pb, fb))
+}
+
+pub fn run(args: &QueryArgs) -> Result<()> {
+    let found =
+        match find_indexed(&args.file, args.author.as_bytes()) {
+            Some(found) => found,
+            None => {
+                let mut pb = progress_bar(0, 1000, "B");
+
+                find_scanning(&args.file, args.author.as_bytes(), &mut pb).map_err(PooError::read(&args.file))?
+            }
+        };
+
+    let freqs =
+        found.as_ref().ok_or_else(|| PooError::AuthorNotFound { path: args.file.clone(), author: args.author.clone() })?;
+
+    let total = freqs.values().sum::<u64>();
+
+    let mut words = freqs.iter().collect::<Vec<_>>();
+
+    words.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+    println!("{}: {} words, {} tokens", args.author, freqs.len(), total);
+
+    if let Ok(stats) = read_bincode::<StatsMap>(&stats_path(&args.file)) {
+        if let Some(stats) = stats.get(args.author.as_bytes()) {
+            println!(
+                "comments: {}, tokens: {}, unique words: {}, mean comment length: {:.1}",
+                stats.comments,
+                stats.tokens,
+                stats.unique_words,
+                stats.mean_length(),
+            );
+            println!(
+                "capitalized letters: {:.1}%, words in capitals: {:.1}%",
+                100.0 * stats.capitalization_rate(),
+                100.0 * stats.shouting_rate(),
+            );
+        }
+    }
+
+    if let Ok(meta) = read_bincode::<MetaMap>(&meta_path(&args.file)) {
+        if let Some(meta) = meta.get(args.author.as_bytes()) {
+            println!(
+                "comments: {}, score: {}, first seen: {}, last seen: {}",
+                meta.comment_count,
+                meta.score,
+                meta.first_seen.map_or("-".to_string(), |v| v.to_string()),
+                meta.last_seen.map_or("-".to_string(), |v| v.to_string()),
+            );
+            println!(
+                "subreddits: {}",
+                meta.subreddits.iter().cloned().collect::<Vec<_>>().join(", "),
+            );
+            println!(
+                "bot score: {:.2}, repeats: {}, templated: {}, signed: {}",
+                meta.bot_score,
+                meta.bot.repeats,
+                meta.bot.templated,
+                meta.bot.signed,
+            );
+        }
+    }
+
+    // the words of a sketched author are its heavy hitters, the others are only in the sketch
+    let sketch =
+        read_bincode::<SketchMap>(&sketch_path(&args.file))
+            .ok()
+            .and_then(|mut sketches| sketches.remove(args.author.as_bytes()));
+
+    if sketch.is_some() {
+        println!("sketched: the counts are estimates, of the {} most used words", freqs.len());
+    }
+
+    if !args.words.is_empty() {
+        for word in &args.words {
+            match (freqs.get(word.as_bytes()), &sketch) {
+                (Some(freq), _) => println!("{}\t{}", word, freq),
+                (None, Some(sketch)) => println!("{}\t{}\testimated", word, sketch.estimate(word_hash(word.as_bytes()))),
+                (None, None) => println!("{}\t0", word),
+            }
+        }
+
+        return Ok(());
+    }
+
+    for (word, freq) in words.iter().take(args.top) {
+        println!(
+            "{}\t{}\t{:.6}",
+            String::from_utf8_lossy(word),
+            freq,
+            **freq as f64 / total as f64,
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,230 @@
+use std::fs::File;
+use std::io::Write;
+
+use tracing::{info, warn};
+
+use crate::cli::RepairArgs;
+use crate::commands::{output_path, progress_bar, read_bincode, stats_path, tmp_path, write_freqs};
+use crate::config::Config;
+use crate::error::{PooError, Result};
+use crate::index::{self, FreqIndex};
+use crate::serializer::{self, Codec};
+use crate::text::text_item::{PooMap, StatsMap};
+
+// the complete authors of the salvaged frames and the counts of the trailer if it survived. the first frame names
+// the codec, classic files have no header and are all ragegun
+fn salvage(frames: &[Vec<u8>], mut codec: Codec) -> (PooMap, Option<(u64, u64)>) {
+    let mut trailer = None;
+    let mut salvaged = PooMap::default();
+
+    for (i, frame) in frames.iter().enumerate() {
+        let body =
+            match serializer::header_of(frame).filter(|_| i == 0) {
+                Some((len, named)) => {
+                    codec = named;
+                    &frame[len.min(frame.len())..]
+                }
+                None => frame.as_slice(),
+            };
+
+        if let Some(counts) = serializer::trailer_of(body, codec) {
+            trailer = Some(counts);
+            continue;
+        }
+
+        serializer::salvage(body, codec, &mut |author, freqs| {
+            salvaged.insert(author, freqs);
+        });
+    }
+
+    (salvaged, trailer)
+}
+
+// every frame is decompressed as far as it goes and the complete authors in it are kept, which of the others were
+// lost comes from the stats written next to the file on ingest, or else the index or trailer if they survived
+pub fn run(args: &RepairArgs, config: &Config) -> Result<()> {
+    let path = &args.file;
+
+    let mut file = File::open(path).map_err(PooError::read(path))?;
+
+    // a truncated file has lost its index with the end
+    let index = FreqIndex::read(&mut file).ok().flatten();
+
+    let frames = index::salvage_frames(&mut file).map_err(PooError::read(path))?;
+
+    let (salvaged, trailer) = salvage(&frames, index.as_ref().map_or(Codec::Ragegun, FreqIndex::codec));
+
+    info!(file = %path.display(), frames = frames.len(), authors = salvaged.len(), "Salvaged the intact authors");
+
+    if let Some(dir) = &config.output.dir {
+        std::fs::create_dir_all(dir).map_err(|source| PooError::CreateDir { path: dir.clone(), source })?;
+    }
+
+    let mut pb = progress_bar(0, 1000, "it");
+
+    let out_path = output_path(path, config.output.dir.as_deref(), ".repaired");
+
+    write_freqs(&out_path, &salvaged, &config.output, &mut pb)?;
+
+    // authors whose words were all pruned on ingest are in the stats but never were in the file
+    let lost =
+        match read_bincode::<StatsMap>(&stats_path(path)) {
+            Ok(stats) => stats.into_keys().filter(|author| !salvaged.contains_key(author)).collect::<Vec<_>>(),
+            Err(_) => {
+                match (&index, trailer) {
+                    (Some(index), _) => {
+                        let blocks = index.blocks().collect::<Vec<_>>();
+
+                        for (i, &(first, authors)) in blocks.iter().enumerate() {
+                            let next = blocks.get(i + 1).map(|&(next, _)| next);
+
+                            let kept =
+                                salvaged.keys()
+                                    .filter(|author| author.as_slice() >= first && next.is_none_or(|next| author.as_slice() < next))
+                                    .count() as u64;
+
+                            if kept < authors {
+                                warn!(first = %String::from_utf8_lossy(first), lost = authors - kept, of = authors, "Lost authors of a block");
+                            }
+                        }
+                    }
+                    (None, Some((authors, _))) => {
+                        warn!(lost = authors.saturating_sub(salvaged.len() as u64), "Lost authors, which ones is unknown without stats");
+                    }
+                    (None, None) => warn!("Without stats, index or trailer it is unknown how many authors were lost"),
+                }
+
+                Vec::new()
+            }
+        };
+
+    if !lost.is_empty() {
+        let lost_path = output_path(path, config.output.dir.as_deref(), ".lost");
+        let tmp = tmp_path(&lost_path);
+
+        let mut out = std::io::BufWriter::new(File::create(&tmp).map_err(PooError::write(&tmp))?);
+
+        for author in &lost {
+            out.write_all(author).and_then(|_| out.write_all(b"\n")).map_err(PooError::write(&tmp))?;
+        }
+
+        out.flush().map_err(PooError::write(&tmp))?;
+        drop(out);
+
+        std::fs::rename(&tmp, &lost_path).map_err(PooError::write(&lost_path))?;
+
+        warn!(lost = lost.len(), path = %lost_path.display(), "Listed the lost authors");
+    }
+
+    info!(path = %out_path.display(), authors = salvaged.len(), lost = lost.len(), "Repaired");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use super::*;
+    use crate::config::OutputConfig;
+
+    // enough authors for a few frames
+    fn fixture() -> PooMap {
+        (0..4_000u64)
+            .map(|i| {
+                let freqs = (0..40).map(|j| (format!("word{}", (i * 7 + j) % 500).into_bytes(), 1 + (i + j) % 300)).collect();
+
+                (format!("author{:05}", i).into_bytes(), freqs)
+            })
+            .collect()
+    }
+
+    // the compressed frames of a file, the index is the last
+    fn frames(data: &[u8]) -> Vec<Range<usize>> {
+        let mut frames = Vec::new();
+        let mut start = 0;
+
+        while start < data.len() {
+            let len = zstd::zstd_safe::find_frame_compressed_size(&data[start..]).unwrap();
+
+            frames.push(start..start + len);
+            start += len;
+        }
+
+        frames
+    }
+
+    fn repaired(data: &[u8], name: &str) -> (PooMap, Option<(u64, u64)>) {
+        let path = std::env::temp_dir().join(format!("poo-repair-{}-{}", std::process::id(), name));
+        std::fs::write(&path, data).unwrap();
+
+        let frames = index::salvage_frames(&mut File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        salvage(&frames, Codec::Ragegun)
+    }
+
+    fn written(data: &PooMap, name: &str) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!("poo-repair-{}-{}.users.freqs", std::process::id(), name));
+        write_freqs(&path, data, &OutputConfig::default(), &mut progress_bar(0, 1000, "it")).unwrap();
+
+        let file = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        file
+    }
+
+    #[test]
+    fn corrupt_frame_loses_its_authors_only() {
+        let data = fixture();
+        let file = written(&data, "corrupt");
+
+        // the authors, the trailer and the index
+        let frames = frames(&file);
+        assert!(frames.len() >= 5, "{} frames", frames.len());
+
+        let (intact, trailer) = repaired(&file, "intact");
+
+        assert_eq!(intact, data);
+        assert_eq!(trailer, Some((data.len() as u64, data.values().map(|freqs| freqs.len() as u64).sum())));
+
+        // the frame no longer starts with the magic, nothing of it can be decompressed
+        let mut corrupt = file.clone();
+        corrupt[frames[1].start..frames[1].start + 4].fill(0);
+
+        let (salvaged, trailer) = repaired(&corrupt, "corrupt");
+
+        let lost = data.keys().filter(|author| !salvaged.contains_key(*author)).collect::<Vec<_>>();
+        let mut in_frame = PooMap::default();
+
+        let frame = zstd::decode_all(&file[frames[1].clone()]).unwrap();
+        serializer::salvage(&frame, Codec::Ragegun, &mut |author, freqs| {
+            in_frame.insert(author, freqs);
+        });
+
+        assert!(!in_frame.is_empty());
+        assert_eq!(lost.len(), in_frame.len());
+        assert!(lost.iter().all(|author| in_frame.contains_key(*author)));
+        assert!(salvaged.iter().all(|(author, freqs)| data[author] == *freqs));
+        assert!(trailer.is_some());
+    }
+
+    #[test]
+    fn truncated_file_keeps_the_complete_authors() {
+        let data = fixture();
+        let file = written(&data, "truncated");
+
+        let frames = frames(&file);
+
+        // half of the last frame of authors is left, which zstd can't decode a block of
+        let last = &frames[frames.len() - 3];
+        let (salvaged, trailer) = repaired(&file[..last.start + last.len() / 2], "truncated");
+
+        let (before, _) = repaired(&file[..last.start], "before");
+
+        assert!(trailer.is_none());
+        assert!(!before.is_empty() && before.len() < data.len());
+        assert_eq!(salvaged, before);
+        assert!(salvaged.iter().all(|(author, freqs)| data[author] == *freqs));
+    }
+}
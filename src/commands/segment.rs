@@ -0,0 +1,199 @@
+use std::io::Write;
+
+use kdam::{BarExt, RichProgress};
+use rayon::prelude::*;
+use tracing::{error, info};
+
+use crate::cli::SegmentArgs;
+use crate::commands::{meta_path, progress_bar, read_bincode, read_freqs};
+use crate::commands::export::open_output;
+use crate::config::Config;
+use crate::error::{PooError, Result};
+use crate::segment::{top_variance, BotPolicy, DensitySegments, KMeans, SegmentMethod};
+use crate::serializer::SortedFreqs;
+use crate::text::text_item::MetaMap;
+use crate::weighting::{SparseVector, Tfidf};
+
+// words printed per segment
+const DESCRIBE_WORDS: usize = 10;
+
+// the segment of every author, the segments are printed with the words that describe them
+fn kmeans(vectors: &[SparseVector], tfidf: &Tfidf, config: &Config, pb: &mut RichProgress) -> Result<Vec<usize>> {
+    let segment = &config.segment;
+
+    info!(iterations = segment.iterations, batch_size = segment.batch_size, k = segment.k, "Segmenting: Running mini-batch k-means");
+
+    pb.reset(Some(segment.iterations));
+
+    let kmeans =
+        KMeans::fit(vectors, tfidf.dimensions(), segment, |iteration| {
+            pb.update_to(iteration);
+        });
+
+    if kmeans.is_empty() {
+        return Err(PooError::Unsupported("no author has any words within the document frequency bounds"));
+    }
+
+    let assignments =
+        vectors.par_iter()
+            .map(|vector| kmeans.assign(vector))
+            .collect::<Vec<_>>();
+
+    let mut sizes = vec![0usize; kmeans.len()];
+    let mut inertia = 0f64;
+
+    for &(segment, distance) in assignments.iter() {
+        sizes[segment] += 1;
+        inertia += distance;
+    }
+
+    println!("inertia: {:.4}", inertia);
+
+    for (i, size) in sizes.iter().enumerate() {
+        let words =
+            kmeans.top_dimensions(i, DESCRIBE_WORDS)
+                .into_iter()
+                .map(|(dim, _)| String::from_utf8_lossy(tfidf.word(dim)).into_owned())
+                .collect::<Vec<_>>();
+
+        println!("{}\t{}\t{}", i, size, words.join(" "));
+    }
+
+    Ok(assignments.into_iter().map(|(segment, _)| segment).collect())
+}
+
+// the weights of the authors for one or two words, the segments are printed with the weights of their peaks
+fn density(vectors: &[SparseVector], tfidf: &Tfidf, config: &Config) -> Result<Vec<usize>> {
+    let segment = &config.segment;
+
+    let dims =
+        match segment.dimensions.is_empty() {
+            true => top_variance(vectors, tfidf.dimensions(), 2),
+            false => {
+                segment.dimensions.iter()
+                    .map(|word| {
+                        tfidf.dimension(word.as_bytes()).ok_or_else(|| {
+                            error!(word = %word, "Not a word within the document frequency bounds");
+                            PooError::Unsupported("the --dimension words have to be within the document frequency bounds")
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?
+            }
+        };
+
+    if dims.is_empty() || dims.len() > 2 {
+        return Err(PooError::Unsupported("the density method needs one or two dimensions"));
+    }
+
+    let words = dims.iter().map(|&dim| String::from_utf8_lossy(tfidf.word(dim)).into_owned()).collect::<Vec<_>>();
+
+    info!(dimensions = %words.join(" "), bandwidth = segment.bandwidth, "Segmenting: Estimating the density");
+
+    // the vectors are in dimension order
+    let points =
+        vectors.par_iter()
+            .map(|vector| {
+                dims.iter()
+                    .map(|dim| vector.binary_search_by_key(dim, |(d, _)| *d).map_or(0.0, |i| vector[i].1 as f64))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+    let segments = DensitySegments::fit(&points, segment);
+
+    let assignments = points.par_iter().map(|point| segments.assign(point)).collect::<Vec<_>>();
+
+    let mut sizes = vec![0usize; segments.len()];
+
+    for &segment in assignments.iter() {
+        sizes[segment] += 1;
+    }
+
+    println!("segments: {}", segments.len());
+
+    for (i, size) in sizes.iter().enumerate() {
+        let mode =
+            words.iter()
+                .zip(segments.mode(i))
+                .map(|(word, weight)| format!("{}={:.4}", word, weight))
+                .collect::<Vec<_>>();
+
+        println!("{}\t{}\t{}", i, size, mode.join(" "));
+    }
+
+    Ok(assignments)
+}
+
+pub fn run(args: &SegmentArgs, config: &Config) -> Result<()> {
+    let mut pb = progress_bar(0, 1000, "B");
+
+    let poo = read_freqs(&args.file, &mut pb)?;
+
+    let tfidf = Tfidf::fit(&poo, &config.tfidf).map_err(PooError::read(&args.file))?;
+
+    info!(authors = tfidf.author_count(), dimensions = tfidf.dimensions(), "Segmenting");
+
+    let meta =
+        match config.segment.bots {
+            BotPolicy::Keep => MetaMap::new(),
+            // written by ingest with --metadata
+            _ => read_bincode::<MetaMap>(&meta_path(&args.file)).map_err(PooError::read(meta_path(&args.file)))?,
+        };
+
+    let mut authors = Vec::with_capacity(poo.len());
+    let mut vectors = Vec::with_capacity(poo.len());
+    let mut bots = Vec::new();
+
+    poo.for_each_sorted(|author, freqs| {
+        if meta.get(author).is_some_and(|meta| meta.bot_score >= config.segment.bot_threshold) {
+            bots.push(author.to_vec());
+            return Ok(());
+        }
+
+        authors.push(author.to_vec());
+        vectors.push(tfidf.vectorize(freqs));
+
+        Ok(())
+    })
+        .map_err(PooError::read(&args.file))?;
+
+    drop(poo);
+
+    if config.segment.bots != BotPolicy::Keep {
+        info!(bots = bots.len(), threshold = config.segment.bot_threshold, "Segmenting: Left out the likely bots");
+    }
+
+    let assignments =
+        match config.segment.method {
+            SegmentMethod::Kmeans => kmeans(&vectors, &tfidf, config, &mut pb)?,
+            SegmentMethod::Density => density(&vectors, &tfidf, config)?,
+        };
+
+    // <name>.users.segments.tsv next to <name>.users.freqs
+    let output =
+        args.output
+            .clone()
+            .unwrap_or_else(|| args.file.with_extension("segments.tsv"));
+
+    info!(path = %output.display(), "Segmenting: Writing the assignments");
+
+    open_output(&output)
+        .and_then(|mut out| {
+            out.write_all(b"author\tsegment\n")?;
+
+            for (author, segment) in authors.iter().zip(assignments.iter()) {
+                out.write_all(author)?;
+                writeln!(out, "\t{}", segment)?;
+            }
+
+            if config.segment.bots == BotPolicy::Separate {
+                for author in &bots {
+                    out.write_all(author)?;
+                    out.write_all(b"\tbots\n")?;
+                }
+            }
+
+            out.flush()
+        })
+        .map_err(PooError::write(&output))
+}
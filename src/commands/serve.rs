@@ -0,0 +1,460 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+
+use kdam::BarExt;
+use serde_json::{json, Value};
+use tracing::{debug, info};
+
+use crate::cli::ServeArgs;
+use crate::commands::merge::{Input, MergedInputs};
+use crate::commands::progress_bar;
+use crate::error::{PooError, Result};
+use crate::serializer::SortedFreqs;
+use crate::similarity::Metric;
+
+// all authors of the inputs in memory, with the words numbered and an inverted index from words to their authors
+pub(crate) struct Store {
+    // sorted, as they come out of the merge
+    authors: Vec<Vec<u8>>,
+    words: Vec<Vec<u8>>,
+    ids: HashMap<Vec<u8>, u32>,
+    // by author, sorted by word id
+    freqs: Vec<Vec<(u32, u64)>>,
+    norms: Vec<f64>,
+    // by word, (author, count) with the highest counts first
+    postings: Vec<Vec<(u32, u64)>>,
+}
+
+impl Store {
+    pub(crate) fn load(data: &impl SortedFreqs, mut progress: impl FnMut(usize)) -> std::io::Result<Self> {
+        let mut store = Self {
+            authors: Vec::with_capacity(data.author_count()),
+            words: Vec::new(),
+            ids: HashMap::new(),
+            freqs: Vec::with_capacity(data.author_count()),
+            norms: Vec::with_capacity(data.author_count()),
+            postings: Vec::new(),
+        };
+
+        data.for_each_sorted(|author, words| {
+            let id = store.authors.len() as u32;
+
+            let mut freqs =
+                words.iter()
+                    .map(|&(word, count)| {
+                        let word =
+                            match store.ids.get(word) {
+                                Some(&word) => word,
+                                None => {
+                                    let next = store.words.len() as u32;
+
+                                    store.ids.insert(word.to_vec(), next);
+                                    store.words.push(word.to_vec());
+                                    store.postings.push(Vec::new());
+
+                                    next
+                                }
+                            };
+
+                        store.postings[word as usize].push((id, count));
+
+                        (word, count)
+                    })
+                    .collect::<Vec<_>>();
+
+            freqs.sort_unstable();
+
+            store.norms.push(freqs.iter().map(|&(_, count)| (count as f64).powi(2)).sum::<f64>().sqrt());
+            store.freqs.push(freqs);
+            store.authors.push(author.to_vec());
+
+            progress(store.authors.len());
+
+            Ok(())
+        })?;
+
+        for postings in &mut store.postings {
+            postings.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        }
+
+        Ok(store)
+    }
+
+    pub(crate) fn authors(&self) -> usize {
+        self.authors.len()
+    }
+
+    pub(crate) fn words(&self) -> usize {
+        self.words.len()
+    }
+
+    pub(crate) fn author(&self, name: &[u8]) -> Option<u32> {
+        self.authors.binary_search_by(|author| author.as_slice().cmp(name)).ok().map(|author| author as u32)
+    }
+
+    pub(crate) fn name(&self, author: u32) -> String {
+        String::from_utf8_lossy(&self.authors[author as usize]).into_owned()
+    }
+
+    pub(crate) fn word(&self, id: u32) -> String {
+        String::from_utf8_lossy(&self.words[id as usize]).into_owned()
+    }
+
+    // distinct words of an author
+    pub(crate) fn len(&self, author: u32) -> usize {
+        self.freqs[author as usize].len()
+    }
+
+    pub(crate) fn tokens(&self, author: u32) -> u64 {
+        self.freqs[author as usize].iter().map(|&(_, count)| count).sum()
+    }
+
+    pub(crate) fn norm(&self, author: u32) -> f64 {
+        self.norms[author as usize]
+    }
+
+    // all words of an author, in byte order
+    pub(crate) fn vector(&self, author: u32) -> Vec<(u32, u64)> {
+        let mut words = self.freqs[author as usize].clone();
+        words.sort_unstable_by(|a, b| self.words[a.0 as usize].cmp(&self.words[b.0 as usize]));
+
+        words
+    }
+
+    // the most frequent words of an author, ties in byte order
+    pub(crate) fn top_words(&self, author: u32, top: usize) -> Vec<(u32, u64)> {
+        let mut words = self.vector(author);
+        words.sort_by_key(|&(_, count)| Reverse(count));
+        words.truncate(top);
+
+        words
+    }
+
+    // cosine over the counts, jaccard over the word sets and the words both use, the most used by the smaller count
+    // first
+    pub(crate) fn compare(&self, a: u32, b: u32) -> Comparison {
+        let (fa, fb) = (&self.freqs[a as usize], &self.freqs[b as usize]);
+
+        let mut shared = Vec::new();
+        let (mut i, mut j) = (0, 0);
+
+        while i < fa.len() && j < fb.len() {
+            match fa[i].0.cmp(&fb[j].0) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    shared.push((fa[i].0, fa[i].1, fb[j].1));
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+
+        let dot = shared.iter().map(|&(_, a, b)| a as f64 * b as f64).sum::<f64>();
+        let norms = self.norm(a) * self.norm(b);
+        let union = fa.len() + fb.len() - shared.len();
+
+        shared.sort_unstable_by(|a, b| {
+            b.1.min(b.2).cmp(&a.1.min(a.2)).then_with(|| self.words[a.0 as usize].cmp(&self.words[b.0 as usize]))
+        });
+
+        Comparison {
+            cosine: if norms > 0.0 { dot / norms } else { 0.0 },
+            jaccard: if union > 0 { shared.len() as f64 / union as f64 } else { 0.0 },
+            shared,
+        }
+    }
+
+    // the `top` other authors most alike to one, found through the authors of its words
+    pub(crate) fn similar(&self, author: u32, top: usize, metric: Metric) -> Vec<(u32, f64)> {
+        let mut scores = vec![0.0f64; self.authors.len()];
+        let mut touched = Vec::new();
+
+        for &(word, count) in &self.freqs[author as usize] {
+            for &(other, other_count) in &self.postings[word as usize] {
+                if scores[other as usize] == 0.0 {
+                    touched.push(other);
+                }
+
+                scores[other as usize] +=
+                    match metric {
+                        Metric::Cosine => count as f64 * other_count as f64,
+                        Metric::Jaccard => 1.0,
+                    };
+            }
+        }
+
+        let len = self.freqs[author as usize].len();
+
+        let mut similar =
+            touched.into_iter()
+                .filter(|&other| other != author)
+                .map(|other| {
+                    let score = scores[other as usize];
+
+                    let score =
+                        match metric {
+                            Metric::Cosine => score / (self.norm(author) * self.norm(other)),
+                            Metric::Jaccard => score / ((len + self.freqs[other as usize].len()) as f64 - score),
+                        };
+
+                    (other, score)
+                })
+                .collect::<Vec<_>>();
+
+        similar.sort_unstable_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        similar.truncate(top);
+
+        similar
+    }
+
+    // (author, count) of everyone using a word, the highest counts first
+    pub(crate) fn search(&self, word: &[u8]) -> Option<&[(u32, u64)]> {
+        Some(&self.postings[*self.ids.get(word)? as usize])
+    }
+}
+
+pub(crate) struct Comparison {
+    pub cosine: f64,
+    pub jaccard: f64,
+    // (word, count of a, count of b)
+    pub shared: Vec<(u32, u64, u64)>,
+}
+
+// `%xx` escapes and `+` for spaces
+fn decode(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escaped =
+            bytes.get(i + 1..i + 3)
+                .filter(|hex| bytes[i] == b'%' && hex.iter().all(u8::is_ascii_hexdigit))
+                .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+
+        match escaped {
+            Some(b) => {
+                out.push(b);
+                i += 3;
+            }
+            None => {
+                out.push(if bytes[i] == b'+' { b' ' } else { bytes[i] });
+                i += 1;
+            }
+        }
+    }
+
+    out
+}
+
+fn not_found(message: String) -> (&'static str, Value) {
+    ("404 Not Found", json!({ "error": message }))
+}
+
+fn bad_request(message: &str) -> (&'static str, Value) {
+    ("400 Bad Request", json!({ "error": message }))
+}
+
+fn find(store: &Store, name: &[u8]) -> std::result::Result<u32, (&'static str, Value)> {
+    store.author(name).ok_or_else(|| not_found(format!("author {} not found", String::from_utf8_lossy(name))))
+}
+
+fn route(store: &Store, target: &str, top: usize) -> std::result::Result<Value, (&'static str, Value)> {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let params =
+        query.split('&')
+            .filter_map(|param| param.split_once('='))
+            .map(|(key, value)| (key, decode(value)))
+            .collect::<HashMap<_, _>>();
+
+    let top =
+        match params.get("n").map(|n| String::from_utf8_lossy(n).parse::<usize>()) {
+            Some(Ok(n)) => n,
+            Some(Err(_)) => return Err(bad_request("n must be a number")),
+            None => top,
+        };
+
+    if let Some(name) = path.strip_prefix("/users/") {
+        let author = find(store, &decode(name))?;
+        let words = store.top_words(author, top);
+
+        return Ok(json!({
+            "author": store.name(author),
+            "words": store.len(author),
+            "tokens": store.tokens(author),
+            "top": words.iter().map(|&(word, count)| json!({ "word": store.word(word), "count": count })).collect::<Vec<_>>(),
+        }));
+    }
+
+    if let Some(name) = path.strip_prefix("/similar/") {
+        let author = find(store, &decode(name))?;
+
+        let metric =
+            match params.get("metric").map(Vec::as_slice) {
+                None | Some(b"cosine") => Metric::Cosine,
+                Some(b"jaccard") => Metric::Jaccard,
+                Some(_) => return Err(bad_request("metric must be cosine or jaccard")),
+            };
+
+        let similar = store.similar(author, top, metric);
+
+        return Ok(json!({
+            "author": store.name(author),
+            "similar": similar.iter().map(|&(other, score)| json!({ "author": store.name(other), "score": score })).collect::<Vec<_>>(),
+        }));
+    }
+
+    match path {
+        "/compare" => {
+            let (Some(a), Some(b)) = (params.get("a"), params.get("b")) else {
+                return Err(bad_request("expected /compare?a=<author>&b=<author>"));
+            };
+
+            let (a, b) = (find(store, a)?, find(store, b)?);
+            let comparison = store.compare(a, b);
+
+            Ok(json!({
+                "a": store.name(a),
+                "b": store.name(b),
+                "cosine": comparison.cosine,
+                "jaccard": comparison.jaccard,
+                "shared_words": comparison.shared.len(),
+                "shared":
+                    comparison.shared.iter()
+                        .take(top)
+                        .map(|&(word, a, b)| json!({ "word": store.word(word), "a": a, "b": b }))
+                        .collect::<Vec<_>>(),
+            }))
+        }
+        "/search" => {
+            let Some(word) = params.get("word") else {
+                return Err(bad_request("expected /search?word=<word>"));
+            };
+
+            let Some(postings) = store.search(word) else {
+                return Err(not_found(format!("no author uses {}", String::from_utf8_lossy(word))));
+            };
+
+            Ok(json!({
+                "word": String::from_utf8_lossy(word),
+                "authors": postings.len(),
+                "count": postings.iter().map(|&(_, count)| count).sum::<u64>(),
+                "top":
+                    postings.iter()
+                        .take(top)
+                        .map(|&(author, count)| json!({ "author": store.name(author), "count": count }))
+                        .collect::<Vec<_>>(),
+            }))
+        }
+        _ => Err(not_found(
+            "try /users/<author>, /similar/<author>, /compare?a=<author>&b=<author> or /search?word=<word>".to_string(),
+        )),
+    }
+}
+
+fn respond(stream: TcpStream, store: &Store, top: usize) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    let mut reader = BufReader::new(stream);
+
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+
+    // the headers don't matter
+    let mut header = String::new();
+
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+
+    let (status, body) =
+        match (parts.next(), parts.next()) {
+            (Some("GET"), Some(target)) => route(store, target, top).map(|body| ("200 OK", body)).unwrap_or_else(|e| e),
+            _ => ("405 Method Not Allowed", json!({ "error": "only GET requests are answered" })),
+        };
+
+    let body = body.to_string();
+
+    write!(
+        reader.get_mut(),
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body,
+    )
+}
+
+// the inputs are merged into memory once, then every connection is answered on a thread of its own until the
+// process is stopped. gRPC is answered next to http from the same authors
+pub fn run(args: &ServeArgs) -> Result<()> {
+    if !cfg!(feature = "grpc") && args.grpc_addr.is_some() {
+        return Err(PooError::Unsupported("gRPC needs a build with the `grpc` feature"));
+    }
+
+    let mut pb = progress_bar(0, 1000, "it");
+
+    let mut inputs = Vec::with_capacity(args.files.len());
+
+    for file in &args.files {
+        inputs.push(Input::open(file, &mut pb)?);
+    }
+
+    let data = MergedInputs { inputs };
+
+    info!(inputs = args.files.len(), authors = data.author_count(), "Serve: Loading the authors");
+    pb.reset(Some(data.author_count()));
+
+    let store =
+        Store::load(&data, |read| {
+            if read.is_multiple_of(1000) {
+                pb.update_to(read);
+            }
+        })
+            .map_err(PooError::Io)?;
+
+    drop(data);
+
+    let store = Arc::new(store);
+
+    #[cfg(feature = "grpc")]
+    if let Some(addr) = args.grpc_addr {
+        let (store, top) = (store.clone(), args.top);
+
+        std::thread::spawn(move || {
+            if let Err(e) = crate::grpc::serve(addr, store, top) {
+                tracing::error!("{}", e);
+                std::process::exit(1);
+            }
+        });
+    }
+
+    let listener = TcpListener::bind(args.addr).map_err(|source| PooError::Listen { addr: args.addr, source })?;
+
+    info!(
+        addr = %listener.local_addr().unwrap_or(args.addr),
+        authors = store.authors(),
+        words = store.words(),
+        "Serve: Answering queries",
+    );
+
+    for stream in listener.incoming().flatten() {
+        let store = store.clone();
+        let top = args.top;
+
+        std::thread::spawn(move || {
+            if let Err(e) = respond(stream, &store, top) {
+                debug!(error = %e, "Could not answer a request");
+            }
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use std::io::Write;
+
+use clap::ValueEnum;
+use rayon::prelude::*;
+use tracing::{info, warn};
+
+use crate::cli::SimilarityArgs;
+use crate::commands::{progress_bar, read_freqs};
+use crate::commands::export::open_output;
+use crate::config::Config;
+use crate::error::{PooError, Result};
+use crate::serializer::SortedFreqs;
+use crate::similarity::{Scratch, SimilarityIndex};
+use crate::text::interner::Interner;
+use crate::weighting::{SparseVector, Tfidf};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SimilarityWeights {
+    // the raw word counts
+    #[default]
+    Counts,
+    // see `[tfidf]` in the config
+    Tfidf,
+}
+
+// one author per line, `#` starts a comment
+fn read_authors_file(path: &std::path::Path) -> std::io::Result<Vec<String>> {
+    Ok(
+        std::fs::read_to_string(path)?
+            .lines()
+            .map(|v| v.split('#').next().unwrap_or("").trim())
+            .filter(|v| !v.is_empty())
+            .map(|v| v.strip_prefix("u/").unwrap_or(v).to_string())
+            .collect()
+    )
+}
+
+pub fn run(args: &SimilarityArgs, config: &Config) -> Result<()> {
+    let mut pb = progress_bar(0, 1000, "B");
+
+    let poo = read_freqs(&args.file, &mut pb)?;
+
+    // in byte order, so selected authors can be found by binary search
+    let mut authors = Vec::with_capacity(poo.len());
+    let mut vectors: Vec<SparseVector> = Vec::with_capacity(poo.len());
+
+    let dimensions =
+        match args.weights {
+            SimilarityWeights::Tfidf => {
+                let tfidf = Tfidf::fit(&poo, &config.tfidf).map_err(PooError::read(&args.file))?;
+
+                poo.for_each_sorted(|author, freqs| {
+                    authors.push(author.to_vec());
+                    vectors.push(tfidf.vectorize(freqs));
+
+                    Ok(())
+                })
+                    .map_err(PooError::read(&args.file))?;
+
+                tfidf.dimensions()
+            }
+            SimilarityWeights::Counts => {
+                let mut words = Interner::default();
+
+                poo.for_each_sorted(|author, freqs| {
+                    let mut vector =
+                        freqs.iter()
+                            .map(|&(word, freq)| (words.intern(word), freq as f32))
+                            .collect::<Vec<_>>();
+
+                    vector.sort_unstable_by_key(|(dim, _)| *dim);
+
+                    authors.push(author.to_vec());
+                    vectors.push(vector);
+
+                    Ok(())
+                })
+                    .map_err(PooError::read(&args.file))?;
+
+                words.len()
+            }
+        };
+
+    drop(poo);
+
+    let mut selected = args.authors.clone();
+
+    if let Some(path) = &args.authors_file {
+        selected.extend(read_authors_file(path).map_err(PooError::read(path))?);
+    }
+
+    // every author if none were selected
+    let queries =
+        if selected.is_empty() {
+            (0..authors.len()).collect::<Vec<_>>()
+        } else {
+            selected.iter()
+                .filter_map(|author| {
+                    let found = authors.binary_search_by(|v| v.as_slice().cmp(author.as_bytes())).ok();
+
+                    if found.is_none() {
+                        warn!(author = %author, "Author not found");
+                    }
+
+                    found
+                })
+                .collect()
+        };
+
+    info!(queries = queries.len(), authors = authors.len(), dimensions, "Similarity: Finding the nearest authors");
+
+    let index = SimilarityIndex::build(&vectors, dimensions);
+
+    let results =
+        queries.par_iter()
+            .map_init(
+                || Scratch::new(authors.len()),
+                |scratch, &query| index.top_k(query, &vectors[query], args.top, args.metric, scratch),
+            )
+            .collect::<Vec<_>>();
+
+    // <name>.users.similar.tsv next to <name>.users.freqs
+    let output =
+        args.output
+            .clone()
+            .unwrap_or_else(|| args.file.with_extension("similar.tsv"));
+
+    info!(path = %output.display(), "Similarity: Writing the neighbours");
+
+    open_output(&output)
+        .and_then(|mut out| {
+            out.write_all(b"author\tsimilar\tsimilarity\n")?;
+
+            for (&query, similar) in queries.iter().zip(results.iter()) {
+                for &(other, similarity) in similar {
+                    out.write_all(&authors[query])?;
+                    out.write_all(b"\t")?;
+                    out.write_all(&authors[other as usize])?;
+                    writeln!(out, "\t{:.6}", similarity)?;
+                }
+            }
+
+            out.flush()
+        })
+        .map_err(PooError::write(&output))
+}
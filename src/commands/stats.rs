@@ -0,0 +1,173 @@
+use kdam::BarExt;
+
+use crate::cli::StatsArgs;
+use crate::commands::merge::{Input, MergedInputs};
+use crate::commands::progress_bar;
+use crate::error::{PooError, Result};
+use crate::serializer::SortedFreqs;
+use crate::text::text_item::PooMapBase;
+
+// total count and the number of authors using it
+#[derive(Default)]
+struct WordStats {
+    count: u64,
+    authors: u64,
+}
+
+// (exponent, r²) of a least-squares line through log frequency over log rank, the exponent is about 1 for
+// natural language
+fn zipf_fit(counts: &[u64]) -> Option<(f64, f64)> {
+    if counts.len() < 2 {
+        return None;
+    }
+
+    let points =
+        counts.iter()
+            .enumerate()
+            .map(|(rank, &count)| (((rank + 1) as f64).ln(), (count as f64).ln()))
+            .collect::<Vec<_>>();
+
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+
+    let (mut cov, mut var_x, mut var_y) = (0.0, 0.0, 0.0);
+
+    for (x, y) in points {
+        cov += (x - mean_x) * (y - mean_y);
+        var_x += (x - mean_x).powi(2);
+        var_y += (y - mean_y).powi(2);
+    }
+
+    // all words equally frequent
+    if var_y == 0.0 {
+        return Some((0.0, 1.0));
+    }
+
+    Some((-cov / var_x, cov * cov / (var_x * var_y)))
+}
+
+// min, median, mean, p90, p99 and max of values sorted ascending
+fn distribution(label: &str, sorted: &[u64]) {
+    if sorted.is_empty() {
+        return;
+    }
+
+    let at = |q: f64| sorted[((sorted.len() - 1) as f64 * q).round() as usize];
+    let mean = sorted.iter().sum::<u64>() as f64 / sorted.len() as f64;
+
+    println!(
+        "{}: min {}, median {}, mean {:.1}, p90 {}, p99 {}, max {}",
+        label,
+        sorted[0],
+        at(0.5),
+        mean,
+        at(0.9),
+        at(0.99),
+        sorted[sorted.len() - 1],
+    );
+}
+
+// authors per power of two of their tokens
+fn histogram(sorted: &[u64]) {
+    println!("authors by tokens:");
+
+    let mut i = 0;
+    let mut low = 1u64;
+
+    while i < sorted.len() {
+        let high = low.saturating_mul(2) - 1;
+        let start = i;
+
+        while i < sorted.len() && sorted[i] <= high {
+            i += 1;
+        }
+
+        let range = if low == high { low.to_string() } else { format!("{}-{}", low, high) };
+        let count = i - start;
+
+        if count > 0 {
+            println!("  {}\t{}\t{:.2}%", range, count, count as f64 * 100.0 / sorted.len() as f64);
+        }
+
+        low = high.saturating_add(1);
+    }
+}
+
+// streams the authors of indexed files one block at a time, only the vocabulary is kept
+pub fn run(args: &StatsArgs) -> Result<()> {
+    let mut pb = progress_bar(0, 1000, "it");
+
+    let input = Input::open(&args.file, &mut pb)?;
+
+    let data = MergedInputs { inputs: vec![input] };
+
+    pb.reset(Some(data.author_count()));
+
+    let mut vocab = PooMapBase::<WordStats>::default();
+    let mut author_tokens = Vec::with_capacity(data.author_count());
+    let mut author_words = Vec::with_capacity(data.author_count());
+
+    let result =
+        data.for_each_sorted(|_, freqs| {
+            for &(word, freq) in freqs {
+                let stats =
+                    match vocab.get_mut(word) {
+                        Some(stats) => stats,
+                        None => vocab.entry(word.to_vec()).or_default(),
+                    };
+
+                stats.count += freq;
+                stats.authors += 1;
+            }
+
+            author_tokens.push(freqs.iter().map(|(_, freq)| freq).sum::<u64>());
+            author_words.push(freqs.len() as u64);
+
+            if author_tokens.len().is_multiple_of(1000) {
+                pb.update_to(author_tokens.len());
+            }
+
+            Ok(())
+        });
+
+    result.map_err(PooError::read(&args.file))?;
+
+    let total = author_tokens.iter().sum::<u64>();
+
+    let mut words = vocab.iter().collect::<Vec<_>>();
+
+    words.sort_by(|a, b| b.1.count.cmp(&a.1.count).then_with(|| a.0.cmp(b.0)));
+
+    author_tokens.sort_unstable();
+    author_words.sort_unstable();
+
+    let hapax = words.iter().filter(|(_, stats)| stats.count == 1).count();
+
+    println!("authors: {}", author_tokens.len());
+    println!("vocabulary: {}", vocab.len());
+    println!("tokens: {}", total);
+    println!("hapax legomena: {} ({:.2}% of the vocabulary)", hapax, hapax as f64 * 100.0 / vocab.len().max(1) as f64);
+
+    if let Some((exponent, r2)) = zipf_fit(&words.iter().map(|(_, stats)| stats.count).collect::<Vec<_>>()) {
+        println!("zipf exponent: {:.3} (r² {:.3})", exponent, r2);
+    }
+
+    distribution("tokens per author", &author_tokens);
+    distribution("words per author", &author_words);
+    histogram(&author_tokens);
+
+    println!("heavy hitters:");
+
+    for (word, stats) in words.iter().take(args.top) {
+        println!(
+            "{}\t{}\t{:.6}\t{}",
+            String::from_utf8_lossy(word),
+            stats.count,
+            stats.count as f64 / total as f64,
+            stats.authors,
+        );
+    }
+
+    Ok(())
+}
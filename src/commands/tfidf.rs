@@ -0,0 +1,67 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use serde::Serialize;
+use tracing::info;
+
+use crate::cli::TfidfArgs;
+use crate::commands::{progress_bar, read_freqs};
+use crate::commands::export::open_output;
+use crate::config::Config;
+use crate::error::{PooError, Result};
+use crate::serializer::SortedFreqs;
+use crate::weighting::Tfidf;
+
+#[derive(Serialize)]
+struct JsonWeights<'a> {
+    author: &'a str,
+    weights: BTreeMap<Cow<'a, str>, f64>,
+}
+
+fn write_weights(mut out: impl Write, data: &impl SortedFreqs, tfidf: &Tfidf, top: Option<usize>) -> std::io::Result<()> {
+    data.for_each_sorted(|author, freqs| {
+        let mut weights = tfidf.weigh(freqs);
+
+        if let Some(top) = top {
+            weights.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+            weights.truncate(top);
+        }
+
+        let row =
+            JsonWeights {
+                author: &String::from_utf8_lossy(author),
+                weights:
+                    weights.into_iter()
+                        .map(|(word, weight)| (String::from_utf8_lossy(word), weight))
+                        .collect(),
+            };
+
+        serde_json::to_writer(&mut out, &row)?;
+        out.write_all(b"\n")
+    })?;
+
+    out.flush()
+}
+
+pub fn run(args: &TfidfArgs, config: &Config) -> Result<()> {
+    let mut pb = progress_bar(0, 1000, "B");
+
+    let poo = read_freqs(&args.file, &mut pb)?;
+
+    let tfidf = Tfidf::fit(&poo, &config.tfidf).map_err(PooError::read(&args.file))?;
+
+    info!(authors = tfidf.author_count(), words = tfidf.dimensions(), "Weighting: Words within the document frequency bounds");
+
+    // <name>.users.tfidf.jsonl next to <name>.users.freqs
+    let output =
+        args.output
+            .clone()
+            .unwrap_or_else(|| args.file.with_extension("tfidf.jsonl"));
+
+    info!(path = %output.display(), "Weighting: Writing the weights");
+
+    open_output(&output)
+        .and_then(|out| write_weights(out, &poo, &tfidf, args.top))
+        .map_err(PooError::write(&output))
+}
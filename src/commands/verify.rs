@@ -0,0 +1,115 @@
+use std::fs::File;
+use std::path::Path;
+
+use kdam::RichProgress;
+use tracing::{error, info};
+
+use crate::cli::VerifyArgs;
+use crate::commands::{progress_bar, report};
+use crate::error::{PooError, Result};
+use crate::index::{self, FreqIndex};
+use crate::manifest::{Manifests, Status};
+use crate::serializer::{self, Verified};
+
+// an error reading the file is corruption too, the problems of the index and manifest are added to those of the
+// authors
+fn check(path: &Path, manifests: Option<&Manifests>, pb: &mut RichProgress) -> std::io::Result<Verified> {
+    let mut file = File::open(path)?;
+
+    let mut problems = Vec::new();
+
+    let indexed =
+        match FreqIndex::read(&mut file) {
+            Ok(Some(index)) => {
+                if !index.is_ordered() {
+                    problems.push("the blocks of the index are out of order".to_string());
+                }
+
+                Some(index.author_count())
+            }
+            Ok(None) => None,
+            Err(e) => {
+                problems.push(format!("the index is unreadable: {}", e));
+                None
+            }
+        };
+
+    let buf = index::decode_file(&mut File::open(path)?)?;
+
+    let mut verified = serializer::verify(&buf, |fb| report(pb, fb))?;
+
+    problems.append(&mut verified.problems);
+
+    if let Some(authors) = indexed.filter(|&authors| authors != verified.authors) {
+        problems.push(format!("the index has {} authors, the file {}", authors, verified.authors));
+    }
+
+    // outputs ingested before the manifest recorded totals are only checked for being complete
+    if let Some(manifests) = manifests {
+        match manifests.get(path) {
+            None => problems.push("it is not in the manifest".to_string()),
+            Some(entry) if entry.status != Status::Complete => {
+                problems.push(format!("the manifest has it as {:?}", entry.status).to_lowercase());
+            }
+            Some(entry) => {
+                let totals = [(entry.authors, verified.authors, "authors"), (entry.words, verified.words, "words")];
+
+                for (recorded, found, what) in totals {
+                    if let Some(recorded) = recorded.filter(|&recorded| recorded != found) {
+                        problems.push(format!("the manifest recorded {} {}, the file has {}", recorded, what, found));
+                    }
+                }
+            }
+        }
+    }
+
+    verified.problems = problems;
+
+    Ok(verified)
+}
+
+// every file is checked even once one of them turned out corrupt
+pub fn run(args: &VerifyArgs) -> Result<()> {
+    let mut pb = progress_bar(0, 1000, "B");
+
+    let manifests = args.manifest.then(Manifests::default);
+
+    let mut corrupt = 0;
+
+    for path in &args.files {
+        info!(file = %path.display(), "Verifying");
+
+        match check(path, manifests.as_ref(), &mut pb) {
+            Ok(verified) if verified.problems.is_empty() => {
+                info!(
+                    file = %path.display(),
+                    version = verified.version,
+                    authors = verified.authors,
+                    words = verified.words,
+                    tokens = verified.tokens,
+                    "Intact",
+                );
+            }
+            Ok(verified) => {
+                for problem in &verified.problems {
+                    error!(file = %path.display(), "{}", problem);
+                }
+
+                corrupt += 1;
+            }
+            Err(e) => {
+                error!(file = %path.display(), "{}", PooError::read(path)(e));
+                corrupt += 1;
+            }
+        }
+    }
+
+    if corrupt > 0 {
+        info!("`repair` salvages the intact authors of a corrupt file");
+    }
+
+    match corrupt {
+        0 => Ok(()),
+        failed => Err(PooError::Failed { failed, total: args.files.len() }),
+    }
+}
@@ -0,0 +1,339 @@
+use std::io::{Error, ErrorKind};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Deserializer};
+
+use crate::cli::OutputArgs;
+use crate::commands::ingest::{ErrorPolicy, InputKind, MemoryAction, Period};
+use crate::dedup::DedupConfig;
+use crate::filter::FilterConfig;
+use crate::progress::ProgressFormat;
+use crate::prune::PruneConfig;
+use crate::api::ApiConfig;
+use crate::dump::{ReadAheadIo, ZstdDecoder};
+use crate::embedding::EmbeddingConfig;
+use crate::segment::SegmentConfig;
+use crate::serializer::Codec;
+use crate::sketch::SketchConfig;
+use crate::text::{StopwordsConfig, TokenizerConfig};
+use crate::text::language::LanguageConfig;
+use crate::text::markdown::MarkdownConfig;
+use crate::weighting::TfidfConfig;
+
+pub const DEFAULT_CONFIG_PATH: &str = "segmenter.toml";
+
+// a size in bytes, parsed from a plain number or e.g. `512M`, `8G` (1024-based)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        let digits = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+
+        let (value, unit) = s.split_at(digits);
+
+        let value =
+            value.parse::<f64>()
+                .map_err(|_| format!("invalid size '{}'", s))?;
+
+        let multiplier =
+            match unit.trim().to_ascii_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+                "" => 1u64,
+                "K" => 1 << 10,
+                "M" => 1 << 20,
+                "G" => 1 << 30,
+                "T" => 1 << 40,
+                _ => return Err(format!("invalid size unit in '{}', expected K, M, G or T", s)),
+            };
+
+        Ok(Self((value * multiplier as f64) as u64))
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Int(u64),
+            Str(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Int(v) => Ok(Self(v)),
+            Raw::Str(v) => v.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+// in the largest unit that keeps it above 1, the way it's parsed
+impl std::fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (unit, shift) =
+            match self.0 {
+                v if v >= 1 << 40 => ("T", 40),
+                v if v >= 1 << 30 => ("G", 30),
+                v if v >= 1 << 20 => ("M", 20),
+                v if v >= 1 << 10 => ("K", 10),
+                v => return write!(f, "{}B", v),
+            };
+
+        write!(f, "{:.1}{}", self.0 as f64 / (1u64 << shift) as f64, unit)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub tokenizer: TokenizerConfig,
+    pub stopwords: StopwordsConfig,
+    pub markdown: MarkdownConfig,
+    pub language: LanguageConfig,
+    pub ingest: IngestConfig,
+    pub filter: FilterConfig,
+    pub dedup: DedupConfig,
+    pub sketch: SketchConfig,
+    pub api: ApiConfig,
+    pub output: OutputConfig,
+    pub prune: PruneConfig,
+    pub tfidf: TfidfConfig,
+    pub segment: SegmentConfig,
+    pub embedding: EmbeddingConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IngestConfig {
+    // number of lines parsed before each parallel aggregation step
+    pub per_iter: usize,
+    // unparseable or empty lines tolerated before `on_max_errors` kicks in
+    pub max_errors: usize,
+    pub on_max_errors: ErrorPolicy,
+    // write the offset, error and start of every unparseable line to <name>.errors.jsonl
+    pub log_errors: bool,
+    // bytes of a bad line kept in the log
+    pub error_sample: usize,
+    // lines between checkpoints of the partial state, 0 disables checkpointing
+    pub checkpoint_lines: u64,
+    // aggregate subreddit, created_utc, score and bot signals per author into <name>.users.meta
+    pub metadata: bool,
+    pub kind: InputKind,
+    // number of files processed concurrently
+    pub jobs: usize,
+    // estimated memory all concurrent files may use together
+    pub memory_budget: Option<ByteSize>,
+    // batches buffered between the decode, parse and aggregate stages
+    pub pipeline_depth: usize,
+    // estimated size of a file's state above which it is flushed to a sorted run on disk
+    pub spill_threshold: Option<ByteSize>,
+    // where runs go (defaults to next to the output)
+    pub spill_dir: Option<PathBuf>,
+    // words an author keeps in memory, the least counted make room for new ones, every spilled run has its own.
+    // the alternative to `sketch`
+    pub max_author_words: Option<usize>,
+    // estimated size of the state of all concurrent files before `on_max_memory` kicks in
+    pub max_memory: Option<ByteSize>,
+    pub on_max_memory: MemoryAction,
+    // split every author's counts by month or week of `created_utc`
+    pub period: Period,
+    // largest zstd window accepted, as a power of two, pushshift dumps use up to 31 (2 GiB)
+    pub window_log_max: u32,
+    pub decoder: ZstdDecoder,
+    // compressed input read ahead of decompression on its own thread, 0 reads it on the decoding thread
+    pub read_ahead: ByteSize,
+    // a thread per input or tokio tasks, the latter needs the `async-io` feature
+    pub read_ahead_io: ReadAheadIo,
+    // serve prometheus metrics on this address while ingesting
+    pub metrics_addr: Option<SocketAddr>,
+    pub progress: ProgressFormat,
+    // json progress goes to stderr without one, a named pipe works too
+    pub progress_file: Option<PathBuf>,
+    // seconds between progress lines or records of a file
+    pub progress_interval: u64,
+    // decompressed bytes a job reads per second, for the estimates of --dry-run
+    pub throughput: ByteSize,
+    // also list the dumps in subdirectories of an input directory
+    pub recursive: bool,
+    // globs on the names of listed dumps like `RC_2019-*.zst`, or on their path below the directory if they have a `/`
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl Default for IngestConfig {
+    fn default() -> Self {
+        Self {
+            per_iter: 10000,
+            max_errors: 10,
+            on_max_errors: ErrorPolicy::AbortFile,
+            log_errors: false,
+            error_sample: 200,
+            checkpoint_lines: 10_000_000,
+            metadata: false,
+            kind: InputKind::Auto,
+            jobs: 1,
+            memory_budget: None,
+            pipeline_depth: 4,
+            spill_threshold: None,
+            spill_dir: None,
+            max_author_words: None,
+            max_memory: None,
+            on_max_memory: MemoryAction::Spill,
+            period: Period::None,
+            window_log_max: 31,
+            decoder: ZstdDecoder::Libzstd,
+            read_ahead: ByteSize(16 << 20),
+            read_ahead_io: ReadAheadIo::Thread,
+            metrics_addr: None,
+            progress: ProgressFormat::Auto,
+            progress_file: None,
+            progress_interval: 30,
+            throughput: ByteSize(64 << 20),
+            recursive: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+// what ingest writes per input
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    // <name>.users.freqs in the ragegun format
+    #[default]
+    Freqs,
+    // <name>.users.parquet with author/word/count rows, needs the `parquet` feature
+    Parquet,
+    // <name>.users.duckdb with authors, vocab and freqs tables, needs the `duckdb` feature
+    Duckdb,
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Freqs => "freqs",
+            Self::Parquet => "parquet",
+            Self::Duckdb => "duckdb",
+        }
+    }
+
+    // merge picks the format from the name of its output
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext == "parquet" => Self::Parquet,
+            Some(ext) if ext == "duckdb" => Self::Duckdb,
+            _ => Self::Freqs,
+        }
+    }
+}
+
+// which shard of a `.freqs` output an author goes to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ShardKey {
+    // a hash of the name, shards of about the same size that don't depend on the other authors
+    #[default]
+    Hash,
+    // consecutive ranges of names by their first two characters, so the shard of a name is known without the others
+    Prefix,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub dir: Option<PathBuf>,
+    pub compression_level: i32,
+    // blocks of a `.freqs` file compressed at the same time
+    pub compression_threads: usize,
+    pub format: OutputFormat,
+    pub codec: Codec,
+    // compress `.freqs` with a dictionary trained on the authors, stored at the start of the file
+    pub dictionary: bool,
+    // split `.freqs` outputs into <name>.users.freqs.00 and so on, 1 writes a single file
+    pub shards: usize,
+    pub shard_by: ShardKey,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            compression_level: 10,
+            compression_threads: 1,
+            format: OutputFormat::Freqs,
+            codec: Codec::Ragegun,
+            dictionary: false,
+            shards: 1,
+            shard_by: ShardKey::Hash,
+        }
+    }
+}
+
+impl OutputConfig {
+    // command line flags take precedence over the config file
+    pub fn apply(&mut self, args: &OutputArgs) {
+        if let Some(dir) = &args.output_dir {
+            self.dir = Some(dir.clone());
+        }
+
+        if let Some(level) = args.compression_level {
+            self.compression_level = level;
+        }
+
+        if let Some(threads) = args.compression_threads {
+            self.compression_threads = threads;
+        }
+
+        if let Some(format) = args.format {
+            self.format = format;
+        }
+
+        if let Some(codec) = args.codec {
+            self.codec = codec;
+        }
+
+        self.dictionary |= args.dictionary;
+
+        if let Some(shards) = args.shards {
+            self.shards = shards;
+        }
+
+        if let Some(key) = args.shard_by {
+            self.shard_by = key;
+        }
+    }
+}
+
+impl Config {
+    // an explicitly given path must exist, the default path is optional
+    pub fn load(path: Option<&Path>) -> std::io::Result<Self> {
+        let path =
+            match path {
+                Some(path) => path.to_path_buf(),
+                None => {
+                    let path = PathBuf::from(DEFAULT_CONFIG_PATH);
+
+                    if !path.exists() {
+                        return Ok(Self::default());
+                    }
+
+                    path
+                }
+            };
+
+        toml::from_str(&std::fs::read_to_string(&path)?)
+            .map_err(|e|
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), e),
+                )
+            )
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::hash::Hasher;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use twox_hash::XxHash64;
+
+// what happens to a comment whose body its author posted before
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupMode {
+    #[default]
+    Off,
+    // only the first one is counted
+    Once,
+    // a `weight` of the repeats is counted, in full so the counts stay whole
+    Downweight,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DedupConfig {
+    pub mode: DedupMode,
+    // of the repeats counted with `downweight`, 0.1 counts every tenth
+    pub weight: f64,
+    // letters and digits below which a body is left alone, people say "thanks!" again without copying anything
+    pub min_chars: usize,
+    // (author, body) pairs remembered per file, the least recently seen are forgotten first
+    pub history: usize,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        Self {
+            mode: DedupMode::Off,
+            weight: 0.1,
+            min_chars: 20,
+            history: 1_000_000,
+        }
+    }
+}
+
+// what `Dedup` makes of a comment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Seen {
+    First,
+    // a repeat that is counted anyway
+    Counted,
+    Dropped,
+}
+
+// keys seen recently with how often they were repeated. two generations approximate an lru: a hit in the old one
+// moves to the new one, which becomes the old one once it's full
+struct Recent {
+    capacity: usize,
+    current: HashMap<u64, u64>,
+    previous: HashMap<u64, u64>,
+}
+
+impl Recent {
+    fn new(history: usize) -> Self {
+        Self {
+            capacity: (history / 2).max(1),
+            current: HashMap::new(),
+            previous: HashMap::new(),
+        }
+    }
+
+    // the repeats before this one
+    fn bump(&mut self, key: u64) -> u64 {
+        let repeats =
+            match self.current.get(&key).copied().or_else(|| self.previous.remove(&key)) {
+                Some(repeats) => repeats + 1,
+                None => 0,
+            };
+
+        if !self.current.contains_key(&key) && self.current.len() >= self.capacity {
+            self.previous = std::mem::take(&mut self.current);
+        }
+
+        self.current.insert(key, repeats);
+
+        repeats
+    }
+}
+
+// the author and the lowercased characters of the text that `keep` keeps, so a copypasta with a changed comma or line
+// break is still the same one
+fn hash_text<'a>(author: &str, texts: impl IntoIterator<Item = &'a str>, keep: fn(&char) -> bool) -> (u64, usize) {
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(author.as_bytes());
+
+    let mut chars = 0;
+
+    for text in texts {
+        hasher.write_u8(0xff);
+
+        for c in text.chars().filter(keep).flat_map(char::to_lowercase) {
+            hasher.write_u32(c as u32);
+            chars += 1;
+        }
+    }
+
+    (hasher.finish(), chars)
+}
+
+// the bodies and templates authors posted recently. the metadata wants the repeats even when they are all counted
+pub struct Dedup {
+    config: DedupConfig,
+    track: bool,
+    bodies: Recent,
+    // the first and last line of bodies with something between them
+    templates: Recent,
+}
+
+impl Dedup {
+    pub fn new(config: &DedupConfig, track: bool) -> Self {
+        Self {
+            config: config.clone(),
+            track: track || config.mode != DedupMode::Off,
+            bodies: Recent::new(config.history),
+            templates: Recent::new(config.history),
+        }
+    }
+
+    pub fn check(&mut self, author: &str, body: &str) -> Seen {
+        if !self.track {
+            return Seen::First;
+        }
+
+        let (key, chars) = hash_text(author, [body], |c| c.is_alphanumeric());
+
+        if chars < self.config.min_chars {
+            return Seen::First;
+        }
+
+        let repeats = self.bodies.bump(key);
+
+        // the n-th repeat is counted whenever n * weight passes a whole number
+        let weight = self.config.weight.clamp(0.0, 1.0);
+
+        match (repeats, self.config.mode) {
+            (0, _) => Seen::First,
+            (_, DedupMode::Off) => Seen::Counted,
+            (n, DedupMode::Downweight) if (n as f64 * weight).floor() > ((n - 1) as f64 * weight).floor() => Seen::Counted,
+            _ => Seen::Dropped,
+        }
+    }
+
+    // whether the author opened and closed an earlier body the same way, only meaningful for bodies that aren't
+    // repeats themselves
+    pub fn templated(&mut self, author: &str, body: &str) -> bool {
+        if !self.track {
+            return false;
+        }
+
+        let mut lines = body.lines().map(str::trim).filter(|line| !line.is_empty());
+
+        let (first, last) =
+            match (lines.next(), lines.next_back()) {
+                (Some(first), Some(last)) => (first, last),
+                _ => return false,
+            };
+
+        // without the digits, which is where templates put the numbers they fill in
+        let (key, chars) = hash_text(author, [first, last], |c| c.is_alphabetic());
+
+        chars >= self.config.min_chars && self.templates.bump(key) > 0
+    }
+}
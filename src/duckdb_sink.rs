@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::io::Error;
+use std::path::Path;
+
+use duckdb::{params, Connection};
+use kdam::{BarExt, RichProgress};
+use tracing::info;
+
+use crate::commands::tmp_path;
+use crate::error::{PooError, Result};
+use crate::serializer::SortedFreqs;
+
+// authors and words are numbered in the order they come, `freqs` refers to both by id, `users` joins them back
+const SCHEMA: &str = "
+    CREATE TABLE authors (id INTEGER PRIMARY KEY, author VARCHAR NOT NULL);
+    CREATE TABLE vocab (id INTEGER PRIMARY KEY, word VARCHAR NOT NULL, count BIGINT NOT NULL, authors BIGINT NOT NULL);
+    CREATE TABLE freqs (author_id INTEGER NOT NULL, word_id INTEGER NOT NULL, count BIGINT NOT NULL);
+    CREATE VIEW users AS
+        SELECT a.author, v.word, f.count
+        FROM freqs f JOIN authors a ON a.id = f.author_id JOIN vocab v ON v.id = f.word_id;
+";
+
+// total count and the number of authors of a word
+struct Word {
+    id: u32,
+    count: u64,
+    authors: u64,
+}
+
+fn write(connection: &Connection, data: &impl SortedFreqs, pb: &mut RichProgress) -> duckdb::Result<()> {
+    connection.execute_batch(SCHEMA)?;
+
+    let mut vocab = HashMap::<Vec<u8>, Word>::new();
+
+    {
+        let mut authors = connection.appender("authors")?;
+        let mut freqs = connection.appender("freqs")?;
+
+        let mut i = 0u32;
+
+        data.for_each_sorted(|author, words| {
+            authors.append_row(params![i, String::from_utf8_lossy(author)]).map_err(Error::other)?;
+
+            for &(word, count) in words {
+                let next = vocab.len() as u32;
+
+                let word =
+                    match vocab.get_mut(word) {
+                        Some(word) => word,
+                        None => vocab.entry(word.to_vec()).or_insert(Word { id: next, count: 0, authors: 0 }),
+                    };
+
+                word.count += count;
+                word.authors += 1;
+
+                freqs.append_row(params![i, word.id, count as i64]).map_err(Error::other)?;
+            }
+
+            i += 1;
+
+            if i.is_multiple_of(1000) {
+                pb.update_to(i as usize);
+            }
+
+            Ok(())
+        })
+            .map_err(|e| duckdb::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+        authors.flush()?;
+        freqs.flush()?;
+    }
+
+    let mut words = vocab.into_iter().collect::<Vec<_>>();
+    words.sort_unstable_by_key(|(_, word)| word.id);
+
+    let mut appender = connection.appender("vocab")?;
+
+    for (word, stats) in words {
+        appender.append_row(params![stats.id, String::from_utf8_lossy(&word), stats.count as i64, stats.authors as i64])?;
+    }
+
+    appender.flush()
+}
+
+// a database with `authors`, `vocab` and `freqs` tables to open with duckdb, written via a temporary file like
+// `write_freqs`
+pub fn write_duckdb(path: &Path, data: &impl SortedFreqs, pb: &mut RichProgress) -> Result<()> {
+    let tmp = tmp_path(path);
+
+    // duckdb opens an existing file instead of replacing it
+    if tmp.exists() {
+        std::fs::remove_file(&tmp).map_err(PooError::write(&tmp))?;
+    }
+
+    let connection = Connection::open(&tmp).map_err(|e| PooError::Write { path: tmp.clone(), source: Error::other(e) })?;
+
+    info!(path = %path.display(), "Saving: Writing duckdb tables");
+    pb.reset(Some(data.author_count()));
+
+    write(&connection, data, pb).map_err(|e| PooError::Write { path: tmp.clone(), source: Error::other(e) })?;
+
+    // the tables are only complete in the file once it's closed
+    connection.close().map_err(|(_, e)| PooError::Write { path: tmp.clone(), source: Error::other(e) })?;
+
+    std::fs::rename(&tmp, path).map_err(PooError::write(path))
+}
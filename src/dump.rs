@@ -0,0 +1,556 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Error, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{sync_channel, Receiver};
+
+use bzip2::read::MultiBzDecoder;
+use clap::ValueEnum;
+use flate2::read::MultiGzDecoder;
+use memchr::memchr;
+use ruzstd::frame_decoder::FrameDecoderError;
+use ruzstd::{BlockDecodingStrategy, FrameDecoder};
+use serde::Deserialize;
+use xz2::read::XzDecoder;
+
+use crate::api::{self, ApiReader};
+use crate::config::{Config, IngestConfig};
+use crate::remote::{self, Remote};
+
+// newer pushshift dumps are zstd, older generations gzip, bzip2 or xz
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Zstd,
+    Gzip,
+    Bzip2,
+    Xz,
+    // `.json`, `.ndjson` or no extension
+    Plain,
+}
+
+impl Compression {
+    // extensions of the files ingest picks up, "" for none
+    pub const EXTENSIONS: [&'static str; 7] = ["zst", "gz", "bz2", "xz", "json", "ndjson", ""];
+
+    pub fn for_extension(path: &Path) -> Option<Self> {
+        let Some(extension) = path.extension() else {
+            return Some(Self::Plain);
+        };
+
+        match extension.to_str()? {
+            "zst" => Some(Self::Zstd),
+            "gz" => Some(Self::Gzip),
+            "bz2" => Some(Self::Bzip2),
+            "xz" => Some(Self::Xz),
+            "json" | "ndjson" => Some(Self::Plain),
+            _ => None,
+        }
+    }
+
+    fn for_magic(magic: &[u8]) -> Option<Self> {
+        match magic {
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Some(Self::Zstd),
+            [0x1f, 0x8b, ..] => Some(Self::Gzip),
+            [b'B', b'Z', b'h', ..] => Some(Self::Bzip2),
+            [0xfd, b'7', b'z', b'X', b'Z', 0x00] => Some(Self::Xz),
+            _ => None,
+        }
+    }
+
+    // by the magic bytes, so misnamed files still work, then by the extension
+    pub fn detect(path: &Path) -> std::io::Result<Option<Self>> {
+        let mut magic = Vec::with_capacity(6);
+        File::open(path)?.take(6).read_to_end(&mut magic)?;
+
+        Ok(Self::for_magic(&magic).or_else(|| Self::for_extension(path)))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Zstd => "zstd",
+            Self::Gzip => "gzip",
+            Self::Bzip2 => "bzip2",
+            Self::Xz => "xz",
+            Self::Plain => "uncompressed",
+        }
+    }
+}
+
+// `-` reads a dump from stdin
+pub fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+// stdin, urls and api queries are read as a single dump instead of listing a directory
+pub fn is_stream(path: &Path) -> bool {
+    is_stdin(path) || remote::is_url(path) || api::is_api(path)
+}
+
+// what the outputs of a dump are named after
+pub fn output_name(path: &Path) -> PathBuf {
+    match path.to_str() {
+        _ if is_stdin(path) => PathBuf::from("stdin"),
+        Some(url) if remote::is_url(path) => PathBuf::from(remote::file_name(url)),
+        Some(spec) if api::is_api(path) => PathBuf::from(api::output_name(spec)),
+        _ => path.to_path_buf(),
+    }
+}
+
+// skippable frames carry metadata, not content
+fn is_skippable(magic: [u8; 4]) -> bool {
+    u32::from_le_bytes(magic) & 0xFFFF_FFF0 == 0x184D_2A50
+}
+
+fn zstd_error(e: FrameDecoderError) -> Error {
+    match e {
+        FrameDecoderError::WindowSizeTooBig { requested } => {
+            Error::new(ErrorKind::InvalidData, format!("a zstd frame needs a window of {} bytes, too large for ruzstd", requested))
+        }
+        e => Error::new(ErrorKind::InvalidData, format!("Error in the zstd decoder: {}", e)),
+    }
+}
+
+// ruzstd rejects frames with windows above 100 MiB, like those of `zstd --long=31`
+const RUZSTD_WINDOW_LOG_MAX: u32 = 26;
+
+// log2 of the window the first frame in `buf` needs, past any skippable frames, if its header is in `buf`
+fn first_window_log(mut buf: &[u8]) -> Option<u32> {
+    while buf.len() >= 8 && is_skippable(buf[..4].try_into().unwrap()) {
+        let size = u32::from_le_bytes(buf[4..8].try_into().unwrap()) as usize;
+        buf = buf.get(8 + size..)?;
+    }
+
+    let descriptor = *buf.get(4)?;
+
+    let window =
+        match descriptor & 0x20 {
+            // single segment frames are decoded in one window the size of their content
+            0x20 => {
+                let dictionary = [0, 1, 2, 4][(descriptor & 3) as usize];
+                let size = [1, 2, 4, 8][(descriptor >> 6) as usize];
+
+                let mut bytes = [0u8; 8];
+                bytes[..size].copy_from_slice(buf.get(5 + dictionary..5 + dictionary + size)?);
+
+                u64::from_le_bytes(bytes) + if size == 2 { 256 } else { 0 }
+            }
+            _ => {
+                let window_descriptor = *buf.get(5)?;
+                let log = 10 + u64::from(window_descriptor >> 3);
+
+                (1 << log) + (1 << log) / 8 * u64::from(window_descriptor & 7)
+            }
+        };
+
+    Some(64 - window.max(1).saturating_sub(1).leading_zeros())
+}
+
+// which library decodes zstd dumps
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ZstdDecoder {
+    // the C library, considerably faster
+    #[default]
+    Libzstd,
+    // pure rust, limited to windows up to 100 MiB
+    Ruzstd,
+}
+
+fn zstd<R: BufRead + 'static>(mut reader: R, config: &IngestConfig) -> std::io::Result<Box<dyn Read>> {
+    let window_log_max = config.window_log_max;
+
+    if !(10..=31).contains(&window_log_max) {
+        return Err(Error::new(ErrorKind::InvalidInput, "window_log_max has to be between 10 and 31"));
+    }
+
+    match first_window_log(reader.fill_buf()?) {
+        Some(log) if log > window_log_max => {
+            Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("the dump needs a zstd window of 2^{} bytes, pass --window-log-max {} to allow it", log, log),
+            ))
+        }
+        Some(log) if log > RUZSTD_WINDOW_LOG_MAX && config.decoder == ZstdDecoder::Ruzstd => {
+            Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("the dump needs a zstd window of 2^{} bytes, too large for ruzstd, use --decoder libzstd", log),
+            ))
+        }
+        _ if config.decoder == ZstdDecoder::Ruzstd => Ok(Box::new(Frames::new(reader))),
+        _ => {
+            // reads through all frames and skips skippable ones by itself
+            let mut decoder = zstd::stream::read::Decoder::with_buffer(reader)?;
+            decoder.window_log_max(window_log_max)?;
+
+            Ok(Box::new(decoder))
+        }
+    }
+}
+
+// every frame of a zstd stream one after the other, `StreamingDecoder` stops after the first
+struct Frames<R: BufRead> {
+    source: R,
+    decoder: FrameDecoder,
+    started: bool,
+}
+
+impl<R: BufRead> Frames<R> {
+    fn new(source: R) -> Self {
+        Self { source, decoder: FrameDecoder::new(), started: false }
+    }
+
+    // false at the end of the stream
+    fn next_frame(&mut self) -> std::io::Result<bool> {
+        loop {
+            if self.source.fill_buf()?.is_empty() {
+                return Ok(false);
+            }
+
+            let mut magic = [0u8; 4];
+            self.source.read_exact(&mut magic)?;
+
+            if !is_skippable(magic) {
+                self.decoder.reset(Cursor::new(magic).chain(&mut self.source)).map_err(zstd_error)?;
+                return Ok(true);
+            }
+
+            let mut size = [0u8; 4];
+            self.source.read_exact(&mut size)?;
+
+            let size = u64::from(u32::from_le_bytes(size));
+
+            if std::io::copy(&mut (&mut self.source).take(size), &mut std::io::sink())? < size {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "truncated skippable zstd frame"));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Read for Frames<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if !self.started || (self.decoder.is_finished() && self.decoder.can_collect() == 0) {
+                if !self.next_frame()? {
+                    return Ok(0);
+                }
+
+                self.started = true;
+            }
+
+            // blocks may decode into the window without anything to collect yet
+            while self.decoder.can_collect() < buf.len() && !self.decoder.is_finished() {
+                let needed = buf.len() - self.decoder.can_collect();
+
+                self.decoder
+                    .decode_blocks(&mut self.source, BlockDecodingStrategy::UptoBytes(needed))
+                    .map_err(zstd_error)?;
+            }
+
+            let read = self.decoder.read(buf)?;
+
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+        }
+    }
+}
+
+fn decoder<R: BufRead + 'static>(reader: R, compression: Compression, config: &IngestConfig) -> std::io::Result<Box<dyn Read>> {
+    Ok(
+        match compression {
+            Compression::Zstd => zstd(reader, config)?,
+            Compression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+            Compression::Bzip2 => Box::new(MultiBzDecoder::new(reader)),
+            Compression::Xz => Box::new(XzDecoder::new_multi_decoder(reader)),
+            Compression::Plain => Box::new(reader),
+        }
+    )
+}
+
+// chunks the read-ahead thread reads at a time
+const READ_AHEAD_CHUNK: usize = 1 << 20;
+
+// the compressed source read on its own thread up to `read_ahead` bytes ahead of the decoder, so a stalled
+// download doesn't leave decompression and parsing idle and they don't hold up the download either
+pub struct ReadAhead {
+    rx: Receiver<std::io::Result<Vec<u8>>>,
+    chunk: Cursor<Vec<u8>>,
+}
+
+impl ReadAhead {
+    // the source is opened on the thread as well, so it doesn't have to be `Send`
+    pub fn spawn<R, F>(open: F, read_ahead: u64) -> Self
+    where
+        R: Read,
+        F: FnOnce() -> std::io::Result<R> + Send + 'static,
+    {
+        let (tx, rx) = sync_channel((read_ahead as usize / READ_AHEAD_CHUNK).max(1));
+
+        std::thread::spawn(move || {
+            let mut source =
+                match open() {
+                    Ok(source) => source,
+                    Err(e) => {
+                        let _ = tx.send(Err(e));
+                        return;
+                    }
+                };
+
+            loop {
+                let mut chunk = Vec::with_capacity(READ_AHEAD_CHUNK);
+
+                // stops at the end of the source or when the decoder went away
+                match (&mut source).take(READ_AHEAD_CHUNK as u64).read_to_end(&mut chunk) {
+                    Ok(0) => return,
+                    Ok(_) if tx.send(Ok(chunk)).is_err() => return,
+                    Ok(_) => {}
+                    Err(e) => {
+                        let _ = tx.send(Err(e));
+                        return;
+                    }
+                }
+            }
+        });
+
+        Self { rx, chunk: Cursor::new(Vec::new()) }
+    }
+}
+
+impl Read for ReadAhead {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let read = self.chunk.read(buf)?;
+
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+
+            match self.rx.recv() {
+                Ok(chunk) => self.chunk = Cursor::new(chunk?),
+                Err(_) => return Ok(0),
+            }
+        }
+    }
+}
+
+// what reads the input ahead of decompression
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadAheadIo {
+    // a thread of its own per input
+    #[default]
+    Thread,
+    // tasks on a tokio runtime, files are read asynchronously and the other sources on its blocking pool
+    Tokio,
+}
+
+// the source read ahead by tokio tasks, into the same chunks as `ReadAhead`
+#[cfg(feature = "async-io")]
+pub struct AsyncReadAhead {
+    // shut down without waiting for a stalled read once the decoder is done
+    runtime: Option<tokio::runtime::Runtime>,
+    rx: tokio::sync::mpsc::Receiver<std::io::Result<Vec<u8>>>,
+    chunk: Cursor<Vec<u8>>,
+}
+
+#[cfg(feature = "async-io")]
+impl AsyncReadAhead {
+    fn runtime() -> std::io::Result<tokio::runtime::Runtime> {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("read-ahead")
+            .build()
+    }
+
+    pub fn file(path: PathBuf, read_ahead: u64) -> std::io::Result<Self> {
+        use tokio::io::AsyncReadExt;
+
+        let runtime = Self::runtime()?;
+        let (tx, rx) = tokio::sync::mpsc::channel((read_ahead as usize / READ_AHEAD_CHUNK).max(1));
+
+        runtime.spawn(async move {
+            let mut file =
+                match tokio::fs::File::open(&path).await {
+                    Ok(file) => file,
+                    Err(e) => {
+                        let _ = tx.send(Err(e)).await;
+                        return;
+                    }
+                };
+
+            loop {
+                let mut chunk = Vec::with_capacity(READ_AHEAD_CHUNK);
+
+                match (&mut file).take(READ_AHEAD_CHUNK as u64).read_to_end(&mut chunk).await {
+                    Ok(0) => return,
+                    Ok(_) if tx.send(Ok(chunk)).await.is_err() => return,
+                    Ok(_) => {}
+                    Err(e) => {
+                        let _ = tx.send(Err(e)).await;
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Self { runtime: Some(runtime), rx, chunk: Cursor::new(Vec::new()) })
+    }
+
+    // sources without an async reader, like the remote ones, block a thread of the pool instead
+    pub fn blocking<R, F>(open: F, read_ahead: u64) -> std::io::Result<Self>
+    where
+        R: Read,
+        F: FnOnce() -> std::io::Result<R> + Send + 'static,
+    {
+        let runtime = Self::runtime()?;
+        let (tx, rx) = tokio::sync::mpsc::channel((read_ahead as usize / READ_AHEAD_CHUNK).max(1));
+
+        runtime.spawn_blocking(move || {
+            let mut source =
+                match open() {
+                    Ok(source) => source,
+                    Err(e) => {
+                        let _ = tx.blocking_send(Err(e));
+                        return;
+                    }
+                };
+
+            loop {
+                let mut chunk = Vec::with_capacity(READ_AHEAD_CHUNK);
+
+                match (&mut source).take(READ_AHEAD_CHUNK as u64).read_to_end(&mut chunk) {
+                    Ok(0) => return,
+                    Ok(_) if tx.blocking_send(Ok(chunk)).is_err() => return,
+                    Ok(_) => {}
+                    Err(e) => {
+                        let _ = tx.blocking_send(Err(e));
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Self { runtime: Some(runtime), rx, chunk: Cursor::new(Vec::new()) })
+    }
+}
+
+#[cfg(feature = "async-io")]
+impl Read for AsyncReadAhead {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let read = self.chunk.read(buf)?;
+
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+
+            match self.rx.blocking_recv() {
+                Some(chunk) => self.chunk = Cursor::new(chunk?),
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async-io")]
+impl Drop for AsyncReadAhead {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+#[cfg(not(feature = "async-io"))]
+fn unsupported_async_io() -> Error {
+    Error::new(ErrorKind::Unsupported, "built without async i/o support, enable the `async-io` feature")
+}
+
+// the source, read ahead unless `read_ahead` is 0
+fn source<R, F>(open: F, config: &IngestConfig) -> std::io::Result<Box<dyn Read>>
+where
+    R: Read + 'static,
+    F: FnOnce() -> std::io::Result<R> + Send + 'static,
+{
+    match (config.read_ahead.0, config.read_ahead_io) {
+        (0, _) => Ok(Box::new(open()?)),
+        (read_ahead, ReadAheadIo::Thread) => Ok(Box::new(ReadAhead::spawn(open, read_ahead))),
+        #[cfg(feature = "async-io")]
+        (read_ahead, ReadAheadIo::Tokio) => Ok(Box::new(AsyncReadAhead::blocking(open, read_ahead)?)),
+        #[cfg(not(feature = "async-io"))]
+        (_, ReadAheadIo::Tokio) => Err(unsupported_async_io()),
+    }
+}
+
+// like `source`, a file is read asynchronously with tokio
+fn file_source(path: &Path, config: &IngestConfig) -> std::io::Result<Box<dyn Read>> {
+    let owned = path.to_path_buf();
+
+    match (config.read_ahead.0, config.read_ahead_io) {
+        #[cfg(feature = "async-io")]
+        (read_ahead, ReadAheadIo::Tokio) if read_ahead > 0 => Ok(Box::new(AsyncReadAhead::file(owned, read_ahead)?)),
+        _ => source(move || File::open(owned), config),
+    }
+}
+
+// the decompressed stream of a dump, concatenated streams are read as one
+pub fn open(path: &Path, config: &Config, submissions: bool) -> std::io::Result<Box<dyn Read>> {
+    if let Some(spec) = path.to_str().filter(|_| api::is_api(path)) {
+        return Ok(Box::new(ApiReader::open(spec, &config.api, submissions)?));
+    }
+
+    if is_stdin(path) {
+        let mut stdin = BufReader::new(source(|| Ok(std::io::stdin()), &config.ingest)?);
+
+        // without a name to go by, anything not compressed is taken as NDJSON
+        let compression = Compression::for_magic(stdin.fill_buf()?).unwrap_or(Compression::Plain);
+
+        return decoder(stdin, compression, &config.ingest);
+    }
+
+    if let Some(url) = path.to_str().filter(|_| remote::is_url(path)) {
+        let owned = url.to_string();
+        let mut body = BufReader::new(source(move || Remote::open(&owned), &config.ingest)?);
+
+        let compression =
+            Compression::for_magic(body.fill_buf()?)
+                .or_else(|| Compression::for_extension(Path::new(remote::file_name(url))))
+                .unwrap_or(Compression::Plain);
+
+        return decoder(body, compression, &config.ingest);
+    }
+
+    let compression =
+        Compression::detect(path)?
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("unknown compression of {}", path.display())))?;
+
+    decoder(BufReader::new(file_source(path, &config.ingest)?), compression, &config.ingest)
+}
+
+// `BufRead::read_until` with a vectorized search for the delimiter, the lines of a dump are long
+pub fn read_until<R: BufRead + ?Sized>(r: &mut R, delim: u8, buf: &mut Vec<u8>) -> Result<usize, Error> {
+    let mut read = 0;
+    loop {
+        let (done, used) = {
+            let available = match r.fill_buf() {
+                Ok(n) => n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            match memchr(delim, available) {
+                Some(i) => {
+                    buf.extend_from_slice(&available[..=i]);
+                    (true, i + 1)
+                }
+                None => {
+                    buf.extend_from_slice(available);
+                    (false, available.len())
+                }
+            }
+        };
+        r.consume(used);
+        read += used;
+        if done || used == 0 {
+            return Ok(read);
+        }
+    }
+}
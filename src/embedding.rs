@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+
+use crate::cli::EmbedArgs;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmbeddingConfig {
+    pub dimensions: usize,
+    // words on either side of a word that are its context
+    pub window: usize,
+    // noise words drawn per context word
+    pub negative: usize,
+    // rarer words get no vector and are dropped from the comments
+    pub min_count: u64,
+    // passes over the dumps after the one counting the words
+    pub epochs: usize,
+    // decays linearly to nothing over the epochs
+    pub learning_rate: f32,
+    // frequent words are dropped with a probability growing above this share of the tokens, 0 keeps all
+    pub subsample: f64,
+    pub seed: u64,
+}
+
+impl Default for EmbeddingConfig {
+    fn default() -> Self {
+        Self {
+            dimensions: 100,
+            window: 5,
+            negative: 5,
+            min_count: 5,
+            epochs: 1,
+            learning_rate: 0.025,
+            subsample: 1e-3,
+            seed: 0,
+        }
+    }
+}
+
+impl EmbeddingConfig {
+    // command line flags take precedence over the config file
+    pub fn apply(&mut self, args: &EmbedArgs) {
+        if let Some(dimensions) = args.dimensions {
+            self.dimensions = dimensions;
+        }
+
+        if let Some(window) = args.window {
+            self.window = window;
+        }
+
+        if let Some(negative) = args.negative {
+            self.negative = negative;
+        }
+
+        if let Some(min_count) = args.min_count {
+            self.min_count = min_count;
+        }
+
+        if let Some(epochs) = args.epochs {
+            self.epochs = epochs;
+        }
+
+        if let Some(seed) = args.seed {
+            self.seed = seed;
+        }
+    }
+}
+
+// the weights are shared by all threads without locks and updated in place (hogwild, Recht et al., 2011), relaxed
+// atomics cost as much as plain floats and a race only loses an update
+struct Weights(Vec<AtomicU32>);
+
+impl Weights {
+    fn new(values: impl Iterator<Item = f32>) -> Self {
+        Self(values.map(|v| AtomicU32::new(v.to_bits())).collect())
+    }
+
+    fn read(&self, row: usize, into: &mut [f32]) {
+        let (start, len) = (row * into.len(), into.len());
+
+        for (v, w) in into.iter_mut().zip(&self.0[start..start + len]) {
+            *v = f32::from_bits(w.load(Ordering::Relaxed));
+        }
+    }
+
+    fn add(&self, row: usize, delta: &[f32]) {
+        let start = row * delta.len();
+
+        for (d, w) in delta.iter().zip(&self.0[start..start + delta.len()]) {
+            w.store((f32::from_bits(w.load(Ordering::Relaxed)) + d).to_bits(), Ordering::Relaxed);
+        }
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+// skip-gram with negative sampling (word2vec, Mikolov et al., 2013), every word is trained to tell the words of its
+// context apart from noise words drawn by their count^0.75
+pub struct Sgns {
+    config: EmbeddingConfig,
+    ids: HashMap<Vec<u8>, u32>,
+    words: Vec<Vec<u8>>,
+    counts: Vec<u64>,
+    total: u64,
+    noise: WeightedIndex<f64>,
+    input: Weights,
+    output: Weights,
+    // tokens trained on so far, for the decay of the learning rate
+    seen: AtomicU64,
+}
+
+// scratch of one thread
+pub struct Scratch {
+    context: Vec<f32>,
+    target: Vec<f32>,
+    gradient: Vec<f32>,
+    delta: Vec<f32>,
+    ids: Vec<u32>,
+}
+
+impl Sgns {
+    // words below `min_count` are left out, most frequent first. none if no word is left
+    pub fn new(counts: HashMap<Vec<u8>, u64>, config: &EmbeddingConfig) -> Option<Self> {
+        let mut vocabulary = counts.into_iter().filter(|(_, count)| *count >= config.min_count.max(1)).collect::<Vec<_>>();
+
+        vocabulary.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let noise = WeightedIndex::new(vocabulary.iter().map(|(_, count)| (*count as f64).powf(0.75))).ok()?;
+
+        let (words, counts): (Vec<_>, Vec<_>) = vocabulary.into_iter().unzip();
+
+        let ids = words.iter().enumerate().map(|(i, word)| (word.clone(), i as u32)).collect();
+
+        let dimensions = config.dimensions;
+        let mut rng = StdRng::seed_from_u64(config.seed);
+
+        // small random inputs and zero outputs, like word2vec
+        let input = Weights::new((0..words.len() * dimensions).map(|_| (rng.gen::<f32>() - 0.5) / dimensions as f32));
+        let output = Weights::new(std::iter::repeat_n(0.0, words.len() * dimensions));
+
+        Some(Self {
+            config: config.clone(),
+            total: counts.iter().sum(),
+            ids,
+            words,
+            counts,
+            noise,
+            input,
+            output,
+            seen: AtomicU64::new(0),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    // tokens of the vocabulary in all epochs
+    pub fn total(&self) -> u64 {
+        self.total * self.config.epochs.max(1) as u64
+    }
+
+    pub fn seen(&self) -> u64 {
+        self.seen.load(Ordering::Relaxed)
+    }
+
+    pub fn scratch(&self) -> Scratch {
+        let dimensions = self.config.dimensions;
+
+        Scratch {
+            context: vec![0.0; dimensions],
+            target: vec![0.0; dimensions],
+            gradient: vec![0.0; dimensions],
+            delta: vec![0.0; dimensions],
+            ids: Vec::new(),
+        }
+    }
+
+    // the chance a word is kept by subsampling
+    fn keep(&self, id: u32) -> f64 {
+        if self.config.subsample <= 0.0 {
+            return 1.0;
+        }
+
+        let threshold = self.config.subsample * self.total as f64;
+        let count = self.counts[id as usize] as f64;
+
+        ((count / threshold).sqrt() + 1.0) * threshold / count
+    }
+
+    // one comment, its words in order
+    pub fn train(&self, comment: &[Vec<u8>], rng: &mut impl Rng, scratch: &mut Scratch) {
+        scratch.ids.clear();
+
+        let mut known = 0;
+
+        for word in comment {
+            if let Some(&id) = self.ids.get(word) {
+                known += 1;
+
+                if rng.gen::<f64>() < self.keep(id) {
+                    scratch.ids.push(id);
+                }
+            }
+        }
+
+        let seen = self.seen.fetch_add(known, Ordering::Relaxed);
+        let progress = seen as f32 / self.total().max(1) as f32;
+        let rate = self.config.learning_rate * (1.0 - progress).max(1e-4);
+
+        let window = self.config.window.max(1);
+        let ids = std::mem::take(&mut scratch.ids);
+
+        for (i, &word) in ids.iter().enumerate() {
+            // nearer words are in more of the shrunken windows, so they weigh more
+            let reach = window - rng.gen_range(0..window);
+
+            let start = i.saturating_sub(reach);
+
+            for (j, &context) in ids.iter().enumerate().take(i + reach + 1).skip(start) {
+                if j != i {
+                    self.pair(context, word, rate, rng, scratch);
+                }
+            }
+        }
+
+        scratch.ids = ids;
+    }
+
+    // moves `context` towards `word` and away from the noise
+    fn pair(&self, context: u32, word: u32, rate: f32, rng: &mut impl Rng, scratch: &mut Scratch) {
+        self.input.read(context as usize, &mut scratch.context);
+        scratch.gradient.fill(0.0);
+
+        for n in 0..=self.config.negative {
+            let (target, label) =
+                match n {
+                    0 => (word, 1.0),
+                    _ => {
+                        let noise = self.noise.sample(rng) as u32;
+
+                        if noise == word {
+                            continue;
+                        }
+
+                        (noise, 0.0)
+                    }
+                };
+
+            self.output.read(target as usize, &mut scratch.target);
+
+            let score = dot(&scratch.context, &scratch.target).clamp(-6.0, 6.0);
+            let step = (label - 1.0 / (1.0 + (-score).exp())) * rate;
+
+            for (g, t) in scratch.gradient.iter_mut().zip(&scratch.target) {
+                *g += step * t;
+            }
+
+            for (d, c) in scratch.delta.iter_mut().zip(&scratch.context) {
+                *d = step * c;
+            }
+
+            self.output.add(target as usize, &scratch.delta);
+        }
+
+        self.input.add(context as usize, &scratch.gradient);
+    }
+
+    // in the word2vec text format, a `<words> <dimensions>` line and then a word and its vector per line
+    pub fn write(&self, out: &mut impl Write) -> std::io::Result<()> {
+        let mut vector = vec![0.0; self.config.dimensions];
+
+        writeln!(out, "{} {}", self.words.len(), self.config.dimensions)?;
+
+        for (i, word) in self.words.iter().enumerate() {
+            self.input.read(i, &mut vector);
+
+            out.write_all(word)?;
+
+            for v in &vector {
+                write!(out, " {:.6}", v)?;
+            }
+
+            writeln!(out)?;
+        }
+
+        out.flush()
+    }
+}
@@ -0,0 +1,53 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::config::ByteSize;
+
+#[derive(Debug, Error)]
+pub enum PooError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("could not load {what}: {source}")]
+    Load { what: &'static str, source: std::io::Error },
+    #[error("could not create {}: {source}", path.display())]
+    CreateDir { path: PathBuf, source: std::io::Error },
+    #[error("could not read {}: {source}", path.display())]
+    Read { path: PathBuf, source: std::io::Error },
+    #[error("could not write {}: {source}", path.display())]
+    Write { path: PathBuf, source: std::io::Error },
+    #[error("{} has no file name", .0.display())]
+    NoFileName(PathBuf),
+    #[error("{} has more than the allowed unparseable lines, {errors} by line {line}", path.display())]
+    TooManyErrors { path: PathBuf, errors: usize, line: u64 },
+    #[error("stopped after another input had too many unparseable lines")]
+    Aborted,
+    #[error("could not listen on {addr}: {source}")]
+    Listen { addr: SocketAddr, source: std::io::Error },
+    #[error("could not configure the thread pool: {0}")]
+    ThreadPool(#[from] rayon::ThreadPoolBuildError),
+    #[error("{} is estimated to need {estimated} of memory, more than the {limit} allowed", path.display())]
+    TooLarge { path: PathBuf, estimated: ByteSize, limit: ByteSize },
+    #[error("{0}")]
+    Unsupported(&'static str),
+    #[error("{author} is not in {}", path.display())]
+    AuthorNotFound { path: PathBuf, author: String },
+    // the others were processed, the failures were reported as they happened
+    #[error("{failed} of {total} inputs failed")]
+    Failed { failed: usize, total: usize },
+}
+
+impl PooError {
+    pub fn read(path: impl Into<PathBuf>) -> impl FnOnce(std::io::Error) -> Self {
+        let path = path.into();
+        move |source| Self::Read { path, source }
+    }
+
+    pub fn write(path: impl Into<PathBuf>) -> impl FnOnce(std::io::Error) -> Self {
+        let path = path.into();
+        move |source| Self::Write { path, source }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, PooError>;
@@ -0,0 +1,380 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Deserializer};
+
+use crate::commands::ingest::Comment;
+
+lazy_static! {
+    // well-known bots whose names don't end in "bot"
+    static ref KNOWN_BOTS: HashSet<&'static str> =
+        vec![
+            "automoderator",
+            "totesmessenger",
+            "savevideo",
+            "savevideobot",
+            "the-paranoid-android",
+            "nice-scores",
+            "transcribersofreddit",
+            "transcribot",
+            "reddit-user-identifier",
+            "vreddit_bot",
+            "stabbot_crop",
+            "gifv-bot",
+            "imguralbumbot",
+            "converter-bot",
+            "timezone_bot",
+            "anti-gif-bot",
+            "sneakpeekbot",
+            "remindmebot",
+            "wikitextbot",
+            "repostsleuthbot",
+            "haikusbot",
+            "b0trank",
+            "goodbot_badbot",
+            "userleansbot",
+            "tweetposter",
+            "twittertostreamable",
+            "link-reply-bot",
+            "nfcaaofficialrefbot",
+        ]
+            .into_iter()
+            .collect();
+}
+
+// phrases bots put into their footers
+const BOT_PHRASES: [&str; 3] = [
+    "i am a bot",
+    "i'm a bot",
+    "this action was performed automatically",
+];
+
+fn contains_ignore_ascii_case(haystack: &str, needle: &str) -> bool {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .any(|w| w.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+pub fn has_bot_name(author: &str) -> bool {
+    let author = author.to_lowercase();
+
+    author.ends_with("bot") || KNOWN_BOTS.contains(author.as_str())
+}
+
+pub fn has_bot_phrase(body: &str) -> bool {
+    BOT_PHRASES.iter().any(|p| contains_ignore_ascii_case(body, p))
+}
+
+pub fn is_bot(author: &str, body: &str) -> bool {
+    has_bot_name(author) || has_bot_phrase(body)
+}
+
+// unix seconds, parsed from either a number or `YYYY-MM-DD[THH:MM[:SS]][Z]` (UTC)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp(pub i64);
+
+// days since 1970-01-01 of a proleptic gregorian date
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146097 + doe - 719468
+}
+
+// the proleptic gregorian (year, month, day) of days since 1970-01-01
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = if days >= 0 { days } else { days - 146096 } / 146097;
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+
+    (yoe + era * 400 + i64::from(month <= 2), month, day)
+}
+
+impl FromStr for Timestamp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if let Ok(v) = s.parse::<i64>() {
+            return Ok(Self(v));
+        }
+
+        let invalid = || format!("invalid timestamp '{}', expected unix seconds or YYYY-MM-DD[THH:MM[:SS]]", s);
+
+        let s = s.strip_suffix('Z').unwrap_or(s);
+
+        let (date, time) =
+            match s.split_once(['T', ' ']) {
+                Some((date, time)) => (date, Some(time)),
+                None => (s, None),
+            };
+
+        let date =
+            date.split('-')
+                .map(|v| v.parse::<i64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid())?;
+
+        let time =
+            time.unwrap_or("0:0")
+                .split(':')
+                .map(|v| v.parse::<i64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid())?;
+
+        match (date.as_slice(), time.as_slice()) {
+            (&[year, month, day], &[hour, minute, ref second @ ..])
+                if (1..=12).contains(&month)
+                    && (1..=31).contains(&day)
+                    && (0..24).contains(&hour)
+                    && (0..60).contains(&minute)
+                    && second.len() <= 1 =>
+            {
+                Ok(Self(
+                    days_from_civil(year, month, day) * 86400
+                        + hour * 3600
+                        + minute * 60
+                        + second.first().copied().unwrap_or(0)
+                ))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Int(i64),
+            Str(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Int(v) => Ok(Self(v)),
+            Raw::Str(v) => v.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+// what to do with comments by deleted authors or with deleted/removed bodies
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum DeletedPolicy {
+    #[default]
+    Keep,
+    Skip,
+    // aggregate them into <name>.deleted.users.freqs
+    Separate,
+}
+
+pub fn is_deleted(comment: &Comment) -> bool {
+    matches!(comment.author.as_str(), "[deleted]" | "[removed]")
+        || matches!(comment.body.trim(), "[deleted]" | "[removed]")
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterConfig {
+    // only keep comments from these subreddits (all if empty)
+    pub subreddits: Vec<String>,
+    pub exclude_subreddits: Vec<String>,
+    // created_utc window, `after` is inclusive and `before` exclusive
+    pub after: Option<Timestamp>,
+    pub before: Option<Timestamp>,
+    // one author per line, `#` starts a comment
+    pub exclude_authors_file: Option<PathBuf>,
+    pub exclude_bots: bool,
+    pub deleted: DeletedPolicy,
+}
+
+// decides which comments make it into the aggregation
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    subreddits: Option<HashSet<String>>,
+    exclude_subreddits: HashSet<String>,
+    after: Option<i64>,
+    before: Option<i64>,
+    exclude_authors: HashSet<String>,
+    exclude_bots: bool,
+}
+
+fn normalize_subreddit(name: &str) -> String {
+    let name = name.trim();
+
+    name.strip_prefix("r/")
+        .or_else(|| name.strip_prefix("/r/"))
+        .unwrap_or(name)
+        .to_lowercase()
+}
+
+impl Filter {
+    pub fn from_config(config: &FilterConfig) -> std::io::Result<Self> {
+        let exclude_authors =
+            match &config.exclude_authors_file {
+                Some(path) =>
+                    std::fs::read_to_string(path)?
+                        .lines()
+                        .map(|v| v.split('#').next().unwrap_or("").trim())
+                        .filter(|v| !v.is_empty())
+                        .map(|v| v.strip_prefix("u/").unwrap_or(v).to_lowercase())
+                        .collect(),
+                None => HashSet::new(),
+            };
+
+        Ok(Self {
+            subreddits:
+                if config.subreddits.is_empty() {
+                    None
+                } else {
+                    Some(config.subreddits.iter().map(|v| normalize_subreddit(v)).collect())
+                },
+            exclude_subreddits:
+                config.exclude_subreddits
+                    .iter()
+                    .map(|v| normalize_subreddit(v))
+                    .collect(),
+            after: config.after.map(|v| v.0),
+            before: config.before.map(|v| v.0),
+            exclude_authors,
+            exclude_bots: config.exclude_bots,
+        })
+    }
+
+    fn needs_subreddit(&self) -> bool {
+        self.subreddits.is_some() || !self.exclude_subreddits.is_empty()
+    }
+
+    pub fn accepts(&self, comment: &Comment) -> bool {
+        if !self.exclude_authors.is_empty() && self.exclude_authors.contains(&comment.author.to_lowercase()) {
+            return false;
+        }
+
+        if self.exclude_bots && is_bot(&comment.author, &comment.body) {
+            return false;
+        }
+
+        if self.after.is_some() || self.before.is_some() {
+            // without a timestamp the comment can't be placed in the window
+            let created_utc =
+                match comment.created_utc {
+                    Some(v) => v,
+                    None => return false,
+                };
+
+            if self.after.is_some_and(|after| created_utc < after) {
+                return false;
+            }
+
+            if self.before.is_some_and(|before| created_utc >= before) {
+                return false;
+            }
+        }
+
+        if self.needs_subreddit() {
+            let subreddit =
+                comment.subreddit
+                    .as_deref()
+                    .map(normalize_subreddit)
+                    .unwrap_or_default();
+
+            if let Some(subreddits) = &self.subreddits {
+                if !subreddits.contains(&subreddit) {
+                    return false;
+                }
+            }
+
+            if self.exclude_subreddits.contains(&subreddit) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timestamp(s: &str) -> Result<i64, String> {
+        s.parse::<Timestamp>().map(|v| v.0)
+    }
+
+    fn comment(created_utc: Option<i64>) -> Comment {
+        Comment {
+            author: "someone".to_string(),
+            body: "some words".to_string(),
+            subreddit: None,
+            created_utc,
+            score: None,
+        }
+    }
+
+    #[test]
+    fn numbers_and_dates() {
+        let may = days_from_civil(2024, 5, 1) * 86400;
+
+        assert_eq!(may, 1714521600);
+        assert_eq!(timestamp("1714521600"), Ok(may));
+        assert_eq!(timestamp(" -86400 "), Ok(-86400));
+        assert_eq!(timestamp("2024-05-01"), Ok(may));
+        assert_eq!(timestamp("2024-05-01Z"), Ok(may));
+        assert_eq!(timestamp("2024-05-01T12:30Z"), Ok(may + 12 * 3600 + 30 * 60));
+        assert_eq!(timestamp("2024-05-01T12:30:15Z"), Ok(may + 12 * 3600 + 30 * 60 + 15));
+        assert_eq!(timestamp("2024-05-01 23:59:59"), Ok(may + 86399));
+        assert_eq!(timestamp("1970-01-01T00:00Z"), Ok(0));
+    }
+
+    #[test]
+    fn invalid_dates() {
+        for s in [
+            "2024-13-01",
+            "2024-00-01",
+            "2024-05-32",
+            "2024-05-01T24:00Z",
+            "2024-05-01T12:60",
+            "2024-05-01T12",
+            "2024-05-01T12:30:15:00",
+            "2024-05",
+            "yesterday",
+            "",
+        ] {
+            assert!(timestamp(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn window_is_after_inclusive_before_exclusive() {
+        let config = FilterConfig { after: Some(Timestamp(1000)), before: Some(Timestamp(2000)), ..Default::default() };
+        let filter = Filter::from_config(&config).unwrap();
+
+        assert!(!filter.accepts(&comment(Some(999))));
+        assert!(filter.accepts(&comment(Some(1000))));
+        assert!(filter.accepts(&comment(Some(1999))));
+        assert!(!filter.accepts(&comment(Some(2000))));
+        assert!(!filter.accepts(&comment(None)));
+
+        let after = Filter::from_config(&FilterConfig { after: Some(Timestamp(1000)), ..Default::default() }).unwrap();
+
+        assert!(after.accepts(&comment(Some(i64::MAX))));
+        assert!(!after.accepts(&comment(None)));
+
+        // without a window a missing timestamp doesn't matter
+        assert!(Filter::default().accepts(&comment(None)));
+    }
+}
@@ -0,0 +1,141 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tonic::{Request, Response, Status};
+use tracing::info;
+
+use crate::commands::serve::Store;
+use crate::error::{PooError, Result};
+use crate::similarity::Metric;
+
+pub mod proto {
+    tonic::include_proto!("segmenter.v1");
+}
+
+use proto::segmenter_server::{Segmenter, SegmenterServer};
+
+// the same answers as the http endpoints of `serve`, from the definitions in proto/segmenter.proto
+struct Service {
+    store: Arc<Store>,
+    // for requests with a `top` of 0
+    top: usize,
+}
+
+impl Service {
+    fn find(&self, name: &str) -> std::result::Result<u32, Status> {
+        self.store.author(name.as_bytes()).ok_or_else(|| Status::not_found(format!("author {} not found", name)))
+    }
+
+    fn top(&self, top: u32) -> usize {
+        match top {
+            0 => self.top,
+            top => top as usize,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Segmenter for Service {
+    async fn get_user(&self, request: Request<proto::GetUserRequest>) -> std::result::Result<Response<proto::User>, Status> {
+        let request = request.into_inner();
+        let (store, author) = (&self.store, self.find(&request.author)?);
+
+        Ok(Response::new(proto::User {
+            author: store.name(author),
+            words: store.len(author) as u64,
+            tokens: store.tokens(author),
+            top:
+                store.top_words(author, self.top(request.top))
+                    .into_iter()
+                    .map(|(word, count)| proto::WordCount { word: store.word(word), count })
+                    .collect(),
+        }))
+    }
+
+    async fn get_vector(&self, request: Request<proto::GetVectorRequest>) -> std::result::Result<Response<proto::Vector>, Status> {
+        let request = request.into_inner();
+        let (store, author) = (&self.store, self.find(&request.author)?);
+
+        let (words, counts) = store.vector(author).into_iter().map(|(word, count)| (store.word(word), count)).unzip();
+
+        Ok(Response::new(proto::Vector { author: store.name(author), words, counts, norm: store.norm(author) }))
+    }
+
+    async fn compare(&self, request: Request<proto::CompareRequest>) -> std::result::Result<Response<proto::Comparison>, Status> {
+        let request = request.into_inner();
+        let (store, a, b) = (&self.store, self.find(&request.a)?, self.find(&request.b)?);
+
+        let comparison = store.compare(a, b);
+
+        Ok(Response::new(proto::Comparison {
+            a: store.name(a),
+            b: store.name(b),
+            cosine: comparison.cosine,
+            jaccard: comparison.jaccard,
+            shared_words: comparison.shared.len() as u64,
+            shared:
+                comparison.shared
+                    .into_iter()
+                    .take(self.top(request.top))
+                    .map(|(word, a, b)| proto::SharedWord { word: store.word(word), a, b })
+                    .collect(),
+        }))
+    }
+
+    async fn similar(&self, request: Request<proto::SimilarRequest>) -> std::result::Result<Response<proto::SimilarResponse>, Status> {
+        let request = request.into_inner();
+        let (store, author) = (&self.store, self.find(&request.author)?);
+
+        let metric =
+            match request.metric() {
+                proto::Metric::Cosine => Metric::Cosine,
+                proto::Metric::Jaccard => Metric::Jaccard,
+            };
+
+        Ok(Response::new(proto::SimilarResponse {
+            author: store.name(author),
+            similar:
+                store.similar(author, self.top(request.top), metric)
+                    .into_iter()
+                    .map(|(other, score)| proto::SimilarAuthor { author: store.name(other), score })
+                    .collect(),
+        }))
+    }
+
+    async fn search(&self, request: Request<proto::SearchRequest>) -> std::result::Result<Response<proto::SearchResponse>, Status> {
+        let request = request.into_inner();
+        let store = &self.store;
+
+        let Some(postings) = store.search(request.word.as_bytes()) else {
+            return Err(Status::not_found(format!("no author uses {}", request.word)));
+        };
+
+        Ok(Response::new(proto::SearchResponse {
+            authors: postings.len() as u64,
+            count: postings.iter().map(|&(_, count)| count).sum(),
+            top:
+                postings.iter()
+                    .take(self.top(request.top))
+                    .map(|&(author, count)| proto::AuthorCount { author: store.name(author), count })
+                    .collect(),
+            word: request.word,
+        }))
+    }
+}
+
+// blocks, answering on a runtime of its own
+pub(crate) fn serve(addr: SocketAddr, store: Arc<Store>, top: usize) -> Result<()> {
+    let runtime =
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+
+    info!(%addr, "Serve: Answering gRPC queries");
+
+    runtime.block_on(
+        tonic::transport::Server::builder()
+            .add_service(SegmenterServer::new(Service { store, top }))
+            .serve(addr),
+    )
+        .map_err(|e| PooError::Listen { addr, source: std::io::Error::other(e) })
+}
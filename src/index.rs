@@ -0,0 +1,526 @@
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::config::OutputConfig;
+use crate::serializer::{deserialize_block, Codec, FreqSink};
+use crate::spill::{RecordSource, RunRecord};
+use crate::text::text_item::{PooMap, PooMapInner};
+
+// uncompressed bytes per block, every block is its own zstd frame
+const BLOCK_SIZE: usize = 256 * 1024;
+// a dictionary keeps small blocks small, so a lookup decompresses less
+const DICTIONARY_BLOCK_SIZE: usize = 32 * 1024;
+
+// zstd skips frames with this magic, so plain decoders never see the index
+const SKIPPABLE_MAGIC: u32 = 0x184D2A50;
+const INDEX_MAGIC: &[u8; 8] = b"rgindex1";
+// starts the payload of the skippable frame at the start of a file written with a dictionary
+const DICTIONARY_MAGIC: &[u8; 8] = b"rgdict01";
+
+// the dictionary is trained once this much of the output is buffered, or at the end of smaller ones,
+// at about a hundredth of it like zstd suggests
+const DICTIONARY_SAMPLE: usize = 8 << 20;
+const DICTIONARY_MAX_SIZE: usize = 112 * 1024;
+// below this a dictionary costs more than it saves
+const DICTIONARY_MIN_SAMPLE: usize = 256 * 1024;
+
+// the dictionary of a file written with `--dictionary`, None if it was written without one
+pub fn read_dictionary(file: &mut File) -> std::io::Result<Option<Vec<u8>>> {
+    let mut head = [0u8; 16];
+
+    file.seek(SeekFrom::Start(0))?;
+
+    let found = file.read(&mut head)? == 16 && head[..4] == SKIPPABLE_MAGIC.to_le_bytes() && head[8..] == *DICTIONARY_MAGIC;
+
+    let dictionary =
+        match found {
+            true => {
+                let size = u32::from_le_bytes(head[4..8].try_into().unwrap()) as usize;
+
+                let mut dictionary = vec![0u8; size.saturating_sub(DICTIONARY_MAGIC.len())];
+                file.read_exact(&mut dictionary)?;
+
+                Some(dictionary)
+            }
+            false => None,
+        };
+
+    file.seek(SeekFrom::Start(0))?;
+
+    Ok(dictionary)
+}
+
+// the whole decompressed file, with or without a dictionary
+pub fn decode_file(file: &mut File) -> std::io::Result<Vec<u8>> {
+    let dictionary = read_dictionary(file)?;
+
+    let mut buf = Vec::new();
+
+    zstd::stream::Decoder::with_dictionary(std::io::BufReader::new(file), dictionary.as_deref().unwrap_or_default())?
+        .read_to_end(&mut buf)?;
+
+    Ok(buf)
+}
+
+// the first `len` decompressed bytes, enough for the header without decoding the whole file
+pub fn decode_head(file: &mut File, len: usize) -> std::io::Result<Vec<u8>> {
+    let dictionary = read_dictionary(file)?;
+
+    let mut buf = Vec::with_capacity(len);
+
+    zstd::stream::Decoder::with_dictionary(std::io::BufReader::new(file), dictionary.as_deref().unwrap_or_default())?
+        .take(len as u64)
+        .read_to_end(&mut buf)?;
+
+    Ok(buf)
+}
+
+// zstd frames start with it, little endian
+const FRAME_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+// every frame of a damaged file decompressed as far as it goes. the rest of a frame that fails is skipped up to the
+// next frame magic, a truncated last frame keeps what was decompressed before the end
+pub fn salvage_frames(file: &mut File) -> std::io::Result<Vec<Vec<u8>>> {
+    let dictionary = read_dictionary(file).unwrap_or_default();
+
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+
+    let mut frames = Vec::new();
+    let mut start = 0;
+
+    while start < data.len() {
+        let mut frame = Vec::new();
+
+        let mut decoder =
+            zstd::stream::read::Decoder::with_dictionary(&data[start..], dictionary.as_deref().unwrap_or_default())?
+                .single_frame();
+
+        match decoder.read_to_end(&mut frame) {
+            Ok(_) => start = data.len() - decoder.finish().len(),
+            Err(_) => {
+                start =
+                    data[start + 1..]
+                        .windows(FRAME_MAGIC.len())
+                        .position(|window| window == FRAME_MAGIC)
+                        .map_or(data.len(), |next| start + 1 + next);
+            }
+        }
+
+        if !frame.is_empty() {
+            frames.push(frame);
+        }
+    }
+
+    Ok(frames)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexBlock {
+    first_author: Vec<u8>,
+    // compressed offset and length of the frame
+    offset: u64,
+    len: u64,
+    authors: u64,
+}
+
+// the first author of every block, in byte order like the authors themselves
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FreqIndex {
+    codec: Codec,
+    blocks: Vec<IndexBlock>,
+    // read from the start of the file, not part of the index
+    #[serde(skip)]
+    dictionary: Option<Vec<u8>>,
+}
+
+impl FreqIndex {
+    // None if the file was written without an index
+    pub fn read(file: &mut File) -> std::io::Result<Option<Self>> {
+        let file_len = file.metadata()?.len();
+
+        if file_len < 24 {
+            return Ok(None);
+        }
+
+        let mut tail = [0u8; 16];
+
+        file.seek(SeekFrom::End(-16))?;
+        file.read_exact(&mut tail)?;
+
+        if tail[8..] != *INDEX_MAGIC {
+            return Ok(None);
+        }
+
+        let len = u64::from_le_bytes(tail[..8].try_into().unwrap());
+
+        if len + 24 > file_len {
+            return Err(Error::new(ErrorKind::InvalidData, "index is longer than the file"));
+        }
+
+        let mut buf = vec![0u8; len as usize];
+
+        file.seek(SeekFrom::End(-16 - len as i64))?;
+        file.read_exact(&mut buf)?;
+
+        let mut index =
+            bincode::deserialize::<Self>(&buf)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+
+        index.dictionary = read_dictionary(file)?;
+
+        Ok(Some(index))
+    }
+
+    pub fn author_count(&self) -> u64 {
+        self.blocks.iter().map(|block| block.authors).sum()
+    }
+
+    // the author and word count of the trailer, which comes in the frames after the last block
+    pub fn counts(&self, file: &mut File) -> std::io::Result<Option<(u64, u64)>> {
+        let Some(last) = self.blocks.last() else {
+            return Ok(None);
+        };
+
+        file.seek(SeekFrom::Start(last.offset + last.len))?;
+
+        let mut tail = Vec::new();
+
+        zstd::stream::Decoder::with_dictionary(std::io::BufReader::new(file), self.dictionary.as_deref().unwrap_or_default())?
+            .read_to_end(&mut tail)?;
+
+        // author count, word count, checksum
+        let Some(trailer) = tail.len().checked_sub(24).map(|start| &tail[start..]) else {
+            return Ok(None);
+        };
+
+        let count = |bytes: &[u8]| u64::from_be_bytes(bytes[..8].try_into().unwrap());
+
+        Ok(Some((count(trailer), count(&trailer[8..]))))
+    }
+
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    // the first author and number of authors of every block
+    pub fn blocks(&self) -> impl Iterator<Item = (&[u8], u64)> {
+        self.blocks.iter().map(|block| (block.first_author.as_slice(), block.authors))
+    }
+
+    // the first authors of the blocks have to be in byte order, for lookups to find them
+    pub fn is_ordered(&self) -> bool {
+        self.blocks.windows(2).all(|pair| pair[0].first_author < pair[1].first_author)
+    }
+
+    fn read_block(&self, file: &mut File, i: usize) -> std::io::Result<PooMap> {
+        let block = &self.blocks[i];
+
+        let mut frame = vec![0u8; block.len as usize];
+
+        file.seek(SeekFrom::Start(block.offset))?;
+        file.read_exact(&mut frame)?;
+
+        let mut buf = Vec::new();
+
+        zstd::stream::Decoder::with_dictionary(frame.as_slice(), self.dictionary.as_deref().unwrap_or_default())?
+            .read_to_end(&mut buf)?;
+
+        deserialize_block(buf, block.authors, self.codec)
+    }
+
+    // decompresses only the block that would contain `author`
+    pub fn lookup(&self, file: &mut File, author: &[u8]) -> std::io::Result<Option<PooMapInner>> {
+        let i = self.blocks.partition_point(|block| block.first_author.as_slice() <= author);
+
+        if i == 0 {
+            return Ok(None);
+        }
+
+        Ok(self.read_block(file, i - 1)?.remove(author))
+    }
+
+    pub fn reader(&self, file: File) -> BlockReader<'_> {
+        BlockReader {
+            file,
+            index: self,
+            block: 0,
+            records: Vec::new().into_iter(),
+        }
+    }
+}
+
+// the authors of an indexed file, decompressed one block at a time
+pub struct BlockReader<'a> {
+    file: File,
+    index: &'a FreqIndex,
+    block: usize,
+    records: std::vec::IntoIter<RunRecord>,
+}
+
+impl RecordSource for BlockReader<'_> {
+    fn next(&mut self) -> std::io::Result<Option<RunRecord>> {
+        loop {
+            if let Some(record) = self.records.next() {
+                return Ok(Some(record));
+            }
+
+            if self.block >= self.index.blocks.len() {
+                return Ok(None);
+            }
+
+            let freqs = self.index.read_block(&mut self.file, self.block)?;
+            self.block += 1;
+
+            let mut records =
+                freqs.into_iter()
+                    .map(|(author, freqs)| {
+                        let mut words = freqs.into_iter().collect::<Vec<_>>();
+                        words.sort_unstable();
+
+                        (author, words)
+                    })
+                    .collect::<Vec<_>>();
+
+            records.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+            self.records = records.into_iter();
+        }
+    }
+}
+
+// blocks held back until the dictionary is trained on them
+#[derive(Default)]
+struct Training {
+    blocks: Vec<(Vec<u8>, Option<IndexBlock>)>,
+    bytes: usize,
+    // the length of every author record, and of the header, as the samples
+    samples: Vec<usize>,
+    // where the authors of the block being buffered start
+    starts: Vec<usize>,
+}
+
+fn compressor(compression_level: i32, dictionary: &[u8]) -> std::io::Result<zstd::bulk::Compressor<'static>> {
+    let mut compressor = zstd::bulk::Compressor::with_dictionary(compression_level, dictionary)?;
+    compressor.include_checksum(true)?;
+
+    Ok(compressor)
+}
+
+// compresses the serialized file block by block and appends the index once finished
+pub struct BlockEncoder<W: Write> {
+    out: W,
+    compression_level: i32,
+    // one per block compressed at the same time
+    compressors: Vec<zstd::bulk::Compressor<'static>>,
+    // blocks waiting for a compressor, written in order
+    pending: Vec<(Vec<u8>, Option<IndexBlock>)>,
+    buf: Vec<u8>,
+    block_size: usize,
+    // compressed bytes written so far
+    offset: u64,
+    // the block being buffered, None while in the header or trailer
+    current: Option<IndexBlock>,
+    index: FreqIndex,
+    training: Option<Training>,
+}
+
+impl<W: Write> BlockEncoder<W> {
+    // with `dictionary`, every frame is compressed with a dictionary trained on the first authors and stored in
+    // a skippable frame at the start, plain zstd can't decompress the file then
+    pub fn new(out: W, output: &OutputConfig) -> std::io::Result<Self> {
+        let compressors =
+            (0..output.compression_threads.max(1))
+                .map(|_| compressor(output.compression_level, &[]))
+                .collect::<std::io::Result<Vec<_>>>()?;
+
+        Ok(Self {
+            out,
+            compression_level: output.compression_level,
+            compressors,
+            pending: Vec::new(),
+            buf: Vec::new(),
+            block_size: if output.dictionary { DICTIONARY_BLOCK_SIZE } else { BLOCK_SIZE },
+            offset: 0,
+            current: None,
+            index: FreqIndex {
+                codec: output.codec,
+                blocks: Vec::new(),
+                dictionary: None,
+            },
+            training: output.dictionary.then(Training::default),
+        })
+    }
+
+    // writes the buffered bytes as one frame, or holds them back for training
+    fn cut(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+
+        let buf = std::mem::take(&mut self.buf);
+        let block = self.current.take();
+
+        let Some(training) = self.training.as_mut() else {
+            return self.write_frame(buf, block);
+        };
+
+        let mut start = 0;
+
+        for &end in training.starts.iter().chain([buf.len()].iter()) {
+            if end > start {
+                training.samples.push(end - start);
+            }
+
+            start = end;
+        }
+
+        training.starts.clear();
+        training.bytes += buf.len();
+        training.blocks.push((buf, block));
+
+        match training.bytes >= DICTIONARY_SAMPLE {
+            true => self.train(),
+            false => Ok(()),
+        }
+    }
+
+    fn write_frame(&mut self, buf: Vec<u8>, block: Option<IndexBlock>) -> std::io::Result<()> {
+        self.pending.push((buf, block));
+
+        match self.pending.len() >= self.compressors.len() {
+            true => self.write_pending(),
+            false => Ok(()),
+        }
+    }
+
+    // the frames are independent, so the pending ones are compressed in parallel
+    fn write_pending(&mut self) -> std::io::Result<()> {
+        let pending = std::mem::take(&mut self.pending);
+
+        let frames =
+            pending.par_iter()
+                .zip(self.compressors.par_iter_mut())
+                .map(|((buf, _), compressor)| compressor.compress(buf))
+                .collect::<std::io::Result<Vec<_>>>()?;
+
+        for ((_, block), frame) in pending.into_iter().zip(frames) {
+            self.out.write_all(&frame)?;
+
+            if let Some(mut block) = block {
+                block.offset = self.offset;
+                block.len = frame.len() as u64;
+
+                self.index.blocks.push(block);
+            }
+
+            self.offset += frame.len() as u64;
+        }
+
+        Ok(())
+    }
+
+    // too few samples for zstd to train on leave the file without a dictionary
+    fn train(&mut self) -> std::io::Result<()> {
+        let Some(training) = self.training.take() else {
+            return Ok(());
+        };
+
+        let continuous = training.blocks.iter().flat_map(|(buf, _)| buf.iter().copied()).collect::<Vec<_>>();
+
+        let trained =
+            match training.bytes {
+                bytes if bytes < DICTIONARY_MIN_SAMPLE => Err(Error::other("too little output")),
+                bytes => zstd::dict::from_continuous(&continuous, &training.samples, (bytes / 100).min(DICTIONARY_MAX_SIZE)),
+            };
+
+        match trained {
+            Ok(dictionary) => {
+                let size = u32::try_from(DICTIONARY_MAGIC.len() + dictionary.len()).expect("Dictionary is too large");
+
+                self.out.write_all(&SKIPPABLE_MAGIC.to_le_bytes())?;
+                self.out.write_all(&size.to_le_bytes())?;
+                self.out.write_all(DICTIONARY_MAGIC)?;
+                self.out.write_all(&dictionary)?;
+
+                self.offset += 8 + u64::from(size);
+
+                for compressor in self.compressors.iter_mut() {
+                    *compressor = self::compressor(self.compression_level, &dictionary)?;
+                }
+            }
+            Err(e) => debug!(error = %e, samples = training.samples.len(), "Writing without a dictionary"),
+        }
+
+        for (buf, block) in training.blocks {
+            self.write_frame(buf, block)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.cut()?;
+        self.train()?;
+        self.write_pending()?;
+
+        let index =
+            bincode::serialize(&self.index)
+                .map_err(Error::other)?;
+
+        let size = u32::try_from(index.len() + 16).expect("Index is too large");
+
+        // skippable frame: magic, payload size, index, index length, index magic
+        self.out.write_all(&SKIPPABLE_MAGIC.to_le_bytes())?;
+        self.out.write_all(&size.to_le_bytes())?;
+        self.out.write_all(&index)?;
+        self.out.write_all(&(index.len() as u64).to_le_bytes())?;
+        self.out.write_all(INDEX_MAGIC)?;
+
+        Ok(self.out)
+    }
+}
+
+impl<W: Write> Write for BlockEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    // frames are only cut between authors
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: Write> FreqSink for BlockEncoder<W> {
+    fn begin_author(&mut self, author: &[u8]) -> std::io::Result<()> {
+        if self.current.is_none() || self.buf.len() >= self.block_size {
+            self.cut()?;
+
+            self.current =
+                Some(IndexBlock {
+                    first_author: author.to_vec(),
+                    offset: 0,
+                    len: 0,
+                    authors: 0,
+                });
+        }
+
+        self.current.as_mut().unwrap().authors += 1;
+
+        if let Some(training) = self.training.as_mut() {
+            training.starts.push(self.buf.len());
+        }
+
+        Ok(())
+    }
+
+    fn end_authors(&mut self) -> std::io::Result<()> {
+        self.cut()
+    }
+}
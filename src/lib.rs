@@ -0,0 +1,44 @@
+// the segmentation behind the `poo` binary, for embedding it without shelling out
+
+pub mod api;
+pub mod builder;
+pub mod bulk;
+pub mod cli;
+pub mod commands;
+pub mod config;
+pub mod dedup;
+pub mod dump;
+#[cfg(feature = "duckdb")]
+pub mod duckdb_sink;
+pub mod embedding;
+pub mod error;
+pub mod filter;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod index;
+pub mod log;
+pub mod manifest;
+pub mod metrics;
+pub mod minhash;
+pub mod npz;
+#[cfg(feature = "parquet")]
+pub mod parquet_sink;
+pub mod progress;
+pub mod prune;
+pub mod remote;
+pub mod text;
+pub mod serializer;
+pub mod segment;
+pub mod similarity;
+pub mod sketch;
+pub mod spill;
+pub mod weighting;
+
+pub use builder::PipelineBuilder;
+pub use config::Config;
+pub use error::{PooError, Result};
+pub use progress::Progress;
+pub use serializer::{deserialize_any, serialize_with_writer, Codec, FreqSink, FreqWriter, SerializerFeedback, SortedFreqs};
+pub use text::interner::FreqStore;
+pub use text::pipeline::{Pipeline, Tokenizer};
+pub use text::text_item::{PooMap, TextItem};
@@ -0,0 +1,83 @@
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use clap::ValueEnum;
+use tracing::Level;
+use tracing_subscriber::fmt::MakeWriter;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    // one object per line with the file, offsets and counts as fields
+    Json,
+}
+
+// set by `init`, embedding the library draws no bars
+static PROGRESS: AtomicBool = AtomicBool::new(false);
+
+// bars are only drawn for someone watching a terminal
+pub fn progress() -> bool {
+    PROGRESS.load(Ordering::Relaxed)
+}
+
+// for output that would be garbled by bars, like json progress records on stderr
+pub fn hide_progress() {
+    PROGRESS.store(false, Ordering::Relaxed);
+}
+
+// stderr, with each event written over the line of the bar, which redraws itself on its next update
+struct OverBars;
+
+impl<'a> MakeWriter<'a> for OverBars {
+    type Writer = OverBarsWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        OverBarsWriter { cleared: false }
+    }
+}
+
+struct OverBarsWriter {
+    cleared: bool,
+}
+
+impl Write for OverBarsWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut stderr = std::io::stderr().lock();
+
+        if !self.cleared {
+            stderr.write_all(b"\r\x1b[2K")?;
+            self.cleared = true;
+        }
+
+        stderr.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::stderr().flush()
+    }
+}
+
+// info by default, every -v shows more and every -q less, errors are always shown
+pub fn init(format: LogFormat, verbose: u8, quiet: u8) {
+    let level =
+        match i16::from(verbose) - i16::from(quiet) {
+            ..=-2 => Level::ERROR,
+            -1 => Level::WARN,
+            0 => Level::INFO,
+            1 => Level::DEBUG,
+            _ => Level::TRACE,
+        };
+
+    let terminal = std::io::stderr().is_terminal();
+
+    PROGRESS.store(terminal && format == LogFormat::Text && quiet == 0, Ordering::Relaxed);
+
+    let builder = tracing_subscriber::fmt().with_max_level(level).with_target(false);
+
+    match format {
+        LogFormat::Json => builder.json().with_writer(std::io::stderr).init(),
+        LogFormat::Text if terminal => builder.with_writer(OverBars).init(),
+        LogFormat::Text => builder.with_ansi(false).with_writer(std::io::stderr).init(),
+    }
+}
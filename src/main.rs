@@ -1,284 +1,330 @@
-#![feature(slice_internals)]
-
-extern crate core;
-
-use std::fs::{DirEntry, File};
-use std::io::{BufRead, BufReader, Error, Write};
-use std::ops::AddAssign;
-use std::path::Path;
-
-use kdam::{BarExt, Column, RichProgress, tqdm};
-use kdam::term::Colorizer;
-use rayon::prelude::*;
-use ruzstd::{FrameDecoder, StreamingDecoder};
-use serde::{Deserialize, Serialize};
-
-use crate::serializer::{serialize_with_writer, SerializerFeedback};
-use crate::text::text_item::{PooMap, PooMapInner, TextItem};
-
-pub mod text;
-pub mod serializer;
-
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Comment {
-    pub author: String,
-    pub body: String,
-    //#[serde(rename = "created_utc")]
-    //pub created_utc: u64,
-}
+use clap::Parser;
 
-fn read_until<R: BufRead + ?Sized>(r: &mut R, delim: u8, buf: &mut Vec<u8>) -> Result<usize, Error> {
-    unsafe {
-        let mut read = 0;
-        loop {
-            let (done, used) = {
-                let available = match r.fill_buf() {
-                    Ok(n) => n,
-                    Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
-                    Err(e) => return Err(e),
-                };
-                match core::slice::memchr::memchr(delim, available) {
-                    Some(i) => {
-                        buf.extend_from_slice(&available[..=i]);
-                        (true, i + 1)
-                    }
-                    None => {
-                        buf.extend_from_slice(available);
-                        (false, available.len())
-                    }
-                }
-            };
-            r.consume(used);
-            read += used;
-            if done || used == 0 {
-                return Ok(read);
-            }
-        }
+use poo::cli::{Cli, Command};
+use poo::config::Config;
+use poo::error::{PooError, Result};
+use poo::{commands, log};
+
+fn main() {
+    let cli = Cli::parse();
+
+    log::init(cli.log_format, cli.verbose, cli.quiet);
+
+    if let Err(e) = run(cli) {
+        tracing::error!("{}", e);
+        std::process::exit(1);
     }
 }
 
-fn run_for_file(path: &Path) {
-    let name = path.file_name().unwrap().to_str().unwrap().to_string();
+fn run(cli: Cli) -> Result<()> {
+    if let Some(threads) = cli.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()?;
+    }
+
+    let mut config = Config::load(cli.config.as_deref()).map_err(|source| PooError::Load { what: "the config", source })?;
+
+    match &cli.command {
+        Command::Ingest(args) => {
+            config.output.apply(&args.output);
+
+            if let Some(lines) = args.checkpoint_lines {
+                config.ingest.checkpoint_lines = lines;
+            }
+
+            config.ingest.metadata |= args.metadata;
+            if let Some(max) = args.max_errors {
+                config.ingest.max_errors = max;
+            }
+
+            if let Some(policy) = args.on_max_errors {
+                config.ingest.on_max_errors = policy;
+            }
+
+            config.ingest.log_errors |= args.log_errors;
+
+            if let Some(kind) = args.kind {
+                config.ingest.kind = kind;
+            }
+
+            if let Some(jobs) = args.jobs {
+                config.ingest.jobs = jobs;
+            }
+
+            if args.memory_budget.is_some() {
+                config.ingest.memory_budget = args.memory_budget;
+            }
+
+            if args.spill_threshold.is_some() {
+                config.ingest.spill_threshold = args.spill_threshold;
+            }
+
+            if let Some(dir) = &args.spill_dir {
+                config.ingest.spill_dir = Some(dir.clone());
+            }
+
+            if args.max_author_words.is_some() {
+                config.ingest.max_author_words = args.max_author_words;
+            }
+
+            config.sketch.enabled |= args.sketch;
+
+            if let Some(width) = args.sketch_width {
+                config.sketch.width = width;
+            }
+
+            if let Some(heavy_hitters) = args.heavy_hitters {
+                config.sketch.heavy_hitters = heavy_hitters;
+            }
+
+            if args.max_memory.is_some() {
+                config.ingest.max_memory = args.max_memory;
+            }
+
+            if let Some(action) = args.on_max_memory {
+                config.ingest.on_max_memory = action;
+            }
+
+            if let Some(period) = args.period {
+                config.ingest.period = period;
+            }
+
+            if let Some(log) = args.window_log_max {
+                config.ingest.window_log_max = log;
+            }
+
+            if let Some(decoder) = args.decoder {
+                config.ingest.decoder = decoder;
+            }
+
+            if let Some(size) = args.read_ahead {
+                config.ingest.read_ahead = size;
+            }
+
+            if let Some(io) = args.read_ahead_io {
+                config.ingest.read_ahead_io = io;
+            }
+
+            if let Some(throughput) = args.throughput {
+                config.ingest.throughput = throughput;
+            }
+
+            config.ingest.recursive |= args.recursive;
+
+            if !args.include.is_empty() {
+                config.ingest.include = args.include.clone();
+            }
+
+            if !args.exclude.is_empty() {
+                config.ingest.exclude = args.exclude.clone();
+            }
+
+            if args.metrics_addr.is_some() {
+                config.ingest.metrics_addr = args.metrics_addr;
+            }
+
+            if let Some(format) = args.progress {
+                config.ingest.progress = format;
+            }
 
-    let mut dec = FrameDecoder::new();
+            if let Some(path) = &args.progress_file {
+                config.ingest.progress_file = Some(path.clone());
+            }
 
-    dec.init(File::open(path).unwrap()).unwrap();
+            if let Some(secs) = args.progress_interval {
+                config.ingest.progress_interval = secs;
+            }
 
-    let mut ti = TextItem::new();
+            if let Some(url) = &args.api_url {
+                config.api.base_url = url.clone();
+            }
 
-    let size = dec.content_size().unwrap_or(0) as usize;
+            if !args.subreddits.is_empty() {
+                config.filter.subreddits = args.subreddits.clone();
+            }
 
-    println!("size: {} GB", size as f64 / 1024.0 / 1024.0 / 1024.0);
+            if !args.exclude_subreddits.is_empty() {
+                config.filter.exclude_subreddits = args.exclude_subreddits.clone();
+            }
 
-    let mut pb = RichProgress::new(
-        tqdm!(
-            total = size,
-            unit_scale = true,
-            unit_divisor = 1024,
-            unit = "B"
-        ),
-        vec![
-            Column::Spinner(
-                "⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏"
-                    .chars()
-                    .map(|x| x.to_string())
-                    .collect::<Vec<String>>(),
-                80.0,
-                1.0,
-            ),
-            Column::text("[bold blue]?"),
-            Column::Bar,
-            Column::Percentage(1),
-            Column::text("•"),
-            Column::CountTotal,
-            Column::text("•"),
-            Column::Rate,
-            Column::text("•"),
-            Column::RemainingTime,
-        ],
-    );
+            if args.after.is_some() {
+                config.filter.after = args.after;
+            }
 
-    pb.write(format!("Loading zstd for file {}...", name).colorize("bold blue"));
+            if args.before.is_some() {
+                config.filter.before = args.before;
+            }
 
-    let mut file = File::open(path).unwrap();
-    let mut decoder =
-        BufReader::new(StreamingDecoder::new(&mut file).unwrap());
+            if let Some(path) = &args.exclude_authors_file {
+                config.filter.exclude_authors_file = Some(path.clone());
+            }
 
-    pb.write(format!("Processing {}...", name).colorize("green"));
+            config.filter.exclude_bots |= args.exclude_bots;
 
-    let mut len_read = 0usize;
-    let mut i = 0u64;
+            if let Some(policy) = args.deleted {
+                config.filter.deleted = policy;
+            }
 
-    let per_iter = 10000usize;
+            if let Some(mode) = args.dedup {
+                config.dedup.mode = mode;
+            }
 
-    let mut err_cnt = 0usize;
+            if let Some(weight) = args.dedup_weight {
+                config.dedup.weight = weight;
+            }
 
-    'a: loop {
-        let mut comments = Vec::<(String, String)>::new();
+            if let Some(mode) = args.tokenizer {
+                config.tokenizer.mode = mode;
+            }
 
-        'b: for _ in 0..per_iter {
-            let mut line = Vec::new();
+            config.markdown.strip |= args.strip_markdown;
+            config.markdown.quotes &= !args.keep_quotes;
+            config.markdown.code &= !args.keep_code;
+            config.markdown.link_urls &= !args.keep_link_urls;
 
-            if let Err(x) = read_until(&mut decoder, b'\n', &mut line) {
-                dbg!(x);
+            if let Some(mode) = args.language_mode {
+                config.language.mode = mode;
+            }
 
-                break 'a;
+            if !args.languages.is_empty() {
+                config.language.languages = args.languages.clone();
             }
 
-            if line.len() == 0 {
-                err_cnt += 1;
+            if let Some(confidence) = args.min_language_confidence {
+                config.language.min_confidence = confidence;
+            }
+
+            config.language.keep_undetermined &= !args.drop_undetermined;
+
+            config.tokenizer.normalize |= args.normalize_unicode;
+            config.tokenizer.lowercase &= !args.keep_case;
+            config.tokenizer.emoji |= args.keep_emoji;
+
+            if let Some(action) = args.urls {
+                config.tokenizer.urls = action;
+            }
+
+            if let Some(action) = args.user_mentions {
+                config.tokenizer.users = action;
+            }
 
-                if err_cnt > 10 {
-                    break 'a;
-                }
+            if let Some(action) = args.subreddit_mentions {
+                config.tokenizer.subreddits = action;
+            }
 
-                break 'b;
+            if let Some(action) = args.numbers {
+                config.tokenizer.numbers = action;
             }
 
-            match simd_json::from_slice::<Comment>(&mut line) {
-                Ok(x) => comments.push((x.author, x.body)),
-                Err(x) => {
-                    err_cnt += 1;
+            if let Some(len) = args.min_token_len {
+                config.tokenizer.min_token_len = Some(len);
+            }
 
-                    if err_cnt > 10 {
-                        break 'a;
-                    }
+            if let Some(len) = args.max_token_len {
+                config.tokenizer.max_token_len = Some(len);
+            }
 
-                    continue;
-                }
+            // a list implies removing it
+            if let Some(path) = &args.stopwords_file {
+                config.stopwords.file = Some(path.clone());
+                config.stopwords.remove = true;
             }
 
-            len_read += line.len();
-            i += 1;
+            config.stopwords.remove |= args.remove_stopwords;
+            config.stopwords.remove &= !args.keep_stopwords;
+
+            // either one replaces the other from the config
+            if args.stem.is_some() {
+                config.tokenizer.stem = args.stem;
+                config.tokenizer.lemmas = None;
+            }
+
+            if let Some(path) = &args.lemmas {
+                config.tokenizer.lemmas = Some(path.clone());
+                config.tokenizer.stem = None;
+            }
+
+            if let Some(n) = args.ngrams {
+                config.tokenizer.ngrams = n;
+            }
+
+            if let Some(separator) = &args.ngram_separator {
+                config.tokenizer.ngram_separator = separator.clone();
+            }
+
+            config.tokenizer.unigrams &= !args.no_unigrams;
+
+            if args.hash_bits.is_some() {
+                config.tokenizer.hash_bits = args.hash_bits;
+            }
+
+            config.prune.apply(&args.prune);
+
+            commands::ingest::run(args, &config)?
         }
+        Command::Migrate(args) => {
+            config.output.apply(&args.output);
+            commands::migrate::run(args, &config)?
+        }
+        Command::Merge(args) => {
+            if let Some(level) = args.compression_level {
+                config.output.compression_level = level;
+            }
 
-        ti.ingest(
-            &comments
-                .par_iter()
-                .map(|(author, comment)|
-                    (
-                        author.as_bytes().to_vec(),
-                        TextItem::process_alt(&comment))
-                )
-                .fold(
-                    || PooMap::new(),
-                    |mut acc, (author, freqs)| {
-                        let author_map =
-                            &mut acc
-                                .entry(author.clone())
-                                .or_insert_with(PooMapInner::new);
-
-                        for (word, freq) in freqs.iter() {
-                            author_map
-                                .entry(word.clone())
-                                .or_insert(0)
-                                .add_assign(*freq);
-                        }
-
-                        acc
-                    },
-                )
-                .reduce(
-                    || PooMap::new(),
-                    |mut acc, mut all_freqs| {
-                        for (author, freqs) in all_freqs.iter() {
-                            let author_map =
-                                &mut acc
-                                    .entry(author.clone())
-                                    .or_insert_with(PooMapInner::new);
-
-                            for (word, freq) in freqs.iter() {
-                                author_map
-                                    .entry(word.clone())
-                                    .or_insert(0)
-                                    .add_assign(*freq);
-                            }
-                        }
-
-                        acc
-                    },
-                ),
-        );
-
-        pb.update_to(len_read);
-    }
+            commands::merge::run(args, &config)?
+        }
+        Command::Prune(args) => {
+            if let Some(level) = args.compression_level {
+                config.output.compression_level = level;
+            }
 
-    let mut file =
-        File::create(
-            path
-                .clone()
-                .with_file_name(
-                    format!("{}.users.freqs", &name),
-                )
-        ).unwrap();
-
-    let mut encoder = zstd::stream::Encoder::new(&mut file, 10).unwrap();
-
-    pb.pb.set_total(ti.word_freqs.len());
-
-    serialize_with_writer(
-        &ti.word_freqs,
-        &mut encoder,
-        |fb|
-            match fb {
-                SerializerFeedback::Message(msg) => {
-                    pb.write(format!("{}", msg).colorize("green"));
-                },
-                SerializerFeedback::Total(total) => {
-                    pb.pb.set_total(total as usize);
-                },
-                SerializerFeedback::Progress(progress) => {
-                    pb.update_to(progress as usize);
-                },
-            },
-    )
-        .map_err(|x|
-            eprintln!("Error serializing: {}", x)
-        );
-
-    if let Err(e) = encoder.finish() {
-        eprintln!("Error finalizing file: {}", e);
+            config.prune.apply(&args.prune);
+            commands::prune::run(args, &config)?
+        }
+        Command::Query(args) => commands::query::run(args)?,
+        Command::Stats(args) => commands::stats::run(args)?,
+        Command::Verify(args) => commands::verify::run(args)?,
+        Command::Repair(args) => {
+            config.output.apply(&args.output);
+            commands::repair::run(args, &config)?
+        }
+        Command::Export(args) => commands::export::run(args)?,
+        Command::Tfidf(args) => {
+            config.tfidf.apply(&args.weighting);
+            commands::tfidf::run(args, &config)?
+        }
+        Command::Segment(args) => {
+            config.tfidf.apply(&args.weighting);
+            config.segment.apply(args);
+            commands::segment::run(args, &config)?
+        }
+        Command::Similarity(args) => {
+            config.tfidf.apply(&args.weighting);
+            commands::similarity::run(args, &config)?
+        }
+        Command::Duplicates(args) => commands::duplicates::run(args)?,
+        Command::Elastic(args) => {
+            config.tfidf.apply(&args.weighting);
+            commands::elastic::run(args, &config)?
+        }
+        Command::Serve(args) => commands::serve::run(args)?,
+        Command::Corpus(args) => {
+            if let Some(kind) = args.kind {
+                config.ingest.kind = kind;
+            }
+
+            commands::corpus::run(args, &config)?
+        }
+        Command::Embed(args) => {
+            if let Some(kind) = args.kind {
+                config.ingest.kind = kind;
+            }
+
+            config.embedding.apply(args);
+            commands::embed::run(args, &config)?
+        }
     }
-}
 
-fn main() {
-    // find folder located at first argument
-    let path = std::env::args().nth(1).expect("No path provided");
-    let path = Path::new(&path);
-
-    // find all files in folder
-    let files = std::fs::read_dir(path).expect("Could not read directory");
-
-    // filter for files ending with .zst
-    let mut files =
-        files
-            .filter_map(|f| f.ok())
-            .filter(|f| {
-                f.path()
-                    .extension()
-                    .map(|ext| ext == "zst")
-                    .unwrap_or(false)
-            })
-            .collect::<Vec<DirEntry>>();
-
-    files.sort_by(|a, b| a.path().file_name().cmp(&b.path().file_name()));
-
-    files
-        .iter()
-        .for_each(|f| {
-            // check if <f.path>.users.freqs exists
-            let freqs_path = f.path().with_file_name(
-                format!(
-                    "{}.users.freqs",
-                    f.path().file_name().unwrap().to_str().unwrap()
-                )
-            );
-
-            if freqs_path.exists() {
-                return;
-            }
-
-            run_for_file(&f.path());
-        });
+    Ok(())
 }
@@ -0,0 +1,207 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use twox_hash::XxHash64;
+
+use crate::commands::tmp_path;
+use crate::config::Config;
+use crate::dump;
+use crate::error::{PooError, Result};
+use crate::index::FreqIndex;
+
+// json, one per output directory. not `.json`, which would be listed as a dump
+pub const MANIFEST_NAME: &str = "poo.manifest";
+
+// bytes hashed at either end of a dump, hashing all of a 30 GB dump would take as long as reading it
+const SAMPLE: u64 = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Started,
+    Complete,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub input: String,
+    // see `source_hash`
+    pub source: String,
+    pub version: String,
+    // see `config_hash`
+    pub config: String,
+    pub status: Status,
+    // of a complete `.freqs` output, from its trailer, none for the others and manifests of older builds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authors: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub words: Option<u64>,
+    // unix seconds of the last change
+    pub updated: u64,
+}
+
+// by the file name of the output
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    outputs: BTreeMap<String, Entry>,
+}
+
+// why an output is written again, none if it's current. the version is only recorded, `migrate` brings the outputs
+// of older versions up to date without reading the dumps again
+pub fn stale(entry: Option<&Entry>, source: &str, config: &str) -> Option<&'static str> {
+    match entry {
+        // outputs from before the manifest are trusted like they used to be
+        None => None,
+        Some(entry) if entry.status != Status::Complete => Some("it was not completed"),
+        Some(entry) if entry.source != source => Some("the input changed"),
+        Some(entry) if entry.config != config => Some("the config changed"),
+        Some(_) => None,
+    }
+}
+
+// the size and both ends of a file, or the name of a stream
+pub fn source_hash(path: &Path) -> std::io::Result<String> {
+    let mut hasher = XxHash64::with_seed(0);
+
+    if dump::is_stream(path) {
+        hasher.write(path.as_os_str().as_encoded_bytes());
+
+        return Ok(format!("{:016x}", hasher.finish()));
+    }
+
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+
+    hasher.write_u64(size);
+
+    let mut buf = Vec::with_capacity(SAMPLE as usize);
+
+    (&mut file).take(SAMPLE).read_to_end(&mut buf)?;
+    hasher.write(&buf);
+
+    if size > SAMPLE {
+        buf.clear();
+        file.seek(SeekFrom::Start(size.saturating_sub(SAMPLE).max(SAMPLE)))?;
+        file.read_to_end(&mut buf)?;
+        hasher.write(&buf);
+    }
+
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+// the settings that change what ends up in an output, not how fast it gets there
+pub fn config_hash(config: &Config) -> String {
+    let ingest = &config.ingest;
+    let output = &config.output;
+
+    let settings =
+        format!(
+            "{:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?}",
+            config.tokenizer,
+            config.stopwords,
+            config.markdown,
+            config.language,
+            config.filter,
+            config.dedup,
+            config.sketch,
+            config.prune,
+            ingest.kind,
+            ingest.metadata,
+            ingest.period,
+            ingest.max_author_words,
+            output.format,
+            output.codec,
+            output.dictionary,
+            output.shards,
+            output.shard_by,
+        );
+
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(settings.as_bytes());
+
+    format!("{:016x}", hasher.finish())
+}
+
+// the counts in the trailer of a `.freqs` output with an index, none for other outputs
+pub fn totals(output: &Path) -> Option<(u64, u64)> {
+    let mut file = File::open(output).ok()?;
+
+    FreqIndex::read(&mut file).ok()??.counts(&mut file).ok()?
+}
+
+// the manifests of all output directories of a run, read on first use and rewritten on every change
+#[derive(Default)]
+pub struct Manifests {
+    dirs: Mutex<HashMap<PathBuf, Manifest>>,
+}
+
+fn locate(output: &Path) -> (PathBuf, String) {
+    let dir = output.parent().map(Path::to_path_buf).unwrap_or_default();
+    let name = output.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+
+    (dir, name)
+}
+
+fn manifest_path(dir: &Path) -> PathBuf {
+    dir.join(MANIFEST_NAME)
+}
+
+fn read(dir: &Path) -> Manifest {
+    let path = manifest_path(dir);
+
+    match std::fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+            warn!(path = %path.display(), error = %e, "Ignoring unreadable manifest");
+            Manifest::default()
+        }),
+        Err(_) => Manifest::default(),
+    }
+}
+
+impl Manifests {
+    pub fn get(&self, output: &Path) -> Option<Entry> {
+        let (dir, name) = locate(output);
+
+        let mut dirs = self.dirs.lock().unwrap();
+
+        dirs.entry(dir.clone()).or_insert_with(|| read(&dir)).outputs.get(&name).cloned()
+    }
+
+    pub fn set(&self, output: &Path, input: &Path, source: &str, config: &str, status: Status, totals: Option<(u64, u64)>) -> Result<()> {
+        let (dir, name) = locate(output);
+
+        let entry = Entry {
+            input: input.display().to_string(),
+            source: source.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            config: config.to_string(),
+            status,
+            authors: totals.map(|(authors, _)| authors),
+            words: totals.map(|(_, words)| words),
+            updated: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+        };
+
+        let mut dirs = self.dirs.lock().unwrap();
+
+        let manifest = dirs.entry(dir.clone()).or_insert_with(|| read(&dir));
+        manifest.outputs.insert(name, entry);
+
+        // written aside and renamed, so an interrupted run leaves the last complete manifest
+        let path = manifest_path(&dir);
+        let tmp = tmp_path(&path);
+
+        let json = serde_json::to_vec_pretty(manifest).map_err(|e| PooError::Write { path: tmp.clone(), source: e.into() })?;
+
+        std::fs::write(&tmp, json).map_err(PooError::write(&tmp))?;
+        std::fs::rename(&tmp, &path).map_err(PooError::write(&path))
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use tracing::{debug, info};
+
+use crate::error::{PooError, Result};
+
+#[derive(Default)]
+struct FileProgress {
+    // decompressed bytes read and the declared size, 0 if unknown
+    offset: u64,
+    size: u64,
+    lines: u64,
+    authors: u64,
+    done: bool,
+}
+
+// what a long ingest run has done so far, scraped by prometheus from `--metrics-addr`
+#[derive(Default)]
+pub struct Metrics {
+    lines: AtomicU64,
+    bytes: AtomicU64,
+    errors: AtomicU64,
+    // estimated size of the state of all files
+    memory: AtomicU64,
+    files: Mutex<BTreeMap<String, FileProgress>>,
+}
+
+impl Metrics {
+    // a resumed file starts at its checkpoint
+    pub fn start_file(&self, name: &str, size: u64, offset: u64, lines: u64) {
+        self.files.lock().unwrap().insert(name.to_string(), FileProgress { offset, size, lines, ..Default::default() });
+    }
+
+    // `offset` and `lines` are where the file is now, the totals grow by what was read since the last call
+    pub fn progress(&self, name: &str, offset: u64, lines: u64, authors: u64, errors: u64) {
+        let mut files = self.files.lock().unwrap();
+        let file = files.entry(name.to_string()).or_default();
+
+        self.bytes.fetch_add(offset.saturating_sub(file.offset), Ordering::Relaxed);
+        self.lines.fetch_add(lines.saturating_sub(file.lines), Ordering::Relaxed);
+        self.errors.fetch_add(errors, Ordering::Relaxed);
+
+        file.offset = offset;
+        file.lines = lines;
+        file.authors = authors;
+    }
+
+    pub fn finish_file(&self, name: &str) {
+        if let Some(file) = self.files.lock().unwrap().get_mut(name) {
+            file.done = true;
+        }
+    }
+
+    pub fn set_memory(&self, bytes: u64) {
+        self.memory.store(bytes, Ordering::Relaxed);
+    }
+
+    // the prometheus text format
+    fn render(&self) -> String {
+        let mut out = String::new();
+
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, u64)]| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+
+            for (labels, value) in samples {
+                let _ = writeln!(out, "{}{} {}", name, labels, value);
+            }
+        };
+
+        let total = |counter: &AtomicU64| [(String::new(), counter.load(Ordering::Relaxed))];
+
+        metric("poo_lines_total", "counter", "Lines read from all dumps, rate() gives lines per second", &total(&self.lines));
+        metric("poo_decompressed_bytes_total", "counter", "Decompressed bytes read from all dumps", &total(&self.bytes));
+        metric("poo_unparseable_lines_total", "counter", "Lines that were not valid comments or submissions", &total(&self.errors));
+        metric("poo_state_bytes", "gauge", "Estimated size of the aggregation state of all files", &total(&self.memory));
+
+        if let Some(resident) = resident_bytes() {
+            metric("poo_resident_memory_bytes", "gauge", "Resident memory of the process", &[(String::new(), resident)]);
+        }
+
+        let files = self.files.lock().unwrap();
+
+        let per_file = |value: fn(&FileProgress) -> u64| {
+            files.iter()
+                .map(|(name, file)| (format!("{{file=\"{}\"}}", escape(name)), value(file)))
+                .collect::<Vec<_>>()
+        };
+
+        let authors = files.values().filter(|file| !file.done).map(|file| file.authors).sum::<u64>();
+
+        metric("poo_authors", "gauge", "Authors in the state of the files being processed", &[(String::new(), authors)]);
+        metric("poo_file_read_bytes", "gauge", "Decompressed bytes read from a dump", &per_file(|file| file.offset));
+        metric("poo_file_size_bytes", "gauge", "Declared decompressed size of a dump, 0 if unknown", &per_file(|file| file.size));
+        metric("poo_file_lines", "gauge", "Lines read from a dump", &per_file(|file| file.lines));
+        metric("poo_file_authors", "gauge", "Authors in the state of a dump", &per_file(|file| file.authors));
+        metric("poo_file_done", "gauge", "1 once a dump has been written", &per_file(|file| file.done as u64));
+
+        out
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// linux only, the second field of statm is in pages
+fn resident_bytes() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages = statm.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+
+    Some(pages * 4096)
+}
+
+fn respond(stream: TcpStream, metrics: &Metrics) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    let mut reader = BufReader::new(stream);
+
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+
+    // the headers don't matter
+    let mut header = String::new();
+
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let (status, body) =
+        match request.split_whitespace().nth(1) {
+            Some("/metrics") => ("200 OK", metrics.render()),
+            _ => ("404 Not Found", "not found, try /metrics\n".to_string()),
+        };
+
+    write!(
+        reader.get_mut(),
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body,
+    )
+}
+
+// answers scrapes on a background thread for as long as the process runs
+pub fn serve(addr: SocketAddr, metrics: Arc<Metrics>) -> Result<()> {
+    let listener = TcpListener::bind(addr).map_err(|source| PooError::Listen { addr, source })?;
+
+    info!(addr = %listener.local_addr().unwrap_or(addr), "Serving metrics");
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if let Err(e) = respond(stream, &metrics) {
+                debug!(error = %e, "Could not answer a scrape");
+            }
+        }
+    });
+
+    Ok(())
+}
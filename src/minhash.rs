@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use twox_hash::XxHash64;
+
+// minhash signatures of word sets (Broder, 1997), the share of equal values of two signatures estimates the jaccard
+// similarity of the sets, and lsh banding that finds the pairs likely above a threshold without comparing all
+pub struct MinHasher {
+    salts: Vec<u64>,
+}
+
+pub fn word_hash(word: &[u8]) -> u64 {
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(word);
+
+    hasher.finish()
+}
+
+// the splitmix64 finalizer, salted it stands in for a random permutation of the word hashes
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+
+    x ^ (x >> 31)
+}
+
+impl MinHasher {
+    pub fn new(hashes: usize, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        Self { salts: (0..hashes).map(|_| rng.gen()).collect() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.salts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.salts.is_empty()
+    }
+
+    // written into `signature`, which has a value per hash
+    pub fn sign(&self, words: &[u64], signature: &mut [u64]) {
+        signature.fill(u64::MAX);
+
+        for &word in words {
+            for (value, salt) in signature.iter_mut().zip(self.salts.iter()) {
+                *value = (*value).min(mix(word ^ salt));
+            }
+        }
+    }
+}
+
+pub fn estimate(a: &[u64], b: &[u64]) -> f64 {
+    a.iter().zip(b).filter(|(a, b)| a == b).count() as f64 / a.len().max(1) as f64
+}
+
+// the pairs of signatures, given one after the other in `signatures`, that agree on all values of at least one
+// band, each pair once with the lower index first. buckets of more than `max_bucket` authors are skipped, their
+// number is returned with the pairs
+pub fn candidates(signatures: &[u64], hashes: usize, bands: usize, max_bucket: usize) -> (Vec<(u32, u32)>, usize) {
+    let rows = hashes / bands.max(1);
+    let authors = signatures.len() / hashes.max(1);
+
+    let per_band =
+        (0..bands)
+            .into_par_iter()
+            .map(|band| {
+                let mut buckets = HashMap::<u64, Vec<u32>, BuildHasherDefault<XxHash64>>::default();
+
+                for author in 0..authors {
+                    let start = author * hashes + band * rows;
+
+                    let mut hasher = XxHash64::with_seed(band as u64);
+
+                    for value in &signatures[start..start + rows] {
+                        hasher.write_u64(*value);
+                    }
+
+                    buckets.entry(hasher.finish()).or_default().push(author as u32);
+                }
+
+                let mut pairs = Vec::new();
+                let mut skipped = 0;
+
+                for bucket in buckets.values().filter(|bucket| bucket.len() > 1) {
+                    if bucket.len() > max_bucket {
+                        skipped += 1;
+                        continue;
+                    }
+
+                    for (i, &a) in bucket.iter().enumerate() {
+                        for &b in &bucket[i + 1..] {
+                            pairs.push((a, b));
+                        }
+                    }
+                }
+
+                (pairs, skipped)
+            })
+            .collect::<Vec<_>>();
+
+    let skipped = per_band.iter().map(|(_, skipped)| skipped).sum();
+
+    let mut pairs = per_band.into_iter().flat_map(|(pairs, _)| pairs).collect::<Vec<_>>();
+
+    pairs.par_sort_unstable();
+    pairs.dedup();
+
+    (pairs, skipped)
+}
@@ -0,0 +1,272 @@
+use std::io::{BufWriter, Error, Write};
+
+use flate2::Crc;
+
+// numpy `.npz` archives, a zip of uncompressed `.npy` arrays like `np.savez` writes, streamed without seeking
+//
+// the crc of an array is only known after it, so it goes into a data descriptor after the array and the central
+// directory, zip64 records make room for arrays and archives over 4 GiB
+
+const LOCAL_HEADER: u32 = 0x04034b50;
+const DATA_DESCRIPTOR: u32 = 0x08074b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const ZIP64_END: u32 = 0x06064b50;
+const ZIP64_LOCATOR: u32 = 0x07064b50;
+const END: u32 = 0x06054b50;
+
+// sizes are in a data descriptor
+const FLAGS: u16 = 1 << 3;
+// 1980-01-01, numpy doesn't look at it
+const DATE: u16 = (1 << 5) | 1;
+const ZIP64_VERSION: u16 = 45;
+const VERSION: u16 = 20;
+
+const MAX_32: u64 = u32::MAX as u64;
+
+struct Entry {
+    name: String,
+    crc: u32,
+    size: u64,
+    offset: u64,
+}
+
+impl Entry {
+    fn zip64(&self) -> bool {
+        self.size >= MAX_32 || self.offset >= MAX_32
+    }
+}
+
+// counts what goes through, for the offsets of the central directory
+struct Counted<W: Write> {
+    out: W,
+    written: u64,
+}
+
+impl<W: Write> Write for Counted<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.out.write(buf)?;
+        self.written += n as u64;
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// the bytes of one array, with their crc
+struct Array<'a, W: Write> {
+    out: &'a mut Counted<W>,
+    crc: Crc,
+}
+
+impl<W: Write> Write for Array<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.out.write(buf)?;
+        self.crc.update(&buf[..n]);
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+pub struct NpzWriter<W: Write> {
+    out: Counted<W>,
+    entries: Vec<Entry>,
+}
+
+// the `.npy` 1.0 header of a little-endian array, padded to a multiple of 64 bytes
+fn npy_header(descr: &str, shape: &[u64]) -> Vec<u8> {
+    let shape =
+        match shape {
+            [n] => format!("({},)", n),
+            _ => format!("({})", shape.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(", ")),
+        };
+
+    let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", descr, shape).into_bytes();
+
+    // magic, version, header length, header and a newline
+    let unpadded = 6 + 2 + 2 + header.len() + 1;
+    header.resize(header.len() + (64 - unpadded % 64) % 64, b' ');
+    header.push(b'\n');
+
+    let mut npy = b"\x93NUMPY\x01\x00".to_vec();
+    npy.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    npy.extend_from_slice(&header);
+
+    npy
+}
+
+impl<W: Write> NpzWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out: Counted { out, written: 0 }, entries: Vec::new() }
+    }
+
+    fn put(&mut self, bytes: &[&[u8]]) -> std::io::Result<()> {
+        for bytes in bytes {
+            self.out.write_all(bytes)?;
+        }
+
+        Ok(())
+    }
+
+    // `<name>.npy` of `descr` elements, e.g. `<i8`, `fill` writes exactly `item_size` bytes for each of them
+    pub fn array(
+        &mut self,
+        name: &str,
+        descr: &str,
+        shape: &[u64],
+        item_size: u64,
+        fill: impl FnOnce(&mut dyn Write) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        let header = npy_header(descr, shape);
+        let size = header.len() as u64 + shape.iter().product::<u64>() * item_size;
+
+        let entry = Entry { name: format!("{}.npy", name), crc: 0, size, offset: self.out.written };
+
+        let (version, extra) =
+            match entry.zip64() {
+                // the sizes are in the data descriptor, zeros here
+                true => (ZIP64_VERSION, [&1u16.to_le_bytes()[..], &16u16.to_le_bytes(), &[0; 16]].concat()),
+                false => (VERSION, Vec::new()),
+            };
+
+        self.put(&[
+            &LOCAL_HEADER.to_le_bytes(),
+            &version.to_le_bytes(),
+            &FLAGS.to_le_bytes(),
+            &0u16.to_le_bytes(),
+            &0u16.to_le_bytes(),
+            &DATE.to_le_bytes(),
+            &0u32.to_le_bytes(),
+            &(if entry.zip64() { u32::MAX } else { 0 }).to_le_bytes(),
+            &(if entry.zip64() { u32::MAX } else { 0 }).to_le_bytes(),
+            &(entry.name.len() as u16).to_le_bytes(),
+            &(extra.len() as u16).to_le_bytes(),
+            entry.name.as_bytes(),
+            &extra,
+        ])?;
+
+        let start = self.out.written;
+
+        let mut array = Array { out: &mut self.out, crc: Crc::new() };
+        array.write_all(&header)?;
+
+        {
+            let mut buffered = BufWriter::with_capacity(1 << 16, &mut array);
+            fill(&mut buffered)?;
+            buffered.flush()?;
+        }
+
+        let crc = array.crc.sum();
+
+        if self.out.written - start != size {
+            return Err(Error::other(format!("{} has {} bytes, not {}", entry.name, self.out.written - start, size)));
+        }
+
+        match entry.zip64() {
+            true => self.put(&[&DATA_DESCRIPTOR.to_le_bytes(), &crc.to_le_bytes(), &size.to_le_bytes(), &size.to_le_bytes()])?,
+            false => {
+                self.put(&[
+                    &DATA_DESCRIPTOR.to_le_bytes(),
+                    &crc.to_le_bytes(),
+                    &(size as u32).to_le_bytes(),
+                    &(size as u32).to_le_bytes(),
+                ])?
+            }
+        }
+
+        self.entries.push(Entry { crc, ..entry });
+
+        Ok(())
+    }
+
+    // the central directory, the archive can't be read without it
+    pub fn finish(mut self) -> std::io::Result<W> {
+        let start = self.out.written;
+        let entries = std::mem::take(&mut self.entries);
+
+        for entry in entries.iter() {
+            let mut extra = Vec::new();
+
+            if entry.size >= MAX_32 {
+                extra.extend_from_slice(&entry.size.to_le_bytes());
+                extra.extend_from_slice(&entry.size.to_le_bytes());
+            }
+
+            if entry.offset >= MAX_32 {
+                extra.extend_from_slice(&entry.offset.to_le_bytes());
+            }
+
+            if !extra.is_empty() {
+                extra = [&1u16.to_le_bytes()[..], &(extra.len() as u16).to_le_bytes(), &extra].concat();
+            }
+
+            let version = if entry.zip64() { ZIP64_VERSION } else { VERSION };
+
+            self.put(&[
+                &CENTRAL_HEADER.to_le_bytes(),
+                &version.to_le_bytes(),
+                &version.to_le_bytes(),
+                &FLAGS.to_le_bytes(),
+                &0u16.to_le_bytes(),
+                &0u16.to_le_bytes(),
+                &DATE.to_le_bytes(),
+                &entry.crc.to_le_bytes(),
+                &(entry.size.min(MAX_32) as u32).to_le_bytes(),
+                &(entry.size.min(MAX_32) as u32).to_le_bytes(),
+                &(entry.name.len() as u16).to_le_bytes(),
+                &(extra.len() as u16).to_le_bytes(),
+                &0u16.to_le_bytes(),
+                &0u16.to_le_bytes(),
+                &0u16.to_le_bytes(),
+                &0u32.to_le_bytes(),
+                &(entry.offset.min(MAX_32) as u32).to_le_bytes(),
+                entry.name.as_bytes(),
+                &extra,
+            ])?;
+        }
+
+        let end = self.out.written;
+        let size = end - start;
+        let count = entries.len() as u64;
+
+        if start >= MAX_32 || size >= MAX_32 {
+            self.put(&[
+                &ZIP64_END.to_le_bytes(),
+                &44u64.to_le_bytes(),
+                &ZIP64_VERSION.to_le_bytes(),
+                &ZIP64_VERSION.to_le_bytes(),
+                &0u32.to_le_bytes(),
+                &0u32.to_le_bytes(),
+                &count.to_le_bytes(),
+                &count.to_le_bytes(),
+                &size.to_le_bytes(),
+                &start.to_le_bytes(),
+                &ZIP64_LOCATOR.to_le_bytes(),
+                &0u32.to_le_bytes(),
+                &end.to_le_bytes(),
+                &1u32.to_le_bytes(),
+            ])?;
+        }
+
+        self.put(&[
+            &END.to_le_bytes(),
+            &0u16.to_le_bytes(),
+            &0u16.to_le_bytes(),
+            &(count as u16).to_le_bytes(),
+            &(count as u16).to_le_bytes(),
+            &(size.min(MAX_32) as u32).to_le_bytes(),
+            &(start.min(MAX_32) as u32).to_le_bytes(),
+            &0u16.to_le_bytes(),
+        ])?;
+
+        self.out.flush()?;
+
+        Ok(self.out.out)
+    }
+}
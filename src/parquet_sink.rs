@@ -0,0 +1,119 @@
+use std::fs::File;
+use std::io::Error;
+use std::path::Path;
+use std::sync::Arc;
+
+use kdam::{BarExt, RichProgress};
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::data_type::{ByteArray, ByteArrayType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use tracing::info;
+
+use crate::commands::tmp_path;
+use crate::error::{PooError, Result};
+use crate::serializer::SortedFreqs;
+
+// one row per (author, word) pair, sorted by author and then word
+const SCHEMA: &str = "
+    message users {
+        required binary author (UTF8);
+        required binary word (UTF8);
+        required int64 count;
+    }
+";
+
+// rows buffered before a row group is written
+const ROW_GROUP_SIZE: usize = 1 << 20;
+
+#[derive(Default)]
+struct Rows {
+    authors: Vec<ByteArray>,
+    words: Vec<ByteArray>,
+    counts: Vec<i64>,
+}
+
+fn write_row_group(writer: &mut SerializedFileWriter<&File>, rows: &mut Rows) -> parquet::errors::Result<()> {
+    let mut group = writer.next_row_group()?;
+
+    // the columns come in schema order
+    for i in 0..3 {
+        let mut column = group.next_column()?.expect("schema has three columns");
+
+        match i {
+            0 => column.typed::<ByteArrayType>().write_batch(&rows.authors, None, None)?,
+            1 => column.typed::<ByteArrayType>().write_batch(&rows.words, None, None)?,
+            _ => column.typed::<Int64Type>().write_batch(&rows.counts, None, None)?,
+        };
+
+        column.close()?;
+    }
+
+    group.close()?;
+
+    *rows = Rows::default();
+
+    Ok(())
+}
+
+fn write(file: &File, data: &impl SortedFreqs, compression_level: i32, pb: &mut RichProgress) -> parquet::errors::Result<()> {
+    let properties =
+        WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::try_new(compression_level)?))
+            .build();
+
+    let mut writer =
+        SerializedFileWriter::new(
+            file,
+            Arc::new(parse_message_type(SCHEMA)?),
+            Arc::new(properties),
+        )?;
+
+    let mut rows = Rows::default();
+    let mut i = 0usize;
+
+    data.for_each_sorted(|author, freqs| {
+        for &(word, freq) in freqs {
+            rows.authors.push(ByteArray::from(author.to_vec()));
+            rows.words.push(ByteArray::from(word.to_vec()));
+            rows.counts.push(freq as i64);
+        }
+
+        if rows.counts.len() >= ROW_GROUP_SIZE {
+            write_row_group(&mut writer, &mut rows).map_err(Error::other)?;
+        }
+
+        i += 1;
+
+        if i.is_multiple_of(1000) {
+            pb.update_to(i);
+        }
+
+        Ok(())
+    })?;
+
+    if !rows.counts.is_empty() {
+        write_row_group(&mut writer, &mut rows)?;
+    }
+
+    writer.close()?;
+
+    Ok(())
+}
+
+// author/word/count triples for Spark, DuckDB or pandas, written via a temporary file like `write_freqs`
+pub fn write_parquet(path: &Path, data: &impl SortedFreqs, compression_level: i32, pb: &mut RichProgress) -> Result<()> {
+    let tmp = tmp_path(path);
+
+    let file = File::create(&tmp).map_err(PooError::write(&tmp))?;
+
+    info!(path = %path.display(), "Saving: Writing parquet rows");
+    pb.reset(Some(data.author_count()));
+
+    write(&file, data, compression_level, pb).map_err(|e| PooError::Write { path: tmp.clone(), source: Error::other(e) })?;
+
+    file.sync_all().map_err(PooError::write(&tmp))?;
+
+    std::fs::rename(&tmp, path).map_err(PooError::write(path))
+}
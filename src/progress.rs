@@ -0,0 +1,210 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::config::{ByteSize, IngestConfig};
+use crate::error::{PooError, Result};
+
+// how ingest shows how far along it is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ProgressFormat {
+    // bars on a terminal, text lines anywhere else, like nohup or systemd logs
+    #[default]
+    Auto,
+    // a log line per file every `progress_interval` seconds, hidden by -q like other info
+    Text,
+    // a record per file every `progress_interval` seconds, for scripts and dashboards
+    Json,
+    None,
+}
+
+// how far a file is, as json records and for `PipelineBuilder::on_progress`
+#[derive(Debug, Clone, Serialize)]
+pub struct Progress<'a> {
+    pub file: &'a str,
+    // decompressed bytes read and the declared size, null if unknown
+    pub bytes: u64,
+    pub total_bytes: Option<u64>,
+    pub lines: u64,
+    // comments or submissions that passed the filters since the run started, not since a resumed checkpoint
+    pub comments: u64,
+    pub authors: u64,
+    pub errors: usize,
+    pub elapsed_secs: f64,
+    pub bytes_per_sec: f64,
+    pub eta_secs: Option<f64>,
+    pub done: bool,
+}
+
+pub type ProgressCallback = Box<dyn Fn(&Progress) + Send + Sync>;
+
+enum Sink {
+    Text,
+    // dropped after the first failed write, a reader going away doesn't stop the run
+    Json(Mutex<Option<Box<dyn Write + Send>>>),
+    Callback(ProgressCallback),
+}
+
+// the progress of all files of a run as log lines, or one json object per line on stderr or `progress_file`
+pub struct ProgressLog {
+    sink: Sink,
+    interval: Duration,
+}
+
+impl ProgressLog {
+    // none while bars are drawn, a named pipe blocks here until something reads from it
+    pub fn open(config: &IngestConfig) -> Result<Option<Self>> {
+        let sink =
+            match config.progress {
+                ProgressFormat::Auto if crate::log::progress() => return Ok(None),
+                ProgressFormat::Auto | ProgressFormat::Text => Sink::Text,
+                ProgressFormat::Json => Sink::Json(Mutex::new(Some(Self::json_output(config)?))),
+                ProgressFormat::None => {
+                    crate::log::hide_progress();
+                    return Ok(None);
+                }
+            };
+
+        // text lines, and json on stderr, would be garbled by bars
+        if config.progress == ProgressFormat::Text || config.progress_file.is_none() {
+            crate::log::hide_progress();
+        }
+
+        Ok(Some(Self { sink, interval: Duration::from_secs(config.progress_interval.max(1)) }))
+    }
+
+    // called after every batch, jobs call it concurrently
+    pub fn callback(callback: ProgressCallback) -> Self {
+        Self { sink: Sink::Callback(callback), interval: Duration::ZERO }
+    }
+
+    fn json_output(config: &IngestConfig) -> Result<Box<dyn Write + Send>> {
+        Ok(
+            match &config.progress_file {
+                Some(path) => {
+                    Box::new(
+                        OpenOptions::new()
+                            .create(true)
+                            .append(true)
+                            .open(path)
+                            .map_err(PooError::write(path))?
+                    )
+                }
+                None => Box::new(std::io::stderr()),
+            }
+        )
+    }
+
+    fn write(&self, record: &Progress) {
+        let out =
+            match &self.sink {
+                Sink::Text => return Self::log(record),
+                Sink::Json(out) => out,
+                Sink::Callback(callback) => return callback(record),
+            };
+
+        let mut out = out.lock().unwrap();
+
+        let Some(writer) = out.as_mut() else {
+            return;
+        };
+
+        let mut line = serde_json::to_vec(record).expect("progress records serialize");
+        line.push(b'\n');
+
+        if let Err(e) = writer.write_all(&line).and_then(|_| writer.flush()) {
+            warn!(error = %e, "Could not write progress, stopping the progress records");
+            *out = None;
+        }
+    }
+
+    // the file is on the span of run_for_file, the end is logged there too
+    fn log(record: &Progress) {
+        if record.done {
+            return;
+        }
+
+        let rate = format!("{}/s", ByteSize(record.bytes_per_sec as u64));
+
+        match (record.total_bytes, record.eta_secs) {
+            (Some(total), Some(eta)) => {
+                info!(
+                    read = %ByteSize(record.bytes),
+                    total = %ByteSize(total),
+                    percent = %format!("{:.1}", record.bytes as f64 * 100.0 / total as f64),
+                    rate = %rate,
+                    eta = %clock(eta),
+                    lines = record.lines,
+                    authors = record.authors,
+                    "Progress",
+                );
+            }
+            _ => info!(read = %ByteSize(record.bytes), rate = %rate, lines = record.lines, authors = record.authors, "Progress"),
+        }
+    }
+}
+
+// h:mm:ss
+pub fn clock(secs: f64) -> String {
+    let secs = secs as u64;
+
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+// rate and eta of one file, from where it started in this run
+pub struct Reporter<'a> {
+    log: &'a ProgressLog,
+    file: String,
+    size: u64,
+    started: Instant,
+    start_offset: u64,
+    last: Instant,
+}
+
+impl<'a> Reporter<'a> {
+    pub fn new(log: &'a ProgressLog, file: &str, size: u64, offset: u64) -> Self {
+        let now = Instant::now();
+
+        Self { log, file: file.to_string(), size, started: now, start_offset: offset, last: now }
+    }
+
+    // writes a record once every interval, and always the last one
+    pub fn update(&mut self, offset: u64, lines: u64, comments: u64, authors: u64, errors: usize, done: bool) {
+        if !done && self.last.elapsed() < self.log.interval {
+            return;
+        }
+
+        self.last = Instant::now();
+
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let rate = (offset - self.start_offset) as f64 / elapsed.max(f64::EPSILON);
+
+        let eta =
+            match (self.size, done) {
+                (_, true) => Some(0.0),
+                (0, _) => None,
+                _ if rate > 0.0 => Some(self.size.saturating_sub(offset) as f64 / rate),
+                _ => None,
+            };
+
+        self.log.write(&Progress {
+            file: &self.file,
+            bytes: offset,
+            total_bytes: (self.size > 0).then_some(self.size),
+            lines,
+            comments,
+            authors,
+            errors,
+            elapsed_secs: elapsed,
+            bytes_per_sec: rate,
+            eta_secs: eta,
+            done,
+        });
+    }
+}
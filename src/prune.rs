@@ -0,0 +1,167 @@
+use serde::Deserialize;
+
+use crate::cli::PruneArgs;
+use crate::serializer::SortedFreqs;
+use crate::text::text_item::PooMapBase;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PruneConfig {
+    // words an author used fewer times are dropped from that author
+    pub min_word_count: Option<u64>,
+    // lines between in-memory passes applying `min_word_count` during ingestion, 0 only prunes
+    // before writing; approximate, a word dropped early starts over from zero
+    pub min_word_count_lines: u64,
+    // words used fewer times across all authors are dropped
+    pub min_total_count: Option<u64>,
+    // words used by a larger share of authors are dropped
+    pub max_doc_freq: Option<f64>,
+    // of the remaining words only the most used are kept
+    pub top_words: Option<usize>,
+}
+
+impl PruneConfig {
+    // command line flags take precedence over the config file
+    pub fn apply(&mut self, args: &PruneArgs) {
+        if args.min_word_count.is_some() {
+            self.min_word_count = args.min_word_count;
+        }
+
+        if let Some(lines) = args.min_word_count_lines {
+            self.min_word_count_lines = lines;
+        }
+
+        if args.min_total_count.is_some() {
+            self.min_total_count = args.min_total_count;
+        }
+
+        if args.max_doc_freq.is_some() {
+            self.max_doc_freq = args.max_doc_freq;
+        }
+
+        if args.top_words.is_some() {
+            self.top_words = args.top_words;
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.min_word_count.is_some() || self.limits_vocabulary()
+    }
+
+    // whether a `Vocabulary` has to be built
+    pub fn limits_vocabulary(&self) -> bool {
+        self.min_total_count.is_some() || self.max_doc_freq.is_some() || self.top_words.is_some()
+    }
+}
+
+// the words surviving the pruning, with their total count
+pub struct Vocabulary {
+    words: PooMapBase<u64>,
+    // distinct words before pruning
+    seen: usize,
+}
+
+impl Vocabulary {
+    // one pass over the data for the total count and number of authors of every word, after
+    // `min_word_count`
+    pub fn build(data: &impl SortedFreqs, config: &PruneConfig) -> std::io::Result<Self> {
+        let mut authors = 0u64;
+        let mut stats = PooMapBase::<(u64, u64)>::default();
+
+        let min_word_count = config.min_word_count.unwrap_or(0);
+
+        data.for_each_sorted(|_, freqs| {
+            authors += 1;
+
+            for &(word, freq) in freqs.iter().filter(|(_, freq)| *freq >= min_word_count) {
+                match stats.get_mut(word) {
+                    Some((count, df)) => {
+                        *count += freq;
+                        *df += 1;
+                    }
+                    None => {
+                        stats.insert(word.to_vec(), (freq, 1));
+                    }
+                }
+            }
+
+            Ok(())
+        })?;
+
+        let seen = stats.len();
+
+        let min_count = config.min_total_count.unwrap_or(0);
+        let max_df = config.max_doc_freq.map_or(u64::MAX, |v| (v * authors as f64).floor() as u64);
+
+        let mut words =
+            stats.into_iter()
+                .filter(|(_, (count, df))| *count >= min_count && *df <= max_df)
+                .map(|(word, (count, _))| (word, count))
+                .collect::<Vec<_>>();
+
+        if let Some(top) = config.top_words {
+            if words.len() > top {
+                // ties broken by the word so the cut doesn't depend on hashing
+                words.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+                words.truncate(top);
+            }
+        }
+
+        Ok(Self {
+            words: words.into_iter().collect(),
+            seen,
+        })
+    }
+
+    pub fn contains(&self, word: &[u8]) -> bool {
+        self.words.contains_key(word)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    pub fn seen(&self) -> usize {
+        self.seen
+    }
+}
+
+// the data without the words an author used rarely or outside the vocabulary, authors left
+// without words are skipped
+pub struct Pruned<'a, D: SortedFreqs> {
+    pub data: &'a D,
+    pub min_word_count: u64,
+    pub vocabulary: Option<&'a Vocabulary>,
+}
+
+impl<D: SortedFreqs> SortedFreqs for Pruned<'_, D> {
+    fn author_count(&self) -> usize {
+        self.data.author_count()
+    }
+
+    fn for_each_sorted(
+        &self,
+        mut f: impl FnMut(&[u8], &[(&[u8], u64)]) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        self.data.for_each_sorted(|author, freqs| {
+            let kept =
+                freqs.iter()
+                    .copied()
+                    .filter(|(word, freq)| {
+                        *freq >= self.min_word_count
+                            && self.vocabulary.is_none_or(|vocabulary| vocabulary.contains(word))
+                    })
+                    .collect::<Vec<_>>();
+
+            if kept.is_empty() {
+                return Ok(());
+            }
+
+            f(author, &kept)
+        })
+    }
+}
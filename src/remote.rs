@@ -0,0 +1,244 @@
+use std::convert::TryFrom;
+use std::io::{Cursor, Error, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use http_req::request::{HttpVersion, Method, RequestBuilder};
+use http_req::tls;
+use http_req::uri::Uri;
+
+// reconnects after the transfer broke off, reset by every successful read
+const RETRIES: u32 = 5;
+const REDIRECTS: usize = 5;
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+pub fn is_url(path: &Path) -> bool {
+    path.to_str()
+        .map(|v| v.starts_with("http://") || v.starts_with("https://") || v.starts_with("s3://"))
+        .unwrap_or(false)
+}
+
+// the last segment of the url without the query of presigned urls, used to name the output
+pub fn file_name(url: &str) -> &str {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+
+    match path.rsplit('/').next() {
+        Some(name) if !name.is_empty() => name,
+        _ => "download",
+    }
+}
+
+// s3://bucket/key goes to the public endpoint of the bucket, private objects need a presigned url
+fn resolve(url: &str) -> String {
+    match url.strip_prefix("s3://") {
+        Some(rest) => {
+            let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+
+            format!("https://{}.s3.amazonaws.com/{}", bucket, key)
+        }
+        None => url.to_string(),
+    }
+}
+
+trait Connection: Read + Write {}
+
+impl<T: Read + Write> Connection for T {}
+
+struct Response {
+    status: u16,
+    length: Option<u64>,
+    location: Option<String>,
+    body: Box<dyn Read>,
+}
+
+fn connect(uri: &Uri) -> std::io::Result<Box<dyn Connection>> {
+    let host = uri.host().unwrap_or("");
+
+    let stream = TcpStream::connect((host, uri.corr_port()))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    match uri.scheme() {
+        "https" => Ok(Box::new(tls::Config::default().connect(host, stream).map_err(Error::other)?)),
+        _ => Ok(Box::new(stream)),
+    }
+}
+
+fn parse_url(url: &str) -> std::io::Result<Uri<'_>> {
+    Uri::try_from(url).map_err(|e| Error::new(ErrorKind::InvalidInput, format!("invalid url {}: {}", url, e)))
+}
+
+// HTTP/1.0 so the body is never chunked and ends with the connection
+fn request(url: &str, offset: u64) -> std::io::Result<Response> {
+    let uri = parse_url(url)?;
+
+    let mut stream = connect(&uri)?;
+
+    let range = format!("bytes={}-", offset);
+
+    let mut builder = RequestBuilder::new(&uri);
+    builder.version(HttpVersion::Http10);
+
+    if offset > 0 {
+        builder.header("Range", &range);
+    }
+
+    builder.write_msg(&mut stream, &builder.parse_msg())?;
+
+    let (head, body_part) = builder.read_head(&mut stream, Instant::now() + TIMEOUT).map_err(Error::other)?;
+
+    Ok(Response {
+        status: head.status_code().into(),
+        length: head.content_len().map(|v| v as u64),
+        location: head.headers().get("Location").cloned(),
+        body: Box::new(Cursor::new(body_part).chain(stream)),
+    })
+}
+
+// percent-encoded for a query string
+pub fn encode_query(value: &str) -> String {
+    value.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// the status and body of the answer to a POST of `body`
+pub fn post(url: &str, headers: &[(&str, &str)], body: &[u8]) -> std::io::Result<(u16, Vec<u8>)> {
+    let uri = parse_url(url)?;
+
+    let mut stream = connect(&uri)?;
+
+    let length = body.len().to_string();
+
+    let mut builder = RequestBuilder::new(&uri);
+    builder.version(HttpVersion::Http10);
+    builder.method(Method::POST);
+    builder.header("Content-Length", &length);
+
+    for (key, value) in headers {
+        builder.header(key, value);
+    }
+
+    builder.body(body);
+
+    let mut msg = builder.parse_msg();
+
+    // http_req loses the `/?` of a url with a query and no path, like clickhouse's
+    if let (None, Some(query)) = (uri.path(), uri.query()) {
+        let target = "POST ".len() + uri.resource().len();
+        msg = [format!("POST /?{}", query).as_bytes(), &msg[target..]].concat();
+    }
+
+    builder.write_msg(&mut stream, &msg)?;
+
+    let (head, body_part) = builder.read_head(&mut stream, Instant::now() + TIMEOUT).map_err(Error::other)?;
+
+    let mut answer = body_part;
+    stream.read_to_end(&mut answer)?;
+
+    Ok((head.status_code().into(), answer))
+}
+
+// redirects to a path stay on the same host
+fn redirect(url: &str, location: &str) -> String {
+    if !location.starts_with('/') {
+        return location.to_string();
+    }
+
+    let origin =
+        url.find("://")
+            .and_then(|scheme| url[scheme + 3..].find('/').map(|host| scheme + 3 + host))
+            .map_or(url, |end| &url[..end]);
+
+    format!("{}{}", origin, location)
+}
+
+// the body of a download, picked up with a range request where the connection dropped
+pub struct Remote {
+    url: String,
+    body: Box<dyn Read>,
+    offset: u64,
+    length: Option<u64>,
+    retries: u32,
+}
+
+impl Remote {
+    pub fn open(url: &str) -> std::io::Result<Self> {
+        let mut url = resolve(url);
+
+        for _ in 0..=REDIRECTS {
+            let response = request(&url, 0)?;
+
+            match (response.status, &response.location) {
+                (200, _) => {
+                    return Ok(Self {
+                        url,
+                        body: response.body,
+                        offset: 0,
+                        length: response.length,
+                        retries: RETRIES,
+                    });
+                }
+                (301 | 302 | 303 | 307 | 308, Some(location)) => url = redirect(&url, location),
+                (status, _) => return Err(Error::other(format!("{} answered with status {}", url, status))),
+            }
+        }
+
+        Err(Error::other(format!("{} redirected more than {} times", url, REDIRECTS)))
+    }
+
+    fn resume(&mut self) -> std::io::Result<()> {
+        let response = request(&self.url, self.offset)?;
+
+        match response.status {
+            206 => self.body = response.body,
+            // the server ignores ranges, skip what was read already
+            200 => {
+                let mut body = response.body;
+
+                if std::io::copy(&mut (&mut body).take(self.offset), &mut std::io::sink())? < self.offset {
+                    return Err(Error::new(ErrorKind::UnexpectedEof, "download got shorter"));
+                }
+
+                self.body = body;
+            }
+            status => return Err(Error::other(format!("{} answered with status {}", self.url, status))),
+        }
+
+        Ok(())
+    }
+}
+
+impl Read for Remote {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let error =
+                match self.body.read(buf) {
+                    Ok(read) if read > 0 || buf.is_empty() || self.length.is_none_or(|len| self.offset >= len) => {
+                        self.offset += read as u64;
+                        self.retries = RETRIES;
+
+                        return Ok(read);
+                    }
+                    Ok(_) => Error::new(ErrorKind::UnexpectedEof, "connection closed before the end of the download"),
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => e,
+                };
+
+            if self.retries == 0 {
+                return Err(error);
+            }
+
+            self.retries -= 1;
+
+            std::thread::sleep(Duration::from_secs(1 << (RETRIES - self.retries - 1)));
+
+            // a failed attempt leaves the dead body in place, so the next read fails and retries again
+            let _ = self.resume();
+        }
+    }
+}
@@ -0,0 +1,674 @@
+use rand::rngs::StdRng;
+use rand::seq::index::sample;
+use clap::ValueEnum;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use crate::cli::SegmentArgs;
+use crate::weighting::SparseVector;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SegmentMethod {
+    // `k` clusters of the full tf-idf vectors
+    #[default]
+    Kmeans,
+    // the modes of a kernel density estimate over one or two words, as many segments as the data has
+    Density,
+}
+
+// what to do with the authors whose bot score in the metadata reaches `bot_threshold`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BotPolicy {
+    #[default]
+    Keep,
+    // left out of the segments and the assignments
+    Exclude,
+    // left out of the segments and assigned to `bots`
+    Separate,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SegmentConfig {
+    pub method: SegmentMethod,
+    // number of segments
+    pub k: usize,
+    // mini-batches drawn before the final assignment
+    pub iterations: usize,
+    pub batch_size: usize,
+    pub seed: u64,
+    // the words whose weights the density method projects the authors onto, the two with the most
+    // variance if empty
+    pub dimensions: Vec<String>,
+    // multiplies the bandwidth of silverman's rule, larger gives fewer segments
+    pub bandwidth: f64,
+    // cells per dimension of the density grid
+    pub grid: usize,
+    // share of the authors below which a segment joins its neighbour across the lowest valley
+    pub min_size: f64,
+    pub bots: BotPolicy,
+    pub bot_threshold: f64,
+}
+
+impl Default for SegmentConfig {
+    fn default() -> Self {
+        Self {
+            method: SegmentMethod::Kmeans,
+            k: 8,
+            iterations: 100,
+            batch_size: 1024,
+            seed: 0,
+            dimensions: Vec::new(),
+            bandwidth: 1.0,
+            grid: 128,
+            min_size: 0.01,
+            bots: BotPolicy::Keep,
+            bot_threshold: 0.5,
+        }
+    }
+}
+
+impl SegmentConfig {
+    // command line flags take precedence over the config file
+    pub fn apply(&mut self, args: &SegmentArgs) {
+        if let Some(k) = args.k {
+            self.k = k;
+        }
+
+        if let Some(iterations) = args.iterations {
+            self.iterations = iterations;
+        }
+
+        if let Some(batch_size) = args.batch_size {
+            self.batch_size = batch_size;
+        }
+
+        if let Some(seed) = args.seed {
+            self.seed = seed;
+        }
+
+        if let Some(method) = args.method {
+            self.method = method;
+        }
+
+        if !args.dimensions.is_empty() {
+            self.dimensions = args.dimensions.clone();
+        }
+
+        if let Some(bandwidth) = args.bandwidth {
+            self.bandwidth = bandwidth;
+        }
+
+        if let Some(min_size) = args.min_size {
+            self.min_size = min_size;
+        }
+
+        if let Some(bots) = args.bots {
+            self.bots = bots;
+        }
+
+        if let Some(bot_threshold) = args.bot_threshold {
+            self.bot_threshold = bot_threshold;
+        }
+    }
+}
+
+// below this the scale is multiplied into the values before precision suffers
+const MIN_SCALE: f64 = 1e-6;
+
+fn squared_norm(vector: &SparseVector) -> f64 {
+    vector.iter().map(|(_, w)| (*w as f64) * (*w as f64)).sum()
+}
+
+// stored as `scale * values` so an update only touches the dimensions of the sample
+struct Center {
+    values: Vec<f32>,
+    scale: f64,
+    // squared length of the scaled values
+    norm: f64,
+    // samples seen, the seed counts as the first
+    count: u64,
+}
+
+impl Center {
+    fn new(seed: &SparseVector, dimensions: usize) -> Self {
+        let mut values = vec![0f32; dimensions];
+
+        for &(dim, weight) in seed {
+            values[dim as usize] = weight;
+        }
+
+        Self {
+            values,
+            scale: 1.0,
+            norm: squared_norm(seed),
+            count: 1,
+        }
+    }
+
+    fn dot(&self, vector: &SparseVector) -> f64 {
+        self.scale
+            * vector.iter()
+                .map(|&(dim, weight)| self.values[dim as usize] as f64 * weight as f64)
+                .sum::<f64>()
+    }
+
+    // squared euclidean distance
+    fn distance(&self, vector: &SparseVector, vector_norm: f64) -> f64 {
+        vector_norm - 2.0 * self.dot(vector) + self.norm
+    }
+
+    // moves the center towards the sample with a per-center learning rate of 1 / count
+    fn update(&mut self, vector: &SparseVector, vector_norm: f64) {
+        let dot = self.dot(vector);
+
+        self.count += 1;
+
+        let eta = 1.0 / self.count as f64;
+
+        self.norm = (1.0 - eta).powi(2) * self.norm + 2.0 * (1.0 - eta) * eta * dot + eta.powi(2) * vector_norm;
+        self.scale *= 1.0 - eta;
+
+        for &(dim, weight) in vector {
+            self.values[dim as usize] += (eta * weight as f64 / self.scale) as f32;
+        }
+
+        if self.scale < MIN_SCALE {
+            let scale = self.scale as f32;
+
+            self.values.iter_mut().for_each(|v| *v *= scale);
+            self.scale = 1.0;
+            self.norm = self.values.iter().map(|v| (*v as f64) * (*v as f64)).sum();
+        }
+    }
+
+    fn value(&self, dim: usize) -> f32 {
+        (self.values[dim] as f64 * self.scale) as f32
+    }
+}
+
+// mini-batch k-means (Sculley, 2010) over sparse vectors
+pub struct KMeans {
+    centers: Vec<Center>,
+}
+
+impl KMeans {
+    pub fn fit(
+        vectors: &[SparseVector],
+        dimensions: usize,
+        config: &SegmentConfig,
+        mut on_iteration: impl FnMut(usize),
+    ) -> Self {
+        let mut rng = StdRng::seed_from_u64(config.seed);
+
+        // authors without a single weighted word would make useless seeds
+        let candidates =
+            (0..vectors.len())
+                .filter(|&i| !vectors[i].is_empty())
+                .collect::<Vec<_>>();
+
+        let norms = vectors.iter().map(squared_norm).collect::<Vec<_>>();
+
+        let mut centers = seed_centers(vectors, &norms, &candidates, dimensions, config.k, &mut rng);
+
+        if centers.is_empty() {
+            return Self { centers };
+        }
+
+        for iteration in 0..config.iterations {
+            let batch =
+                (0..config.batch_size)
+                    .map(|_| candidates[rng.gen_range(0..candidates.len())])
+                    .collect::<Vec<_>>();
+
+            // assignments are made against the centers as they were before the batch
+            let assignments =
+                batch.par_iter()
+                    .map(|&i| nearest(&centers, &vectors[i], norms[i]).0)
+                    .collect::<Vec<_>>();
+
+            for (&i, center) in batch.iter().zip(assignments) {
+                centers[center].update(&vectors[i], norms[i]);
+            }
+
+            on_iteration(iteration + 1);
+        }
+
+        Self { centers }
+    }
+
+    pub fn len(&self) -> usize {
+        self.centers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.centers.is_empty()
+    }
+
+    // segment and squared distance to its center
+    pub fn assign(&self, vector: &SparseVector) -> (usize, f64) {
+        nearest(&self.centers, vector, squared_norm(vector))
+    }
+
+    // the center's heaviest dimensions, to describe a segment
+    pub fn top_dimensions(&self, center: usize, n: usize) -> Vec<(u32, f32)> {
+        let center = &self.centers[center];
+
+        let mut dims =
+            (0..center.values.len())
+                .map(|dim| (dim as u32, center.value(dim)))
+                .filter(|(_, value)| *value > 0.0)
+                .collect::<Vec<_>>();
+
+        dims.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        dims.truncate(n);
+
+        dims
+    }
+}
+
+// authors considered for the initial centers
+const SEED_POOL: usize = 8192;
+
+// k-means++ over a sample of the candidates
+fn seed_centers(
+    vectors: &[SparseVector],
+    norms: &[f64],
+    candidates: &[usize],
+    dimensions: usize,
+    k: usize,
+    rng: &mut StdRng,
+) -> Vec<Center> {
+    let pool =
+        sample(rng, candidates.len(), candidates.len().min(SEED_POOL))
+            .into_iter()
+            .map(|i| candidates[i])
+            .collect::<Vec<_>>();
+
+    let mut centers = Vec::with_capacity(k);
+
+    if pool.is_empty() {
+        return centers;
+    }
+
+    // squared distance of every pool author to its closest center so far
+    let mut distances = vec![f64::INFINITY; pool.len()];
+
+    let with_center = |distances: &[f64], center: &Center| {
+        distances.par_iter()
+            .zip(pool.par_iter())
+            .map(|(distance, &i)| distance.min(center.distance(&vectors[i], norms[i]).max(0.0)))
+            .collect::<Vec<_>>()
+    };
+
+    let first = Center::new(&vectors[pool[rng.gen_range(0..pool.len())]], dimensions);
+
+    distances = with_center(&distances, &first);
+    centers.push(first);
+
+    // greedy k-means++ like sklearn: of a few candidates drawn by squared distance keep the one
+    // leaving the least total, a single draw still often puts two seeds into one segment
+    let trials = 2 + (k as f64).ln() as usize;
+
+    while centers.len() < k.min(pool.len()) {
+        let total = distances.iter().sum::<f64>();
+
+        // every remaining author coincides with a center
+        if total <= 0.0 {
+            break;
+        }
+
+        let (center, next) =
+            (0..trials)
+                .map(|_| {
+                    let mut target = rng.gen::<f64>() * total;
+
+                    let i =
+                        pool.iter()
+                            .zip(distances.iter())
+                            .find(|(_, distance)| {
+                                target -= **distance;
+                                target <= 0.0
+                            })
+                            .map_or(pool[pool.len() - 1], |(&i, _)| i);
+
+                    let center = Center::new(&vectors[i], dimensions);
+                    let next = with_center(&distances, &center);
+
+                    (center, next)
+                })
+                .min_by(|a, b| a.1.iter().sum::<f64>().total_cmp(&b.1.iter().sum::<f64>()))
+                .unwrap();
+
+        distances = next;
+        centers.push(center);
+    }
+
+    centers
+}
+
+fn nearest(centers: &[Center], vector: &SparseVector, vector_norm: f64) -> (usize, f64) {
+    centers.iter()
+        .map(|center| center.distance(vector, vector_norm))
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap()
+}
+
+// the dimensions with the most variance of their weights across all authors, missing weights count as 0
+pub fn top_variance(vectors: &[SparseVector], dimensions: usize, n: usize) -> Vec<u32> {
+    let mut sums = vec![(0f64, 0f64); dimensions];
+
+    for vector in vectors {
+        for &(dim, weight) in vector {
+            sums[dim as usize].0 += weight as f64;
+            sums[dim as usize].1 += (weight as f64).powi(2);
+        }
+    }
+
+    let count = vectors.len().max(1) as f64;
+
+    let mut dims =
+        sums.iter()
+            .enumerate()
+            .map(|(dim, (sum, squares))| (dim as u32, squares / count - (sum / count).powi(2)))
+            .collect::<Vec<_>>();
+
+    dims.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    dims.into_iter().take(n).map(|(dim, _)| dim).collect()
+}
+
+// one axis of the grid
+#[derive(Debug, Clone, Copy)]
+struct Axis {
+    min: f64,
+    width: f64,
+}
+
+impl Axis {
+    fn cell(&self, value: f64, cells: usize) -> usize {
+        match self.width > 0.0 {
+            true => (((value - self.min) / self.width) as usize).min(cells - 1),
+            false => 0,
+        }
+    }
+}
+
+// authors projected onto one or two dimensions, binned into a grid that is smoothed with a gaussian kernel,
+// every cell then climbs to the highest of its neighbours and the peak it ends at is its segment, so the
+// segments are the basins of the density modes and their borders run along the valleys
+pub struct DensitySegments {
+    axes: Vec<Axis>,
+    cells: usize,
+    // segment of every cell, row-major
+    labels: Vec<usize>,
+    // the cell of the highest point of every segment
+    modes: Vec<usize>,
+}
+
+impl DensitySegments {
+    pub fn fit(points: &[Vec<f64>], config: &SegmentConfig) -> Self {
+        let dims = points.first().map_or(1, |p| p.len());
+        let cells = config.grid.max(2);
+        let n = points.len().max(1) as f64;
+
+        let axes =
+            (0..dims)
+                .map(|d| {
+                    let min = points.iter().map(|p| p[d]).fold(f64::INFINITY, f64::min);
+                    let max = points.iter().map(|p| p[d]).fold(f64::NEG_INFINITY, f64::max);
+
+                    Axis { min, width: (max - min) / cells as f64 }
+                })
+                .collect::<Vec<_>>();
+
+        let point_cells = points.iter().map(|point| Self::index(&axes, cells, point)).collect::<Vec<_>>();
+
+        let mut density = vec![0f64; cells.pow(dims as u32)];
+
+        for &cell in point_cells.iter() {
+            density[cell] += 1.0;
+        }
+
+        // silverman's rule per dimension, in cells
+        for (d, axis) in axes.iter().enumerate() {
+            if axis.width <= 0.0 {
+                continue;
+            }
+
+            let mean = points.iter().map(|p| p[d]).sum::<f64>() / n;
+            let sd = (points.iter().map(|p| (p[d] - mean).powi(2)).sum::<f64>() / n).sqrt();
+
+            let h = config.bandwidth * sd * (4.0 / ((dims + 2) as f64 * n)).powf(1.0 / (dims + 4) as f64);
+
+            density = smooth(&density, cells, dims, d, (h / axis.width).max(0.5));
+        }
+
+        let (mut labels, mut modes) = climb(&density, cells, dims);
+
+        merge_small(&density, &mut labels, &mut modes, cells, dims, &point_cells, config.min_size);
+
+        Self { axes, cells, labels, modes }
+    }
+
+    fn index(axes: &[Axis], cells: usize, point: &[f64]) -> usize {
+        axes.iter()
+            .zip(point)
+            .fold(0, |index, (axis, &value)| index * cells + axis.cell(value, cells))
+    }
+
+    pub fn len(&self) -> usize {
+        self.modes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modes.is_empty()
+    }
+
+    pub fn assign(&self, point: &[f64]) -> usize {
+        self.labels[Self::index(&self.axes, self.cells, point)]
+    }
+
+    // the coordinates of the peak of a segment, at the center of its cell
+    pub fn mode(&self, segment: usize) -> Vec<f64> {
+        let mut index = self.modes[segment];
+        let mut coordinates = vec![0f64; self.axes.len()];
+
+        for (d, axis) in self.axes.iter().enumerate().rev() {
+            coordinates[d] = axis.min + (index % self.cells) as f64 * axis.width + axis.width / 2.0;
+            index /= self.cells;
+        }
+
+        coordinates
+    }
+}
+
+// a gaussian of `sigma` cells along dimension `d`, cut off at 3 sigma
+fn smooth(density: &[f64], cells: usize, dims: usize, d: usize, sigma: f64) -> Vec<f64> {
+    let radius = (3.0 * sigma).ceil() as isize;
+
+    let kernel =
+        (-radius..=radius)
+            .map(|i| (-(i as f64).powi(2) / (2.0 * sigma * sigma)).exp())
+            .collect::<Vec<_>>();
+
+    // the cells of dimension `d` are this far apart in the row-major grid
+    let stride = cells.pow((dims - 1 - d) as u32);
+
+    let mut out = vec![0f64; density.len()];
+
+    for (index, value) in out.iter_mut().enumerate() {
+        let position = (index / stride % cells) as isize;
+
+        for (k, weight) in kernel.iter().enumerate() {
+            let other = position + k as isize - radius;
+
+            if other >= 0 && other < cells as isize {
+                *value += weight * density[(index as isize + (other - position) * stride as isize) as usize];
+            }
+        }
+    }
+
+    out
+}
+
+// the cells next to `index`, diagonals included
+fn neighbours(index: usize, cells: usize, dims: usize) -> Vec<usize> {
+    let mut coordinates = vec![0isize; dims];
+    let mut rest = index;
+
+    for c in coordinates.iter_mut().rev() {
+        *c = (rest % cells) as isize;
+        rest /= cells;
+    }
+
+    let mut out = Vec::new();
+
+    // every offset is a number in base 3, a digit per dimension for -1, 0 and +1
+    'offsets: for offset in 0..3usize.pow(dims as u32) {
+        let mut other = 0usize;
+
+        for (d, &c) in coordinates.iter().enumerate() {
+            let shifted = c + (offset / 3usize.pow((dims - 1 - d) as u32) % 3) as isize - 1;
+
+            if shifted < 0 || shifted >= cells as isize {
+                continue 'offsets;
+            }
+
+            other = other * cells + shifted as usize;
+        }
+
+        if other != index {
+            out.push(other);
+        }
+    }
+
+    out
+}
+
+// the segment of every cell and the peak of every segment, by steepest ascent
+fn climb(density: &[f64], cells: usize, dims: usize) -> (Vec<usize>, Vec<usize>) {
+    // ties go to the lower index so flat regions still end in a single peak
+    let higher = |a: usize, b: usize| density[a] > density[b] || (density[a] == density[b] && a < b);
+
+    let up =
+        (0..density.len())
+            .map(|index| {
+                neighbours(index, cells, dims)
+                    .into_iter()
+                    .fold(index, |best, other| if higher(other, best) { other } else { best })
+            })
+            .collect::<Vec<_>>();
+
+    let mut labels = vec![usize::MAX; density.len()];
+    let mut modes = Vec::new();
+
+    for start in 0..density.len() {
+        let mut path = Vec::new();
+        let mut index = start;
+
+        while labels[index] == usize::MAX && up[index] != index {
+            path.push(index);
+            index = up[index];
+        }
+
+        if labels[index] == usize::MAX {
+            labels[index] = modes.len();
+            modes.push(index);
+        }
+
+        let label = labels[index];
+
+        for cell in path {
+            labels[cell] = label;
+        }
+    }
+
+    (labels, modes)
+}
+
+// joins every segment holding fewer than `min_size` of the authors into the neighbour it shares the highest
+// border cell with, smallest first, then numbers the rest by size
+fn merge_small(
+    density: &[f64],
+    labels: &mut [usize],
+    modes: &mut Vec<usize>,
+    cells: usize,
+    dims: usize,
+    point_cells: &[usize],
+    min_size: f64,
+) {
+    let mut sizes = vec![0usize; modes.len()];
+
+    for &cell in point_cells {
+        sizes[labels[cell]] += 1;
+    }
+
+    let min = (min_size * point_cells.len() as f64).ceil() as usize;
+
+    loop {
+        let alive = (0..modes.len()).filter(|&s| sizes[s] > 0).collect::<Vec<_>>();
+
+        let Some(&small) = alive.iter().filter(|&&s| sizes[s] < min).min_by_key(|&&s| (sizes[s], s)) else {
+            break;
+        };
+
+        if alive.len() < 2 {
+            break;
+        }
+
+        // crossing the border is as high as the lower cell of a neighbouring pair, the highest crossing is the pass
+        let labels_now = &*labels;
+        let sizes_now = &sizes;
+
+        let target =
+            (0..labels_now.len())
+                .filter(|&index| labels_now[index] == small)
+                .flat_map(|index| {
+                    neighbours(index, cells, dims)
+                        .into_iter()
+                        .filter(move |&other| labels_now[other] != small && sizes_now[labels_now[other]] > 0)
+                        .map(move |other| (density[index].min(density[other]), labels_now[other]))
+                })
+                .max_by(|a, b| a.0.total_cmp(&b.0).then_with(|| b.1.cmp(&a.1)))
+                .map(|(_, label)| label);
+
+        // an island without authors around it joins the largest segment
+        let target = target.unwrap_or_else(|| *alive.iter().filter(|&&s| s != small).max_by_key(|&&s| (sizes[s], s)).unwrap());
+
+        for label in labels.iter_mut().filter(|label| **label == small) {
+            *label = target;
+        }
+
+        if density[modes[small]] > density[modes[target]] {
+            modes[target] = modes[small];
+        }
+
+        sizes[target] += sizes[small];
+        sizes[small] = 0;
+    }
+
+    // basins without authors are merged away, the largest segment is 0
+    let mut order = (0..modes.len()).filter(|&s| sizes[s] > 0).collect::<Vec<_>>();
+    order.sort_by_key(|&s| (std::cmp::Reverse(sizes[s]), s));
+
+    let mut renumbered = vec![usize::MAX; modes.len()];
+
+    for (new, &old) in order.iter().enumerate() {
+        renumbered[old] = new;
+    }
+
+    // no author is in the cells of empty basins
+    for label in labels.iter_mut() {
+        *label = match renumbered[*label] {
+            usize::MAX => 0,
+            new => new,
+        };
+    }
+
+    *modes = order.iter().map(|&s| modes[s]).collect();
+}
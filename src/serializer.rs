@@ -1,11 +1,17 @@
-use std::collections::{BTreeMap, BTreeSet, HashSet};
-use std::io::Write;
-use std::ops::Sub;
-use std::path::Path;
+use std::cmp::Ordering;
+use std::hash::Hasher;
+use std::ops::{ControlFlow, Range};
+use std::io::{Error, ErrorKind, Write};
 
-use kdam::term::Colorizer;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_bytes::{ByteBuf, Bytes};
+use tracing::warn;
+use twox_hash::XxHash64;
 
-use crate::text::text_item::{PooMap, PooMapBase, PooMapInner, PooMapRoot};
+use crate::text::is_junk;
+use crate::text::interner::FreqStore;
+use crate::text::text_item::{PooMap, PooMapInner};
 
 pub enum SerializerFeedback {
     Message(String),
@@ -13,42 +19,172 @@ pub enum SerializerFeedback {
     Progress(u64),
 }
 
-#[inline(always)]
-pub fn serialize_with_writer<W: Write>(
-    data: &PooMap,
-    writer: &mut W,
-    mut fn_feedback: impl FnMut(SerializerFeedback) -> (),
-) -> std::io::Result<()> {
-    let mut serbuf = data.iter().collect::<Vec<_>>();
+// anything that can hand out its authors and their words in byte order
+pub trait SortedFreqs {
+    // only used for progress, may be an upper bound
+    fn author_count(&self) -> usize;
 
-    let mut i = 0u64;
+    fn for_each_sorted(
+        &self,
+        f: impl FnMut(&[u8], &[(&[u8], u64)]) -> std::io::Result<()>,
+    ) -> std::io::Result<()>;
+}
 
-    fn_feedback(SerializerFeedback::Message("Saving: Writing authors..".into()));
-    fn_feedback(SerializerFeedback::Total(serbuf.len() as u64));
+impl SortedFreqs for PooMap {
+    fn author_count(&self) -> usize {
+        self.len()
+    }
 
-    // write magic
-    writer.write_all(b"ragegun")?;
+    fn for_each_sorted(
+        &self,
+        mut f: impl FnMut(&[u8], &[(&[u8], u64)]) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        let mut authors = self.iter().collect::<Vec<_>>();
+        authors.sort_unstable_by(|a, b| a.0.cmp(b.0));
 
-    // write version (1u32)
-    writer.write_all(&1u32.to_be_bytes())?;
+        for (author, freqs) in authors {
+            let mut freqs =
+                freqs.iter()
+                    .map(|(word, freq)| (word.as_slice(), *freq))
+                    .collect::<Vec<_>>();
 
-    // write author count (u64)
-    writer.write_all(&(serbuf.len() as u64).to_be_bytes())?;
+            freqs.sort_unstable_by(|a, b| a.0.cmp(b.0));
 
-    // write word count
-    let word_count = serbuf.iter().map(|(_, v)| v.len()).sum::<usize>() as u64;
-    writer.write_all(&word_count.to_be_bytes())?;
+            f(author, &freqs)?;
+        }
 
-    for (author, freqs) in serbuf {
-        let mut abuf = Vec::new();
+        Ok(())
+    }
+}
+
+impl SortedFreqs for FreqStore {
+    fn author_count(&self) -> usize {
+        self.freqs.len()
+    }
+
+    fn for_each_sorted(
+        &self,
+        mut f: impl FnMut(&[u8], &[(&[u8], u64)]) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        let mut authors =
+            self.freqs
+                .iter()
+                .map(|(author, freqs)| (self.authors.resolve(*author), freqs))
+                .collect::<Vec<_>>();
+
+        authors.sort_unstable_by(|a, b| a.0.cmp(b.0));
+
+        for (author, freqs) in authors {
+            let mut freqs =
+                freqs.iter()
+                    .map(|(word, freq)| (self.words.resolve(*word), *freq))
+                    .collect::<Vec<_>>();
+
+            freqs.sort_unstable_by(|a, b| a.0.cmp(b.0));
+
+            f(author, &freqs)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub const FORMAT_VERSION: u32 = 3;
+
+// how the authors between header and trailer are encoded, recorded in the header
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    // the marker format below
+    #[default]
+    Ragegun,
+    Bincode,
+    Postcard,
+    Msgpack,
+}
+
+impl Codec {
+    fn id(self) -> u8 {
+        match self {
+            Self::Ragegun => 0,
+            Self::Bincode => 1,
+            Self::Postcard => 2,
+            Self::Msgpack => 3,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::Ragegun),
+            1 => Some(Self::Bincode),
+            2 => Some(Self::Postcard),
+            3 => Some(Self::Msgpack),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Ragegun => "ragegun",
+            Self::Bincode => "bincode",
+            Self::Postcard => "postcard",
+            Self::Msgpack => "msgpack",
+        }
+    }
+
+    fn body(self) -> &'static dyn BodyCodec {
+        match self {
+            Self::Ragegun => &RagegunCodec,
+            Self::Bincode => &BincodeCodec,
+            Self::Postcard => &PostcardCodec,
+            Self::Msgpack => &MsgpackCodec,
+        }
+    }
+}
+
+pub trait BodyCodec {
+    fn encode_author(&self, out: &mut Vec<u8>, author: &[u8], freqs: &[(&[u8], u64)]) -> std::io::Result<()>;
+
+    // written after the last author
+    fn encode_end(&self, _out: &mut Vec<u8>) {}
+
+    // `body` ends where the trailer starts and holds `authors` authors, `f` can stop the scan early
+    fn scan(
+        &self,
+        body: &[u8],
+        authors: u64,
+        fn_feedback: &mut dyn FnMut(SerializerFeedback),
+        f: &mut dyn FnMut(Vec<u8>, PooMapInner) -> ControlFlow<()>,
+    ) -> std::io::Result<()>;
+
+    fn decode(
+        &self,
+        body: &[u8],
+        authors: u64,
+        fn_feedback: &mut dyn FnMut(SerializerFeedback),
+    ) -> std::io::Result<PooMap> {
+        let mut freq_vec = PooMap::default();
+
+        self.scan(body, authors, fn_feedback, &mut |author, freqs| {
+            freq_vec.insert(author, freqs);
+            ControlFlow::Continue(())
+        })?;
+
+        Ok(freq_vec)
+    }
+}
+
+struct RagegunCodec;
 
-        abuf.extend_from_slice(&[author.as_slice(), &[245, 0]].concat());
+impl BodyCodec for RagegunCodec {
+    fn encode_author(&self, abuf: &mut Vec<u8>, author: &[u8], freqs: &[(&[u8], u64)]) -> std::io::Result<()> {
+        abuf.extend_from_slice(&[author, &[245, 0]].concat());
 
-        for (word, freq) in freqs {
-            abuf.extend_from_slice(word.as_slice());
+        for &(word, freq) in freqs {
+            abuf.extend_from_slice(word);
 
-            match *freq {
-                x if freq <= &255u64 => {
+            match freq {
+                x if freq <= 255u64 => {
                     abuf.extend_from_slice(
                         &[
                             (x as u8).to_be_bytes().as_slice(),
@@ -57,7 +193,7 @@ pub fn serialize_with_writer<W: Write>(
                             .concat(),
                     );
                 }
-                x if freq <= &(u32::MAX as u64) => {
+                x if freq <= u32::MAX as u64 => {
                     abuf.extend_from_slice(
                         &[
                             (x as u32).to_be_bytes().as_slice(),
@@ -69,7 +205,7 @@ pub fn serialize_with_writer<W: Write>(
                 x => {
                     abuf.extend_from_slice(
                         &[
-                            (x as u64).to_be_bytes().as_slice(),
+                            x.to_be_bytes().as_slice(),
                             [253, 0].as_slice(),
                         ]
                             .concat(),
@@ -80,28 +216,336 @@ pub fn serialize_with_writer<W: Write>(
 
         abuf.extend_from_slice(&[244, 0]);
 
-        writer.write_all(abuf.as_slice())?;
+        Ok(())
+    }
 
-        i += 1;
+    fn encode_end(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[243, 0]);
+    }
 
-        if i % 1000 == 0 {
-            fn_feedback(SerializerFeedback::Progress(i as u64));
-        }
+    fn scan(
+        &self,
+        body: &[u8],
+        _authors: u64,
+        fn_feedback: &mut dyn FnMut(SerializerFeedback),
+        f: &mut dyn FnMut(Vec<u8>, PooMapInner) -> ControlFlow<()>,
+    ) -> std::io::Result<()> {
+        scan_original(body, fn_feedback, f);
+        Ok(())
     }
+}
+
+// the serde codecs store one (author, [(word, count)]) record per author
+type RecordRef<'a> = (&'a Bytes, Vec<(&'a Bytes, u64)>);
+type Record = (ByteBuf, Vec<(ByteBuf, u64)>);
 
-    writer.write_all(&[243, 0])?;
+fn record<'a>(author: &'a [u8], freqs: &[(&'a [u8], u64)]) -> RecordRef<'a> {
+    (
+        Bytes::new(author),
+        freqs.iter()
+            .map(|&(word, freq)| (Bytes::new(word), freq))
+            .collect(),
+    )
+}
+
+fn scan_records(
+    mut body: &[u8],
+    authors: u64,
+    fn_feedback: &mut dyn FnMut(SerializerFeedback),
+    f: &mut dyn FnMut(Vec<u8>, PooMapInner) -> ControlFlow<()>,
+    mut next: impl FnMut(&mut &[u8]) -> std::io::Result<Record>,
+) -> std::io::Result<()> {
+    fn_feedback(SerializerFeedback::Message("Reading: Loading authors..".into()));
+    fn_feedback(SerializerFeedback::Total(authors));
+
+    for i in 0..authors {
+        let (author, freqs) = next(&mut body)?;
+
+        let freqs =
+            freqs.into_iter()
+                .map(|(word, freq)| (word.into_vec(), freq))
+                .collect();
+
+        if f(author.into_vec(), freqs).is_break() {
+            break;
+        }
+
+        if i.is_multiple_of(1000) {
+            fn_feedback(SerializerFeedback::Progress(i));
+        }
+    }
 
     Ok(())
 }
 
-const DEBUG: bool = true;
+struct BincodeCodec;
+
+impl BodyCodec for BincodeCodec {
+    fn encode_author(&self, out: &mut Vec<u8>, author: &[u8], freqs: &[(&[u8], u64)]) -> std::io::Result<()> {
+        bincode::serialize_into(out, &record(author, freqs))
+            .map_err(Error::other)
+    }
+
+    fn scan(
+        &self,
+        body: &[u8],
+        authors: u64,
+        fn_feedback: &mut dyn FnMut(SerializerFeedback),
+        f: &mut dyn FnMut(Vec<u8>, PooMapInner) -> ControlFlow<()>,
+    ) -> std::io::Result<()> {
+        scan_records(body, authors, fn_feedback, f, |rest| {
+            bincode::deserialize_from(rest)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+        })
+    }
+}
+
+struct PostcardCodec;
+
+impl BodyCodec for PostcardCodec {
+    fn encode_author(&self, out: &mut Vec<u8>, author: &[u8], freqs: &[(&[u8], u64)]) -> std::io::Result<()> {
+        postcard::to_io(&record(author, freqs), out)
+            .map(|_| ())
+            .map_err(Error::other)
+    }
+
+    fn scan(
+        &self,
+        body: &[u8],
+        authors: u64,
+        fn_feedback: &mut dyn FnMut(SerializerFeedback),
+        f: &mut dyn FnMut(Vec<u8>, PooMapInner) -> ControlFlow<()>,
+    ) -> std::io::Result<()> {
+        scan_records(body, authors, fn_feedback, f, |rest| {
+            let (record, tail) =
+                postcard::take_from_bytes(rest)
+                    .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+
+            *rest = tail;
+
+            Ok(record)
+        })
+    }
+}
+
+struct MsgpackCodec;
+
+impl BodyCodec for MsgpackCodec {
+    fn encode_author(&self, out: &mut Vec<u8>, author: &[u8], freqs: &[(&[u8], u64)]) -> std::io::Result<()> {
+        rmp_serde::encode::write(out, &record(author, freqs))
+            .map_err(Error::other)
+    }
+
+    fn scan(
+        &self,
+        body: &[u8],
+        authors: u64,
+        fn_feedback: &mut dyn FnMut(SerializerFeedback),
+        f: &mut dyn FnMut(Vec<u8>, PooMapInner) -> ControlFlow<()>,
+    ) -> std::io::Result<()> {
+        scan_records(body, authors, fn_feedback, f, |rest| {
+            rmp_serde::decode::from_read(rest)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+        })
+    }
+}
+
+// where a FreqWriter's bytes go, told where authors start so it can cut blocks that decode on their own
+pub trait FreqSink: Write {
+    fn begin_author(&mut self, _author: &[u8]) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    // called before the end marker and trailer
+    fn end_authors(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// writes one author at a time, the counts go into the trailer so they needn't be known up front
+pub struct FreqWriter<'a, W: FreqSink> {
+    writer: &'a mut W,
+    codec: &'static dyn BodyCodec,
+    hasher: XxHash64,
+    authors: u64,
+    words: u64,
+}
+
+impl<'a, W: FreqSink> FreqWriter<'a, W> {
+    pub fn new(writer: &'a mut W, codec: Codec) -> std::io::Result<Self> {
+        let mut out =
+            Self {
+                writer,
+                codec: codec.body(),
+                hasher: XxHash64::with_seed(0),
+                authors: 0,
+                words: 0,
+            };
+
+        let tool_version = env!("CARGO_PKG_VERSION").as_bytes();
+
+        // write magic
+        out.put(b"ragegun")?;
+
+        // write version (u32)
+        out.put(&FORMAT_VERSION.to_be_bytes())?;
+
+        // write the version of the tool that wrote the file (u8 length + utf8)
+        out.put(&[tool_version.len() as u8])?;
+        out.put(tool_version)?;
+
+        // write codec (u8)
+        out.put(&[codec.id()])?;
+
+        Ok(out)
+    }
+
+    // everything but the checksum itself is covered by the checksum
+    fn put(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.hasher.write(bytes);
+        self.writer.write_all(bytes)
+    }
+
+    // authors have to come in byte order, their words too
+    pub fn write_author(&mut self, author: &[u8], freqs: &[(&[u8], u64)]) -> std::io::Result<()> {
+        let mut abuf = Vec::new();
+
+        self.codec.encode_author(&mut abuf, author, freqs)?;
+
+        self.writer.begin_author(author)?;
+        self.put(abuf.as_slice())?;
+
+        self.authors += 1;
+        self.words += freqs.len() as u64;
+
+        Ok(())
+    }
+
+    pub fn written(&self) -> u64 {
+        self.authors
+    }
+
+    pub fn finish(mut self) -> std::io::Result<()> {
+        self.writer.end_authors()?;
+
+        let mut end = Vec::new();
+        self.codec.encode_end(&mut end);
+
+        self.put(&end)?;
+
+        // write author and word count (u64)
+        self.put(&self.authors.to_be_bytes())?;
+        self.put(&self.words.to_be_bytes())?;
+
+        // write checksum (u64)
+        let checksum = self.hasher.finish();
+        self.writer.write_all(&checksum.to_be_bytes())
+    }
+}
+
+#[inline(always)]
+pub fn serialize_with_writer<W: FreqSink, D: SortedFreqs>(
+    data: &D,
+    writer: &mut W,
+    codec: Codec,
+    mut fn_feedback: impl FnMut(SerializerFeedback),
+) -> std::io::Result<()> {
+    fn_feedback(SerializerFeedback::Message("Saving: Writing authors..".into()));
+    fn_feedback(SerializerFeedback::Total(data.author_count() as u64));
+
+    let mut out = FreqWriter::new(writer, codec)?;
+
+    data.for_each_sorted(|author, freqs| {
+        out.write_author(author, freqs)?;
+
+        if out.written().is_multiple_of(1000) {
+            fn_feedback(SerializerFeedback::Progress(out.written()));
+        }
+
+        Ok(())
+    })?;
+
+    out.finish()
+}
+
+// like `serialize_with_writer`, but every author goes to the writer `shard` picks for it, each writer becomes a
+// complete file with the authors it got
+pub fn serialize_sharded<W: FreqSink, D: SortedFreqs>(
+    data: &D,
+    writers: &mut [W],
+    codec: Codec,
+    mut shard: impl FnMut(&[u8]) -> usize,
+    mut fn_feedback: impl FnMut(SerializerFeedback),
+) -> std::io::Result<()> {
+    fn_feedback(SerializerFeedback::Message(format!("Saving: Writing authors to {} shards..", writers.len())));
+    fn_feedback(SerializerFeedback::Total(data.author_count() as u64));
+
+    let mut outs =
+        writers.iter_mut()
+            .map(|writer| FreqWriter::new(writer, codec))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+    let mut written = 0u64;
+
+    data.for_each_sorted(|author, freqs| {
+        let i = shard(author).min(outs.len() - 1);
+
+        outs[i].write_author(author, freqs)?;
+
+        written += 1;
+
+        if written.is_multiple_of(1000) {
+            fn_feedback(SerializerFeedback::Progress(written));
+        }
+
+        Ok(())
+    })?;
+
+    outs.into_iter().try_for_each(FreqWriter::finish)
+}
+
+// like `serialize_with_writer`, but frees every author's words once they are written
+pub fn serialize_draining<W: FreqSink>(
+    mut data: FreqStore,
+    writer: &mut W,
+    codec: Codec,
+    mut fn_feedback: impl FnMut(SerializerFeedback),
+) -> std::io::Result<()> {
+    fn_feedback(SerializerFeedback::Message("Saving: Writing authors..".into()));
+    fn_feedback(SerializerFeedback::Total(data.author_count() as u64));
+
+    let mut out = FreqWriter::new(writer, codec)?;
+
+    let mut authors = data.freqs.keys().copied().collect::<Vec<_>>();
+    authors.sort_unstable_by(|a, b| data.authors.resolve(*a).cmp(data.authors.resolve(*b)));
+
+    for author in authors {
+        let freqs = data.freqs.remove(&author).unwrap_or_default();
+
+        let mut freqs =
+            freqs.iter()
+                .map(|(word, freq)| (data.words.resolve(*word), *freq))
+                .collect::<Vec<_>>();
+
+        freqs.sort_unstable_by(|a, b| a.0.cmp(b.0));
+
+        out.write_author(data.authors.resolve(author), &freqs)?;
+
+        if out.written().is_multiple_of(1000) {
+            fn_feedback(SerializerFeedback::Progress(out.written()));
+        }
+    }
+
+    out.finish()
+}
 
 /*
-file format:
+file format (version 3, ragegun codec):
 ragegun
 version (u32)
-author count (u64)
-word count (u64)
+tool version length (u8)
+tool version (utf8)
+codec (u8)
 --
 author1
 0x245
@@ -125,6 +569,21 @@ author2
 0x243
 0x0
 --
+author count (u64)
+word count (u64)
+xxhash64 of everything above (u64)
+--
+
+the other codecs replace everything between codec and trailer with one
+serde record per author, (author, [(word, count)]), and have no end marker
+
+on disk this is zstd-compressed, cut into frames between authors with an
+index in a skippable frame at the end, see `index.rs`, and with `--dictionary`
+a skippable frame at the start holding the dictionary of every frame
+
+version 2 is version 3 without the codec byte, always ragegun
+version 1 (Nov2022A) has the author and word count right after the version
+and neither tool version nor trailer
 */
 
 enum Action {
@@ -141,7 +600,7 @@ fn establish_freqs(
         Marker::FreqU8 => {
             Action::FreqWordOffset(
                 frame[frame.len() - 2] as u64,
-                1,
+                2,
             )
         }
         Marker::FreqU32 => {
@@ -214,110 +673,392 @@ impl From<&[u8]> for Marker {
     }
 }
 
-#[derive(Debug)]
-enum RGFileFormat {
-    Nov2022A(u64, u64),
-    Unknown,
-    TooShort,
+// the format version in the first bytes of a file, none if it isn't one of ours
+pub fn format_version(head: &[u8]) -> Option<u32> {
+    match head.get(..11) {
+        Some(head) if head[..7] == *b"ragegun" => Some(u32::from_be_bytes([head[7], head[8], head[9], head[10]])),
+        _ => None,
+    }
 }
 
-impl RGFileFormat {
-    fn from_byte(
-        has_magic: bool,
-        version: u32,
-        authors: u64,
-        words: u64,
-    ) -> Self {
-        if !has_magic {
-            return Self::Unknown;
-        }
+// author count, word count and checksum
+const TRAILER_LEN: usize = 24;
+
+fn be_u64(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&bytes[..8]);
 
+    u64::from_be_bytes(buf)
+}
+
+// what the header of a layout says about the rest of the file
+struct Header {
+    codec: Codec,
+    // where the author records are
+    body: Range<usize>,
+    // author and word count from the header or trailer, unknown for classic files
+    counts: Option<(u64, u64)>,
+    // only FreqWriter guarantees byte order
+    sorted: bool,
+    description: String,
+}
+
+type ReadHeader = fn(&[u8]) -> std::io::Result<Header>;
+
+// every layout a file has been written in, by the version after the magic. a format change adds a version here and
+// keeps the old ones, so outputs of older builds stay readable without being generated again. files without the
+// magic are classic, from before there was a header
+const LAYOUTS: [(u32, ReadHeader); 3] = [(1, header_v1), (2, header_v2), (3, header_v3)];
+
+fn too_short() -> Error {
+    Error::new(ErrorKind::UnexpectedEof, "file is too short")
+}
+
+fn header_classic(data: &[u8]) -> std::io::Result<Header> {
+    Ok(Header {
+        codec: Codec::Ragegun,
+        body: 0..data.len(),
+        counts: None,
+        sorted: false,
+        description: "unknown, assuming classic".into(),
+    })
+}
+
+// Nov2022A, the author and word count right after the version
+fn header_v1(data: &[u8]) -> std::io::Result<Header> {
+    if data.len() < 27 {
+        return Err(too_short());
+    }
+
+    let (authors, words) = (be_u64(&data[11..19]), be_u64(&data[19..27]));
+
+    Ok(Header {
+        codec: Codec::Ragegun,
+        body: 27..data.len(),
+        counts: Some((authors, words)),
+        sorted: false,
+        description: format!("Nov2022A ({} authors, {} words)", authors, words),
+    })
+}
+
+// version 3 without the codec byte
+fn header_v2(data: &[u8]) -> std::io::Result<Header> {
+    header_trailed(data, 2)
+}
+
+fn header_v3(data: &[u8]) -> std::io::Result<Header> {
+    header_trailed(data, 3)
+}
+
+// the tool version after the version and the counts and checksum in a trailer
+fn header_trailed(data: &[u8], version: u32) -> std::io::Result<Header> {
+    let tool_end =
+        match data.get(11) {
+            Some(len) => 12 + *len as usize,
+            None => return Err(too_short()),
+        };
+
+    let (codec, body) =
         match version {
-            1 => Self::Nov2022A(authors, words),
-            _ => Self::Unknown,
-        }
+            2 => (Some(Codec::Ragegun), tool_end),
+            _ => (data.get(tool_end).and_then(|id| Codec::from_id(*id)), tool_end + 1),
+        };
+
+    if data.len() < body + TRAILER_LEN {
+        return Err(too_short());
     }
 
-    fn from_buf(data: &[u8]) -> Self {
-        if data.len() < 10 {
-            return Self::TooShort;
-        }
+    let trailer = data.len() - TRAILER_LEN;
 
-        dbg!(&data[0..40]);
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(&data[..data.len() - 8]);
 
-        // check if the first bytes are 'ragegun'
-        let has_magic = data[0..7] == *b"ragegun";
+    if hasher.finish() != be_u64(&data[data.len() - 8..]) {
+        return Err(Error::new(ErrorKind::InvalidData, "checksum mismatch, the file is corrupted or truncated"));
+    }
+
+    let Some(codec) = codec else {
+        return Err(Error::new(ErrorKind::InvalidData, format!("unknown codec {}", data[tool_end])));
+    };
+
+    let (authors, words) = (be_u64(&data[trailer..]), be_u64(&data[trailer + 8..]));
+
+    Ok(Header {
+        codec,
+        body: body..trailer,
+        counts: Some((authors, words)),
+        sorted: true,
+        description:
+            format!(
+                "version {} written by {} ({}, {} authors, {} words)",
+                version,
+                String::from_utf8_lossy(&data[12..tool_end]),
+                codec.name(),
+                authors,
+                words,
+            ),
+    })
+}
 
-        // check if the next 4 bytes (u32) are 1 or 2
-        let version = u32::from_be_bytes([data[7], data[8], data[9], data[10]]);
+// the counts in the header or trailer have to match what was actually read
+fn check_counts(freqs: PooMap, authors: u64, words: u64) -> std::io::Result<PooMap> {
+    let found_words = freqs.values().map(|v| v.len() as u64).sum::<u64>();
+
+    if freqs.len() as u64 != authors || found_words != words {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "expected {} authors and {} words, found {} and {}, the file is truncated",
+                authors,
+                words,
+                freqs.len(),
+                found_words,
+            ),
+        ));
+    }
 
-        // check if the next 8 bytes (u64) are the number of authors
-        let authors = u64::from_be_bytes([data[11], data[12], data[13], data[14], data[15], data[16], data[17], data[18]]);
+    Ok(freqs)
+}
 
-        // check if the next 8 bytes (u64) are the number of words
-        let words = u64::from_be_bytes([data[19], data[20], data[21], data[22], data[23], data[24], data[25], data[26]]);
+// where the authors of a file are
+struct Body<'a> {
+    codec: Codec,
+    data: &'a [u8],
+    counts: Option<(u64, u64)>,
+    sorted: bool,
+}
 
-        Self::from_byte(
-            has_magic,
-            version,
-            authors,
-            words,
-        )
+// the layout is picked by the version in the header
+fn body_of<'a>(
+    data: &'a [u8],
+    fn_feedback: &mut dyn FnMut(SerializerFeedback),
+) -> std::io::Result<Body<'a>> {
+    if data.len() < 11 {
+        return Err(too_short());
     }
+
+    let header =
+        match format_version(data) {
+            Some(version) => match LAYOUTS.iter().find(|(v, _)| *v == version) {
+                Some((_, read)) => read(data)?,
+                None => {
+                    let known = LAYOUTS.iter().map(|(v, _)| v.to_string()).collect::<Vec<_>>().join(", ");
+
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("unsupported format version {}, this build reads versions {} and classic files", version, known),
+                    ));
+                }
+            },
+            None => header_classic(data)?,
+        };
+
+    fn_feedback(SerializerFeedback::Message(format!("Loading: File format is {}", header.description)));
+
+    Ok(Body {
+        codec: header.codec,
+        data: &data[header.body],
+        counts: header.counts,
+        sorted: header.sorted,
+    })
 }
 
-const HTTP_NEEDLE: &'static [u8] = b"http";
+// every layout in `LAYOUTS` and classic files
+pub fn deserialize_any(
+    data: &[u8],
+    mut fn_feedback: impl FnMut(SerializerFeedback),
+) -> std::io::Result<PooMap> {
+    let body = body_of(data, &mut fn_feedback)?;
+
+    let authors = body.counts.map_or(0, |(authors, _)| authors);
+
+    let freqs = body.codec.body().decode(body.data, authors, &mut fn_feedback)?;
 
-pub fn deserialize(
+    match body.counts {
+        Some((authors, words)) => check_counts(freqs, authors, words),
+        None => Ok(freqs),
+    }
+}
+
+// decodes authors one by one without keeping them, stopping once `author` has been found or passed
+pub fn find_author(
     data: &[u8],
-    mut fn_feedback: impl FnMut(SerializerFeedback) -> (),
-) -> PooMap {
-    match RGFileFormat::from_buf(data) {
-        RGFileFormat::Nov2022A(authors, words) => {
-            fn_feedback(SerializerFeedback::Message(
-                format!("Loading: File format is Nov2022A ({} authors, {} words)", authors, words)
-            ));
+    author: &[u8],
+    mut fn_feedback: impl FnMut(SerializerFeedback),
+) -> std::io::Result<Option<PooMapInner>> {
+    let body = body_of(data, &mut fn_feedback)?;
 
-            try_deserialize_Nov2022A(
-                data,
-                fn_feedback,
-            )
+    let authors = body.counts.map_or(0, |(authors, _)| authors);
+
+    let mut found = None;
+
+    body.codec.body().scan(body.data, authors, &mut fn_feedback, &mut |name, freqs| {
+        match name.as_slice().cmp(author) {
+            Ordering::Equal => {
+                found = Some(freqs);
+                ControlFlow::Break(())
+            }
+            Ordering::Greater if body.sorted => ControlFlow::Break(()),
+            _ => ControlFlow::Continue(()),
         }
-        RGFileFormat::Unknown => {
-            fn_feedback(SerializerFeedback::Message("Loading: File format is unknown, assuming classic".into()));
+    })?;
 
-            try_deserialize_original(
-                data,
-                fn_feedback,
-            )
+    Ok(found)
+}
+
+// what `verify` found in a file, the header and checksum are checked before any of it
+#[derive(Debug, Default)]
+pub struct Verified {
+    pub version: Option<u32>,
+    pub authors: u64,
+    pub words: u64,
+    pub tokens: u64,
+    // anything wrong with the authors, the first few of each kind
+    pub problems: Vec<String>,
+}
+
+// problems of a kind beyond this are only counted
+const REPORTED: usize = 5;
+
+// decodes every author without keeping them, checking order, counts and the totals of the header or trailer
+pub fn verify(data: &[u8], mut fn_feedback: impl FnMut(SerializerFeedback)) -> std::io::Result<Verified> {
+    let body = body_of(data, &mut fn_feedback)?;
+
+    let mut verified = Verified { version: format_version(data), ..Default::default() };
+
+    let (mut unordered, mut empty, mut zero, mut overflow) = (0u64, 0u64, 0u64, false);
+    let mut previous: Option<Vec<u8>> = None;
+
+    let authors = body.counts.map_or(0, |(authors, _)| authors);
+
+    body.codec.body().scan(body.data, authors, &mut fn_feedback, &mut |author, freqs| {
+        let name = String::from_utf8_lossy(&author).into_owned();
+
+        // only FreqWriter promises an order, equal neighbours are an author written twice
+        if let Some(previous) = previous.as_ref().filter(|_| body.sorted) {
+            if previous.as_slice() >= author.as_slice() {
+                unordered += 1;
+
+                if unordered as usize <= REPORTED {
+                    verified.problems.push(format!("author {} comes after {}", name, String::from_utf8_lossy(previous)));
+                }
+            }
+        }
+
+        if freqs.is_empty() {
+            empty += 1;
+
+            if empty as usize <= REPORTED {
+                verified.problems.push(format!("author {} has no words", name));
+            }
+        }
+
+        for (word, &count) in &freqs {
+            if count == 0 {
+                zero += 1;
+
+                if zero as usize <= REPORTED {
+                    verified.problems.push(format!("author {} has a count of 0 for {}", name, String::from_utf8_lossy(word)));
+                }
+            }
+
+            match verified.tokens.checked_add(count) {
+                Some(tokens) => verified.tokens = tokens,
+                None if !overflow => {
+                    overflow = true;
+                    verified.problems.push(format!("the counts overflow a u64 by author {}", name));
+                }
+                None => {}
+            }
+        }
+
+        verified.authors += 1;
+        verified.words += freqs.len() as u64;
+
+        previous = Some(author);
+
+        ControlFlow::Continue(())
+    })?;
+
+    for (count, what) in [(unordered, "authors out of order"), (empty, "authors without words"), (zero, "counts of 0")] {
+        if count as usize > REPORTED {
+            verified.problems.push(format!("{} {} in all", count, what));
         }
-        RGFileFormat::TooShort => {
-            fn_feedback(SerializerFeedback::Message("Loading: File is too short".into()));
-            return PooMap::new();
+    }
+
+    if let Some((authors, words)) = body.counts {
+        if verified.authors != authors || verified.words != words {
+            verified.problems.push(format!(
+                "expected {} authors and {} words, found {} and {}, the file is truncated",
+                authors,
+                words,
+                verified.authors,
+                verified.words,
+            ));
         }
     }
+
+    Ok(verified)
 }
 
-pub fn try_deserialize_Nov2022A(
-    data: &[u8],
-    mut fn_feedback: impl FnMut(SerializerFeedback) -> (),
-) -> PooMap {
-    try_deserialize_original(
-        &data[28..],
-        fn_feedback,
-    )
+// the length of the header a piece of a file starts with and the codec it names, none if it doesn't start with one.
+// unlike `body_of` it needs nothing past the header
+pub fn header_of(piece: &[u8]) -> Option<(usize, Codec)> {
+    match format_version(piece)? {
+        1 => Some((27, Codec::Ragegun)),
+        2 => Some((12 + *piece.get(11)? as usize, Codec::Ragegun)),
+        3 => {
+            let tool_end = 12 + *piece.get(11)? as usize;
+
+            Some((tool_end + 1, Codec::from_id(*piece.get(tool_end)?)?))
+        }
+        _ => None,
+    }
 }
 
-pub fn try_deserialize_original(
-    data: &[u8],
-    mut fn_feedback: impl FnMut(SerializerFeedback) -> (),
-) -> PooMap {
-    let mut freq_vec = PooMap::new();
+// the author and word count if the piece is the end marker and trailer, which come in a piece of their own
+pub fn trailer_of(piece: &[u8], codec: Codec) -> Option<(u64, u64)> {
+    let mut end = Vec::new();
+    codec.body().encode_end(&mut end);
+
+    match piece.len() == end.len() + TRAILER_LEN && piece.starts_with(&end) {
+        true => Some((be_u64(&piece[end.len()..]), be_u64(&piece[end.len() + 8..]))),
+        false => None,
+    }
+}
+
+// the complete authors of a piece of a body, the first damaged or cut off record ends it
+pub fn salvage(piece: &[u8], codec: Codec, f: &mut dyn FnMut(Vec<u8>, PooMapInner)) {
+    if trailer_of(piece, codec).is_some() {
+        return;
+    }
+
+    // ended like `deserialize_block` does, an author cut off by the end is dropped at the end marker
+    let mut piece = piece.to_vec();
+    codec.body().encode_end(&mut piece);
 
+    let _ = codec.body().scan(&piece, u64::MAX, &mut |_| {}, &mut |author, freqs| {
+        f(author, freqs);
+        ControlFlow::Continue(())
+    });
+}
+
+// whole author records cut out of a body by a FreqSink, without header and trailer
+pub fn deserialize_block(mut block: Vec<u8>, authors: u64, codec: Codec) -> std::io::Result<PooMap> {
+    codec.body().encode_end(&mut block);
+    codec.body().decode(&block, authors, &mut |_| {})
+}
+
+fn scan_original(
+    data: &[u8],
+    mut fn_feedback: impl FnMut(SerializerFeedback),
+    mut f: impl FnMut(Vec<u8>, PooMapInner) -> ControlFlow<()>,
+) {
     let mut state = DeState::FindAuthor;
 
     let mut i = 0;
-    let mut last_marker_pos = 0;
+    // start of the frame following the last marker
+    let mut frame_start = 0;
 
     fn_feedback(SerializerFeedback::Message("Reading: Loading authors..".into()));
     fn_feedback(SerializerFeedback::Total(data.len() as u64));
@@ -332,7 +1073,7 @@ pub fn try_deserialize_original(
                 Marker::Unknown
             };
 
-        if i % 1000 == 0 {
+        if i.is_multiple_of(1000) {
             fn_feedback(SerializerFeedback::Progress(i as u64));
         }
 
@@ -348,44 +1089,34 @@ pub fn try_deserialize_original(
                     Marker::Author => {
                         state =
                             DeState::Author(
-                                data[last_marker_pos..i - 1].to_vec(),
-                                PooMapInner::new(),
+                                data[frame_start..i - 1].to_vec(),
+                                PooMapInner::default(),
                             );
+
+                        frame_start = i + 1;
                     }
                     Marker::End => {
-                        last_marker_pos = i;
-
-                        return freq_vec;
+                        return;
                     }
                     _ => {
-                        println!("Invalid author marker at {}: expected 245.", i);
+                        warn!(offset = i, "Invalid author marker, expected 245");
                     }
                 }
             }
             DeState::Author(ref author, ref mut freqs) => {
-                let frame = &data[last_marker_pos + 1..i];
+                let frame = &data[frame_start..i];
 
                 match marker {
                     Marker::FreqU8
                     | Marker::FreqU32
                     | Marker::FreqU64 => {
-                        last_marker_pos = i;
+                        frame_start = i + 1;
 
                         match establish_freqs(&marker, frame) {
                             Action::FreqWordOffset(freq, word_offset) => {
                                 let word = frame[..frame.len() - word_offset as usize].to_vec();
 
-                                let mut should_skip = false;
-
-                                should_skip |=
-                                    word.windows(HTTP_NEEDLE.len())
-                                        .any(|w| w == HTTP_NEEDLE);
-
-                                should_skip |=
-                                    !word.iter()
-                                        .any(|w| !(*w as char).is_ascii_digit());
-
-                                if !should_skip {
+                                if !is_junk(&word) {
                                     freqs.insert(
                                         word,
                                         freq,
@@ -393,41 +1124,28 @@ pub fn try_deserialize_original(
                                 }
                             }
                             Action::Continue => {
-                                dbg!(frame, i, last_marker_pos);
-
-                                println!(
-                                    "Invalid frame at [{} - {}] with len {}: should be 1, 4 or 8 bytes.",
-                                    last_marker_pos,
-                                    i,
-                                    frame.len(),
-                                );
+                                warn!(start = i - frame.len(), end = i, "Invalid frame, should be 1, 4 or 8 bytes");
                             }
                         }
                     }
                     Marker::AuthorEnd => {
-                        last_marker_pos = i;
+                        frame_start = i + 1;
 
-                        freq_vec.insert(
-                            author.clone(),
-                            freqs.clone(),
-                        );
+                        if f(author.clone(), freqs.clone()).is_break() {
+                            return;
+                        }
 
                         state = DeState::FindAuthor;
                     }
                     Marker::End => {
-                        last_marker_pos = i;
-
-                        return freq_vec;
+                        return;
                     }
                     _ => {
-                        println!(
-                            "({}/{:?})): Invalid frequency marker at {}: expected 255, 254 or 253.",
-                            String::from_utf8(author.clone())
-                                .unwrap_or(
-                                    "invalid author".to_string(),
-                                ),
-                            marker,
-                            i,
+                        warn!(
+                            author = %String::from_utf8_lossy(author),
+                            marker = ?marker,
+                            offset = i,
+                            "Invalid frequency marker, expected 255, 254 or 253",
                         );
                     }
                 }
@@ -437,7 +1155,118 @@ pub fn try_deserialize_original(
         i += 1;
     }
 
-    println!("Warning: reached end of file without finding end marker.");
+    warn!("Reached the end of the file without finding the end marker");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl FreqSink for Vec<u8> {}
+
+    // counts next to the markers and around the u8, u32 and u64 encodings
+    fn fixture() -> PooMap {
+        let counts = [1, 2, 243, 244, 245, 253, 254, 255, 256, 65_535, u32::MAX as u64, u32::MAX as u64 + 1, u64::MAX];
+
+        ["alice", "bob", "carol"]
+            .iter()
+            .enumerate()
+            .map(|(i, author)| {
+                let freqs =
+                    counts.iter()
+                        .skip(i)
+                        .enumerate()
+                        .map(|(j, count)| (format!("word{}", j).into_bytes(), *count))
+                        .collect();
+
+                (author.as_bytes().to_vec(), freqs)
+            })
+            .collect()
+    }
+
+    fn write(data: &PooMap, codec: Codec) -> Vec<u8> {
+        let mut out = Vec::new();
+        serialize_with_writer(data, &mut out, codec, |_| {}).unwrap();
+
+        out
+    }
+
+    fn with_checksum(mut data: Vec<u8>) -> Vec<u8> {
+        let mut hasher = XxHash64::with_seed(0);
+        hasher.write(&data);
+
+        data.extend_from_slice(&hasher.finish().to_be_bytes());
+        data
+    }
+
+    // the files of older builds with the same authors, from a version 3 ragegun file
+    fn older_layouts(v3: &[u8]) -> Vec<(u32, Vec<u8>)> {
+        let tool_end = 12 + v3[11] as usize;
+        let body = &v3[tool_end + 1..v3.len() - TRAILER_LEN];
+        let counts = &v3[v3.len() - TRAILER_LEN..v3.len() - 8];
+
+        vec![
+            (1, [b"ragegun".as_slice(), &1u32.to_be_bytes(), counts, body].concat()),
+            (2, with_checksum([b"ragegun".as_slice(), &2u32.to_be_bytes(), &v3[11..tool_end], body, counts].concat())),
+        ]
+    }
+
+    #[test]
+    fn every_codec_round_trips() {
+        let data = fixture();
+
+        for codec in [Codec::Ragegun, Codec::Bincode, Codec::Postcard, Codec::Msgpack] {
+            let file = write(&data, codec);
+
+            assert_eq!(format_version(&file), Some(FORMAT_VERSION));
+            assert_eq!(deserialize_any(&file, |_| {}).unwrap(), data, "{:?}", codec);
+        }
+    }
+
+    #[test]
+    fn every_layout_round_trips() {
+        let data = fixture();
+        let v3 = write(&data, Codec::Ragegun);
+
+        let mut files = older_layouts(&v3);
+        files.push((FORMAT_VERSION, v3.clone()));
+
+        // a new layout needs a file here
+        assert_eq!(LAYOUTS.map(|(version, _)| version), [1, 2, FORMAT_VERSION]);
+
+        for (version, file) in files {
+            assert_eq!(format_version(&file), Some(version));
+            assert_eq!(deserialize_any(&file, |_| {}).unwrap(), data, "version {}", version);
+        }
+
+        // classic files are the ragegun body alone
+        let classic = &v3[13 + v3[11] as usize..v3.len() - TRAILER_LEN];
+
+        assert_eq!(format_version(classic), None);
+        assert_eq!(deserialize_any(classic, |_| {}).unwrap(), data);
+    }
 
-    freq_vec
-}
\ No newline at end of file
+    #[test]
+    fn truncated_files_are_rejected() {
+        let data = fixture();
+        let words = data.values().map(|freqs| freqs.len() as u64).sum::<u64>();
+
+        assert!(check_counts(data.clone(), data.len() as u64, words).is_ok());
+        assert!(check_counts(data.clone(), data.len() as u64 + 1, words).is_err());
+        assert!(check_counts(data.clone(), data.len() as u64, words + 1).is_err());
+
+        let v3 = write(&data, Codec::Ragegun);
+
+        // the checksum covers the trailer
+        for cut in [1, 8, TRAILER_LEN, TRAILER_LEN + 40] {
+            assert!(deserialize_any(&v3[..v3.len() - cut], |_| {}).is_err(), "cut {}", cut);
+        }
+
+        // version 1 has only the counts to go by
+        let (_, v1) = older_layouts(&v3).remove(0);
+
+        let e = deserialize_any(&v1[..v1.len() - 40], |_| {}).unwrap_err();
+
+        assert!(e.to_string().contains("truncated"), "{}", e);
+    }
+}
@@ -0,0 +1,114 @@
+use clap::ValueEnum;
+
+use crate::weighting::SparseVector;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Metric {
+    // over the weights
+    #[default]
+    Cosine,
+    // over the word sets, ignores the weights
+    Jaccard,
+}
+
+// per-thread accumulators, sized to the number of authors
+pub struct Scratch {
+    scores: Vec<f32>,
+    touched: Vec<u32>,
+    similar: Vec<(u32, f32)>,
+}
+
+impl Scratch {
+    pub fn new(authors: usize) -> Self {
+        Self {
+            scores: vec![0.0; authors],
+            touched: Vec::new(),
+            similar: Vec::new(),
+        }
+    }
+}
+
+// inverted index over the vectors, so a query only visits authors sharing at least one word with it
+pub struct SimilarityIndex {
+    // by dimension: (author, weight)
+    postings: Vec<Vec<(u32, f32)>>,
+    norms: Vec<f32>,
+    lens: Vec<u32>,
+}
+
+impl SimilarityIndex {
+    pub fn build(vectors: &[SparseVector], dimensions: usize) -> Self {
+        let mut postings = vec![Vec::new(); dimensions];
+
+        for (author, vector) in vectors.iter().enumerate() {
+            for &(dim, weight) in vector {
+                postings[dim as usize].push((author as u32, weight));
+            }
+        }
+
+        Self {
+            postings,
+            norms:
+                vectors.iter()
+                    .map(|vector| vector.iter().map(|(_, w)| w * w).sum::<f32>().sqrt())
+                    .collect(),
+            lens: vectors.iter().map(|vector| vector.len() as u32).collect(),
+        }
+    }
+
+    // the `k` most similar other authors, most similar first
+    pub fn top_k(&self, query: usize, vector: &SparseVector, k: usize, metric: Metric, scratch: &mut Scratch) -> Vec<(u32, f32)> {
+        for &(dim, weight) in vector {
+            for &(author, other) in self.postings[dim as usize].iter() {
+                if scratch.scores[author as usize] == 0.0 {
+                    scratch.touched.push(author);
+                }
+
+                scratch.scores[author as usize] +=
+                    match metric {
+                        Metric::Cosine => weight * other,
+                        Metric::Jaccard => 1.0,
+                    };
+            }
+        }
+
+        let similar = &mut scratch.similar;
+
+        similar.clear();
+        similar.extend(
+            scratch.touched
+                .drain(..)
+                .filter(|&author| author as usize != query)
+                .map(|author| {
+                    let score = scratch.scores[author as usize];
+
+                    let similarity =
+                        match metric {
+                            Metric::Cosine => score / (self.norms[query] * self.norms[author as usize]),
+                            Metric::Jaccard => score / ((self.lens[query] + self.lens[author as usize]) as f32 - score),
+                        };
+
+                    (author, similarity)
+                })
+        );
+
+        // reset for the next query, the query itself was touched too
+        for &(author, _) in similar.iter() {
+            scratch.scores[author as usize] = 0.0;
+        }
+
+        scratch.scores[query] = 0.0;
+
+        let by_similarity = |a: &(u32, f32), b: &(u32, f32)| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0));
+
+        if similar.len() > k && k > 0 {
+            similar.select_nth_unstable_by(k - 1, by_similarity);
+        }
+
+        // a fresh vector, the scratch one has room for every author
+        let mut similar = similar[..similar.len().min(k)].to_vec();
+        similar.sort_by(by_similarity);
+
+        similar
+    }
+}
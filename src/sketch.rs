@@ -0,0 +1,335 @@
+use std::collections::BTreeMap;
+use std::hash::Hasher;
+
+use serde::{Deserialize, Serialize};
+use twox_hash::XxHash64;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SketchConfig {
+    // authors with more words than `width` are counted in a count-min sketch, the others stay exact
+    pub enabled: bool,
+    // counters per row, a word is overcounted by at most e / width of the author's tokens with a probability of
+    // 1 - e^-depth
+    pub width: usize,
+    pub depth: usize,
+    // the most used words of a sketched author, which go into the `.users.freqs` with their estimates
+    pub heavy_hitters: usize,
+}
+
+impl Default for SketchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            width: 512,
+            depth: 4,
+            heavy_hitters: 64,
+        }
+    }
+}
+
+impl SketchConfig {
+    pub fn check(&self) -> std::io::Result<()> {
+        if self.enabled && (self.width == 0 || self.depth == 0 || self.heavy_hitters == 0) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "the width, depth and heavy hitters of the sketches have to be above 0",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+// written as-is with bincode next to the `.users.freqs`, every sketch knows its width so the settings can change
+// between files
+pub type SketchMap = BTreeMap<Vec<u8>, Sketch>;
+
+const SEED: u64 = 0;
+
+// of the bytes of the word so the sketches are readable without the interner, the rows derive theirs from the two
+// halves (kirsch-mitzenmacher)
+pub fn word_hash(word: &[u8]) -> u64 {
+    let mut hasher = XxHash64::with_seed(SEED);
+    hasher.write(word);
+    hasher.finish()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sketch {
+    width: u32,
+    // of `word_hash`, sketches of other seeds count other words in the same cells
+    seed: u64,
+    // `depth` rows of `width` counters, saturating
+    counters: Vec<u32>,
+}
+
+impl Sketch {
+    pub fn new(config: &SketchConfig) -> Self {
+        Self {
+            width: config.width as u32,
+            seed: SEED,
+            counters: vec![0; config.width * config.depth],
+        }
+    }
+
+    fn depth(&self) -> usize {
+        self.counters.len() / self.width as usize
+    }
+
+    fn cell(&self, hash: u64, row: usize) -> usize {
+        let (low, high) = (hash as u32 as u64, hash >> 32);
+
+        row * self.width as usize + (low.wrapping_add(row as u64 * high) % self.width as u64) as usize
+    }
+
+    // adds `n` and returns the new estimate, only the counters at the minimum are raised (conservative update) so
+    // the overcounting is smaller than with raising all of them
+    pub fn add(&mut self, hash: u64, n: u64) -> u64 {
+        let estimate = self.estimate(hash).saturating_add(n).min(u32::MAX as u64) as u32;
+
+        for row in 0..self.depth() {
+            let cell = self.cell(hash, row);
+            self.counters[cell] = self.counters[cell].max(estimate);
+        }
+
+        estimate as u64
+    }
+
+    pub fn estimate(&self, hash: u64) -> u64 {
+        (0..self.depth()).map(|row| self.counters[self.cell(hash, row)]).min().unwrap_or(0) as u64
+    }
+
+    // heap bytes, see `FreqStore::estimated_size`
+    pub fn size(&self) -> usize {
+        self.counters.len() * std::mem::size_of::<u32>()
+    }
+
+    // cell-wise, so the estimates stay above the counts of the words of both. only for sketches of the same width,
+    // depth and seed
+    pub fn merge(&mut self, other: &Sketch) -> std::io::Result<()> {
+        if (self.width, self.depth(), self.seed) != (other.width, other.depth(), other.seed) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "the sketches differ in width, depth or seed",
+            ));
+        }
+
+        for (counter, other) in self.counters.iter_mut().zip(&other.counters) {
+            *counter = counter.saturating_add(*other);
+        }
+
+        Ok(())
+    }
+}
+
+// registers of a `Distinct`, the standard error is 1.04 / sqrt(2^10), about 3%
+const PRECISION: u32 = 10;
+const REGISTERS: usize = 1 << PRECISION;
+
+// a hyperloglog of the distinct words of an author, from `word_hash`. sparse, as sorted (register << 8 | rank),
+// until dense takes less space, so authors with few words stay small
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Distinct {
+    sparse: Vec<u32>,
+    dense: Vec<u8>,
+}
+
+impl Distinct {
+    pub fn insert(&mut self, hash: u64) {
+        let register = (hash >> (64 - PRECISION)) as usize;
+        // the leading zeros of the remaining bits, plus one
+        let rank = ((hash << PRECISION) | (1 << (PRECISION - 1))).leading_zeros() as u8 + 1;
+
+        self.raise(register, rank);
+    }
+
+    fn raise(&mut self, register: usize, rank: u8) {
+        if !self.dense.is_empty() {
+            self.dense[register] = self.dense[register].max(rank);
+            return;
+        }
+
+        match self.sparse.binary_search_by_key(&register, |v| (*v >> 8) as usize) {
+            Ok(i) => self.sparse[i] = self.sparse[i].max((register as u32) << 8 | rank as u32),
+            Err(i) => self.sparse.insert(i, (register as u32) << 8 | rank as u32),
+        }
+
+        if self.sparse.len() * std::mem::size_of::<u32>() > REGISTERS {
+            self.dense = vec![0; REGISTERS];
+
+            for v in self.sparse.drain(..) {
+                self.dense[(v >> 8) as usize] = v as u8;
+            }
+
+            self.sparse.shrink_to_fit();
+        }
+    }
+
+    // of the words in either, the highest rank of every register
+    pub fn union(&mut self, other: &Distinct) {
+        match other.dense.is_empty() {
+            true => other.sparse.iter().for_each(|v| self.raise((*v >> 8) as usize, *v as u8)),
+            false => {
+                for (register, rank) in other.dense.iter().enumerate().filter(|(_, rank)| **rank > 0) {
+                    self.raise(register, *rank);
+                }
+            }
+        }
+    }
+
+    pub fn estimate(&self) -> u64 {
+        let ranks =
+            match self.dense.is_empty() {
+                true => self.sparse.iter().map(|v| *v as u8).collect::<Vec<_>>(),
+                false => self.dense.iter().copied().filter(|rank| *rank > 0).collect(),
+            };
+
+        let m = REGISTERS as f64;
+        let zeros = REGISTERS - ranks.len();
+
+        let sum = zeros as f64 + ranks.iter().map(|rank| 2f64.powi(-(*rank as i32))).sum::<f64>();
+        let raw = 0.7213 / (1.0 + 1.079 / m) * m * m / sum;
+
+        // linear counting is better while registers are still empty
+        let estimate =
+            match zeros > 0 && raw <= 2.5 * m {
+                true => m * (m / zeros as f64).ln(),
+                false => raw,
+            };
+
+        estimate.round() as u64
+    }
+
+    // heap bytes, see `FreqStore::estimated_size`
+    pub fn size(&self) -> usize {
+        self.sparse.capacity() * std::mem::size_of::<u32>() + self.dense.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(width: usize, depth: usize) -> SketchConfig {
+        SketchConfig { enabled: true, width, depth, heavy_hitters: 8 }
+    }
+
+    fn hash(i: u64) -> u64 {
+        word_hash(format!("w{}", i).as_bytes())
+    }
+
+    // a few heavy words and a long tail
+    fn counts() -> Vec<(u64, u64)> {
+        (0..2000).map(|i| (hash(i), 1 + i % 7 + if i < 10 { 500 } else { 0 })).collect()
+    }
+
+    #[test]
+    fn sketch_overestimates_within_bound() {
+        let config = config(256, 4);
+        let mut sketch = Sketch::new(&config);
+
+        let counts = counts();
+        let total = counts.iter().map(|(_, n)| n).sum::<u64>();
+
+        // in two passes, the second raises the conservative estimates of the first
+        for &(hash, n) in &counts {
+            sketch.add(hash, n - n / 2);
+        }
+
+        for &(hash, n) in &counts {
+            sketch.add(hash, n / 2);
+        }
+
+        // e / width of the tokens, for all but e^-depth of the words
+        let bound = std::f64::consts::E * total as f64 / config.width as f64;
+        let mut over = 0;
+
+        for &(hash, n) in &counts {
+            let estimate = sketch.estimate(hash);
+
+            assert!(estimate >= n, "{} estimated as {}", n, estimate);
+            over += ((estimate - n) as f64 > bound) as usize;
+        }
+
+        assert!((over as f64) <= counts.len() as f64 * (-(config.depth as f64)).exp(), "{} words over the bound", over);
+    }
+
+    #[test]
+    fn sketch_merge_adds_the_cells() {
+        let config = config(64, 3);
+        let (mut a, mut b) = (Sketch::new(&config), Sketch::new(&config));
+
+        for &(hash, n) in &counts() {
+            a.add(hash, n);
+            b.add(hash, 1);
+        }
+
+        let cells = a.counters.iter().zip(&b.counters).map(|(a, b)| a + b).collect::<Vec<_>>();
+
+        a.merge(&b).unwrap();
+
+        assert_eq!(a.counters, cells);
+
+        for &(hash, n) in &counts() {
+            assert!(a.estimate(hash) > n);
+        }
+    }
+
+    #[test]
+    fn sketch_merge_needs_the_same_shape() {
+        let mut a = Sketch::new(&config(64, 3));
+
+        assert!(a.merge(&Sketch::new(&config(32, 3))).is_err());
+        assert!(a.merge(&Sketch::new(&config(64, 4))).is_err());
+
+        let mut other = Sketch::new(&config(64, 3));
+        other.seed = SEED + 1;
+
+        assert!(a.merge(&other).is_err());
+    }
+
+    #[test]
+    fn distinct_within_three_standard_errors() {
+        let limit = 3.0 * 1.04 / (REGISTERS as f64).sqrt();
+
+        for n in [10, 100, 1_000, 10_000, 100_000] {
+            let mut distinct = Distinct::default();
+
+            // repeats don't count
+            for i in (0..n).chain(0..n / 2) {
+                distinct.insert(hash(i));
+            }
+
+            let error = (distinct.estimate() as f64 - n as f64).abs() / n as f64;
+
+            assert!(error < limit, "{} words estimated as {}", n, distinct.estimate());
+        }
+    }
+
+    #[test]
+    fn distinct_union_is_of_both() {
+        let (mut sparse, mut dense, mut all) = (Distinct::default(), Distinct::default(), Distinct::default());
+
+        for i in 0..50 {
+            sparse.insert(hash(i));
+        }
+
+        for i in 25..5_000 {
+            dense.insert(hash(i));
+        }
+
+        for i in 0..5_000 {
+            all.insert(hash(i));
+        }
+
+        let mut union = sparse.clone();
+        union.union(&dense);
+
+        assert_eq!(union.estimate(), all.estimate());
+
+        dense.union(&sparse);
+
+        assert_eq!(dense, all);
+    }
+}
@@ -0,0 +1,193 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufReader, Error};
+use std::path::{Path, PathBuf};
+
+use crate::serializer::SortedFreqs;
+use crate::text::interner::FreqStore;
+
+// one author and their words in byte order, as stored in a run
+pub type RunRecord = (Vec<u8>, Vec<(Vec<u8>, u64)>);
+
+// hands out authors in byte order, each at most once
+pub trait RecordSource {
+    fn next(&mut self) -> std::io::Result<Option<RunRecord>>;
+}
+
+// <out>.run.<n>, in `dir` if given
+pub fn run_path(out_path: &Path, dir: Option<&Path>, index: usize) -> PathBuf {
+    let mut name = out_path.file_name().unwrap().to_os_string();
+    name.push(format!(".run.{}", index));
+
+    match dir {
+        Some(dir) => dir.join(name),
+        None => out_path.with_file_name(name),
+    }
+}
+
+// writes the store sorted by author as zstd-compressed bincode, prefixed by the author count
+pub fn write_run(path: &Path, store: &FreqStore) -> std::io::Result<()> {
+    let mut file = File::create(path)?;
+    let mut encoder = zstd::stream::Encoder::new(&mut file, 1)?;
+
+    bincode::serialize_into(&mut encoder, &(store.len() as u64))
+        .map_err(Error::other)?;
+
+    store.for_each_sorted(|author, freqs| {
+        bincode::serialize_into(&mut encoder, &(author, freqs))
+            .map_err(Error::other)
+    })?;
+
+    encoder.finish()?;
+    file.sync_all()
+}
+
+struct RunReader {
+    decoder: zstd::stream::Decoder<'static, BufReader<File>>,
+    remaining: u64,
+}
+
+impl RunReader {
+    fn open(path: &Path) -> std::io::Result<Self> {
+        let mut decoder = zstd::stream::Decoder::new(File::open(path)?)?;
+
+        let remaining =
+            bincode::deserialize_from(&mut decoder)
+                .map_err(Error::other)?;
+
+        Ok(Self { decoder, remaining })
+    }
+}
+
+impl RecordSource for RunReader {
+    fn next(&mut self) -> std::io::Result<Option<RunRecord>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+
+        self.remaining -= 1;
+
+        bincode::deserialize_from(&mut self.decoder)
+            .map(Some)
+            .map_err(Error::other)
+    }
+}
+
+// merges two word lists that are both in byte order
+fn merge_sorted(a: Vec<(Vec<u8>, u64)>, b: Vec<(Vec<u8>, u64)>) -> Vec<(Vec<u8>, u64)> {
+    let mut merged = Vec::with_capacity(a.len().max(b.len()));
+
+    let mut a = a.into_iter().peekable();
+    let mut b = b.into_iter().peekable();
+
+    loop {
+        let next =
+            match (a.peek(), b.peek()) {
+                (Some(x), Some(y)) if x.0 == y.0 => {
+                    let (word, freq) = a.next().unwrap();
+                    (word, freq + b.next().unwrap().1)
+                }
+                (Some(x), Some(y)) if x.0 < y.0 => a.next().unwrap(),
+                (Some(_), Some(_)) => b.next().unwrap(),
+                (Some(_), None) => a.next().unwrap(),
+                (None, Some(_)) => b.next().unwrap(),
+                (None, None) => return merged,
+            };
+
+        merged.push(next);
+    }
+}
+
+fn merge_runs(
+    paths: &[PathBuf],
+    f: impl FnMut(&[u8], &[(&[u8], u64)]) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let readers =
+        paths.iter()
+            .map(|path| RunReader::open(path))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+    merge_records(readers, f)
+}
+
+// k-way merge over the sources, calling `f` once per author in byte order
+pub fn merge_records<R: RecordSource>(
+    mut readers: Vec<R>,
+    mut f: impl FnMut(&[u8], &[(&[u8], u64)]) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let mut heads = vec![Vec::new(); readers.len()];
+    let mut heap = BinaryHeap::new();
+
+    for (i, reader) in readers.iter_mut().enumerate() {
+        if let Some((author, words)) = reader.next()? {
+            heads[i] = words;
+            heap.push(Reverse((author, i)));
+        }
+    }
+
+    while let Some(Reverse((author, i))) = heap.pop() {
+        let mut words = std::mem::take(&mut heads[i]);
+
+        if let Some((next, next_words)) = readers[i].next()? {
+            heads[i] = next_words;
+            heap.push(Reverse((next, i)));
+        }
+
+        // the same author may appear in several runs
+        while heap.peek().is_some_and(|Reverse((next, _))| *next == author) {
+            let Reverse((_, j)) = heap.pop().unwrap();
+
+            words = merge_sorted(words, std::mem::take(&mut heads[j]));
+
+            if let Some((next, next_words)) = readers[j].next()? {
+                heads[j] = next_words;
+                heap.push(Reverse((next, j)));
+            }
+        }
+
+        let words =
+            words.iter()
+                .map(|(word, freq)| (word.as_slice(), *freq))
+                .collect::<Vec<_>>();
+
+        f(&author, &words)?;
+    }
+
+    Ok(())
+}
+
+// the merged view of several spilled runs
+pub struct MergedRuns {
+    paths: Vec<PathBuf>,
+    // authors appearing in several runs are counted once per run
+    authors: usize,
+}
+
+impl MergedRuns {
+    pub fn open(paths: &[PathBuf]) -> std::io::Result<Self> {
+        let mut authors = 0;
+
+        for path in paths {
+            authors += RunReader::open(path)?.remaining as usize;
+        }
+
+        Ok(Self {
+            paths: paths.to_vec(),
+            authors,
+        })
+    }
+}
+
+impl SortedFreqs for MergedRuns {
+    fn author_count(&self) -> usize {
+        self.authors
+    }
+
+    fn for_each_sorted(
+        &self,
+        f: impl FnMut(&[u8], &[(&[u8], u64)]) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        merge_runs(&self.paths, f)
+    }
+}
@@ -0,0 +1,535 @@
+use std::collections::BTreeSet;
+use std::collections::hash_map::Entry;
+use std::sync::Arc;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::serializer::SortedFreqs;
+use crate::sketch::{word_hash, Distinct, Sketch, SketchConfig, SketchMap};
+
+use super::text_item::{PooMap, PooMapBase, PooMapInner, PooMapRoot};
+
+// hands out dense u32 symbols so every distinct string is stored once
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    strings: Vec<Arc<[u8]>>,
+    symbols: PooMapRoot<Arc<[u8]>, u32>,
+    bytes: usize,
+}
+
+impl Interner {
+    pub fn intern(&mut self, string: &[u8]) -> u32 {
+        if let Some(symbol) = self.symbols.get(string) {
+            return *symbol;
+        }
+
+        let symbol = u32::try_from(self.strings.len()).expect("Interner is full");
+        let string: Arc<[u8]> = Arc::from(string);
+
+        self.bytes += string.len();
+        self.strings.push(string.clone());
+        self.symbols.insert(string, symbol);
+
+        symbol
+    }
+
+    pub fn get(&self, string: &[u8]) -> Option<u32> {
+        self.symbols.get(string).copied()
+    }
+
+    pub fn resolve(&self, symbol: u32) -> &[u8] {
+        &self.strings[symbol as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    // approximate heap usage, see `FreqStore::estimated_size`
+    pub fn estimated_size(&self) -> usize {
+        self.bytes + self.strings.len() * STRING_OVERHEAD
+    }
+}
+
+// rough per-item costs including allocation and hash table overhead
+const STRING_OVERHEAD: usize = 64;
+const AUTHOR_OVERHEAD: usize = 64;
+const ENTRY_SIZE: usize = 24;
+const ORDERED_SIZE: usize = 40;
+const SKETCH_OVERHEAD: usize = 64;
+
+// only the strings are stored, the symbols are their positions
+impl Serialize for Interner {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.strings.iter().map(|v| &v[..]))
+    }
+}
+
+impl<'de> Deserialize<'de> for Interner {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut interner = Self::default();
+
+        for string in Vec::<Vec<u8>>::deserialize(deserializer)? {
+            interner.intern(&string);
+        }
+
+        Ok(interner)
+    }
+}
+
+pub type SymbolFreqs = PooMapRoot<u32, u64>;
+
+// how `FreqStore::ingest_with` counts the words of an author
+#[derive(Debug, Clone, Copy)]
+pub enum Counting<'a> {
+    Exact,
+    // see `ingest_capped`
+    Capped(usize),
+    // see `ingest_sketched`
+    Sketched(&'a SketchConfig),
+}
+
+// per-author word counts keyed by interned symbols, for long-lived aggregates
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FreqStore {
+    pub authors: Interner,
+    pub words: Interner,
+    pub freqs: PooMapRoot<u32, SymbolFreqs>,
+    // (author, word) pairs across all authors
+    entries: usize,
+    // the words of the authors `ingest_capped` filled up by count, for finding the least counted one. rebuilt when
+    // needed, so neither checkpointed nor kept through pruning
+    #[serde(skip)]
+    order: PooMapRoot<u32, BTreeSet<(u64, u32)>>,
+    #[serde(skip)]
+    ordered: usize,
+    // the authors `ingest_sketched` counts approximately, their freqs are the heavy hitters
+    sketches: PooMapRoot<u32, Sketch>,
+    sketched: usize,
+    // the distinct words of the authors whose freqs stopped being all the words they used, see `track`
+    distinct: PooMapRoot<u32, Distinct>,
+    distinct_size: usize,
+}
+
+impl FreqStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // from now on the distinct words of `author` are counted in a hyperloglog, starting with the ones it has
+    fn track(&mut self, author: u32) {
+        if self.distinct.contains_key(&author) {
+            return;
+        }
+
+        let mut distinct = Distinct::default();
+
+        for word in self.freqs.get(&author).into_iter().flat_map(|freqs| freqs.keys()) {
+            distinct.insert(word_hash(self.words.resolve(*word)));
+        }
+
+        self.distinct_size += distinct.size();
+        self.distinct.insert(author, distinct);
+    }
+
+    fn observe_distinct(&mut self, author: u32, freqs: &PooMapInner) {
+        if let Some(distinct) = self.distinct.get_mut(&author) {
+            self.distinct_size -= distinct.size();
+
+            for word in freqs.keys() {
+                distinct.insert(word_hash(word));
+            }
+
+            self.distinct_size += distinct.size();
+        }
+    }
+
+    // before a batch could push an author past `limit` words
+    fn track_above(&mut self, author: u32, freqs: &PooMapInner, limit: usize) {
+        if self.freqs.get(&author).map_or(0, |known| known.len()) + freqs.len() > limit {
+            self.track(author);
+        }
+    }
+
+    pub fn ingest(&mut self, other: &PooMap) {
+        for (author, freqs) in other.iter() {
+            let author = self.authors.intern(author);
+            self.observe_distinct(author, freqs);
+
+            let author_freqs =
+                self.freqs
+                    .entry(author)
+                    .or_default();
+
+            for (word, freq) in freqs.iter() {
+                match author_freqs.entry(self.words.intern(word)) {
+                    Entry::Occupied(mut entry) => *entry.get_mut() += *freq,
+                    Entry::Vacant(entry) => {
+                        entry.insert(*freq);
+                        self.entries += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn ingest_with(&mut self, other: &PooMap, counting: Counting) {
+        match counting {
+            Counting::Exact => self.ingest(other),
+            Counting::Capped(cap) => self.ingest_capped(other, cap),
+            Counting::Sketched(config) => self.ingest_sketched(other, config),
+        }
+    }
+
+    // like `ingest`, but an author keeps at most `cap` words: once full, a new word takes the place of the least
+    // counted one and starts from its count (space-saving), so the most used words are counted about right and
+    // the counts of the tail are overestimates
+    pub fn ingest_capped(&mut self, other: &PooMap, cap: usize) {
+        let cap = cap.max(1);
+
+        for (author, freqs) in other.iter() {
+            let author = self.authors.intern(author);
+            self.track_above(author, freqs, cap);
+            self.observe_distinct(author, freqs);
+
+            let author_freqs =
+                self.freqs
+                    .entry(author)
+                    .or_default();
+
+            for (word, freq) in freqs.iter() {
+                let word = self.words.intern(word);
+
+                if let Some(count) = author_freqs.get_mut(&word) {
+                    if let Some(order) = self.order.get_mut(&author) {
+                        order.remove(&(*count, word));
+                        order.insert((*count + freq, word));
+                    }
+
+                    *count += freq;
+                    continue;
+                }
+
+                if author_freqs.len() < cap {
+                    author_freqs.insert(word, *freq);
+                    self.entries += 1;
+                    continue;
+                }
+
+                let order =
+                    self.order.entry(author).or_insert_with(|| {
+                        self.ordered += author_freqs.len();
+                        author_freqs.iter().map(|(&word, &count)| (count, word)).collect()
+                    });
+
+                let (min, evicted) = order.pop_first().expect("a full author has words");
+
+                author_freqs.remove(&evicted);
+                author_freqs.insert(word, min + freq);
+                order.insert((min + freq, word));
+            }
+        }
+    }
+
+    // like `ingest` until an author has more than `width` words, from then on all of them are counted in a sketch
+    // and only the estimates of the most used are kept, which is what gets written for the author
+    pub fn ingest_sketched(&mut self, other: &PooMap, config: &SketchConfig) {
+        for (author, freqs) in other.iter() {
+            let author = self.authors.intern(author);
+            self.track_above(author, freqs, config.width);
+            self.observe_distinct(author, freqs);
+
+            let author_freqs =
+                self.freqs
+                    .entry(author)
+                    .or_default();
+
+            let sketch =
+                match self.sketches.get_mut(&author) {
+                    Some(sketch) => sketch,
+                    None => {
+                        for (word, freq) in freqs.iter() {
+                            match author_freqs.entry(self.words.intern(word)) {
+                                Entry::Occupied(mut entry) => *entry.get_mut() += *freq,
+                                Entry::Vacant(entry) => {
+                                    entry.insert(*freq);
+                                    self.entries += 1;
+                                }
+                            }
+                        }
+
+                        if author_freqs.len() <= config.width {
+                            continue;
+                        }
+
+                        let mut sketch = Sketch::new(config);
+
+                        for (&word, &freq) in author_freqs.iter() {
+                            sketch.add(word_hash(self.words.resolve(word)), freq);
+                        }
+
+                        let mut heavy = author_freqs.drain().collect::<Vec<_>>();
+                        heavy.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+                        self.entries -= heavy.len();
+                        heavy.truncate(config.heavy_hitters);
+                        self.entries += heavy.len();
+
+                        // the estimates, which heavy hitters added later are compared with
+                        author_freqs.extend(heavy.into_iter().map(|(word, _)| (word, sketch.estimate(word_hash(self.words.resolve(word))))));
+
+                        self.sketched += sketch.size() + SKETCH_OVERHEAD;
+                        self.sketches.insert(author, sketch);
+
+                        continue;
+                    }
+                };
+
+            for (word, freq) in freqs.iter() {
+                let estimate = sketch.add(word_hash(word), *freq);
+                let word = self.words.intern(word);
+
+                if let Some(count) = author_freqs.get_mut(&word) {
+                    *count = estimate;
+                    continue;
+                }
+
+                if author_freqs.len() < config.heavy_hitters {
+                    author_freqs.insert(word, estimate);
+                    self.entries += 1;
+                    continue;
+                }
+
+                // few enough heavy hitters to look through
+                let (&least, &count) =
+                    author_freqs.iter()
+                        .min_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+                        .expect("a sketched author has heavy hitters");
+
+                if estimate > count {
+                    author_freqs.remove(&least);
+                    author_freqs.insert(word, estimate);
+                }
+            }
+        }
+    }
+
+    // the sketches by author, for writing them next to the output
+    pub fn sketches(&self) -> SketchMap {
+        self.sketches
+            .iter()
+            .map(|(author, sketch)| (self.authors.resolve(*author).to_vec(), sketch.clone()))
+            .collect()
+    }
+
+    // drops the words not matching `keep` and the authors left without any, the interners keep
+    // their strings since symbols are positions
+    pub fn retain_words(&mut self, keep: impl Fn(&[u8]) -> bool) {
+        let kept =
+            (0..self.words.len() as u32)
+                .map(|symbol| keep(self.words.resolve(symbol)))
+                .collect::<Vec<_>>();
+
+        let mut entries = 0;
+
+        self.freqs.retain(|_, freqs| {
+            freqs.retain(|word, _| kept[*word as usize]);
+            entries += freqs.len();
+
+            !freqs.is_empty()
+        });
+
+        self.entries = entries;
+        self.order.clear();
+        self.ordered = 0;
+    }
+
+    // drops the words an author used fewer than `min` times and the authors left without any
+    pub fn retain_min_count(&mut self, min: u64) {
+        let mut entries = 0;
+
+        self.freqs.retain(|_, freqs| {
+            freqs.retain(|_, freq| *freq >= min);
+            entries += freqs.len();
+
+            !freqs.is_empty()
+        });
+
+        self.entries = entries;
+        self.order.clear();
+        self.ordered = 0;
+    }
+
+    // `retain_min_count` while still ingesting, the authors losing words have their distinct words counted from
+    // now on
+    pub fn forget_min_count(&mut self, min: u64) {
+        let losing =
+            self.freqs
+                .iter()
+                .filter(|(_, freqs)| freqs.values().any(|freq| *freq < min))
+                .map(|(author, _)| *author)
+                .collect::<Vec<_>>();
+
+        for author in losing {
+            self.track(author);
+        }
+
+        self.retain_min_count(min);
+    }
+
+    // the hyperloglogs by author, for keeping them when the store is spilled and starts over
+    pub fn take_distinct(&mut self) -> PooMapBase<Distinct> {
+        self.distinct_size = 0;
+
+        std::mem::take(&mut self.distinct)
+            .into_iter()
+            .map(|(author, distinct)| (self.authors.resolve(author).to_vec(), distinct))
+            .collect()
+    }
+
+    pub fn restore_distinct(&mut self, distinct: PooMapBase<Distinct>) {
+        for (author, distinct) in distinct {
+            self.distinct_size += distinct.size();
+
+            let author = self.authors.intern(&author);
+            self.distinct.insert(author, distinct);
+        }
+    }
+
+    // adds the words of the authors with a hyperloglog in `data`, the runs spilled before they got one
+    pub fn union_distinct(&mut self, data: &impl SortedFreqs) -> std::io::Result<()> {
+        data.for_each_sorted(|author, freqs| {
+            if let Some(distinct) = self.authors.get(author).and_then(|author| self.distinct.get_mut(&author)) {
+                for (word, _) in freqs {
+                    distinct.insert(word_hash(word));
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    // the hyperloglogs of the distinct words of the authors whose freqs lost some
+    pub fn distinct_words(&self) -> impl Iterator<Item = (&[u8], &Distinct)> + '_ {
+        self.distinct
+            .iter()
+            .map(|(author, distinct)| (self.authors.resolve(*author), distinct))
+    }
+
+    pub fn len(&self) -> usize {
+        self.freqs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.freqs.is_empty()
+    }
+
+    // approximate heap usage in bytes, used to decide when to spill
+    pub fn estimated_size(&self) -> usize {
+        self.authors.estimated_size()
+            + self.words.estimated_size()
+            + self.freqs.len() * AUTHOR_OVERHEAD
+            + self.entries * ENTRY_SIZE
+            + self.ordered * ORDERED_SIZE
+            + self.sketched
+            + self.distinct_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // five words used 10 times in every batch and a tail of words used a few times in one batch
+    fn batches() -> (Vec<PooMap>, PooMapInner) {
+        let mut exact = PooMapInner::default();
+
+        let batches =
+            (0..100u64)
+                .map(|batch| {
+                    let mut freqs = PooMapInner::default();
+
+                    for heavy in 0..5 {
+                        freqs.insert(format!("heavy{}", heavy).into_bytes(), 10);
+                    }
+
+                    for tail in (batch..1_000).step_by(100) {
+                        freqs.insert(format!("tail{}", tail).into_bytes(), 1 + tail % 3);
+                    }
+
+                    for (word, freq) in freqs.iter() {
+                        *exact.entry(word.clone()).or_default() += freq;
+                    }
+
+                    PooMap::from_iter([(b"author".to_vec(), freqs)])
+                })
+                .collect();
+
+        (batches, exact)
+    }
+
+    fn words(store: &FreqStore) -> Vec<(&[u8], u64)> {
+        let author = store.authors.get(b"author").unwrap();
+
+        store.freqs[&author].iter().map(|(word, count)| (store.words.resolve(*word), *count)).collect()
+    }
+
+    #[test]
+    fn capped_keeps_the_heavy_hitters() {
+        let cap = 50;
+        let (batches, exact) = batches();
+        let total = exact.values().sum::<u64>();
+
+        let mut store = FreqStore::new();
+
+        for batch in &batches {
+            store.ingest_capped(batch, cap);
+        }
+
+        let kept = words(&store);
+
+        assert_eq!(kept.len(), cap);
+
+        // space-saving overcounts a word by at most total / cap, so every word above that is kept
+        for (word, count) in &kept {
+            assert!(*count >= exact[*word] && *count - exact[*word] <= total / cap as u64);
+        }
+
+        for (word, count) in exact.iter().filter(|(_, count)| **count > total / cap as u64) {
+            assert!(kept.iter().any(|(kept, _)| kept == word), "{} used {} times is missing", String::from_utf8_lossy(word), count);
+        }
+
+        // the distinct words are estimated once some were dropped
+        let (_, distinct) = store.distinct_words().next().unwrap();
+
+        assert!((distinct.estimate() as f64 - exact.len() as f64).abs() < exact.len() as f64 * 0.1);
+    }
+
+    #[test]
+    fn sketched_keeps_the_heavy_hitters() {
+        let config = SketchConfig { enabled: true, width: 64, depth: 4, heavy_hitters: 5 };
+        let (batches, exact) = batches();
+
+        let mut store = FreqStore::new();
+
+        for batch in &batches {
+            store.ingest_sketched(batch, &config);
+        }
+
+        let mut kept = words(&store).into_iter().map(|(word, _)| word.to_vec()).collect::<Vec<_>>();
+        kept.sort();
+
+        assert_eq!(kept, (0..5).map(|heavy| format!("heavy{}", heavy).into_bytes()).collect::<Vec<_>>());
+
+        let sketches = store.sketches();
+        let sketch = &sketches[b"author".as_slice()];
+
+        for (word, count) in exact.iter() {
+            assert!(sketch.estimate(word_hash(word)) >= *count);
+        }
+    }
+}
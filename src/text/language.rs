@@ -0,0 +1,124 @@
+use std::io::{Error, ErrorKind};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use whatlang::Lang;
+
+use super::pipeline::{Document, TextStage};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LanguageMode {
+    // no detection
+    #[default]
+    Off,
+    // only count comments in one of `languages`
+    Filter,
+    // prefix every word with the language of its comment, e.g. "deu:hallo", so each author holds
+    // separate counts per language
+    Tag,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LanguageConfig {
+    pub mode: LanguageMode,
+    // ISO 639-3 codes like "eng" or "deu"
+    pub languages: Vec<String>,
+    // detections below this are undetermined, short comments rarely reach it
+    pub min_confidence: f64,
+    // count undetermined comments when filtering
+    pub keep_undetermined: bool,
+}
+
+impl Default for LanguageConfig {
+    fn default() -> Self {
+        Self {
+            mode: LanguageMode::Off,
+            languages: vec!["eng".to_string()],
+            min_confidence: 0.5,
+            keep_undetermined: true,
+        }
+    }
+}
+
+// the tag of undetermined comments
+const UNDETERMINED: &str = "und";
+
+#[derive(Debug, Clone)]
+pub struct LanguageFilter {
+    mode: LanguageMode,
+    languages: Vec<Lang>,
+    min_confidence: f64,
+    keep_undetermined: bool,
+}
+
+impl LanguageFilter {
+    pub fn from_config(config: &LanguageConfig) -> std::io::Result<Option<Self>> {
+        if config.mode == LanguageMode::Off {
+            return Ok(None);
+        }
+
+        let languages =
+            config.languages
+                .iter()
+                .map(|code| {
+                    Lang::from_code(code.as_str()).ok_or_else(|| {
+                        Error::new(ErrorKind::InvalidInput, format!("unknown language '{}', expected an ISO 639-3 code like eng", code))
+                    })
+                })
+                .collect::<std::io::Result<Vec<_>>>()?;
+
+        Ok(Some(Self {
+            mode: config.mode,
+            languages,
+            min_confidence: config.min_confidence,
+            keep_undetermined: config.keep_undetermined,
+        }))
+    }
+
+    fn detect(&self, text: &str) -> Option<Lang> {
+        whatlang::detect(text)
+            .filter(|info| info.confidence() >= self.min_confidence)
+            .map(|info| info.lang())
+    }
+
+    pub fn route(&self, text: &str) -> Route {
+        let lang = self.detect(text);
+
+        match self.mode {
+            LanguageMode::Off => Route::Count,
+            LanguageMode::Filter => {
+                let keep =
+                    match lang {
+                        Some(lang) => self.languages.contains(&lang),
+                        None => self.keep_undetermined,
+                    };
+
+                if keep { Route::Count } else { Route::Drop }
+            }
+            LanguageMode::Tag => Route::Tag(lang.map_or(UNDETERMINED, |lang| lang.code())),
+        }
+    }
+}
+
+// what happens to the words of a comment
+pub enum Route {
+    Drop,
+    Count,
+    // prefixed with the language code
+    Tag(&'static str),
+}
+
+impl TextStage for LanguageFilter {
+    fn apply(&self, doc: &mut Document) -> bool {
+        match self.route(&doc.text) {
+            Route::Drop => false,
+            Route::Count => true,
+            Route::Tag(lang) => {
+                doc.prefix = Some(lang);
+                true
+            }
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use super::pipeline::Transform;
+
+// maps inflected forms to their lemma, e.g. "ran" and "running" to "run"
+#[derive(Debug, Clone, Default)]
+pub struct LemmaTable {
+    lemmas: HashMap<String, String>,
+}
+
+impl LemmaTable {
+    // one `form,lemma` (or tab separated) pair per line, `#` starts a comment
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let mut lemmas = HashMap::new();
+
+        for (i, line) in std::fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let Some((form, lemma)) = line.split_once(['\t', ',']) else {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{}:{}: expected form,lemma", path.display(), i + 1),
+                ));
+            };
+
+            // keyed like the tokenizer leaves words
+            let form = form.trim().chars().filter(|c| super::is_word_char(*c)).collect::<String>().to_lowercase();
+
+            if !form.is_empty() {
+                lemmas.insert(form, lemma.trim().to_lowercase());
+            }
+        }
+
+        Ok(Self { lemmas })
+    }
+
+    pub fn lemma(&self, word: &str) -> Option<&str> {
+        self.lemmas.get(word).map(String::as_str)
+    }
+}
+
+// words not in the table are kept
+impl Transform for LemmaTable {
+    fn apply<'a>(&self, word: Cow<'a, str>) -> Cow<'a, str> {
+        match self.lemma(&word) {
+            Some(lemma) => Cow::Owned(lemma.to_string()),
+            None => word,
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::borrow::Cow;
+
+use serde::Deserialize;
+
+use super::pipeline::{Document, TextStage};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MarkdownConfig {
+    // decode html entities and drop emphasis, headings and spoiler markers before tokenizing
+    pub strip: bool,
+    // lines quoting someone else, starting with `>`
+    pub quotes: bool,
+    // fenced or indented code blocks and `inline code`
+    pub code: bool,
+    // the url of `[text](url)`, the text is kept
+    pub link_urls: bool,
+}
+
+impl Default for MarkdownConfig {
+    fn default() -> Self {
+        Self {
+            strip: false,
+            quotes: true,
+            code: true,
+            link_urls: true,
+        }
+    }
+}
+
+// reddit stores bodies html-escaped
+const ENTITIES: [(&str, &str); 6] = [
+    ("&gt;", ">"),
+    ("&lt;", "<"),
+    ("&nbsp;", " "),
+    ("&#x200B;", ""),
+    ("&#x200b;", ""),
+    // last, so "&amp;gt;" stays "&gt;"
+    ("&amp;", "&"),
+];
+
+// emphasis, strikethrough, superscript and spoilers
+const FORMATTING: [&str; 7] = ["**", "__", "~~", ">!", "!<", "*", "^"];
+
+fn decode_entities(text: &str) -> String {
+    let mut text = text.to_string();
+
+    for (entity, replacement) in ENTITIES {
+        if text.contains(entity) {
+            text = text.replace(entity, replacement);
+        }
+    }
+
+    text
+}
+
+// `[text](url)` to `text`
+fn strip_link_urls(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(open) = rest.find('[') {
+        let link =
+            rest[open..].find("](")
+                .map(|close| open + close)
+                .and_then(|close| rest[close..].find(')').map(|end| (close, close + end)));
+
+        match link {
+            Some((close, end)) => {
+                out.push_str(&rest[..open]);
+                out.push_str(&rest[open + 1..close]);
+                out.push(' ');
+                rest = &rest[end + 1..];
+            }
+            None => break,
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+// without the `inline code` spans
+fn strip_inline_code(line: &str) -> String {
+    line.split('`')
+        .enumerate()
+        .filter(|(i, _)| i % 2 == 0)
+        .map(|(_, part)| part)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn strip(text: &str, config: &MarkdownConfig) -> String {
+    let text = decode_entities(text);
+
+    let mut out = String::with_capacity(text.len());
+    let mut in_fence = false;
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            continue;
+        }
+
+        if config.code && (in_fence || line.starts_with("    ") || line.starts_with('\t')) {
+            continue;
+        }
+
+        if config.quotes && trimmed.starts_with('>') && !trimmed.starts_with(">!") {
+            continue;
+        }
+
+        let mut line = trimmed.trim_start_matches('#').to_string();
+
+        if config.link_urls {
+            line = strip_link_urls(&line);
+        }
+
+        if config.code {
+            line = strip_inline_code(&line);
+        }
+
+        for marker in FORMATTING {
+            if line.contains(marker) {
+                line = line.replace(marker, "");
+            }
+        }
+
+        out.push_str(&line);
+        out.push('\n');
+    }
+
+    out
+}
+
+impl TextStage for MarkdownConfig {
+    fn apply(&self, doc: &mut Document) -> bool {
+        doc.text = Cow::Owned(strip(&doc.text, self));
+        true
+    }
+}
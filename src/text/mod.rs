@@ -1,11 +1,22 @@
 use std::collections::{HashMap, HashSet};
 use std::io::BufReader;
+use std::path::PathBuf;
 
-
-
+use clap::ValueEnum;
 use lazy_static::lazy_static;
 use nlprule::tokenizer::Tokenizer;
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::Deserialize;
+
+use special::SpecialAction;
 
+pub mod interner;
+pub mod language;
+pub mod lemma;
+pub mod markdown;
+pub mod pipeline;
+pub mod special;
+mod stages;
 pub mod text_item;
 
 lazy_static! {
@@ -245,3 +256,163 @@ lazy_static! {
             .collect();
 }
 
+// Snowball stemmers, see https://snowballstem.org/algorithms/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum StemLanguage {
+    Arabic,
+    Danish,
+    Dutch,
+    English,
+    Finnish,
+    French,
+    German,
+    Greek,
+    Hungarian,
+    Italian,
+    Norwegian,
+    Portuguese,
+    Romanian,
+    Russian,
+    Spanish,
+    Swedish,
+    Tamil,
+    Turkish,
+}
+
+impl StemLanguage {
+    pub fn stemmer(self) -> Stemmer {
+        Stemmer::create(
+            match self {
+                Self::Arabic => Algorithm::Arabic,
+                Self::Danish => Algorithm::Danish,
+                Self::Dutch => Algorithm::Dutch,
+                Self::English => Algorithm::English,
+                Self::Finnish => Algorithm::Finnish,
+                Self::French => Algorithm::French,
+                Self::German => Algorithm::German,
+                Self::Greek => Algorithm::Greek,
+                Self::Hungarian => Algorithm::Hungarian,
+                Self::Italian => Algorithm::Italian,
+                Self::Norwegian => Algorithm::Norwegian,
+                Self::Portuguese => Algorithm::Portuguese,
+                Self::Romanian => Algorithm::Romanian,
+                Self::Russian => Algorithm::Russian,
+                Self::Spanish => Algorithm::Spanish,
+                Self::Swedish => Algorithm::Swedish,
+                Self::Tamil => Algorithm::Tamil,
+                Self::Turkish => Algorithm::Turkish,
+            }
+        )
+    }
+}
+
+// how comment bodies are split into words
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenizerMode {
+    // drop everything but letters, digits and whitespace, then split on whitespace
+    #[default]
+    Fast,
+    // the english nlprule tokenizer, handles contractions, hyphens and punctuation but is much slower
+    Accurate,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TokenizerConfig {
+    pub mode: TokenizerMode,
+    pub lowercase: bool,
+    // NFKC normalization and unicode case folding instead of plain lowercasing, words can differ
+    // from files written without it so keep it the same across files that get merged
+    pub normalize: bool,
+    pub urls: SpecialAction,
+    // u/user mentions
+    pub users: SpecialAction,
+    // r/subreddit references
+    pub subreddits: SpecialAction,
+    // left to the tokenizers numbers are junk, the ragegun readers always skipped them
+    pub numbers: SpecialAction,
+    // in characters, words outside are dropped, urls, mentions and emoji are never
+    pub min_token_len: Option<usize>,
+    pub max_token_len: Option<usize>,
+    // count emoji as words instead of dropping them with the other symbols
+    pub emoji: bool,
+    // reduce words to their stem after removing stopwords, e.g. "running" and "runs" to "run"
+    pub stem: Option<StemLanguage>,
+    // or replace them by their lemma from a `form,lemma` table, words not in it are kept
+    pub lemmas: Option<PathBuf>,
+    // also count runs of up to this many consecutive words, joined by `ngram_separator`
+    pub ngrams: usize,
+    pub ngram_separator: String,
+    // count single words next to the longer n-grams
+    pub unigrams: bool,
+    // count words in one of 2^hash_bits buckets named like "#3f2a1" instead of by themselves, which
+    // bounds the vocabulary at the cost of unrelated words sharing a bucket
+    pub hash_bits: Option<u32>,
+}
+
+impl Default for TokenizerConfig {
+    fn default() -> Self {
+        Self {
+            mode: TokenizerMode::Fast,
+            lowercase: true,
+            normalize: false,
+            urls: SpecialAction::Split,
+            users: SpecialAction::Split,
+            subreddits: SpecialAction::Split,
+            numbers: SpecialAction::Split,
+            min_token_len: None,
+            max_token_len: None,
+            emoji: false,
+            stem: None,
+            lemmas: None,
+            ngrams: 1,
+            ngram_separator: "_".to_string(),
+            unigrams: true,
+            hash_bits: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StopwordsConfig {
+    pub remove: bool,
+    // one word per line, replaces the built-in list
+    pub file: Option<PathBuf>,
+}
+
+impl StopwordsConfig {
+    // the words as the tokenizer leaves them, so "don't" matches "dont"
+    pub fn load(&self) -> std::io::Result<Option<HashSet<String>>> {
+        if !self.remove {
+            return Ok(None);
+        }
+
+        let normalize = |v: &str| {
+            v.chars()
+                .filter(|c| is_word_char(*c))
+                .collect::<String>()
+                .to_lowercase()
+        };
+
+        let words =
+            match &self.file {
+                Some(path) => std::fs::read_to_string(path)?.lines().map(normalize).collect::<HashSet<_>>(),
+                None => STOPWORDS.iter().map(|v| normalize(v)).collect(),
+            };
+
+        Ok(Some(words.into_iter().filter(|v| !v.is_empty()).collect()))
+    }
+}
+
+// numbers and links are not counted, ragegun readers have always skipped them
+pub fn is_junk(word: &[u8]) -> bool {
+    word.windows(4).any(|w| w == b"http") || word.iter().all(u8::is_ascii_digit)
+}
+
+// what survives the character filter of the fast tokenizer
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c.is_whitespace()
+}
@@ -0,0 +1,267 @@
+use std::borrow::Cow;
+use std::hash::Hasher;
+use std::io::{Error, ErrorKind};
+use std::ops::AddAssign;
+
+use twox_hash::XxHash64;
+
+use super::{StopwordsConfig, TokenizerConfig, TokenizerMode};
+use super::language::{LanguageConfig, LanguageFilter};
+use super::lemma::LemmaTable;
+use super::markdown::MarkdownConfig;
+use super::special::Specials;
+use super::stages::{Accurate, Fast, Junk, Length, Nfkc, Stopwords};
+use super::text_item::PooMapInner;
+
+// turns a comment body into word counts
+pub trait Tokenizer: Send + Sync {
+    fn tokenize(&self, text: &str) -> PooMapInner;
+
+    // the counted unigrams in the order they were written, for what learns from their context. tokenizers that
+    // don't keep the order give each word as often as it was counted
+    fn words(&self, text: &str) -> Vec<Vec<u8>> {
+        self.tokenize(text)
+            .into_iter()
+            .flat_map(|(word, freq)| std::iter::repeat_n(word, freq as usize))
+            .collect()
+    }
+}
+
+// a comment on its way through the text stages
+pub struct Document<'a> {
+    pub text: Cow<'a, str>,
+    // counted words become "prefix:word"
+    pub prefix: Option<&'static str>,
+}
+
+// rewrites a comment before it's split, returning false drops it
+pub trait TextStage: Send + Sync {
+    fn apply(&self, doc: &mut Document) -> bool;
+}
+
+pub struct Token<'a> {
+    pub text: Cow<'a, str>,
+    // urls, mentions and emoji, which skip the filters and transforms
+    pub special: bool,
+}
+
+impl<'a> Token<'a> {
+    pub fn word(text: impl Into<Cow<'a, str>>) -> Self {
+        Self { text: text.into(), special: false }
+    }
+
+    pub fn special(text: impl Into<Cow<'a, str>>) -> Self {
+        Self { text: text.into(), special: true }
+    }
+}
+
+pub trait Splitter: Send + Sync {
+    fn split<'a>(&self, text: &'a str) -> Vec<Token<'a>>;
+}
+
+pub trait Filter: Send + Sync {
+    fn keep(&self, word: &str) -> bool;
+}
+
+pub trait Transform: Send + Sync {
+    fn apply<'a>(&self, word: Cow<'a, str>) -> Cow<'a, str>;
+}
+
+// normalize and strip, split, filter, transform, then count the words and n-grams of what's left
+pub struct Pipeline {
+    text: Vec<Box<dyn TextStage>>,
+    splitter: Box<dyn Splitter>,
+    filters: Vec<Box<dyn Filter>>,
+    transforms: Vec<Box<dyn Transform>>,
+    ngrams: usize,
+    ngram_separator: String,
+    unigrams: bool,
+    hash_bits: Option<u32>,
+}
+
+impl Pipeline {
+    // just splits, nothing is filtered or transformed
+    pub fn new(splitter: impl Splitter + 'static) -> Self {
+        Self {
+            text: Vec::new(),
+            splitter: Box::new(splitter),
+            filters: Vec::new(),
+            transforms: Vec::new(),
+            ngrams: 1,
+            ngram_separator: "_".to_string(),
+            unigrams: true,
+            hash_bits: None,
+        }
+    }
+
+    pub fn from_config(
+        tokenizer: &TokenizerConfig,
+        stopwords: &StopwordsConfig,
+        markdown: &MarkdownConfig,
+        language: &LanguageConfig,
+    ) -> std::io::Result<Self> {
+        if tokenizer.stem.is_some() && tokenizer.lemmas.is_some() {
+            return Err(Error::new(ErrorKind::InvalidInput, "stem and lemmas are alternatives, set only one"));
+        }
+
+        if tokenizer.hash_bits.is_some_and(|bits| bits == 0 || bits > 32) {
+            return Err(Error::new(ErrorKind::InvalidInput, "hash_bits has to be between 1 and 32"));
+        }
+
+        if let (Some(min), Some(max)) = (tokenizer.min_token_len, tokenizer.max_token_len) {
+            if min > max {
+                return Err(Error::new(ErrorKind::InvalidInput, "min_token_len is above max_token_len"));
+            }
+        }
+
+        let splitter: Box<dyn Splitter> =
+            match tokenizer.mode {
+                TokenizerMode::Fast => Box::new(Fast { lowercase: tokenizer.lowercase, normalize: tokenizer.normalize }),
+                TokenizerMode::Accurate => Box::new(Accurate { lowercase: tokenizer.lowercase, normalize: tokenizer.normalize }),
+            };
+
+        let mut pipeline =
+            Self {
+                ngrams: tokenizer.ngrams.max(1),
+                ngram_separator: tokenizer.ngram_separator.clone(),
+                unigrams: tokenizer.unigrams || tokenizer.ngrams <= 1,
+                hash_bits: tokenizer.hash_bits,
+                ..Self::new(Specials::wrap(tokenizer, splitter))
+            };
+
+        if markdown.strip {
+            pipeline = pipeline.with_text_stage(markdown.clone());
+        }
+
+        // on the comment as written, before normalizing can change it
+        if let Some(language) = LanguageFilter::from_config(language)? {
+            pipeline = pipeline.with_text_stage(language);
+        }
+
+        if tokenizer.normalize {
+            pipeline = pipeline.with_text_stage(Nfkc);
+        }
+
+        pipeline = pipeline.with_filter(Junk);
+
+        if tokenizer.min_token_len.is_some() || tokenizer.max_token_len.is_some() {
+            pipeline =
+                pipeline.with_filter(Length {
+                    min: tokenizer.min_token_len.unwrap_or(0),
+                    max: tokenizer.max_token_len.unwrap_or(usize::MAX),
+                });
+        }
+
+        if let Some(words) = stopwords.load()? {
+            pipeline = pipeline.with_filter(Stopwords { words, lowercase: tokenizer.lowercase });
+        }
+
+        if let Some(stem) = tokenizer.stem {
+            pipeline = pipeline.with_transform(stem.stemmer());
+        }
+
+        if let Some(path) = &tokenizer.lemmas {
+            pipeline = pipeline.with_transform(LemmaTable::load(path)?);
+        }
+
+        Ok(pipeline)
+    }
+
+    // stages run in the order they were added
+    pub fn with_text_stage(mut self, stage: impl TextStage + 'static) -> Self {
+        self.text.push(Box::new(stage));
+        self
+    }
+
+    pub fn with_filter(mut self, filter: impl Filter + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    pub fn with_transform(mut self, transform: impl Transform + 'static) -> Self {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+
+    fn key(&self, word: &str, prefix: Option<&str>) -> Vec<u8> {
+        let key =
+            match prefix {
+                Some(prefix) => [prefix.as_bytes(), b":", word.as_bytes()].concat(),
+                None => word.as_bytes().to_vec(),
+            };
+
+        match self.hash_bits {
+            Some(bits) => bucket(&key, bits),
+            None => key,
+        }
+    }
+
+    fn count(&self, acc: &mut PooMapInner, word: &str, prefix: Option<&str>) {
+        acc.entry(self.key(word, prefix)).or_insert(0).add_assign(1u64);
+    }
+
+    // `f` gets the words left after the stages, filters and transforms, none if a stage dropped the comment
+    fn with_words<T>(&self, text: &str, f: impl FnOnce(&[Cow<str>], Option<&str>) -> T) -> Option<T> {
+        let mut doc = Document { text: Cow::Borrowed(text), prefix: None };
+
+        for stage in self.text.iter() {
+            if !stage.apply(&mut doc) {
+                return None;
+            }
+        }
+
+        let words =
+            self.splitter
+                .split(&doc.text)
+                .into_iter()
+                .filter(|token| token.special || self.filters.iter().all(|filter| filter.keep(&token.text)))
+                .map(|token| {
+                    match token.special {
+                        true => token.text,
+                        false => self.transforms.iter().fold(token.text, |word, transform| transform.apply(word)),
+                    }
+                })
+                .collect::<Vec<_>>();
+
+        Some(f(&words, doc.prefix))
+    }
+}
+
+impl Tokenizer for Pipeline {
+    fn tokenize(&self, text: &str) -> PooMapInner {
+        self.with_words(text, |words, prefix| {
+            let mut acc = PooMapInner::default();
+
+            if self.unigrams {
+                for word in words.iter() {
+                    self.count(&mut acc, word, prefix);
+                }
+            }
+
+            // n-grams span the words left after stopword removal
+            for n in 2..=self.ngrams {
+                for window in words.windows(n) {
+                    self.count(&mut acc, &window.join(self.ngram_separator.as_str()), prefix);
+                }
+            }
+
+            acc
+        })
+            .unwrap_or_default()
+    }
+
+    fn words(&self, text: &str) -> Vec<Vec<u8>> {
+        self.with_words(text, |words, prefix| words.iter().map(|word| self.key(word, prefix)).collect())
+            .unwrap_or_default()
+    }
+}
+
+// hex so the name is never mistaken for a number, fixed width so buckets sort by number
+fn bucket(word: &[u8], bits: u32) -> Vec<u8> {
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(word);
+
+    let bucket = hasher.finish() & ((1u64 << bits) - 1);
+
+    format!("#{:0width$x}", bucket, width = bits.div_ceil(4) as usize).into_bytes()
+}
@@ -0,0 +1,213 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::TokenizerConfig;
+use super::pipeline::{Splitter, Token};
+
+// what happens to urls, u/user mentions, r/subreddit references and numbers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SpecialAction {
+    // left to the tokenizer, which breaks them into fragments like "httpsexamplecom"
+    #[default]
+    Split,
+    Drop,
+    // urls become `<URL>`, mentions `<USER>`, subreddits a lowercase `r/name` and numbers `<YEAR>`, `<ID>` or `<NUM>`
+    Normalize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Url,
+    User,
+    Subreddit,
+    Number,
+}
+
+// digit strings at least this long are ids, phone numbers, timestamps and the like rather than amounts
+const ID_DIGITS: usize = 7;
+
+// a stretch of ordinary text or a special token that is counted as is
+enum Piece<'a> {
+    Text(&'a str),
+    Special(String),
+}
+
+// cuts urls, mentions and emoji out of the text before the inner splitter can shred them
+pub struct Specials {
+    urls: SpecialAction,
+    users: SpecialAction,
+    subreddits: SpecialAction,
+    numbers: SpecialAction,
+    emoji: bool,
+    inner: Box<dyn Splitter>,
+}
+
+impl Specials {
+    pub fn wrap(config: &TokenizerConfig, inner: Box<dyn Splitter>) -> Self {
+        Self {
+            urls: config.urls,
+            users: config.users,
+            subreddits: config.subreddits,
+            numbers: config.numbers,
+            emoji: config.emoji,
+            inner,
+        }
+    }
+}
+
+// punctuation commonly wrapped around a reference, as in "(see r/rust)."
+fn trim_punctuation(token: &str) -> &str {
+    token.trim_matches(|c: char| "()[]{}<>,.!?:;\"'*".contains(c))
+}
+
+fn classify(token: &str) -> Option<(Kind, &str)> {
+    let token = trim_punctuation(token);
+
+    // "2,000" and "3.14" too, which the tokenizers would turn into "2000" and "314"
+    if token.starts_with(|c: char| c.is_ascii_digit()) && token.bytes().all(|b| b.is_ascii_digit() || b == b'.' || b == b',') {
+        return Some((Kind::Number, token));
+    }
+
+    let lower = token.get(..8).unwrap_or(token).to_ascii_lowercase();
+
+    if lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("www.") {
+        return Some((Kind::Url, token));
+    }
+
+    let reference = token.strip_prefix('/').unwrap_or(token);
+
+    let kind =
+        match reference.get(..2) {
+            Some("u/" | "U/") => Kind::User,
+            Some("r/" | "R/") => Kind::Subreddit,
+            _ => return None,
+        };
+
+    let name = &reference[2..];
+
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return None;
+    }
+
+    Some((kind, name))
+}
+
+fn action(kind: Kind, options: &Specials) -> SpecialAction {
+    match kind {
+        Kind::Url => options.urls,
+        Kind::User => options.users,
+        Kind::Subreddit => options.subreddits,
+        Kind::Number => options.numbers,
+    }
+}
+
+fn number(token: &str) -> &'static str {
+    match token.parse::<u32>() {
+        Ok(1900..=2099) if token.len() == 4 => "<YEAR>",
+        _ if token.len() >= ID_DIGITS && token.bytes().all(|b| b.is_ascii_digit()) => "<ID>",
+        _ => "<NUM>",
+    }
+}
+
+// pictographs, dingbats, flags and skin tones, not the symbols like © that happen to have an emoji form
+fn is_emoji(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2300..=0x23FF | 0x2B00..=0x2BFF | 0x3030 | 0x303D | 0x3297 | 0x3299
+    )
+}
+
+// every emoji grapheme, including zwj sequences like 👩‍💻, becomes a token of its own, "nice😂😂" is
+// "nice" and two "😂"
+fn push_text<'a>(pieces: &mut Vec<Piece<'a>>, text: &'a str, options: &Specials) {
+    if !options.emoji || !text.chars().any(is_emoji) {
+        pieces.push(Piece::Text(text));
+        return;
+    }
+
+    let mut start = 0;
+
+    for (offset, grapheme) in text.grapheme_indices(true) {
+        if !grapheme.chars().any(is_emoji) {
+            continue;
+        }
+
+        if offset > start {
+            pieces.push(Piece::Text(&text[start..offset]));
+        }
+
+        // with or without the variation selector asking for emoji or text presentation it's the same emoji
+        pieces.push(Piece::Special(grapheme.chars().filter(|c| !matches!(c, '\u{FE0E}' | '\u{FE0F}')).collect()));
+
+        start = offset + grapheme.len();
+    }
+
+    if start < text.len() {
+        pieces.push(Piece::Text(&text[start..]));
+    }
+}
+
+fn pieces<'a>(text: &'a str, options: &Specials) -> Vec<Piece<'a>> {
+    if [options.urls, options.users, options.subreddits, options.numbers].iter().all(|v| *v == SpecialAction::Split) {
+        let mut pieces = Vec::new();
+        push_text(&mut pieces, text, options);
+
+        return pieces;
+    }
+
+    let mut pieces = Vec::new();
+    let mut start = 0;
+
+    for token in text.split_whitespace() {
+        let Some((kind, name)) = classify(token) else {
+            continue;
+        };
+
+        let action = action(kind, options);
+
+        if action == SpecialAction::Split {
+            continue;
+        }
+
+        let offset = token.as_ptr() as usize - text.as_ptr() as usize;
+
+        if offset > start {
+            push_text(&mut pieces, &text[start..offset], options);
+        }
+
+        if action == SpecialAction::Normalize {
+            pieces.push(Piece::Special(
+                match kind {
+                    Kind::Url => "<URL>".to_string(),
+                    Kind::User => "<USER>".to_string(),
+                    Kind::Subreddit => format!("r/{}", name.to_ascii_lowercase()),
+                    Kind::Number => number(name).to_string(),
+                }
+            ));
+        }
+
+        start = offset + token.len();
+    }
+
+    if start < text.len() {
+        push_text(&mut pieces, &text[start..], options);
+    }
+
+    pieces
+}
+
+impl Splitter for Specials {
+    fn split<'a>(&self, text: &'a str) -> Vec<Token<'a>> {
+        pieces(text, self)
+            .into_iter()
+            .flat_map(|piece| {
+                match piece {
+                    Piece::Text(text) => self.inner.split(text),
+                    Piece::Special(token) => vec![Token::special(token)],
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,138 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use caseless::default_case_fold_str;
+use rust_stemmers::Stemmer;
+use unicode_normalization::{is_nfkc_quick, IsNormalized, UnicodeNormalization};
+
+use super::{is_junk, is_word_char, EN_TOKENIZER};
+use super::pipeline::{Document, Filter, Splitter, TextStage, Token, Transform};
+
+// so composed and decomposed "café" or full-width letters end up as the same word
+pub struct Nfkc;
+
+impl TextStage for Nfkc {
+    fn apply(&self, doc: &mut Document) -> bool {
+        if is_nfkc_quick(doc.text.chars()) != IsNormalized::Yes {
+            doc.text = Cow::Owned(doc.text.nfkc().collect());
+        }
+
+        true
+    }
+}
+
+// full unicode case folding when normalizing, "Straße" becomes "strasse"
+fn lower(text: String, lowercase: bool, normalize: bool) -> String {
+    match (lowercase, normalize) {
+        (false, _) => text,
+        (true, false) => text.to_lowercase(),
+        // folding can leave text that isn't normalized anymore
+        (true, true) => default_case_fold_str(&text).nfkc().collect(),
+    }
+}
+
+// only letters, digits and whitespace, so "don't" becomes "dont" and "well-known" "wellknown"
+pub struct Fast {
+    pub lowercase: bool,
+    pub normalize: bool,
+}
+
+impl Splitter for Fast {
+    fn split<'a>(&self, text: &'a str) -> Vec<Token<'a>> {
+        let text = text.chars().filter(|c| is_word_char(*c)).collect::<String>();
+
+        lower(text, self.lowercase, self.normalize)
+            .split_whitespace()
+            .map(|word| Token::word(word.to_string()))
+            .collect()
+    }
+}
+
+// the tokens of the english tokenizer with contractions rejoined, so "don't" and "it's" stay whole
+// and "well-known" too, tokens without a single letter or digit are punctuation and dropped
+pub struct Accurate {
+    pub lowercase: bool,
+    pub normalize: bool,
+}
+
+impl Splitter for Accurate {
+    fn split<'a>(&self, text: &'a str) -> Vec<Token<'a>> {
+        let is_apostrophe = |c: char| c == '\'' || c == '\u{2019}';
+
+        let mut words: Vec<String> = Vec::new();
+
+        for sentence in EN_TOKENIZER.sentencize(text) {
+            // the tokenizer splits at apostrophes, "don't" arrives as "don", "'" and "t"
+            let mut joinable = false;
+
+            for token in sentence.iter() {
+                let word = token.word().text().as_str();
+
+                let apostrophe = word.chars().all(is_apostrophe);
+
+                match words.last_mut() {
+                    Some(last) if !token.has_space_before() && (joinable || apostrophe) => last.push_str(word),
+                    _ => words.push(word.to_string()),
+                }
+
+                joinable = apostrophe;
+            }
+        }
+
+        words
+            .into_iter()
+            .filter(|word| word.chars().any(char::is_alphanumeric))
+            .map(|word| Token::word(lower(word, self.lowercase, self.normalize)))
+            .collect()
+    }
+}
+
+pub struct Junk;
+
+impl Filter for Junk {
+    fn keep(&self, word: &str) -> bool {
+        !is_junk(word.as_bytes())
+    }
+}
+
+// in characters, of the words as split, before stemming and lemmas shorten them
+pub struct Length {
+    pub min: usize,
+    pub max: usize,
+}
+
+impl Filter for Length {
+    fn keep(&self, word: &str) -> bool {
+        // a word has no more characters than bytes
+        if word.len() < self.min {
+            return false;
+        }
+
+        (self.min..=self.max).contains(&word.chars().count())
+    }
+}
+
+pub struct Stopwords {
+    pub words: HashSet<String>,
+    pub lowercase: bool,
+}
+
+impl Filter for Stopwords {
+    fn keep(&self, word: &str) -> bool {
+        // stopwords are stored as the fast tokenizer leaves them
+        if self.lowercase && word.chars().all(char::is_alphanumeric) {
+            return !self.words.contains(word);
+        }
+
+        !self.words.contains(&word.chars().filter(|c| is_word_char(*c)).collect::<String>().to_lowercase())
+    }
+}
+
+impl Transform for Stemmer {
+    fn apply<'a>(&self, word: Cow<'a, str>) -> Cow<'a, str> {
+        match word {
+            Cow::Borrowed(word) => self.stem(word),
+            Cow::Owned(word) => Cow::Owned(self.stem(&word).into_owned()),
+        }
+    }
+}
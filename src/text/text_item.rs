@@ -1,71 +1,264 @@
-use std::collections::{BTreeMap, HashMap};
-use std::iter::Cloned;
-use std::ops::AddAssign;
-use std::str::SplitWhitespace;
-use lazy_static::lazy_static;
-
-use rayon::iter::ParallelIterator;
-use rayon::prelude::*;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::BuildHasherDefault;
+
 use serde::{Deserialize, Serialize};
+use twox_hash::XxHash64;
 
-use super::EN_TOKENIZER;
+use super::interner::{Counting, FreqStore};
+use crate::sketch::Distinct;
 
-pub type PooMapRoot<K, V> = BTreeMap<K, V>;
-pub type PooMapBase<T> = BTreeMap<Vec<u8>, T>;
+// unordered for fast inserts, the serializer sorts keys so outputs stay deterministic
+pub type PooMapRoot<K, V> = HashMap<K, V, BuildHasherDefault<XxHash64>>;
+pub type PooMapBase<T> = PooMapRoot<Vec<u8>, T>;
 pub type PooMapInner = PooMapBase<u64>;
 pub type PooMap = PooMapBase<PooMapInner>;
+// written as-is with bincode, so it stays ordered
+pub type MetaMap = BTreeMap<Vec<u8>, AuthorMeta>;
+pub type StatsMap = BTreeMap<Vec<u8>, AuthorStats>;
+
+// per-author denominators for normalizing the word counts, of everything an author wrote before any pruning
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AuthorStats {
+    pub comments: u64,
+    pub tokens: u64,
+    // of the bodies, in unicode scalar values
+    pub characters: u64,
+    // filled in when the output is written, from all the words the author used, estimated for the authors who lost
+    // some while ingesting
+    pub unique_words: u64,
+    // of the letters of the bodies, the tokens are lowercased so the style is only kept here
+    pub letters: u64,
+    pub capitals: u64,
+    // whitespace separated, and those of two or more letters all in capitals
+    pub words: u64,
+    pub shouted: u64,
+    // the hyperloglog behind `unique_words` of the authors who lost words, so merges can union them
+    pub distinct: Option<Distinct>,
+}
+
+impl AuthorStats {
+    pub fn observe(&mut self, tokens: u64, body: &str) {
+        self.comments += 1;
+        self.tokens += tokens;
+        self.characters += body.chars().count() as u64;
+
+        for word in body.split_whitespace() {
+            let (letters, capitals) =
+                word.chars()
+                    .filter(|c| c.is_alphabetic())
+                    .fold((0, 0), |(letters, capitals), c| (letters + 1, capitals + c.is_uppercase() as u64));
+
+            self.words += 1;
+            self.letters += letters;
+            self.capitals += capitals;
+            self.shouted += (letters >= 2 && capitals == letters) as u64;
+        }
+    }
+
+    // the unique words of merged stats are those of the merged words, not a sum. the words of an author without a
+    // hyperloglog in one of the inputs are all in the merged words, so they're added while merging
+    pub fn merge(&mut self, other: &AuthorStats) {
+        self.comments += other.comments;
+        self.tokens += other.tokens;
+        self.characters += other.characters;
+        self.letters += other.letters;
+        self.capitals += other.capitals;
+        self.words += other.words;
+        self.shouted += other.shouted;
+
+        match (&mut self.distinct, &other.distinct) {
+            (Some(distinct), Some(other)) => distinct.union(other),
+            (None, Some(other)) => self.distinct = Some(other.clone()),
+            _ => {}
+        }
+    }
+
+    pub fn mean_length(&self) -> f64 {
+        self.characters as f64 / self.comments.max(1) as f64
+    }
+
+    pub fn capitalization_rate(&self) -> f64 {
+        self.capitals as f64 / self.letters.max(1) as f64
+    }
+
+    pub fn shouting_rate(&self) -> f64 {
+        self.shouted as f64 / self.words.max(1) as f64
+    }
+}
+
+// per-author aggregates of the comment metadata
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AuthorMeta {
+    pub first_seen: Option<i64>,
+    pub last_seen: Option<i64>,
+    pub comment_count: u64,
+    pub score: i64,
+    pub subreddits: BTreeSet<String>,
+    pub bot: BotSignals,
+    // from 0 to 1, kept up to date with the signals so readers of the metadata don't need to know how it's weighed
+    pub bot_score: f64,
+}
+
+// fewer gaps than this tell nothing about how regular the posting is
+const MIN_GAPS: u64 = 5;
+
+// what automated accounts give themselves away by, as sums so metadata of several batches or files merges
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BotSignals {
+    // a name ending in "bot" or a known bot
+    pub named: bool,
+    // bodies the author posted before, as far as the dedup history remembers
+    pub repeats: u64,
+    // bodies with the same first and last line as an earlier one but something else between them
+    pub templated: u64,
+    // "i am a bot" footers and the like
+    pub signed: u64,
+    // the seconds between consecutive comments, for the coefficient of variation, which is near 0 for a schedule
+    pub gaps: u64,
+    pub gap_sum: f64,
+    pub gap_squares: f64,
+}
+
+impl BotSignals {
+    fn gap(&mut self, seconds: i64) {
+        if seconds >= 0 {
+            self.gaps += 1;
+            self.gap_sum += seconds as f64;
+            self.gap_squares += seconds as f64 * seconds as f64;
+        }
+    }
+
+    // a name decides it, otherwise the shares of the comments giving the author away, weighed by how telling they
+    // are, and how regular the posting is
+    pub fn score(&self, comments: u64) -> f64 {
+        if self.named {
+            return 1.0;
+        }
+
+        if comments == 0 {
+            return 0.0;
+        }
+
+        let share = |n: u64| (n as f64 / comments as f64).min(1.0);
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+        let regularity =
+            match self.gaps {
+                gaps if gaps < MIN_GAPS => 0.0,
+                gaps => {
+                    let mean = self.gap_sum / gaps as f64;
+                    let variance = (self.gap_squares / gaps as f64 - mean * mean).max(0.0);
+
+                    match mean > 0.0 {
+                        true => (1.0 - variance.sqrt() / mean).max(0.0),
+                        // everything in the same second
+                        false => 1.0,
+                    }
+                }
+            };
+
+        (0.35 * share(self.signed) + 0.25 * share(self.repeats) + 0.2 * share(self.templated) + 0.2 * regularity).min(1.0)
+    }
+}
+
+impl AuthorMeta {
+    // the gaps are only exact for comments in time order, which the dumps are within a file
+    pub fn observe_bot(&mut self, created_utc: Option<i64>, named: bool, repeat: bool, templated: bool, signed: bool) {
+        if let (Some(ts), Some(last)) = (created_utc, self.last_seen) {
+            self.bot.gap(ts - last);
+        }
+
+        self.bot.named |= named;
+        self.bot.repeats += repeat as u64;
+        self.bot.templated += templated as u64;
+        self.bot.signed += signed as u64;
+    }
+
+    pub fn observe(&mut self, created_utc: Option<i64>, score: Option<i64>, subreddit: Option<&str>) {
+        if let Some(ts) = created_utc {
+            self.first_seen = Some(self.first_seen.map_or(ts, |v| v.min(ts)));
+            self.last_seen = Some(self.last_seen.map_or(ts, |v| v.max(ts)));
+        }
+
+        self.comment_count += 1;
+        self.score += score.unwrap_or(0);
+
+        if let Some(subreddit) = subreddit {
+            if !self.subreddits.contains(subreddit) {
+                self.subreddits.insert(subreddit.to_string());
+            }
+        }
+
+        self.bot_score = self.bot.score(self.comment_count);
+    }
+
+    pub fn merge(&mut self, other: &AuthorMeta) {
+        // the gap where the two meet, when other follows self
+        if let (Some(last), Some(first)) = (self.last_seen, other.first_seen) {
+            self.bot.gap(first - last);
+        }
+
+        self.bot.named |= other.bot.named;
+        self.bot.repeats += other.bot.repeats;
+        self.bot.templated += other.bot.templated;
+        self.bot.signed += other.bot.signed;
+        self.bot.gaps += other.bot.gaps;
+        self.bot.gap_sum += other.bot.gap_sum;
+        self.bot.gap_squares += other.bot.gap_squares;
+
+        self.first_seen =
+            match (self.first_seen, other.first_seen) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+
+        self.last_seen =
+            match (self.last_seen, other.last_seen) {
+                (Some(a), Some(b)) => Some(a.max(b)),
+                (a, b) => a.or(b),
+            };
+
+        self.comment_count += other.comment_count;
+        self.score += other.score;
+        self.subreddits.extend(other.subreddits.iter().cloned());
+
+        self.bot_score = self.bot.score(self.comment_count);
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TextItem {
-    pub word_freqs: PooMap,
+    pub word_freqs: FreqStore,
+    #[serde(default)]
+    pub meta: MetaMap,
+    #[serde(default)]
+    pub stats: StatsMap,
 }
 
 impl TextItem {
     pub fn new() -> Self {
-        Self {
-            word_freqs: PooMap::new(),
-        }
+        Self::default()
     }
 
-    pub fn ingest(&mut self, other: &PooMap) {
-        for (author, freqs) in other.iter() {
-            let author_freqs =
-                self.word_freqs
-                    .entry(author.clone())
-                    .or_insert_with(PooMapInner::new);
-
-            for (word, freq) in freqs.iter() {
-                author_freqs
-                    .entry(word.clone())
-                    .or_insert(0)
-                    .add_assign(*freq);
-            }
+    pub fn ingest(&mut self, other: &PooMap, counting: Counting) {
+        self.word_freqs.ingest_with(other, counting);
+    }
+
+    pub fn ingest_meta(&mut self, other: &MetaMap) {
+        for (author, meta) in other.iter() {
+            self.meta
+                .entry(author.clone())
+                .or_default()
+                .merge(meta);
         }
     }
 
-    #[inline(always)]
-    pub fn process_alt(text: &str) -> PooMapInner {
-        text
-            .chars()
-            .filter(|c| c.is_alphanumeric() || c.is_whitespace())
-            .collect::<String>()
-            .to_lowercase()
-            .split_whitespace()
-            .fold(
-                PooMapInner::new(),
-                |mut acc, word| {
-                    acc
-                        .entry(
-                            word.trim()
-                                .as_bytes()
-                                .to_vec()
-                        )
-                        .or_insert(0)
-                        .add_assign(1u64);
-
-                    acc
-                },
-            )
+    pub fn ingest_stats(&mut self, other: &StatsMap) {
+        for (author, stats) in other.iter() {
+            self.stats
+                .entry(author.clone())
+                .or_default()
+                .merge(stats);
+        }
     }
 }
 
@@ -0,0 +1,180 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::cli::WeightingArgs;
+use crate::serializer::SortedFreqs;
+use crate::text::text_item::PooMapBase;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TfScheme {
+    // the count itself
+    Raw,
+    // 1 + ln(count), dampens authors repeating themselves
+    #[default]
+    Log,
+    // count divided by the author's total
+    Relative,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TfidfConfig {
+    pub tf: TfScheme,
+    // words used by fewer authors are dropped
+    pub min_df: u64,
+    // words used by a larger share of authors are dropped
+    pub max_df: f64,
+    // scale every author's vector to unit length
+    pub normalize: bool,
+}
+
+impl Default for TfidfConfig {
+    fn default() -> Self {
+        Self {
+            tf: TfScheme::Log,
+            min_df: 1,
+            max_df: 1.0,
+            normalize: true,
+        }
+    }
+}
+
+impl TfidfConfig {
+    // command line flags take precedence over the config file
+    pub fn apply(&mut self, args: &WeightingArgs) {
+        if let Some(tf) = args.tf {
+            self.tf = tf;
+        }
+
+        if let Some(min_df) = args.min_df {
+            self.min_df = min_df;
+        }
+
+        if let Some(max_df) = args.max_df {
+            self.max_df = max_df;
+        }
+
+        self.normalize &= !args.no_normalize;
+    }
+}
+
+// document frequencies across authors, every author counts as one document
+pub struct Tfidf {
+    config: TfidfConfig,
+    authors: u64,
+    // only words within the df bounds, with their dimension in `vectorize`
+    idf: PooMapBase<(u32, f64)>,
+    // by dimension
+    words: Vec<Vec<u8>>,
+}
+
+// (dimension, weight) in dimension order
+pub type SparseVector = Vec<(u32, f32)>;
+
+impl Tfidf {
+    pub fn fit(data: &impl SortedFreqs, config: &TfidfConfig) -> std::io::Result<Self> {
+        let mut authors = 0u64;
+        let mut dfs = PooMapBase::<u64>::default();
+
+        data.for_each_sorted(|_, freqs| {
+            authors += 1;
+
+            for &(word, _) in freqs {
+                match dfs.get_mut(word) {
+                    Some(df) => *df += 1,
+                    None => {
+                        dfs.insert(word.to_vec(), 1);
+                    }
+                }
+            }
+
+            Ok(())
+        })?;
+
+        let max_df = (config.max_df * authors as f64).floor() as u64;
+
+        let mut words =
+            dfs.into_iter()
+                .filter(|(_, df)| *df >= config.min_df && *df <= max_df)
+                .collect::<Vec<_>>();
+
+        // dimensions in byte order so they don't depend on hashing
+        words.sort_unstable();
+
+        // smoothed like sklearn, so words every author uses still get a small positive weight
+        let idf =
+            words.iter()
+                .enumerate()
+                .map(|(dim, (word, df))| (word.clone(), (dim as u32, ((1 + authors) as f64 / (1 + df) as f64).ln() + 1.0)))
+                .collect();
+
+        Ok(Self {
+            config: config.clone(),
+            authors,
+            idf,
+            words: words.into_iter().map(|(word, _)| word).collect(),
+        })
+    }
+
+    pub fn author_count(&self) -> u64 {
+        self.authors
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn word(&self, dim: u32) -> &[u8] {
+        &self.words[dim as usize]
+    }
+
+    // None for words outside the df bounds
+    pub fn dimension(&self, word: &[u8]) -> Option<u32> {
+        self.idf.get(word).map(|(dim, _)| *dim)
+    }
+
+    pub fn weigh<'a>(&self, freqs: &[(&'a [u8], u64)]) -> Vec<(&'a [u8], f64)> {
+        self.weigh_with(freqs, |word, _| word)
+    }
+
+    pub fn vectorize(&self, freqs: &[(&[u8], u64)]) -> SparseVector {
+        let mut vector =
+            self.weigh_with(freqs, |_, dim| dim)
+                .into_iter()
+                .map(|(dim, weight)| (dim, weight as f32))
+                .collect::<Vec<_>>();
+
+        vector.sort_unstable_by_key(|(dim, _)| *dim);
+
+        vector
+    }
+
+    fn weigh_with<'a, T>(&self, freqs: &[(&'a [u8], u64)], key: impl Fn(&'a [u8], u32) -> T) -> Vec<(T, f64)> {
+        let total = freqs.iter().map(|(_, freq)| *freq).sum::<u64>() as f64;
+
+        let mut weights =
+            freqs.iter()
+                .filter_map(|&(word, freq)| {
+                    let tf =
+                        match self.config.tf {
+                            TfScheme::Raw => freq as f64,
+                            TfScheme::Log => 1.0 + (freq as f64).ln(),
+                            TfScheme::Relative => freq as f64 / total,
+                        };
+
+                    self.idf.get(word).map(|&(dim, idf)| (key(word, dim), tf * idf))
+                })
+                .collect::<Vec<_>>();
+
+        if self.config.normalize {
+            let norm = weights.iter().map(|(_, w)| w * w).sum::<f64>().sqrt();
+
+            if norm > 0.0 {
+                weights.iter_mut().for_each(|(_, w)| *w /= norm);
+            }
+        }
+
+        weights
+    }
+}